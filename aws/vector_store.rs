@@ -1,430 +1,439 @@
 use std::collections::HashMap;
-use std::error::Error;
-use serde::{Serialize, Deserialize};
-use crate::Agent;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::sync::Mutex;
 
-/// Theme for site generation
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Theme {
-    Default,
-    Light,
-    Dark,
-    ModernLight,
-    ModernDark,
-    Custom(String),
-}
+use crate::{http, BeaError, ProviderError};
+use crate::dataset::DatasetRecord;
 
-impl Theme {
-    /// Get the CSS class for this theme
-    pub fn css_class(&self) -> &str {
-        match self {
-            Theme::Default => "bea-theme-default",
-            Theme::Light => "bea-theme-light",
-            Theme::Dark => "bea-theme-dark",
-            Theme::ModernLight => "bea-theme-modern-light",
-            Theme::ModernDark => "bea-theme-modern-dark",
-            Theme::Custom(_) => "bea-theme-custom",
-        }
-    }
+/// Number of texts embedded per request by default
+pub const DEFAULT_EMBED_BATCH_SIZE: usize = 100;
+
+/// Produces embeddings for text, so it can be looked up in a `VectorStore`
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    /// Embed a batch of texts, returning one embedding per text in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, BeaError>;
     
-    /// Parse a theme from a string
-    pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "default" => Theme::Default,
-            "light" => Theme::Light,
-            "dark" => Theme::Dark,
-            "modern-light" => Theme::ModernLight,
-            "modern-dark" => Theme::ModernDark,
-            _ => Theme::Custom(s.to_string()),
-        }
+    /// Most texts to send in one `embed` call
+    fn max_batch_size(&self) -> usize {
+        DEFAULT_EMBED_BATCH_SIZE
     }
 }
 
-/// Authentication configuration for site
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthConfig {
-    /// Authentication method
-    pub method: AuthMethod,
-    /// Redirect URL for OAuth
-    pub redirect_url: Option<String>,
-    /// Client ID for OAuth
-    pub client_id: Option<String>,
-    /// Client secret for OAuth
-    pub client_secret: Option<String>,
-    /// Allowed domains for email authentication
-    pub allowed_domains: Option<Vec<String>>,
-    /// Custom authentication headers
-    pub headers: Option<HashMap<String, String>>,
-}
+/// Default OpenAI API base URL
+const OPENAI_API_URL: &str = "https://api.openai.com/v1";
 
-/// Authentication method
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum AuthMethod {
-    None,
-    Basic,
-    OAuth2,
-    OIDC,
-    Email,
-    Custom,
-}
-
-impl From<crate::Auth> for AuthMethod {
-    fn from(auth: crate::Auth) -> Self {
-        match auth {
-            crate::Auth::None => AuthMethod::None,
-            crate::Auth::Basic => AuthMethod::Basic,
-            crate::Auth::OAuth2 => AuthMethod::OAuth2,
-            crate::Auth::OIDC => AuthMethod::OIDC,
-            crate::Auth::Custom(_) => AuthMethod::Custom,
-        }
-    }
-}
-
-/// Site configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SiteConfig {
-    /// Site ID
-    pub id: String,
-    /// Site name
-    pub name: String,
-    /// Site theme
-    pub theme: Theme,
-    /// Custom domain
-    pub domain: Option<String>,
-    /// Associated agent ID
-    pub agent_id: Option<String>,
-    /// Authentication configuration
-    pub auth: Option<AuthConfig>,
-    /// Custom CSS
-    pub custom_css: Option<String>,
-    /// Custom JS
-    pub custom_js: Option<String>,
-    /// Custom HTML head
-    pub custom_head: Option<String>,
-    /// Site settings
-    pub settings: HashMap<String, String>,
-}
+/// Embedding model used by `OpenAIEmbedder` unless another is set
+const OPENAI_EMBEDDING_MODEL: &str = "text-embedding-3-small";
 
-/// Site generator
-pub struct SiteGenerator {
-    pub(crate) config: SiteConfig,
+/// Embedder backed by OpenAI's embeddings API
+pub struct OpenAIEmbedder {
+    api_key: String,
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+    max_batch_size: usize,
 }
 
-impl SiteGenerator {
-    /// Create a new site generator
-    pub fn new() -> Self {
+impl OpenAIEmbedder {
+    /// Create a new embedder using `text-embedding-3-small`
+    pub fn new(api_key: &str) -> Self {
         Self {
-            config: SiteConfig {
-                id: uuid::Uuid::new_v4().to_string(),
-                name: "Bea Bot Agent".to_string(),
-                theme: Theme::Default,
-                domain: None,
-                agent_id: None,
-                auth: None,
-                custom_css: None,
-                custom_js: None,
-                custom_head: None,
-                settings: HashMap::new(),
-            },
+            api_key: api_key.to_string(),
+            model: OPENAI_EMBEDDING_MODEL.to_string(),
+            base_url: OPENAI_API_URL.to_string(),
+            client: http::client(),
+            max_batch_size: DEFAULT_EMBED_BATCH_SIZE,
         }
     }
     
-    /// Set the site name
-    pub fn with_name(mut self, name: &str) -> Self {
-        self.config.name = name.to_string();
+    /// Use a different embedding model
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
         self
     }
     
-    /// Connect the site to an agent
-    pub fn with_agent(mut self, agent: &Agent) -> Self {
-        self.config.agent_id = Some(agent.name().to_string());
-        self.config.name = format!("{} Agent", agent.name());
+    /// Override the API base URL (e.g. for a proxy or a mock server)
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
         self
     }
     
-    /// Set the site theme
-    pub fn with_theme(mut self, theme: &str) -> Self {
-        self.config.theme = Theme::from_str(theme);
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
         self
     }
     
-    /// Set a custom domain for the site
-    pub fn with_custom_domain(mut self, domain: &str) -> Self {
-        self.config.domain = Some(domain.to_string());
+    /// Set the most texts sent per request
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
         self
     }
-    
-    /// Set an authentication method for the site
-    pub fn with_auth(mut self, auth: crate::Auth) -> Self {
-        let method = AuthMethod::from(auth);
+}
+
+#[async_trait]
+impl Embedder for OpenAIEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, BeaError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
         
-        self.config.auth = Some(AuthConfig {
-            method,
-            redirect_url: None,
-            client_id: None,
-            client_secret: None,
-            allowed_domains: None,
-            headers: None,
-        });
+        let response = self.client
+            .post(format!("{}/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({
+                "model": self.model,
+                "input": texts,
+            }))
+            .send()
+            .await?;
         
-        self
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body).into());
+        }
+        
+        let body: serde_json::Value = response.json().await?;
+        let mut data: Vec<&serde_json::Value> = body["data"].as_array().into_iter().flatten().collect();
+        data.sort_by_key(|d| d["index"].as_u64().unwrap_or(0));
+        
+        let embeddings: Vec<Vec<f32>> = data
+            .into_iter()
+            .map(|d| {
+                d["embedding"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|x| x.as_f64().map(|x| x as f32))
+                    .collect()
+            })
+            .collect();
+        
+        if embeddings.len() != texts.len() {
+            return Err(BeaError::InvalidEmbedding(format!(
+                "asked for {} embeddings, got {}",
+                texts.len(),
+                embeddings.len()
+            )));
+        }
+        
+        Ok(embeddings)
     }
     
-    /// Add custom CSS to the site
-    pub fn with_custom_css(mut self, css: &str) -> Self {
-        self.config.custom_css = Some(css.to_string());
-        self
+    fn max_batch_size(&self) -> usize {
+        self.max_batch_size
     }
+}
+
+/// Embed dataset records and add them to a vector store
+///
+/// Records are embedded in batches of the embedder's `max_batch_size`. Each
+/// is stored under `source:line` when it has them, or a fresh UUID when it
+/// doesn't, so indexing another dataset never replaces earlier entries. The
+/// record's metadata and content become the entry's metadata. Returns the
+/// number of records indexed.
+pub async fn index_dataset(
+    store: &VectorStore,
+    embedder: &dyn Embedder,
+    records: &[DatasetRecord],
+) -> Result<usize, BeaError> {
+    let batch_size = embedder.max_batch_size().max(1);
     
-    /// Add custom JS to the site
-    pub fn with_custom_js(mut self, js: &str) -> Self {
-        self.config.custom_js = Some(js.to_string());
-        self
+    for batch in records.chunks(batch_size) {
+        let texts: Vec<String> = batch.iter().map(|r| r.content.clone()).collect();
+        let embeddings = embedder.embed(&texts).await?;
+        if embeddings.len() != batch.len() {
+            return Err(BeaError::InvalidEmbedding(format!(
+                "asked for {} embeddings, got {}",
+                batch.len(),
+                embeddings.len()
+            )));
+        }
+        
+        for (record, embedding) in batch.iter().zip(embeddings) {
+            let id = match (record.metadata.get("source"), record.metadata.get("line")) {
+                (Some(source), Some(line)) => format!("{}:{}", source, line),
+                _ => uuid::Uuid::new_v4().to_string(),
+            };
+            
+            let mut metadata: serde_json::Map<String, serde_json::Value> = record
+                .metadata
+                .iter()
+                .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                .collect();
+            metadata.insert("content".to_string(), serde_json::Value::String(record.content.clone()));
+            
+            store.add(&id, embedding, serde_json::Value::Object(metadata)).await?;
+        }
     }
     
-    /// Add custom HTML to the head
-    pub fn with_custom_head(mut self, head: &str) -> Self {
-        self.config.custom_head = Some(head.to_string());
-        self
+    Ok(records.len())
+}
+
+/// Stored embedding with its metadata
+#[derive(Debug, Clone)]
+struct Entry {
+    embedding: Vec<f32>,
+    norm: f32,
+    metadata: serde_json::Value,
+}
+
+/// Entries of a store, with the dimension they all share
+#[derive(Debug, Default)]
+struct Entries {
+    by_id: HashMap<String, Entry>,
+    dimension: Option<usize>,
+}
+
+/// In-memory vector store with cosine similarity search
+///
+/// Search is a brute-force scan over every entry, which is fine for the
+/// knowledge bases agents ship with today. The API doesn't expose the scan,
+/// so it can be swapped for an ANN index later.
+#[derive(Debug, Clone)]
+pub struct VectorStore {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl VectorStore {
+    /// Create a new, empty vector store
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Entries::default())),
+        }
     }
     
-    /// Add a setting to the site
-    pub fn with_setting(mut self, key: &str, value: &str) -> Self {
-        self.config.settings.insert(key.to_string(), value.to_string());
-        self
+    /// Add an embedding, replacing any existing entry with the same ID
+    ///
+    /// All embeddings in a store must have the same dimension, which is set
+    /// by the first one added and cleared once the store is empty again.
+    pub async fn add(&self, id: &str, embedding: Vec<f32>, metadata: serde_json::Value) -> Result<(), BeaError> {
+        let norm = norm(&embedding);
+        if embedding.is_empty() || norm == 0.0 {
+            return Err(BeaError::InvalidEmbedding(format!("embedding for {} is empty or all zeros", id)));
+        }
+        
+        let mut entries = self.entries.lock().await;
+        match entries.dimension {
+            Some(dimension) if dimension != embedding.len() => {
+                return Err(BeaError::InvalidEmbedding(format!(
+                    "embedding for {} has {} dimensions, store has {}",
+                    id,
+                    embedding.len(),
+                    dimension
+                )));
+            }
+            _ => entries.dimension = Some(embedding.len()),
+        }
+        
+        entries.by_id.insert(id.to_string(), Entry { embedding, norm, metadata });
+        Ok(())
     }
     
-    /// Generate the site HTML
-    pub fn generate_html(&self) -> Result<String, Box<dyn Error>> {
-        // In a real implementation, this would generate the HTML for the site
+    /// Find the `k` entries most similar to `query`, best first
+    ///
+    /// Returns each entry's ID, cosine similarity and metadata. A query whose
+    /// dimension doesn't match the store returns no results.
+    pub async fn search(&self, query: Vec<f32>, k: usize) -> Vec<(String, f32, serde_json::Value)> {
+        let query_norm = norm(&query);
+        if query_norm == 0.0 {
+            return Vec::new();
+        }
         
-        // For demonstration purposes, we'll return a simple template
-        let html = format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/tailwindcss@2.2.19/dist/tailwind.min.css">
-    <script src="https://cdn.jsdelivr.net/npm/alpinejs@3.12.3/dist/cdn.min.js" defer></script>
-    {}
-    <style>
-        :root {{
-            --primary-color: #4f46e5;
-            --secondary-color: #7c3aed;
-            --text-color: #111827;
-            --bg-color: #ffffff;
-            --accent-color: #8b5cf6;
-        }}
+        let entries = self.entries.lock().await;
+        if entries.dimension != Some(query.len()) {
+            return Vec::new();
+        }
         
-        .dark {{
-            --primary-color: #818cf8;
-            --secondary-color: #a78bfa;
-            --text-color: #f9fafb;
-            --bg-color: #111827;
-            --accent-color: #c4b5fd;
-        }}
+        let mut scored: Vec<(String, f32, serde_json::Value)> = entries
+            .by_id
+            .iter()
+            .map(|(id, entry)| {
+                let dot: f32 = entry.embedding.iter().zip(&query).map(|(a, b)| a * b).sum();
+                (id.clone(), dot / (entry.norm * query_norm), entry.metadata.clone())
+            })
+            .collect();
         
-        body {{
-            background-color: var(--bg-color);
-            color: var(--text-color);
-            transition: background-color 0.3s, color 0.3s;
-        }}
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.truncate(k);
+        scored
+    }
+    
+    /// Remove an entry, returning whether it existed
+    pub async fn remove(&self, id: &str) -> bool {
+        let mut entries = self.entries.lock().await;
+        let removed = entries.by_id.remove(id).is_some();
+        if entries.by_id.is_empty() {
+            entries.dimension = None;
+        }
+        removed
+    }
+    
+    /// Get the number of stored embeddings
+    pub async fn len(&self) -> usize {
+        self.entries.lock().await.by_id.len()
+    }
+    
+    /// Whether the store has no embeddings
+    pub async fn is_empty(&self) -> bool {
+        self.entries.lock().await.by_id.is_empty()
+    }
+}
+
+impl Default for VectorStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Euclidean length of a vector
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    async fn store_with(entries: &[(&str, Vec<f32>)]) -> VectorStore {
+        let store = VectorStore::new();
+        for (id, embedding) in entries {
+            store.add(id, embedding.clone(), serde_json::json!({ "id": id })).await.unwrap();
+        }
+        store
+    }
+    
+    fn ids(results: &[(String, f32, serde_json::Value)]) -> Vec<&str> {
+        results.iter().map(|(id, _, _)| id.as_str()).collect()
+    }
+    
+    #[tokio::test]
+    async fn search_returns_the_most_similar_entries_first() {
+        let store = store_with(&[
+            ("east", vec![1.0, 0.0]),
+            ("north", vec![0.0, 1.0]),
+            ("north-east", vec![1.0, 1.0]),
+            ("west", vec![-1.0, 0.0]),
+        ])
+        .await;
         
-        .chat-container {{
-            height: calc(100vh - 12rem);
-        }}
+        let results = store.search(vec![2.0, 0.1], 4).await;
         
-        .message {{
-            border-radius: 1rem;
-            padding: 1rem;
-            margin-bottom: 1rem;
-            max-width: 80%;
-        }}
+        assert_eq!(ids(&results), vec!["east", "north-east", "north", "west"]);
+        assert!((results[0].1 - 0.9988).abs() < 1e-3);
+        assert!((results[3].1 + 0.9988).abs() < 1e-3);
+        assert_eq!(results[0].2, serde_json::json!({ "id": "east" }));
+    }
+    
+    #[tokio::test]
+    async fn search_returns_at_most_k_entries() {
+        let store = store_with(&[
+            ("east", vec![1.0, 0.0]),
+            ("north", vec![0.0, 1.0]),
+            ("north-east", vec![1.0, 1.0]),
+        ])
+        .await;
+        
+        assert_eq!(ids(&store.search(vec![1.0, 0.0], 2).await), vec!["east", "north-east"]);
+        assert!(store.search(vec![1.0, 0.0], 0).await.is_empty());
+        assert_eq!(store.search(vec![1.0, 0.0], 10).await.len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn embeddings_of_another_dimension_are_rejected() {
+        let store = store_with(&[("east", vec![1.0, 0.0])]).await;
         
-        .user-message {{
-            background-color: var(--primary-color);
-            color: white;
-            align-self: flex-end;
-        }}
+        let err = store.add("up", vec![0.0, 0.0, 1.0], serde_json::Value::Null).await.unwrap_err();
         
-        .assistant-message {{
-            background-color: #f3f4f6;
-            color: var(--text-color);
-            align-self: flex-start;
-        }}
+        assert!(matches!(err, BeaError::InvalidEmbedding(_)));
+        assert_eq!(store.len().await, 1);
+        assert!(store.search(vec![0.0, 0.0, 1.0], 1).await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn empty_and_zero_vectors_are_rejected() {
+        let store = store_with(&[("east", vec![1.0, 0.0])]).await;
         
-        .dark .assistant-message {{
-            background-color: #1f2937;
-            color: #f9fafb;
-        }}
+        assert!(matches!(
+            store.add("zero", vec![0.0, 0.0], serde_json::Value::Null).await,
+            Err(BeaError::InvalidEmbedding(_))
+        ));
+        assert!(matches!(
+            store.add("empty", Vec::new(), serde_json::Value::Null).await,
+            Err(BeaError::InvalidEmbedding(_))
+        ));
+        assert_eq!(store.len().await, 1);
+        assert!(store.search(vec![0.0, 0.0], 1).await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn removed_entries_are_no_longer_found() {
+        let store = store_with(&[("east", vec![1.0, 0.0]), ("north", vec![0.0, 1.0])]).await;
         
-        .typing-indicator span {{
-            animation: blink 1.4s infinite both;
-        }}
+        assert!(store.remove("east").await);
+        assert!(!store.remove("east").await);
         
-        .typing-indicator span:nth-child(2) {{
-            animation-delay: 0.2s;
-        }}
+        assert_eq!(ids(&store.search(vec![1.0, 0.0], 2).await), vec!["north"]);
+        assert_eq!(store.len().await, 1);
+    }
+    
+    #[tokio::test]
+    async fn an_emptied_store_takes_a_new_dimension() {
+        let store = store_with(&[("east", vec![1.0, 0.0])]).await;
+        store.remove("east").await;
         
-        .typing-indicator span:nth-child(3) {{
-            animation-delay: 0.4s;
-        }}
+        store.add("up", vec![0.0, 0.0, 1.0], serde_json::Value::Null).await.unwrap();
         
-        @keyframes blink {{
-            0% {{ opacity: 0.1; }}
-            20% {{ opacity: 1; }}
-            100% {{ opacity: 0.1; }}
-        }}
+        assert_eq!(ids(&store.search(vec![0.0, 0.0, 1.0], 1).await), vec!["up"]);
+    }
+    
+    /// Embeds each text as its length, a batch at a time
+    struct LengthEmbedder;
+    
+    #[async_trait]
+    impl Embedder for LengthEmbedder {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, BeaError> {
+            Ok(texts.iter().map(|t| vec![1.0, t.len() as f32]).collect())
+        }
         
-        {}
-    </style>
-</head>
-<body class="{}">
-    <div x-data="chatApp()" class="flex flex-col h-screen">
-        <nav class="bg-gray-800 text-white p-4">
-            <div class="container mx-auto flex justify-between items-center">
-                <div class="flex items-center">
-                    <svg class="w-8 h-8 mr-2" viewBox="0 0 24 24" fill="none" xmlns="http://www.w3.org/2000/svg">
-                        <path d="M12 2C6.48 2 2 6.48 2 12C2 17.52 6.48 22 12 22C17.52 22 22 17.52 22 12C22 6.48 17.52 2 12 2ZM12 20C7.59 20 4 16.41 4 12C4 7.59 7.59 4 12 4C16.41 4 20 7.59 20 12C20 16.41 16.41 20 12 20Z" fill="currentColor"/>
-                        <path d="M12 17C14.7614 17 17 14.7614 17 12C17 9.23858 14.7614 7 12 7C9.23858 7 7 9.23858 7 12C7 14.7614 9.23858 17 12 17Z" fill="currentColor"/>
-                    </svg>
-                    <span class="text-xl font-bold">{}</span>
-                </div>
-                <div class="flex items-center">
-                    <button @click="toggleTheme()" class="p-2 rounded-full hover:bg-gray-700">
-                        <svg x-show="!darkMode" class="w-6 h-6" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M20.354 15.354A9 9 0 018.646 3.646 9.003 9.003 0 0012 21a9.003 9.003 0 008.354-5.646z"></path>
-                        </svg>
-                        <svg x-show="darkMode" class="w-6 h-6" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 3v1m0 16v1m9-9h-1M4 12H3m15.364 6.364l-.707-.707M6.343 6.343l-.707-.707m12.728 0l-.707.707M6.343 17.657l-.707.707M16 12a4 4 0 11-8 0 4 4 0 018 0z"></path>
-                        </svg>
-                    </button>
-                    <button class="ml-4 px-4 py-2 bg-indigo-600 rounded-md hover:bg-indigo-700">Settings</button>
-                </div>
-            </div>
-        </nav>
+        fn max_batch_size(&self) -> usize {
+            2
+        }
+    }
+    
+    fn record(content: &str) -> DatasetRecord {
+        DatasetRecord {
+            content: content.to_string(),
+            metadata: HashMap::new(),
+        }
+    }
+    
+    #[tokio::test]
+    async fn records_without_a_source_never_replace_each_other() {
+        let store = VectorStore::new();
+        let first = vec![record("a"), record("bb"), record("ccc")];
+        let second = vec![record("dddd"), record("eeeee")];
         
-        <div class="flex-1 overflow-hidden flex flex-col">
-            <div class="flex-1 overflow-y-auto p-4">
-                <div class="container mx-auto max-w-4xl">
-                    <div class="flex flex-col">
-                        <template x-for="(message, index) in messages" :key="index">
-                            <div :class="{{'message': true, 'user-message': message.role === 'user', 'assistant-message': message.role === 'assistant'}}">
-                                <div x-text="message.content"></div>
-                            </div>
-                        </template>
-                        <div x-show="isTyping" class="message assistant-message typing-indicator">
-                            <span>.</span><span>.</span><span>.</span>
-                        </div>
-                    </div>
-                </div>
-            </div>
-            
-            <div class="border-t p-4">
-                <div class="container mx-auto max-w-4xl">
-                    <form @submit.prevent="sendMessage()" class="flex">
-                        <input 
-                            type="text" 
-                            x-model="userInput" 
-                            class="flex-1 rounded-l-lg border-2 border-gray-300 p-2 focus:outline-none focus:border-indigo-500 dark:bg-gray-700 dark:border-gray-600 dark:text-white"
-                            placeholder="Type your message..."
-                        >
-                        <button 
-                            type="submit" 
-                            class="bg-indigo-600 text-white px-4 py-2 rounded-r-lg hover:bg-indigo-700 focus:outline-none"
-                            :disabled="userInput.trim() === ''"
-                        >
-                            <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M14 5l7 7m0 0l-7 7m7-7H3"></path>
-                            </svg>
-                        </button>
-                    </form>
-                </div>
-            </div>
-        </div>
-    </div>
-
-    <script>
-        function chatApp() {{
-            return {{
-                darkMode: window.matchMedia('(prefers-color-scheme: dark)').matches,
-                userInput: '',
-                messages: [],
-                isTyping: false,
-                
-                init() {{
-                    if (this.darkMode) {{
-                        document.body.classList.add('dark');
-                    }}
-                    
-                    // Welcome message
-                    setTimeout(() => {{
-                        this.addMessage('assistant', 'Hello! I\'m your AI assistant. How can I help you today?');
-                    }}, a500);
-                }},
-                
-                toggleTheme() {{
-                    this.darkMode = !this.darkMode;
-                    document.body.classList.toggle('dark');
-                }},
-                
-                sendMessage() {{
-                    if (this.userInput.trim() === '') return;
-                    
-                    const userMessage = this.userInput;
-                    this.addMessage('user', userMessage);
-                    this.userInput = '';
-                    
-                    // Simulate typing
-                    this.isTyping = true;
-                    
-                    // Simulate API call to agent
-                    setTimeout(() => {{
-                        this.isTyping = false;
-                        this.addMessage('assistant', 'I\'m processing your request: "' + userMessage + '". This is a demo interface for the Bea Bot agent platform.');
-                    }}, 1500);
-                }},
-                
-                addMessage(role, content) {{
-                    this.messages.push({{ role, content }});
-                    // Scroll to bottom
-                    setTimeout(() => {{
-                        const container = document.querySelector('.overflow-y-auto');
-                        container.scrollTop = container.scrollHeight;
-                    }}, 50);
-                }}
-            }};
-        }}
-    </script>
-    {}
-</body>
-</html>"#,
-            self.config.name,
-            self.config.custom_head.as_deref().unwrap_or(""),
-            self.config.custom_css.as_deref().unwrap_or(""),
-            self.config.theme.css_class(),
-            self.config.name,
-            self.config.custom_js.as_deref().unwrap_or(""),
-        );
+        assert_eq!(index_dataset(&store, &LengthEmbedder, &first).await.unwrap(), 3);
+        assert_eq!(index_dataset(&store, &LengthEmbedder, &second).await.unwrap(), 2);
         
-        Ok(html)
+        assert_eq!(store.len().await, 5);
     }
     
-    /// Deploy the site
-    pub async fn deploy(&self) -> Result<String, Box<dyn Error>> {
-        // In a real implementation, this would deploy the site to the hosting platform
+    #[tokio::test]
+    async fn records_with_a_source_are_stored_under_it() {
+        let store = VectorStore::new();
+        let mut faq = record("How do I reset my password?");
+        faq.metadata.insert("source".to_string(), "faq.jsonl".to_string());
+        faq.metadata.insert("line".to_string(), "3".to_string());
         
-        // For demonstration purposes, we'll just return the URL
-        let domain = self.config.domain.clone().unwrap_or_else(|| {
-            if let Some(agent_id) = &self.config.agent_id {
-                format!("{}.bea-bot.app", agent_id)
-            } else {
-                format!("{}.bea-bot.app", self.config.id)
-            }
-        });
+        index_dataset(&store, &LengthEmbedder, &[faq.clone()]).await.unwrap();
+        index_dataset(&store, &LengthEmbedder, &[faq]).await.unwrap();
         
-        Ok(format!("https://{}", domain))
+        assert_eq!(store.len().await, 1);
+        assert!(store.remove("faq.jsonl:3").await);
     }
 }
@@ -1,6 +1,22 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use bea_bot::{Agent, TokenManager, SiteGenerator, Auth};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use bea_bot::{Agent, AgentStateManager, BeaError, TokenManager};
+use bea_bot::dataset::DataType;
+use bea_bot::logs::{LogEntry, LogLevel};
+use bea_bot::deploy::{DeployOptions, DeploymentConfig, DeploymentFilter, DeploymentManager, DeploymentProvider, Environment, FleetFailure};
+use bea_bot::config::{load_agent, load_environment_configs, load_fleet, load_provider_keys, save_agent};
+use bea_bot::models::{AgentEvent, Message, ModelRegistry, ModelRequest, StreamEvent, TokenUsage};
+use bea_bot::sites::SiteGenerator;
+use bea_bot::tokens::TokenStore;
+
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
 
 #[derive(Parser)]
 #[command(name = "bea")]
@@ -10,6 +26,19 @@ use bea_bot::{Agent, TokenManager, SiteGenerator, Auth};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    
+    /// Output format for every command
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+}
+
+/// How command results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// A single JSON document on stdout
+    Json,
 }
 
 #[derive(Subcommand)]
@@ -35,13 +64,25 @@ enum Commands {
         /// Performance tier (standard, high, ultra)
         #[arg(short, long, default_value = "standard")]
         performance: String,
+        
+        /// Sampling temperature (0.0 to 2.0)
+        #[arg(long)]
+        temperature: Option<f32>,
+        
+        /// Maximum tokens per response
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        
+        /// Where to write the agent spec (defaults to ~/.bea/agents/<name>.toml)
+        #[arg(long)]
+        config: Option<PathBuf>,
     },
     
-    /// Deploy an agent
+    /// Deploy an agent, or a fleet of agents
     Deploy {
         /// Name of the agent to deploy
-        #[arg(short, long)]
-        name: String,
+        #[arg(short, long, required_unless_present = "fleet", conflicts_with = "fleet")]
+        name: Option<String>,
         
         /// Environment to deploy to (production, staging, development)
         #[arg(short, long, default_value = "development")]
@@ -52,16 +93,28 @@ enum Commands {
         token: bool,
         
         /// Whether to generate a site
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "fleet")]
         site: bool,
         
         /// Custom domain for the site
-        #[arg(long)]
+        #[arg(long, conflicts_with = "fleet")]
         domain: Option<String>,
         
         /// Theme for the site
         #[arg(long, default_value = "default")]
         theme: String,
+        
+        /// Agent spec to deploy (defaults to ~/.bea/agents/<name>.toml)
+        #[arg(long, conflicts_with = "fleet")]
+        config: Option<PathBuf>,
+        
+        /// Fleet file listing several agents under [[agent]]
+        #[arg(long)]
+        fleet: Option<PathBuf>,
+        
+        /// Remove the fleet's deployed agents again if any agent fails
+        #[arg(long, requires = "fleet")]
+        atomic: bool,
     },
     
     /// List all agents
@@ -75,15 +128,64 @@ enum Commands {
         detailed: bool,
     },
     
+    /// Stop and delete an agent's deployment and revoke its token
+    Destroy {
+        /// Name of the agent
+        #[arg(short, long)]
+        name: String,
+        
+        /// Environment to remove the agent from
+        #[arg(short, long, default_value = "development")]
+        environment: String,
+        
+        /// Don't ask for confirmation
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Show an agent's request logs
+    Logs {
+        /// Name of the agent
+        #[arg(short, long)]
+        name: String,
+        
+        /// Environment the agent is deployed to
+        #[arg(short, long, default_value = "development")]
+        environment: String,
+        
+        /// Keep streaming new entries until interrupted
+        #[arg(short, long)]
+        follow: bool,
+        
+        /// Number of recent entries to show
+        #[arg(long, default_value_t = 50)]
+        tail: usize,
+        
+        /// Read from this runtime URL instead of the deployment's endpoint
+        #[arg(long)]
+        url: Option<String>,
+    },
+    
     /// Generate tokens
     Tokens {
         /// Environment to generate token for
         #[arg(short, long)]
         environment: String,
         
-        /// Output format (text, json)
-        #[arg(short, long, default_value = "text")]
-        format: String,
+        /// Deprecated alias for the global `--output`
+        #[arg(short, long, value_enum, hide = true)]
+        format: Option<OutputFormat>,
+        
+        /// Print only a masked form of the token
+        ///
+        /// Without this flag the full token is printed to stdout, where it can
+        /// end up in shell history and CI logs.
+        #[arg(long)]
+        mask: bool,
+        
+        /// Write the full token to this file (mode 0600) instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
     },
     
     /// Import data for an agent
@@ -92,131 +194,908 @@ enum Commands {
         #[arg(short, long)]
         name: String,
         
-        /// Path to the data file
+        /// Path to the data file, or a directory of data files
         #[arg(short, long)]
         file: PathBuf,
         
         /// Type of data (jsonl, csv, text)
         #[arg(short, long)]
         data_type: String,
+        
+        /// Fail on the first malformed line instead of skipping it
+        #[arg(long)]
+        strict: bool,
     },
+    
+    /// Chat with an agent interactively
+    Chat {
+        /// Name of the agent
+        #[arg(short, long)]
+        name: String,
+        
+        /// AI model to use
+        #[arg(short, long, default_value = "anthropic/claude-3-sonnet")]
+        model: String,
+        
+        /// System context/prompt for the agent
+        #[arg(short, long, default_value = "You are a helpful assistant.")]
+        context: String,
+    },
+}
+
+/// Result of `bea deploy`
+#[derive(Serialize)]
+struct DeployOutput {
+    agent: String,
+    environment: String,
+    endpoint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+/// Result of `bea deploy --fleet`
+#[derive(Serialize)]
+struct FleetOutput {
+    environment: String,
+    deployed: Vec<DeployOutput>,
+    failed: Vec<FleetFailure>,
+    skipped: Vec<String>,
+    rolled_back: Vec<String>,
+}
+
+/// Agent entry printed by `bea list`
+#[derive(Serialize)]
+struct AgentListing {
+    name: String,
+    model: String,
+    environment: String,
+    status: String,
+    deployment_id: String,
+    endpoint: Option<String>,
+    created_at: String,
+    request_count: u64,
+}
+
+impl AgentListing {
+    fn from_deployment(deployment: &DeploymentConfig, request_count: u64) -> Self {
+        Self {
+            name: deployment.agent_id.clone(),
+            model: deployment.model.clone(),
+            environment: deployment.environment.name(),
+            status: format!("{:?}", deployment.status).to_lowercase(),
+            deployment_id: deployment.id.clone(),
+            endpoint: deployment.endpoint.clone(),
+            created_at: deployment.created_at.to_rfc3339(),
+            request_count,
+        }
+    }
+}
+
+/// File where the CLI keeps its deployments between runs
+///
+/// `BEA_STATE_FILE` overrides the default of `~/.bea/deployments.json`.
+fn state_file() -> PathBuf {
+    if let Ok(path) = std::env::var("BEA_STATE_FILE") {
+        return PathBuf::from(path);
+    }
+    
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join(".bea").join("deployments.json")
+}
+
+/// File where the CLI keeps the usage counts of the agents it talks to
+///
+/// `BEA_AGENT_STATE_FILE` overrides the default of `~/.bea/agents.json`.
+fn agent_state_file() -> PathBuf {
+    if let Ok(path) = std::env::var("BEA_AGENT_STATE_FILE") {
+        return PathBuf::from(path);
+    }
+    
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join(".bea").join("agents.json")
+}
+
+/// File where the CLI keeps the metadata of the tokens it issues
+///
+/// `BEA_TOKEN_STORE_FILE` overrides the default of `~/.bea/tokens.json`.
+fn token_store_file() -> PathBuf {
+    if let Ok(path) = std::env::var("BEA_TOKEN_STORE_FILE") {
+        return PathBuf::from(path);
+    }
+    
+    let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join(".bea").join("tokens.json")
+}
+
+/// Load the token store, signing with `BEA_JWT_SECRET`
+///
+/// The runtime serving the agents must use the same secret to accept the
+/// tokens, so there is no default.
+async fn token_store(path: &Path) -> Result<Arc<TokenStore>, BeaError> {
+    let secret = std::env::var("BEA_JWT_SECRET").map_err(|_| {
+        BeaError::InvalidConfig("BEA_JWT_SECRET must be set to issue or revoke tokens".to_string())
+    })?;
+    Ok(Arc::new(TokenStore::load(path, &secret).await?))
+}
+
+/// Load the deployment manager and attach the environment configs, if any
+///
+/// Configs come from `BEA_CONFIG_FILE`, or `~/.bea/config.toml` by default;
+/// a missing file leaves the manager without any.
+async fn deployment_manager(state: &Path) -> Result<DeploymentManager, BeaError> {
+    let manager = DeploymentManager::load(state).await?;
+    
+    let path = match std::env::var("BEA_CONFIG_FILE") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => {
+            let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+            home.join(".bea").join("config.toml")
+        }
+    };
+    if !path.exists() {
+        return Ok(manager);
+    }
+    
+    Ok(manager.with_environment_configs(load_environment_configs(&path)?))
+}
+
+/// Deploy every agent in a fleet file and report what happened to each
+///
+/// Fails if any agent wasn't deployed, after saving whatever was.
+async fn deploy_fleet(
+    path: &Path,
+    environment: &str,
+    token: bool,
+    atomic: bool,
+    text: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let agents = load_fleet(path)?;
+    let total = agents.len();
+    if text {
+        println!("Deploying {} agents from {} to {}", total, path.display(), environment);
+    }
+    
+    let state = state_file();
+    let mut manager = deployment_manager(&state).await?;
+    
+    // Each agent gets its own token, so destroying one doesn't cut off the rest
+    let mut options = DeployOptions::new();
+    let tokens_file = token_store_file();
+    let tokens = if token {
+        if text {
+            println!("Issuing a token per agent for environment: {}", environment);
+        }
+        let tokens = token_store(&tokens_file).await?;
+        manager = manager.with_token_store(tokens.clone());
+        options = options.with_token();
+        Some(tokens)
+    } else {
+        None
+    };
+    
+    let outcome = manager
+        .deploy_fleet(
+            agents,
+            environment,
+            "default",
+            DeploymentProvider::Custom("bea".to_string()),
+            options,
+            atomic,
+        )
+        .await;
+    manager.save(&state).await?;
+    if let Some(tokens) = &tokens {
+        tokens.save(&tokens_file).await?;
+    }
+    
+    if text {
+        for deployment in &outcome.deployed {
+            println!("{}ok{}        {} at {}", GREEN, RESET, deployment.agent_id, deployment.endpoint.as_deref().unwrap_or("-"));
+            if let Some(token) = &deployment.token {
+                println!("          token: {}", token);
+            }
+        }
+        for failure in &outcome.failed {
+            println!("{}failed{}    {}: {}", RED, RESET, failure.agent, failure.reason);
+        }
+        for agent in &outcome.skipped {
+            println!("{}skipped{}   {}", DIM, RESET, agent);
+        }
+        for deployment in &outcome.rolled_back {
+            println!("{}rolled back{} {}", DIM, RESET, deployment.agent_id);
+        }
+    } else {
+        print_json(&FleetOutput {
+            environment: environment.to_string(),
+            deployed: outcome
+                .deployed
+                .iter()
+                .map(|d| DeployOutput {
+                    agent: d.agent_id.clone(),
+                    environment: environment.to_string(),
+                    endpoint: d.endpoint.clone().unwrap_or_default(),
+                    token: d.token.clone(),
+                })
+                .collect(),
+            failed: outcome.failed.clone(),
+            skipped: outcome.skipped.clone(),
+            rolled_back: outcome.rolled_back.iter().map(|d| d.agent_id.clone()).collect(),
+        })?;
+    }
+    
+    if !outcome.is_complete() {
+        return Err(format!(
+            "{} of {} agents deployed to {}",
+            outcome.deployed.len(),
+            total,
+            environment
+        )
+        .into());
+    }
+    
+    if text {
+        println!("Fleet deployed successfully!");
+    }
+    Ok(())
+}
+
+/// Default location of an agent's spec file
+///
+/// `BEA_AGENTS_DIR` overrides the default directory of `~/.bea/agents`.
+fn agent_file(name: &str) -> PathBuf {
+    let dir = match std::env::var("BEA_AGENTS_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+            home.join(".bea").join("agents")
+        }
+    };
+    dir.join(format!("{}.toml", name))
+}
+
+/// Result of `bea destroy`
+#[derive(Serialize)]
+struct DestroyOutput {
+    agent: String,
+    environment: String,
+    deployments: Vec<String>,
+    revoked_tokens: Vec<String>,
+}
+
+/// Result of `bea tokens`
+#[derive(Serialize)]
+struct TokenOutput {
+    environment: String,
+    created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_file: Option<String>,
+    token: String,
+}
+
+/// Result of `bea import`
+#[derive(Serialize)]
+struct ImportOutput {
+    agent: String,
+    path: String,
+    format: String,
+    files: usize,
+    records: usize,
+    skipped: Vec<String>,
+}
+
+/// One reply in `bea chat`, printed as a JSON line
+#[derive(Serialize)]
+struct ChatReplyOutput {
+    reply: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<TokenUsage>,
+}
+
+/// Print a command's result as pretty JSON
+fn print_json<T: Serialize>(value: &T) -> Result<(), serde_json::Error> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Renders agent events to the terminal
+///
+/// Streams text deltas inline and prints tool activity on its own dimmed or
+/// colored line. When stdout is not a TTY, colors are dropped so output stays
+/// clean in pipes and logs.
+struct EventRenderer {
+    tty: bool,
+    mid_line: bool,
+}
+
+impl EventRenderer {
+    fn new() -> Self {
+        Self {
+            tty: std::io::stdout().is_terminal(),
+            mid_line: false,
+        }
+    }
+    
+    /// Print the input prompt
+    fn prompt(&self) -> std::io::Result<()> {
+        if self.tty {
+            print!("> ");
+            std::io::stdout().flush()?;
+        }
+        Ok(())
+    }
+    
+    /// Render a single event
+    fn render(&mut self, event: &AgentEvent) -> std::io::Result<()> {
+        let mut out = std::io::stdout().lock();
+        
+        match event {
+            AgentEvent::TextDelta(text) => {
+                write!(out, "{}", text)?;
+                if !text.is_empty() {
+                    self.mid_line = !text.ends_with('\n');
+                }
+            },
+            AgentEvent::ToolCallStart { name, arguments } => {
+                self.end_line(&mut out)?;
+                let line = format!("🔧 calling {}({})", name, format_arguments(arguments));
+                self.styled(&mut out, DIM, &line)?;
+            },
+            AgentEvent::ToolCallResult { name, summary, success } => {
+                self.end_line(&mut out)?;
+                let (color, mark) = if *success { (GREEN, "✓") } else { (RED, "✗") };
+                let line = format!("{} {}: {}", mark, name, truncate(summary, 120));
+                self.styled(&mut out, color, &line)?;
+            },
+            AgentEvent::Done(usage) => {
+                self.end_line(&mut out)?;
+                if self.tty {
+                    let line = format!("({} tokens)", usage.total_tokens());
+                    self.styled(&mut out, DIM, &line)?;
+                }
+            },
+        }
+        
+        out.flush()
+    }
+    
+    /// Finish a partially streamed line before printing a status line
+    fn end_line(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        if self.mid_line {
+            write!(out, "{}", if self.tty { "\r\n" } else { "\n" })?;
+            self.mid_line = false;
+        }
+        Ok(())
+    }
+    
+    /// Print a full line, colored only when writing to a terminal
+    fn styled(&self, out: &mut impl Write, color: &str, line: &str) -> std::io::Result<()> {
+        if self.tty {
+            writeln!(out, "{}{}{}", color, line, RESET)
+        } else {
+            writeln!(out, "{}", line)
+        }
+    }
+}
+
+/// Format tool-call JSON arguments as `key=value` pairs
+fn format_arguments(arguments: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(arguments) {
+        Ok(serde_json::Value::Object(map)) => map
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                format!("{}={}", key, truncate(&value, 40))
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => truncate(arguments, 40),
+    }
+}
+
+/// Mask a token down to its prefix and last four characters
+fn mask_token(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() <= 12 {
+        return "…".to_string();
+    }
+    
+    let head: String = chars[..8].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+/// Write a secret to a file readable only by the current user
+fn write_secret(path: &std::path::Path, secret: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        
+        // `mode` only applies to newly created files
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", secret)
+}
+
+/// Print a log entry as a line of text, or of JSON
+fn print_log_entry(entry: &LogEntry, text: bool) -> Result<(), serde_json::Error> {
+    if !text {
+        println!("{}", serde_json::to_string(entry)?);
+        return Ok(());
+    }
+    
+    let level = match entry.level {
+        LogLevel::Info => "INFO ",
+        LogLevel::Error => "ERROR",
+    };
+    println!("{} {} {:<8} {}", entry.timestamp.to_rfc3339(), level, entry.event, entry.message);
+    Ok(())
+}
+
+/// Truncate text to `max` characters, appending an ellipsis when clipped
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        format!("{}…", text.chars().take(max).collect::<String>())
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    bea_bot::init_tracing();
     let cli = Cli::parse();
+    let text = cli.output == OutputFormat::Text;
 
     match cli.command {
-        Commands::Create { name, model, memory, context, performance } => {
-            println!("Creating agent: {}", name);
+        Commands::Create { name, model, memory, context, performance, temperature, max_tokens, config } => {
+            if text {
+                println!("Creating agent: {}", name);
+            }
             
-            let agent = Agent::new(&name)
+            let mut builder = Agent::builder(&name)
                 .with_model(&model)
                 .with_memory(memory)
                 .with_context(&context)
                 .with_performance_tier(&performance);
+            if let Some(temperature) = temperature {
+                builder = builder.with_temperature(temperature);
+            }
+            if let Some(max_tokens) = max_tokens {
+                builder = builder.with_max_tokens(max_tokens);
+            }
+            let agent = builder.build().map_err(BeaError::InvalidAgent)?;
+            
+            let path = config.unwrap_or_else(|| agent_file(&name));
+            save_agent(&agent, &path)?;
             
-            // In a real implementation, this would save the agent configuration
-            println!("Agent {} created successfully!", name);
-            println!("  Model: {}", model);
-            println!("  Memory: {}", if memory { "enabled" } else { "disabled" });
-            println!("  Performance: {}", performance);
+            if !text {
+                print_json(&agent.to_config())?;
+            } else {
+                println!("Agent {} created successfully!", name);
+                println!("  Model: {}", model);
+                println!("  Memory: {}", if memory { "enabled" } else { "disabled" });
+                println!("  Performance: {}", performance);
+                if let Some(temperature) = temperature {
+                    println!("  Temperature: {}", temperature);
+                }
+                if let Some(max_tokens) = max_tokens {
+                    println!("  Max tokens: {}", max_tokens);
+                }
+                println!("  Spec: {}", path.display());
+            }
         },
         
-        Commands::Deploy { name, environment, token, site, domain, theme } => {
-            println!("Deploying agent: {} to {}", name, environment);
+        Commands::Deploy { name, environment, token, site, domain, theme, config, fleet, atomic } => {
+            if let Some(fleet) = fleet {
+                return deploy_fleet(&fleet, &environment, token, atomic, text).await;
+            }
+            
+            // clap requires a name whenever there's no fleet
+            let name = name.unwrap_or_default();
+            if text {
+                println!("Deploying agent: {} to {}", name, environment);
+            }
+            
+            let path = config.unwrap_or_else(|| agent_file(&name));
+            if !path.exists() {
+                return Err(BeaError::AgentNotFound(format!(
+                    "{} (no spec at {}; run `bea create` first)",
+                    name,
+                    path.display()
+                ))
+                .into());
+            }
+            let agent = load_agent(&path)?;
             
-            // In a real implementation, this would load the agent configuration
-            let agent = Agent::new(&name);
+            let state = state_file();
+            let mut manager = deployment_manager(&state).await?;
             
-            let token_manager = if token {
-                println!("Generating token for environment: {}", environment);
-                Some(TokenManager::new().generate_token(&environment))
+            let mut options = DeployOptions::new();
+            let tokens_file = token_store_file();
+            let tokens = if token {
+                if text {
+                    println!("Issuing token for environment: {}", environment);
+                }
+                let tokens = token_store(&tokens_file).await?;
+                manager = manager.with_token_store(tokens.clone());
+                options = options.with_token();
+                Some(tokens)
             } else {
                 None
             };
             
-            let site_generator = if site {
-                println!("Generating site with theme: {}", theme);
+            if site {
+                if text {
+                    println!("Generating site with theme: {}", theme);
+                }
                 let mut generator = SiteGenerator::new()
                     .with_agent(&agent)
                     .with_theme(&theme);
                 
                 if let Some(domain_str) = domain {
-                    println!("Using custom domain: {}", domain_str);
+                    if text {
+                        println!("Using custom domain: {}", domain_str);
+                    }
                     generator = generator.with_custom_domain(&domain_str);
                 }
                 
-                Some(generator)
-            } else {
-                None
-            };
+                options = options.with_site(generator);
+            }
             
-            let endpoint = bea_bot::deploy(agent, token_manager, site_generator).await?;
-            println!("Deployment successful!");
-            println!("Agent is available at: {}", endpoint);
+            let deployment = manager
+                .deploy_agent(
+                    agent,
+                    &environment,
+                    "default",
+                    DeploymentProvider::Custom("bea".to_string()),
+                    options,
+                )
+                .await?;
+            manager.save(&state).await?;
+            if let Some(tokens) = &tokens {
+                tokens.save(&tokens_file).await?;
+            }
+            
+            let endpoint = deployment.endpoint.unwrap_or_default();
+            if text {
+                println!("Deployment successful!");
+                println!("Agent is available at: {}", endpoint);
+                if let Some(token) = &deployment.token {
+                    println!("Deployment token: {}", token);
+                    println!("The token is only shown now; store it somewhere safe.");
+                }
+            } else {
+                print_json(&DeployOutput { agent: name, environment, endpoint, token: deployment.token })?;
+            }
         },
         
         Commands::List { environment, detailed } => {
-            println!("Listing agents{}:", if let Some(env) = &environment {
-                format!(" in {} environment", env)
-            } else {
-                String::new()
+            let manager = DeploymentManager::load(&state_file()).await?;
+            let mut deployments = match &environment {
+                Some(env) => manager.list_deployments_for_environment(env).await,
+                None => manager.list_deployments(DeploymentFilter::new()).await.deployments,
+            };
+            deployments.sort_by(|a, b| {
+                (&a.agent_id, a.environment.name()).cmp(&(&b.agent_id, b.environment.name()))
             });
             
-            // Mock data for display purposes
-            let agents = vec![
-                ("customer-support", "anthropic/claude-3-haiku", "production"),
-                ("data-analysis", "anthropic/claude-3-opus", "staging"),
-                ("code-assistant", "anthropic/claude-3-sonnet", "development"),
-            ];
+            let agents = AgentStateManager::load(&agent_state_file()).await?;
+            let mut listings = Vec::with_capacity(deployments.len());
+            for deployment in &deployments {
+                let request_count = agents.stats(&deployment.agent_id).await.map_or(0, |stats| stats.request_count);
+                listings.push(AgentListing::from_deployment(deployment, request_count));
+            }
             
-            for (name, model, env) in agents {
-                if environment.is_none() || environment.as_ref() == Some(&env.to_string()) {
+            if !text {
+                print_json(&listings)?;
+            } else if listings.is_empty() {
+                match &environment {
+                    Some(env) => println!("No agents deployed in {} environment.", env),
+                    None => println!("No agents deployed. Deploy one with `bea deploy --name <agent>`."),
+                }
+            } else {
+                println!("Listing agents{}:", if let Some(env) = &environment {
+                    format!(" in {} environment", env)
+                } else {
+                    String::new()
+                });
+                
+                for agent in &listings {
                     if detailed {
-                        println!("  {} ({})", name, env);
-                        println!("    Model: {}", model);
-                        println!("    Status: active");
-                        println!("    Uptime: 3d 7h 22m");
-                        println!("    Requests: 1,457");
+                        println!("  {} ({})", agent.name, agent.environment);
+                        println!("    Model: {}", agent.model);
+                        println!("    Status: {}", agent.status);
+                        println!("    Deployment: {}", agent.deployment_id);
+                        if let Some(endpoint) = &agent.endpoint {
+                            println!("    Endpoint: {}", endpoint);
+                        }
+                        println!("    Created: {}", agent.created_at);
+                        println!("    Requests: {}", agent.request_count);
                     } else {
-                        println!("  {} ({}, {})", name, model, env);
+                        println!("  {} ({}, {}, {})", agent.name, agent.model, agent.environment, agent.status);
                     }
                 }
             }
         },
         
-        Commands::Tokens { environment, format } => {
-            println!("Generating token for {} environment", environment);
+        Commands::Destroy { name, environment, force } => {
+            let state = state_file();
+            let manager = DeploymentManager::load(&state).await?;
+            let deployments: Vec<DeploymentConfig> = manager
+                .list_deployments_for_agent(&name)
+                .await
+                .into_iter()
+                .filter(|d| d.environment.name() == Environment::from(environment.as_str()).name())
+                .collect();
+            
+            if deployments.is_empty() {
+                return Err(format!("No deployment of {} in {} environment", name, environment).into());
+            }
+            
+            if !force {
+                eprint!("Destroy {} ({} deployment(s) in {})? [y/N] ", name, deployments.len(), environment);
+                std::io::stderr().flush()?;
+                
+                let answer = BufReader::new(tokio::io::stdin()).lines().next_line().await?.unwrap_or_default();
+                if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                    eprintln!("Aborted.");
+                    std::process::exit(1);
+                }
+            }
+            
+            // Open the token store up front, so a missing secret fails before anything is removed
+            let tokens_file = token_store_file();
+            let tokens = if deployments.iter().any(|d| d.token_id.is_some()) {
+                Some(token_store(&tokens_file).await?)
+            } else {
+                None
+            };
+            
+            let mut removed = Vec::new();
+            let mut revoked = Vec::new();
+            for deployment in &deployments {
+                let deployment = manager.destroy_deployment(&deployment.id, tokens.as_deref()).await?;
+                if text {
+                    println!("Removed deployment {} ({}, {})", deployment.id, deployment.model, deployment.environment.name());
+                    if let Some(token_id) = &deployment.token_id {
+                        println!("  Revoked token {}", token_id);
+                    }
+                }
+                removed.push(deployment.id);
+                revoked.extend(deployment.token_id);
+            }
+            manager.save(&state).await?;
+            if let Some(tokens) = &tokens {
+                tokens.save(&tokens_file).await?;
+            }
+            
+            if !text {
+                print_json(&DestroyOutput { agent: name, environment, deployments: removed, revoked_tokens: revoked })?;
+            }
+        },
+        
+        Commands::Logs { name, environment, follow, tail, url } => {
+            let base = match url {
+                Some(url) => url,
+                None => {
+                    let manager = DeploymentManager::load(&state_file()).await?;
+                    manager
+                        .list_deployments_for_agent(&name)
+                        .await
+                        .into_iter()
+                        .filter(|d| d.environment.name() == Environment::from(environment.as_str()).name())
+                        .max_by_key(|d| d.created_at)
+                        .and_then(|d| d.endpoint)
+                        .ok_or_else(|| format!("No deployment of {} in {} environment", name, environment))?
+                }
+            };
+            
+            let client = reqwest::Client::new();
+            let mut request = client
+                .get(format!("{}/v1/logs", base.trim_end_matches('/')))
+                .query(&[("tail", tail.to_string()), ("follow", follow.to_string())]);
+            if let Ok(token) = std::env::var("BEA_TOKEN") {
+                request = request.bearer_auth(token);
+            }
+            
+            let mut response = request.send().await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(format!("Reading logs failed ({}): {}", status, body.trim()).into());
+            }
+            
+            if !follow {
+                let entries: Vec<LogEntry> = response.json().await?;
+                for entry in &entries {
+                    print_log_entry(entry, text)?;
+                }
+                return Ok(());
+            }
+            
+            // Entries arrive as server-sent events until the server closes the stream or Ctrl-C
+            let mut buffer = String::new();
+            loop {
+                let chunk = tokio::select! {
+                    chunk = response.chunk() => chunk?,
+                    _ = tokio::signal::ctrl_c() => break,
+                };
+                let Some(chunk) = chunk else {
+                    break;
+                };
+                buffer.push_str(&String::from_utf8_lossy(&chunk));
+                
+                while let Some(end) = buffer.find("\n\n") {
+                    let event: String = buffer.drain(..end + 2).collect();
+                    let is_error = event.lines().any(|line| line.strip_prefix("event:").map(str::trim) == Some("error"));
+                    for data in event.lines().filter_map(|line| line.strip_prefix("data:")) {
+                        if is_error {
+                            eprintln!("Log stream error: {}", data.trim());
+                            continue;
+                        }
+                        
+                        match serde_json::from_str::<LogEntry>(data.trim()) {
+                            Ok(entry) => print_log_entry(&entry, text)?,
+                            Err(e) => eprintln!("Skipped malformed log event ({}): {}", e, data.trim()),
+                        }
+                    }
+                }
+            }
+        },
+        
+        Commands::Tokens { environment, format, mask, out } => {
+            let text = format.unwrap_or(cli.output) == OutputFormat::Text;
+            if text {
+                println!("Generating token for {} environment", environment);
+            }
             
             let token_manager = TokenManager::new().generate_token(&environment);
             let token = token_manager.get_token(&environment).unwrap();
+            let created_at = token_manager.token_info(&environment).unwrap().created_at.to_rfc3339();
+            
+            if let Some(path) = &out {
+                write_secret(path, token)?;
+            }
+            
+            // Never echo the full token once it has been written to a file
+            let shown = if mask || out.is_some() {
+                mask_token(token)
+            } else {
+                token.to_string()
+            };
             
-            match format.as_str() {
-                "json" => {
-                    println!("{{");
-                    println!("  \"environment\": \"{}\",", environment);
-                    println!("  \"token\": \"{}\"", token);
-                    println!("}}");
-                },
-                _ => {
-                    println!("Token: {}", token);
-                    println!("Environment: {}", environment);
-                    println!("");
-                    println!("To use this token, add it to your configuration:");
-                    println!("export BEA_TOKEN=\"{}\"", token);
+            if !text {
+                print_json(&TokenOutput {
+                    environment,
+                    created_at,
+                    token_file: out.as_ref().map(|path| path.display().to_string()),
+                    token: shown,
+                })?;
+            } else {
+                println!("Token: {}", shown);
+                println!("Environment: {}", environment);
+                println!("Created: {}", created_at);
+                println!();
+                match &out {
+                    Some(path) => {
+                        println!("Full token written to {}", path.display());
+                        println!("To use this token, add it to your configuration:");
+                        println!("export BEA_TOKEN=\"$(cat {})\"", path.display());
+                    },
+                    None if mask => {
+                        println!("The full token was not saved; pass --out <file> to keep it.");
+                    },
+                    None => {
+                        println!("To use this token, add it to your configuration:");
+                        println!("export BEA_TOKEN=\"{}\"", token);
+                    },
                 }
             }
         },
         
-        Commands::Import { name, file, data_type } => {
-            println!("Importing {} data from {:?} for agent {}", data_type, file, name);
+        Commands::Import { name, file, data_type, strict } => {
+            if text {
+                println!("Importing {} data from {:?} for agent {}", data_type, file, name);
+            }
             
-            // In a real implementation, this would read and process the file
-            println!("Data imported successfully!");
-            println!("  File: {:?}", file);
-            println!("  Format: {}", data_type);
-            println!("  Records: 1,024");
+            // In a real implementation, this would store the records for the agent
+            let data_type: DataType = data_type.parse()?;
+            let summary = bea_bot::dataset::import(&file, data_type, strict)?;
+            
+            if !text {
+                print_json(&ImportOutput {
+                    agent: name,
+                    path: file.display().to_string(),
+                    format: data_type.to_string(),
+                    files: summary.files.len(),
+                    records: summary.records.len(),
+                    skipped: summary.malformed.iter().map(|line| line.to_string()).collect(),
+                })?;
+            } else {
+                for line in &summary.malformed {
+                    eprintln!("Skipped {}", line);
+                }
+                
+                println!("Data imported successfully!");
+                println!("  Path: {:?}", file);
+                println!("  Files: {}", summary.files.len());
+                println!("  Format: {}", data_type);
+                println!("  Records: {}", summary.records.len());
+                if !summary.malformed.is_empty() {
+                    println!("  Skipped: {}", summary.malformed.len());
+                }
+            }
+        },
+        
+        Commands::Chat { name, model, context } => {
+            let registry = ModelRegistry::with_default_providers(&load_provider_keys()).await?;
+            let agent_state = agent_state_file();
+            let agents = AgentStateManager::load(&agent_state).await?;
+            agents.register_agent(&Agent::new(&name).with_model(&model)).await?;
+            
+            if text {
+                println!("Chatting with {} ({}). Press Ctrl-D to exit.", name, model);
+            }
+            
+            let mut renderer = EventRenderer::new();
+            let mut history = vec![Message::system(&context)];
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            
+            loop {
+                if text {
+                    renderer.prompt()?;
+                }
+                let Some(line) = lines.next_line().await? else {
+                    break;
+                };
+                
+                let input = line.trim();
+                if input.is_empty() {
+                    continue;
+                }
+                history.push(Message::user(input));
+                
+                let request = ModelRequest::new(&model, history.clone()).with_stream(true);
+                let mut stream = registry.generate_stream(request).await?;
+                
+                let mut reply = String::new();
+                let mut usage = None;
+                while let Some(event) = stream.recv().await {
+                    match event? {
+                        StreamEvent::ContentDelta(delta) => {
+                            reply.push_str(&delta);
+                            if text {
+                                renderer.render(&AgentEvent::TextDelta(delta))?;
+                            }
+                        }
+                        StreamEvent::Done(totals) => usage = Some(totals),
+                        _ => {}
+                    }
+                }
+                
+                agents.record_request(&name, &usage.clone().unwrap_or_default()).await?;
+                agents.save(&agent_state).await?;
+                
+                if !text {
+                    // One compact object per line, so replies can be read as JSONL
+                    let output = ChatReplyOutput { reply: reply.clone(), usage };
+                    println!("{}", serde_json::to_string(&output)?);
+                } else if let Some(usage) = usage {
+                    renderer.render(&AgentEvent::Done(usage))?;
+                }
+                history.push(Message::assistant(&reply));
+            }
         },
     }
 
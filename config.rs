@@ -0,0 +1,530 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::deploy::{DeploymentProvider, Environment};
+use crate::{Agent, AgentConfig, BeaError};
+
+/// Azure OpenAI API version used when `AZURE_OPENAI_API_VERSION` is unset
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-06-01";
+
+/// Credentials and endpoints for the model providers
+///
+/// Each field is `None` when its variable is unset or empty.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderKeys {
+    /// `ANTHROPIC_API_KEY`
+    pub anthropic_api_key: Option<String>,
+    /// `OPENAI_API_KEY`
+    pub openai_api_key: Option<String>,
+    /// `OPENAI_ORG`
+    pub openai_org: Option<String>,
+    /// `GOOGLE_API_KEY`
+    pub google_api_key: Option<String>,
+    /// `AZURE_OPENAI_ENDPOINT`
+    pub azure_openai_endpoint: Option<String>,
+    /// `AZURE_OPENAI_API_KEY`
+    pub azure_openai_api_key: Option<String>,
+    /// `AZURE_OPENAI_API_VERSION`
+    pub azure_openai_api_version: Option<String>,
+    /// `OLLAMA_HOST`, with `http://` added if it has no scheme
+    pub ollama_host: Option<String>,
+}
+
+/// Load provider keys from the environment
+///
+/// A `.env` file in the current directory or one of its parents is read
+/// first if there is one. Variables already set in the environment take
+/// precedence over the file.
+pub fn load_provider_keys() -> ProviderKeys {
+    dotenvy::dotenv().ok();
+    
+    ProviderKeys {
+        anthropic_api_key: var("ANTHROPIC_API_KEY"),
+        openai_api_key: var("OPENAI_API_KEY"),
+        openai_org: var("OPENAI_ORG"),
+        google_api_key: var("GOOGLE_API_KEY"),
+        azure_openai_endpoint: var("AZURE_OPENAI_ENDPOINT"),
+        azure_openai_api_key: var("AZURE_OPENAI_API_KEY"),
+        azure_openai_api_version: var("AZURE_OPENAI_API_VERSION"),
+        ollama_host: var("OLLAMA_HOST").map(|host| {
+            if host.starts_with("http://") || host.starts_with("https://") {
+                host
+            } else {
+                format!("http://{}", host)
+            }
+        }),
+    }
+}
+
+/// Read a variable, treating empty values as unset
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+/// Format of an agent spec file, chosen by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpecFormat {
+    Toml,
+    Json,
+}
+
+impl SpecFormat {
+    /// Anything other than `.json` is read and written as TOML
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => SpecFormat::Json,
+            _ => SpecFormat::Toml,
+        }
+    }
+}
+
+/// Fleet file: a list of agent specs under `[[agent]]`
+#[derive(Debug, Deserialize)]
+struct FleetSpec {
+    #[serde(rename = "agent", default)]
+    agents: Vec<AgentConfig>,
+}
+
+/// Read and parse a spec file in the format given by its extension
+fn read_spec<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, BeaError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        BeaError::InvalidConfig(format!("cannot read {}: {}", path.display(), e))
+    })?;
+    
+    match SpecFormat::from_path(path) {
+        SpecFormat::Toml => toml::from_str(&contents)
+            .map_err(|e| BeaError::InvalidConfig(format!("{}: {}", path.display(), e))),
+        SpecFormat::Json => serde_json::from_str(&contents)
+            .map_err(|e| BeaError::InvalidConfig(format!("{}: {}", path.display(), e))),
+    }
+}
+
+/// Write a state file readable only by its owner, replacing it atomically
+///
+/// Parent directories are created as needed. The contents go to a
+/// temporary file next to `path`, created with mode 0600 on Unix, which is
+/// then renamed over it.
+pub(crate) async fn write_state_file(path: &Path, contents: &[u8]) -> Result<(), BeaError> {
+    use tokio::io::AsyncWriteExt;
+    
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    
+    let tmp = path.with_extension("json.tmp");
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    
+    // A leftover temporary file keeps its old mode, so start from a fresh one
+    if let Err(e) = tokio::fs::remove_file(&tmp).await {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+    
+    let mut file = options.open(&tmp).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    drop(file);
+    
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Load an agent from a spec file
+///
+/// Specs are TOML unless the file ends in `.json`. Besides the field names
+/// of `AgentConfig`, `memory`, `context` and `dataset` are accepted for
+/// `memory_enabled`, `system_blocks` and `dataset_path`. The agent is
+/// validated before it's returned.
+pub fn load_agent(path: impl AsRef<Path>) -> Result<Agent, BeaError> {
+    let config: AgentConfig = read_spec(path.as_ref())?;
+    
+    let agent = Agent::from_config(config);
+    agent.validate().map_err(BeaError::InvalidAgent)?;
+    Ok(agent)
+}
+
+/// Load the agents in a fleet file
+///
+/// Each `[[agent]]` entry is an agent spec as read by `load_agent`; in JSON
+/// the entries go in an `agent` array. Every agent is validated, and names
+/// must be unique within the fleet.
+pub fn load_fleet(path: impl AsRef<Path>) -> Result<Vec<Agent>, BeaError> {
+    let path = path.as_ref();
+    let fleet: FleetSpec = read_spec(path)?;
+    
+    if fleet.agents.is_empty() {
+        return Err(BeaError::InvalidConfig(format!("{}: no [[agent]] entries", path.display())));
+    }
+    
+    let mut names = HashSet::new();
+    let mut agents = Vec::with_capacity(fleet.agents.len());
+    for config in fleet.agents {
+        if !names.insert(config.name.clone()) {
+            return Err(BeaError::InvalidConfig(format!(
+                "{}: agent {} is listed more than once",
+                path.display(),
+                config.name
+            )));
+        }
+        
+        let agent = Agent::from_config(config);
+        agent.validate().map_err(BeaError::InvalidAgent)?;
+        agents.push(agent);
+    }
+    
+    Ok(agents)
+}
+
+/// Write an agent to a spec file that `load_agent` can read back
+///
+/// Missing parent directories are created, and the file is replaced
+/// atomically.
+pub fn save_agent(agent: &Agent, path: impl AsRef<Path>) -> Result<(), BeaError> {
+    let path = path.as_ref();
+    let config = agent.to_config();
+    
+    let contents = match SpecFormat::from_path(path) {
+        SpecFormat::Toml => toml::to_string_pretty(&config)
+            .map_err(|e| BeaError::InvalidConfig(format!("{}: {}", path.display(), e)))?,
+        SpecFormat::Json => serde_json::to_string_pretty(&config)?,
+    };
+    
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Deployment defaults for an environment
+///
+/// Every field is optional so an overlay only needs to list what differs
+/// from the base config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    /// Region to deploy to
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Provider to deploy with
+    #[serde(default)]
+    pub provider: Option<DeploymentProvider>,
+    /// Lifetime of tokens issued for the environment, in seconds
+    #[serde(default)]
+    pub token_expiry_secs: Option<u64>,
+    /// Requests per minute allowed against the deployment
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+    /// Requests allowed at once before rate limiting kicks in
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+impl EnvironmentConfig {
+    /// Apply an overlay on top of this config
+    ///
+    /// Fields set in `overlay` win; the rest keep their current value.
+    pub fn merge(&self, overlay: &EnvironmentConfig) -> EnvironmentConfig {
+        EnvironmentConfig {
+            region: overlay.region.clone().or_else(|| self.region.clone()),
+            provider: overlay.provider.clone().or_else(|| self.provider.clone()),
+            token_expiry_secs: overlay.token_expiry_secs.or(self.token_expiry_secs),
+            requests_per_minute: overlay.requests_per_minute.or(self.requests_per_minute),
+            burst: overlay.burst.or(self.burst),
+        }
+    }
+}
+
+/// Base deployment config plus per-environment overlays
+///
+/// Read from a `config.toml` with a `[base]` table and one
+/// `[environment.<name>]` table per overlay:
+///
+/// ```toml
+/// [base]
+/// region = "us-east-1"
+/// provider = "AWS"
+/// token_expiry_secs = 86400
+///
+/// [environment.production]
+/// region = "eu-west-1"
+/// requests_per_minute = 600
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EnvironmentConfigs {
+    /// Defaults shared by every environment
+    #[serde(default)]
+    pub base: EnvironmentConfig,
+    /// Overlays keyed by environment name
+    #[serde(rename = "environment", default)]
+    pub environments: HashMap<String, EnvironmentConfig>,
+}
+
+impl EnvironmentConfigs {
+    /// Create an empty set of configs
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set the base config
+    pub fn with_base(mut self, base: EnvironmentConfig) -> Self {
+        self.base = base;
+        self
+    }
+    
+    /// Add or replace the overlay for an environment
+    pub fn with_overlay(mut self, environment: &str, overlay: EnvironmentConfig) -> Self {
+        self.environments.insert(Environment::from(environment).name(), overlay);
+        self
+    }
+    
+    /// Get the overlay for an environment, if it has one
+    ///
+    /// Names are matched the way `Environment::from_str` parses them, so
+    /// `Production` and `production` find the same overlay.
+    pub fn overlay(&self, environment: &str) -> Option<&EnvironmentConfig> {
+        let name = Environment::from(environment).name();
+        self.environments.get(&name).or_else(|| {
+            self.environments
+                .iter()
+                .find(|(key, _)| Environment::from(key.as_str()).name() == name)
+                .map(|(_, overlay)| overlay)
+        })
+    }
+    
+    /// Resolve the config for an environment: the base with its overlay applied
+    ///
+    /// A custom environment without an overlay gets the base config and a
+    /// warning, since that's usually a typo in the environment name.
+    pub fn resolve(&self, environment: &str) -> EnvironmentConfig {
+        match self.overlay(environment) {
+            Some(overlay) => self.base.merge(overlay),
+            None => {
+                if matches!(Environment::from(environment), Environment::Custom(_)) {
+                    tracing::warn!(environment, "no config overlay for custom environment; using base defaults");
+                }
+                self.base.clone()
+            }
+        }
+    }
+}
+
+/// Load the base config and environment overlays from a `config.toml`
+///
+/// The file is read as JSON instead if it ends in `.json`.
+pub fn load_environment_configs(path: impl AsRef<Path>) -> Result<EnvironmentConfigs, BeaError> {
+    read_spec(path.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tool;
+    
+    /// A fresh directory under the system temp dir
+    fn temp_dir() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("bea-config-{}", uuid::Uuid::new_v4().simple()))
+    }
+    
+    /// An agent with every field set away from its default
+    fn full_agent() -> Agent {
+        Agent::new("support")
+            .with_model("openai/gpt-4o")
+            .with_model_for_env("staging", "openai/gpt-4o-mini")
+            .with_memory(true)
+            .with_system_blocks(vec!["You help customers.", "Be brief."])
+            .with_tools(vec![Tool::new("search").with_description("Search the docs").with_parameter("index", "docs")])
+            .with_dataset("data/faq.jsonl")
+            .with_performance_tier("high")
+            .with_parameter("region", "eu")
+            .with_version("1.2.0")
+            .with_temperature(0.3)
+            .with_top_p(0.9)
+            .with_max_tokens(512)
+    }
+    
+    #[test]
+    fn agents_round_trip_through_toml_and_json() {
+        let dir = temp_dir();
+        let agent = full_agent();
+        
+        for file in ["support.toml", "support.json"] {
+            let path = dir.join(file);
+            save_agent(&agent, &path).unwrap();
+            let loaded = load_agent(&path).unwrap();
+            
+            assert_eq!(
+                serde_json::to_value(loaded.to_config()).unwrap(),
+                serde_json::to_value(agent.to_config()).unwrap(),
+                "{}",
+                file
+            );
+        }
+        
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn invalid_agents_are_not_loaded() {
+        let dir = temp_dir();
+        let path = dir.join("bad.toml");
+        save_agent(&Agent::new("Bad Name"), &path).unwrap();
+        
+        assert!(matches!(load_agent(&path), Err(BeaError::InvalidAgent(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+    
+    /// Write `contents` to `file` in a fresh directory and load it as environment configs
+    fn load_configs(file: &str, contents: &str) -> Result<EnvironmentConfigs, BeaError> {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), contents).unwrap();
+        let configs = load_environment_configs(dir.join(file));
+        fs::remove_dir_all(dir).unwrap();
+        configs
+    }
+    
+    #[test]
+    fn overlays_win_over_the_base() {
+        let configs = load_configs(
+            "config.toml",
+            r#"
+            [base]
+            region = "us-east-1"
+            provider = "AWS"
+            token_expiry_secs = 86400
+            
+            [environment.production]
+            region = "eu-west-1"
+            requests_per_minute = 600
+            "#,
+        )
+        .unwrap();
+        
+        let production = configs.resolve("production");
+        assert_eq!(production.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(production.provider, Some(DeploymentProvider::AWS));
+        assert_eq!(production.token_expiry_secs, Some(86400));
+        assert_eq!(production.requests_per_minute, Some(600));
+        assert_eq!(production.burst, None);
+        
+        let json = load_configs("config.json", r#"{ "environment": { "staging": { "burst": 5 } } }"#).unwrap();
+        assert_eq!(json.resolve("staging").burst, Some(5));
+    }
+    
+    #[test]
+    fn environments_are_looked_up_case_insensitively() {
+        let configs = load_configs("config.toml", "[environment.Production]\nregion = \"eu-west-1\"\n").unwrap();
+        for name in ["production", "Production", "PRODUCTION"] {
+            assert_eq!(configs.resolve(name).region.as_deref(), Some("eu-west-1"), "{}", name);
+        }
+        
+        let configs = EnvironmentConfigs::new().with_overlay(
+            "Staging",
+            EnvironmentConfig { burst: Some(5), ..Default::default() },
+        );
+        assert_eq!(configs.overlay("staging").and_then(|overlay| overlay.burst), Some(5));
+    }
+    
+    #[test]
+    fn environments_without_an_overlay_get_the_base() {
+        let configs = EnvironmentConfigs::new()
+            .with_base(EnvironmentConfig { region: Some("us-east-1".to_string()), ..Default::default() })
+            .with_overlay("production", EnvironmentConfig { region: Some("eu-west-1".to_string()), ..Default::default() });
+        
+        for name in ["development", "qa"] {
+            assert!(configs.overlay(name).is_none(), "{}", name);
+            assert_eq!(configs.resolve(name).region.as_deref(), Some("us-east-1"), "{}", name);
+        }
+        
+        let missing = load_environment_configs(temp_dir().join("config.toml"));
+        assert!(matches!(missing, Err(BeaError::InvalidConfig(message)) if message.starts_with("cannot read")));
+    }
+    
+    /// Write `contents` to `file` in a fresh directory and load it as a fleet
+    fn load_fleet_file(file: &str, contents: &str) -> Result<Vec<Agent>, BeaError> {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file), contents).unwrap();
+        let fleet = load_fleet(dir.join(file));
+        fs::remove_dir_all(dir).unwrap();
+        fleet
+    }
+    
+    #[test]
+    fn fleets_load_every_agent_in_order() {
+        let agents = load_fleet_file(
+            "fleet.toml",
+            r#"
+            [[agent]]
+            name = "support"
+            model = "anthropic/claude-3-5-sonnet"
+            
+            [[agent]]
+            name = "billing"
+            model = "openai/gpt-4o"
+            memory = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(agents.iter().map(Agent::name).collect::<Vec<_>>(), vec!["support", "billing"]);
+        
+        let json = load_fleet_file("fleet.json", r#"{ "agent": [{ "name": "support", "model": "openai/gpt-4o" }] }"#).unwrap();
+        assert_eq!(json.len(), 1);
+    }
+    
+    #[test]
+    fn fleets_with_duplicate_names_are_rejected() {
+        let result = load_fleet_file(
+            "fleet.toml",
+            r#"
+            [[agent]]
+            name = "support"
+            model = "anthropic/claude-3-5-sonnet"
+            
+            [[agent]]
+            name = "support"
+            model = "openai/gpt-4o"
+            "#,
+        );
+        assert!(matches!(result, Err(BeaError::InvalidConfig(message)) if message.ends_with("agent support is listed more than once")));
+    }
+    
+    #[test]
+    fn empty_fleets_are_rejected() {
+        for (file, contents) in [("fleet.toml", ""), ("fleet.json", r#"{ "agent": [] }"#)] {
+            let result = load_fleet_file(file, contents);
+            assert!(matches!(result, Err(BeaError::InvalidConfig(message)) if message.ends_with("no [[agent]] entries")), "{}", file);
+        }
+    }
+    
+    #[test]
+    fn fleets_with_an_invalid_agent_are_rejected() {
+        let invalid = load_fleet_file(
+            "fleet.toml",
+            r#"
+            [[agent]]
+            name = "support"
+            model = "anthropic/claude-3-5-sonnet"
+            
+            [[agent]]
+            name = "Not Valid"
+            model = "gpt-4o"
+            "#,
+        );
+        assert!(matches!(invalid, Err(BeaError::InvalidAgent(errors)) if errors.len() == 2));
+        
+        let unparsable = load_fleet_file("fleet.toml", "[[agent]]\nname = \"support\"\n");
+        assert!(matches!(unparsable, Err(BeaError::InvalidConfig(message)) if message.contains("missing field `model`")));
+    }
+}
@@ -0,0 +1,409 @@
+use std::sync::Arc;
+
+use crate::{Agent, BeaError};
+use crate::filters::{ContentFilter, FilterChain};
+use crate::models::{count_tokens, Message, MessageRole, ModelRegistry, ModelRequest};
+use crate::tools::{ToolRegistry, ToolResult};
+
+/// Number of history messages kept by default
+const DEFAULT_MAX_MESSAGES: usize = 50;
+
+/// Number of model calls a tool loop makes by default
+const DEFAULT_MAX_ITERATIONS: usize = 8;
+
+/// Number of tool calls a tool loop runs at once by default
+const DEFAULT_MAX_TOOL_CONCURRENCY: usize = 4;
+
+/// Trims the oldest history from a message list until it fits a token budget
+///
+/// System messages and the most recent user turn are always kept, even if
+/// they alone exceed the budget. History is dropped a whole turn at a time,
+/// so tool results are never left without the call that produced them.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryTruncator {
+    max_tokens: usize,
+    estimate: fn(&str) -> usize,
+}
+
+impl HistoryTruncator {
+    /// Create a new truncator using the chars/4 token estimate
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            estimate: count_tokens,
+        }
+    }
+    
+    /// Use a different token estimate, e.g. a real tokenizer
+    pub fn with_estimator(mut self, estimate: fn(&str) -> usize) -> Self {
+        self.estimate = estimate;
+        self
+    }
+    
+    /// Get the token budget
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+    
+    /// Estimate the tokens used by a list of messages
+    pub fn estimate(&self, messages: &[Message]) -> usize {
+        messages.iter().map(|m| (self.estimate)(m.content())).sum()
+    }
+    
+    /// Drop the oldest turns from `messages` until they fit the budget
+    pub fn truncate(&self, messages: &mut Vec<Message>) {
+        let mut total = self.estimate(messages);
+        
+        while total > self.max_tokens {
+            // Everything from the last user message on is the current turn
+            let Some(current) = messages.iter().rposition(|m| matches!(m.role(), MessageRole::User)) else {
+                return;
+            };
+            let Some(start) = messages[..current].iter().position(|m| !matches!(m.role(), MessageRole::System)) else {
+                return;
+            };
+            let end = messages[start + 1..current]
+                .iter()
+                .position(|m| matches!(m.role(), MessageRole::User))
+                .map(|i| start + 1 + i)
+                .unwrap_or(current);
+            
+            let mut i = start;
+            for _ in start..end {
+                if matches!(messages[i].role(), MessageRole::System) {
+                    i += 1;
+                } else {
+                    total -= (self.estimate)(messages[i].content());
+                    messages.remove(i);
+                }
+            }
+        }
+    }
+}
+
+/// Conversation with an agent, sending each turn through a model registry
+///
+/// The agent's system prompt is added to every request, so the history only
+/// holds user, assistant and tool messages. Agents without memory only see
+/// the latest turn.
+#[derive(Clone)]
+pub struct Conversation {
+    agent: Agent,
+    registry: Arc<ModelRegistry>,
+    messages: Vec<Message>,
+    max_messages: usize,
+    truncator: Option<HistoryTruncator>,
+    filters: FilterChain,
+}
+
+impl Conversation {
+    /// Create a new conversation with an agent
+    pub fn new(agent: Agent, registry: Arc<ModelRegistry>) -> Self {
+        Self {
+            agent,
+            registry,
+            messages: Vec::new(),
+            max_messages: DEFAULT_MAX_MESSAGES,
+            truncator: None,
+            filters: FilterChain::new(),
+        }
+    }
+    
+    /// Set the maximum number of history messages kept between turns
+    pub fn with_max_messages(mut self, max_messages: usize) -> Self {
+        self.max_messages = max_messages.max(1);
+        self
+    }
+    
+    /// Trim the oldest history from each request so it fits `tokens`
+    pub fn with_token_budget(mut self, tokens: usize) -> Self {
+        self.truncator = Some(HistoryTruncator::new(tokens));
+        self
+    }
+    
+    /// Run each turn through a content filter, after any already added
+    ///
+    /// User input is filtered before it is stored or sent, and replies
+    /// before they are stored or returned.
+    pub fn with_content_filter(mut self, filter: Arc<dyn ContentFilter>) -> Self {
+        self.filters = self.filters.with_filter(filter);
+        self
+    }
+    
+    /// Get the agent this conversation is with
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+    
+    /// Send a user message and return the agent's reply
+    ///
+    /// Both messages are appended to the history. With a token budget, the
+    /// request is trimmed to fit but the stored history is left alone. If the
+    /// request fails, or a content filter blocks either message, the history
+    /// is left unchanged.
+    pub async fn send(&mut self, user_input: &str) -> Result<Message, BeaError> {
+        let user_input = self.filters.filter_input(user_input)?;
+        
+        // Clearing and trimming happen before the request, so keep the
+        // history to put back if it fails
+        let previous = self.messages.clone();
+        if !self.agent.memory_enabled() {
+            self.messages.clear();
+        }
+        
+        self.messages.push(Message::user(&user_input));
+        self.trim();
+        
+        let mut request = ModelRequest::from_agent(&self.agent, self.messages.clone());
+        if let Some(truncator) = &self.truncator {
+            truncator.truncate(request.messages_mut());
+        }
+        
+        let response = self.registry.generate(request).await.and_then(|mut response| {
+            self.filters.filter_response(&mut response)?;
+            Ok(response)
+        });
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                self.messages = previous;
+                return Err(e);
+            }
+        };
+        
+        let reply = response.message().clone();
+        self.messages.push(reply.clone());
+        Ok(reply)
+    }
+    
+    /// Append a message to the history
+    pub fn push(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+    
+    /// Get the messages in the conversation
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+    
+    /// Drop the oldest messages once the history is over its limit
+    ///
+    /// Trimming stops at a user message, so the history never starts with an
+    /// assistant reply or tool result whose request was dropped.
+    fn trim(&mut self) {
+        if self.messages.len() <= self.max_messages {
+            return;
+        }
+        
+        let excess = self.messages.len() - self.max_messages;
+        let start = self.messages[excess..]
+            .iter()
+            .position(|m| matches!(m.role(), MessageRole::User))
+            .map(|i| excess + i)
+            .unwrap_or(self.messages.len() - 1);
+        self.messages.drain(..start);
+    }
+    
+    /// Remove the last assistant turn so it can be regenerated
+    ///
+    /// Drops the trailing assistant message along with any tool calls and
+    /// tool results exchanged since the preceding user message. Returns
+    /// whether anything was removed.
+    pub fn pop_last_assistant_turn(&mut self) -> bool {
+        let len = self.messages.len();
+        
+        while let Some(last) = self.messages.last() {
+            if !matches!(last.role(), MessageRole::Assistant | MessageRole::Tool) {
+                break;
+            }
+            self.messages.pop();
+        }
+        
+        self.messages.len() != len
+    }
+}
+
+/// Outcome of running a `ToolLoop`
+#[derive(Debug, Clone)]
+pub struct ToolLoopOutcome {
+    /// Last assistant message
+    ///
+    /// Still carries tool calls if the loop stopped at its iteration cap.
+    pub message: Message,
+    /// Results of every tool call made, in order
+    pub tool_results: Vec<ToolResult>,
+    /// Full message list, including tool calls and results
+    pub messages: Vec<Message>,
+}
+
+/// Runs a model and its tool calls until the model answers without tools
+///
+/// Each round calls the model, executes any tool calls it makes through the
+/// tool registry, concurrently when there are several, and appends the
+/// results to the request in call order. Tool failures, including calls to
+/// unknown tools, are reported back to the model as error results rather
+/// than ending the loop.
+#[derive(Clone)]
+pub struct ToolLoop {
+    models: Arc<ModelRegistry>,
+    tools: Arc<ToolRegistry>,
+    max_iterations: usize,
+    max_tool_concurrency: usize,
+}
+
+impl ToolLoop {
+    /// Create a new tool loop
+    pub fn new(models: Arc<ModelRegistry>, tools: Arc<ToolRegistry>) -> Self {
+        Self {
+            models,
+            tools,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_tool_concurrency: DEFAULT_MAX_TOOL_CONCURRENCY,
+        }
+    }
+    
+    /// Set the maximum number of model calls per run
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+    
+    /// Set how many of a turn's tool calls may run at once
+    pub fn with_max_tool_concurrency(mut self, max_tool_concurrency: usize) -> Self {
+        self.max_tool_concurrency = max_tool_concurrency.max(1);
+        self
+    }
+    
+    /// Run the loop starting from `request`
+    ///
+    /// The request should carry the tool definitions the model may call.
+    pub async fn run(&self, mut request: ModelRequest) -> Result<ToolLoopOutcome, BeaError> {
+        let mut tool_results = Vec::new();
+        let mut iterations = 0;
+        
+        loop {
+            iterations += 1;
+            let response = self.models.generate(request.clone()).await?;
+            let message = response.message().clone();
+            request.messages_mut().push(message.clone());
+            
+            let calls = message.tool_calls().unwrap_or(&[]).to_vec();
+            if calls.is_empty() || iterations == self.max_iterations {
+                return Ok(ToolLoopOutcome {
+                    message,
+                    tool_results,
+                    messages: request.messages().to_vec(),
+                });
+            }
+            
+            let requests = calls
+                .iter()
+                .map(|call| (call.name().to_string(), call.arguments_json()))
+                .collect();
+            let results = self.tools.execute_many(requests, self.max_tool_concurrency).await;
+            
+            for (call, result) in calls.iter().zip(results) {
+                let reply = self.tools.result_message(&result).with_tool_call_id(call.id());
+                request.messages_mut().push(reply);
+                tool_results.push(result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MockProvider, ModelResponse, ToolCall};
+    use crate::tools::{CalculatorTool, ToolStatus};
+    
+    #[tokio::test]
+    async fn long_history_is_truncated_to_the_budget() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let provider = MockProvider::from_fn(move |request| {
+            recorded.lock().unwrap().push(request.messages().to_vec());
+            ModelResponse::new(Message::assistant("ok"), "")
+        })
+        .with_name("anthropic");
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(provider).await.unwrap();
+        
+        let agent = Agent::new("support").with_memory(true);
+        let mut conversation = Conversation::new(agent, registry).with_token_budget(1_000).with_max_messages(100);
+        let long = "x".repeat(400);
+        for i in 0..50 {
+            let message = if i % 2 == 0 { Message::user(&long) } else { Message::assistant(&long) };
+            conversation.push(message);
+        }
+        
+        conversation.send("What now?").await.unwrap();
+        
+        let sent = seen.lock().unwrap()[0].clone();
+        let truncator = HistoryTruncator::new(1_000);
+        assert!(truncator.estimate(&sent) <= 1_000, "request is {} tokens", truncator.estimate(&sent));
+        assert!(matches!(sent[0].role(), MessageRole::System));
+        assert_eq!(sent.last().unwrap().content(), "What now?");
+        assert!(sent.len() < 51);
+        // The stored history is left alone
+        assert_eq!(conversation.messages().len(), 52);
+    }
+    
+    #[tokio::test]
+    async fn failed_sends_leave_the_history_unchanged() {
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(MockProvider::with_responses(Vec::new()).with_name("anthropic")).await.unwrap();
+        
+        // Without memory the history is cleared before each request
+        let agent = Agent::new("support").with_memory(false);
+        let mut conversation = Conversation::new(agent, registry).with_max_messages(2);
+        conversation.push(Message::user("first question"));
+        conversation.push(Message::assistant("first answer"));
+        
+        assert!(conversation.send("second question").await.is_err());
+        
+        let contents: Vec<&str> = conversation.messages().iter().map(|m| m.content()).collect();
+        assert_eq!(contents, vec!["first question", "first answer"]);
+    }
+    
+    #[test]
+    fn system_messages_and_the_current_turn_survive_a_tiny_budget() {
+        let mut messages = vec![
+            Message::system("Be brief."),
+            Message::user("first question"),
+            Message::assistant("first answer"),
+            Message::user("second question"),
+        ];
+        HistoryTruncator::new(1).truncate(&mut messages);
+        
+        let contents: Vec<&str> = messages.iter().map(|m| m.content()).collect();
+        assert_eq!(contents, vec!["Be brief.", "second question"]);
+    }
+    
+    #[tokio::test]
+    async fn tool_loop_runs_the_requested_tool_then_returns_the_answer() {
+        let provider = MockProvider::with_responses(vec![
+            ModelResponse::new(
+                Message::assistant("").with_tool_calls(vec![ToolCall::new("call_1", "calculator", r#"{"expression":"6 * 7"}"#)]),
+                "",
+            ),
+            ModelResponse::new(Message::assistant("It's 42."), ""),
+        ]);
+        let models = Arc::new(ModelRegistry::new());
+        models.register_provider(provider).await.unwrap();
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(CalculatorTool::new()).await.unwrap();
+        
+        let request = ModelRequest::new("mock/model", vec![Message::user("What's 6 * 7?")]);
+        let outcome = ToolLoop::new(models, tools).run(request).await.unwrap();
+        
+        assert_eq!(outcome.message.content(), "It's 42.");
+        assert_eq!(outcome.tool_results.len(), 1);
+        assert!(matches!(outcome.tool_results[0].status, ToolStatus::Success));
+        
+        let roles: Vec<&MessageRole> = outcome.messages.iter().map(|m| m.role()).collect();
+        assert!(matches!(roles[..], [MessageRole::User, MessageRole::Assistant, MessageRole::Tool, MessageRole::Assistant]));
+        assert_eq!(outcome.messages[2].tool_call_id(), Some("call_1"));
+        assert!(outcome.messages[2].content().contains("42"), "{}", outcome.messages[2].content());
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{Agent, BeaError};
+
+/// Format of a dataset's files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    /// One JSON object (or string) per line
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Plain text, one record per line
+    Text,
+}
+
+impl DataType {
+    /// File extensions accepted for this data type
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            DataType::Jsonl => &["jsonl"],
+            DataType::Csv => &["csv"],
+            DataType::Text => &["txt", "text", "md"],
+        }
+    }
+    
+    /// Infer the data type from a file's extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        [DataType::Jsonl, DataType::Csv, DataType::Text]
+            .into_iter()
+            .find(|t| t.extensions().contains(&extension.as_str()))
+    }
+}
+
+impl FromStr for DataType {
+    type Err = BeaError;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" => Ok(DataType::Jsonl),
+            "csv" => Ok(DataType::Csv),
+            "text" => Ok(DataType::Text),
+            other => Err(BeaError::InvalidDataset(format!(
+                "unknown data type {} (expected jsonl, csv or text)",
+                other
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Jsonl => write!(f, "jsonl"),
+            DataType::Csv => write!(f, "csv"),
+            DataType::Text => write!(f, "text"),
+        }
+    }
+}
+
+/// Single record loaded from a dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatasetRecord {
+    /// Text of the record
+    pub content: String,
+    /// Remaining fields, plus the `source` file and `line` it came from
+    pub metadata: HashMap<String, String>,
+}
+
+/// Line that couldn't be parsed into a record
+#[derive(Debug, Clone)]
+pub struct MalformedLine {
+    /// File containing the line
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+    /// What was wrong with it
+    pub reason: String,
+}
+
+impl fmt::Display for MalformedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.reason)
+    }
+}
+
+/// Summary of an imported dataset
+#[derive(Debug, Clone)]
+pub struct DatasetSummary {
+    /// Files that were read
+    pub files: Vec<PathBuf>,
+    /// Records loaded across all files
+    pub records: Vec<DatasetRecord>,
+    /// Lines skipped because they couldn't be parsed
+    pub malformed: Vec<MalformedLine>,
+}
+
+/// Collect the files to import from a dataset path
+///
+/// A file path is used as-is. A directory is searched recursively for files
+/// whose extension matches `data_type`, returned in sorted order. Symlinks
+/// inside the directory are skipped rather than followed, so a link back up
+/// the tree can't make the walk loop.
+pub fn collect_files(path: &Path, data_type: DataType) -> Result<Vec<PathBuf>, BeaError> {
+    let extensions = data_type.extensions();
+    
+    let metadata = fs::metadata(path).map_err(|e| {
+        BeaError::InvalidDataset(format!("cannot read {}: {}", path.display(), e))
+    })?;
+    
+    if !metadata.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    
+    let mut files = Vec::new();
+    let mut seen_any = false;
+    let mut dirs = vec![path.to_path_buf()];
+    
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let entry_path = entry.path();
+            
+            if file_type.is_dir() {
+                dirs.push(entry_path);
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            
+            seen_any = true;
+            let matches = entry_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| extensions.contains(&e.to_lowercase().as_str()))
+                .unwrap_or(false);
+            
+            if matches {
+                files.push(entry_path);
+            }
+        }
+    }
+    
+    if !seen_any {
+        return Err(BeaError::InvalidDataset(format!(
+            "directory {} is empty",
+            path.display()
+        )));
+    }
+    
+    if files.is_empty() {
+        return Err(BeaError::InvalidDataset(format!(
+            "directory {} contains no .{} files",
+            path.display(),
+            extensions.join(" or .")
+        )));
+    }
+    
+    files.sort();
+    Ok(files)
+}
+
+/// Load the records in a file or directory of files
+///
+/// Malformed lines are skipped; use `import` to find out which.
+pub fn load(path: &Path, data_type: DataType) -> Result<Vec<DatasetRecord>, BeaError> {
+    import(path, data_type, false).map(|summary| summary.records)
+}
+
+/// Load the dataset attached to an agent with `Agent::with_dataset`
+///
+/// The data type is taken from the file's extension. Agents without a
+/// dataset have no records.
+pub fn load_for_agent(agent: &Agent) -> Result<Vec<DatasetRecord>, BeaError> {
+    let Some(path) = agent.dataset_path() else {
+        return Ok(Vec::new());
+    };
+    
+    let path = Path::new(path);
+    let data_type = DataType::from_path(path).ok_or_else(|| {
+        BeaError::InvalidDataset(format!(
+            "cannot tell the data type of {} from its extension",
+            path.display()
+        ))
+    })?;
+    
+    load(path, data_type)
+}
+
+/// Import a dataset from a file or a directory of files
+///
+/// Lines that can't be parsed are collected in the summary with their line
+/// numbers. With `strict`, the first one fails the import instead.
+pub fn import(path: &Path, data_type: DataType, strict: bool) -> Result<DatasetSummary, BeaError> {
+    let files = collect_files(path, data_type)?;
+    
+    let mut records = Vec::new();
+    let mut malformed = Vec::new();
+    for file in &files {
+        parse_file(file, data_type, &mut records, &mut malformed)?;
+        
+        if strict {
+            if let Some(first) = malformed.first() {
+                return Err(BeaError::InvalidDataset(first.to_string()));
+            }
+        }
+    }
+    
+    Ok(DatasetSummary { files, records, malformed })
+}
+
+/// Parse the records in a single data file
+fn parse_file(
+    path: &Path,
+    data_type: DataType,
+    records: &mut Vec<DatasetRecord>,
+    malformed: &mut Vec<MalformedLine>,
+) -> Result<(), BeaError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        BeaError::InvalidDataset(format!("cannot read {}: {}", path.display(), e))
+    })?;
+    
+    let mut header: Option<Vec<String>> = None;
+    
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let parsed = match data_type {
+            DataType::Jsonl => parse_json_line(line),
+            DataType::Text => Ok(DatasetRecord {
+                content: line.trim_end().to_string(),
+                metadata: HashMap::new(),
+            }),
+            // The first line of a CSV file is its header
+            DataType::Csv => match &header {
+                None => {
+                    match split_csv_line(line) {
+                        Ok(fields) => header = Some(fields),
+                        Err(reason) => {
+                            return Err(BeaError::InvalidDataset(format!(
+                                "{}:{}: bad header: {}",
+                                path.display(),
+                                index + 1,
+                                reason
+                            )))
+                        }
+                    }
+                    continue;
+                }
+                Some(header) => parse_csv_row(header, line),
+            },
+        };
+        
+        match parsed {
+            Ok(mut record) => {
+                record.metadata.entry("source".to_string()).or_insert_with(|| path.display().to_string());
+                record.metadata.entry("line".to_string()).or_insert_with(|| (index + 1).to_string());
+                records.push(record);
+            }
+            Err(reason) => malformed.push(MalformedLine {
+                path: path.to_path_buf(),
+                line: index + 1,
+                reason,
+            }),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Parse a JSONL line
+///
+/// Objects take their content from a `content` or `text` field, and keep
+/// the rest of their fields as metadata. A bare string is its own content.
+fn parse_json_line(line: &str) -> Result<DatasetRecord, String> {
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    
+    match value {
+        serde_json::Value::String(content) => Ok(DatasetRecord {
+            content,
+            metadata: HashMap::new(),
+        }),
+        serde_json::Value::Object(mut fields) => {
+            let content = ["content", "text"]
+                .iter()
+                .find_map(|key| match fields.remove(*key) {
+                    Some(serde_json::Value::String(content)) => Some(content),
+                    _ => None,
+                })
+                .ok_or_else(|| "missing string content or text field".to_string())?;
+            
+            let metadata = fields
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(value) => (key, value),
+                    value => (key, value.to_string()),
+                })
+                .collect();
+            
+            Ok(DatasetRecord { content, metadata })
+        }
+        _ => Err("expected a JSON object or string".to_string()),
+    }
+}
+
+/// Parse a CSV row against its header
+///
+/// The `content` or `text` column is the record's content, falling back to
+/// the first column; the other columns become metadata.
+fn parse_csv_row(header: &[String], line: &str) -> Result<DatasetRecord, String> {
+    let fields = split_csv_line(line)?;
+    if fields.len() != header.len() {
+        return Err(format!("expected {} fields, found {}", header.len(), fields.len()));
+    }
+    
+    let content_column = header
+        .iter()
+        .position(|h| h.eq_ignore_ascii_case("content") || h.eq_ignore_ascii_case("text"))
+        .unwrap_or(0);
+    
+    let mut content = String::new();
+    let mut metadata = HashMap::new();
+    for (i, (name, value)) in header.iter().zip(fields).enumerate() {
+        if i == content_column {
+            content = value;
+        } else {
+            metadata.insert(name.clone(), value);
+        }
+    }
+    
+    Ok(DatasetRecord { content, metadata })
+}
+
+/// Split a CSV line into fields
+///
+/// Fields may be quoted, with `""` for a literal quote. A quoted field must
+/// end at its closing quote, and quoted fields can't span lines.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut closed = false;
+    let mut chars = line.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => {
+                quoted = false;
+                closed = true;
+            }
+            (',', false) => {
+                fields.push(std::mem::take(&mut field));
+                closed = false;
+            }
+            (c, false) if closed => {
+                return Err(format!("unexpected {:?} after closing quote in field {}", c, fields.len() + 1));
+            }
+            ('"', false) if field.is_empty() => quoted = true,
+            (c, _) => field.push(c),
+        }
+    }
+    
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    
+    fields.push(field);
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bea-dataset-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn directories_are_searched_recursively_for_matching_files() {
+        let dir = temp_dir();
+        fs::create_dir_all(dir.join("support/billing")).unwrap();
+        fs::write(dir.join("faq.jsonl"), "{\"text\": \"a\"}\n").unwrap();
+        fs::write(dir.join("support/billing/refunds.JSONL"), "{\"text\": \"b\"}\n").unwrap();
+        fs::write(dir.join("support/notes.txt"), "ignored\n").unwrap();
+        
+        let files = collect_files(&dir, DataType::Jsonl).unwrap();
+        
+        assert_eq!(files, vec![dir.join("faq.jsonl"), dir.join("support/billing/refunds.JSONL")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn empty_directories_are_rejected() {
+        let dir = temp_dir();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        
+        let err = collect_files(&dir, DataType::Jsonl).unwrap_err();
+        
+        assert!(matches!(err, BeaError::InvalidDataset(message) if message.contains("is empty")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn directories_without_matching_files_are_rejected() {
+        let dir = temp_dir();
+        fs::write(dir.join("notes.txt"), "hello\n").unwrap();
+        
+        let err = collect_files(&dir, DataType::Csv).unwrap_err();
+        
+        assert!(matches!(err, BeaError::InvalidDataset(message) if message.contains("no .csv files")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn a_single_file_is_used_as_is() {
+        let dir = temp_dir();
+        let file = dir.join("faq.data");
+        fs::write(&file, "hello\n").unwrap();
+        
+        assert_eq!(collect_files(&file, DataType::Jsonl).unwrap(), vec![file]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_not_followed() {
+        let dir = temp_dir();
+        let outside = temp_dir();
+        fs::write(dir.join("faq.jsonl"), "{}\n").unwrap();
+        fs::write(outside.join("secret.jsonl"), "{}\n").unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("outside")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+        
+        let files = collect_files(&dir, DataType::Jsonl).unwrap();
+        
+        assert_eq!(files, vec![dir.join("faq.jsonl")]);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+    
+    /// Write `contents` to `file` in a fresh directory, returning its path
+    fn data_file(file: &str, contents: &str) -> PathBuf {
+        let path = temp_dir().join(file);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+    
+    fn remove(path: &Path) {
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+    
+    #[test]
+    fn jsonl_records_take_content_or_text_and_keep_the_rest_as_metadata() {
+        assert_eq!(
+            parse_json_line(r#"{"content": "Refunds take 5 days", "topic": "billing", "votes": 3}"#).unwrap(),
+            DatasetRecord {
+                content: "Refunds take 5 days".to_string(),
+                metadata: HashMap::from([
+                    ("topic".to_string(), "billing".to_string()),
+                    ("votes".to_string(), "3".to_string()),
+                ]),
+            }
+        );
+        assert_eq!(parse_json_line(r#"{"text": "Hello"}"#).unwrap().content, "Hello");
+        assert_eq!(parse_json_line(r#""Just a string""#).unwrap().content, "Just a string");
+        
+        assert_eq!(parse_json_line(r#"{"title": "No body"}"#).unwrap_err(), "missing string content or text field");
+        assert_eq!(parse_json_line(r#"{"content": 42}"#).unwrap_err(), "missing string content or text field");
+        assert_eq!(parse_json_line("[1, 2]").unwrap_err(), "expected a JSON object or string");
+        assert!(parse_json_line("{not json").is_err());
+    }
+    
+    #[test]
+    fn csv_fields_can_be_quoted_with_escaped_quotes() {
+        assert_eq!(split_csv_line("a,b,,c").unwrap(), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            split_csv_line(r#""Hello, world","She said ""hi""","""""#).unwrap(),
+            vec!["Hello, world", r#"She said "hi""#, r#"""#]
+        );
+        assert_eq!(split_csv_line(r#"5" screen,x"#).unwrap(), vec![r#"5" screen"#, "x"]);
+    }
+    
+    #[test]
+    fn csv_text_after_a_closing_quote_is_malformed() {
+        assert_eq!(split_csv_line(r#""a"b,c"#).unwrap_err(), r#"unexpected 'b' after closing quote in field 1"#);
+        assert_eq!(split_csv_line(r#"x,"a" ,c"#).unwrap_err(), r#"unexpected ' ' after closing quote in field 2"#);
+        assert_eq!(split_csv_line(r#""a"bc"#).unwrap_err(), r#"unexpected 'b' after closing quote in field 1"#);
+    }
+    
+    #[test]
+    fn unterminated_csv_quotes_are_malformed() {
+        assert_eq!(split_csv_line(r#"a,"b,c"#).unwrap_err(), "unterminated quoted field");
+    }
+    
+    #[test]
+    fn csv_rows_must_match_the_header() {
+        let header = vec!["id".to_string(), "text".to_string(), "topic".to_string()];
+        
+        let record = parse_csv_row(&header, "7,Refunds take 5 days,billing").unwrap();
+        assert_eq!(record.content, "Refunds take 5 days");
+        assert_eq!(record.metadata["id"], "7");
+        assert_eq!(record.metadata["topic"], "billing");
+        
+        assert_eq!(parse_csv_row(&header, "7,Refunds").unwrap_err(), "expected 3 fields, found 2");
+        assert_eq!(parse_csv_row(&header, "7,a,b,c").unwrap_err(), "expected 3 fields, found 4");
+        
+        // Without a content or text column the first column is the content
+        let header = vec!["question".to_string(), "answer".to_string()];
+        assert_eq!(parse_csv_row(&header, "How long?,5 days").unwrap().content, "How long?");
+    }
+    
+    #[test]
+    fn malformed_lines_are_reported_with_their_line_numbers() {
+        let path = data_file("faq.csv", "id,text\n1,Hello\n\n2,\"unterminated\n3,\"a\"b\n4,Bye\n");
+        
+        let summary = import(&path, DataType::Csv, false).unwrap();
+        
+        let contents: Vec<&str> = summary.records.iter().map(|r| r.content.as_str()).collect();
+        assert_eq!(contents, vec!["Hello", "Bye"]);
+        assert_eq!(summary.records[1].metadata["line"], "6");
+        assert_eq!(summary.records[1].metadata["source"], path.display().to_string());
+        
+        let lines: Vec<usize> = summary.malformed.iter().map(|m| m.line).collect();
+        assert_eq!(lines, vec![4, 5]);
+        assert_eq!(summary.malformed[0].to_string(), format!("{}:4: unterminated quoted field", path.display()));
+        remove(&path);
+    }
+    
+    #[test]
+    fn strict_imports_fail_on_the_first_malformed_line() {
+        let path = data_file("faq.jsonl", "{\"text\": \"a\"}\n[1]\n{oops\n");
+        
+        let err = import(&path, DataType::Jsonl, true).unwrap_err();
+        assert!(
+            matches!(&err, BeaError::InvalidDataset(message) if *message == format!("{}:2: expected a JSON object or string", path.display())),
+            "{}",
+            err
+        );
+        
+        let summary = import(&path, DataType::Jsonl, false).unwrap();
+        assert_eq!(summary.records.len(), 1);
+        assert_eq!(summary.malformed.len(), 2);
+        remove(&path);
+    }
+}
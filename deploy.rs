@@ -1,11 +1,19 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+#[cfg(any(feature = "aws-deployment", feature = "gcp-deployment", feature = "azure-deployment"))]
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
+use futures::future::join_all;
 use tokio::sync::Mutex;
 use serde::{Serialize, Deserialize};
+use tokio::time::Duration;
 use uuid::Uuid;
 
-use crate::{Agent, TokenManager, SiteGenerator};
+use crate::{Agent, BeaError};
+use crate::config::{EnvironmentConfig, EnvironmentConfigs};
+use crate::models::{Message, ModelRegistry, ModelRequest};
+use crate::sites::{AuthMethod, SiteGenerator, SiteStore, Theme};
+use crate::tokens::{TokenStore, TokenType};
 
 /// Deployment environment
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,9 +24,9 @@ pub enum Environment {
     Custom(String),
 }
 
-impl Environment {
+impl From<&str> for Environment {
     /// Parse environment from string
-    pub fn from_str(s: &str) -> Self {
+    fn from(s: &str) -> Self {
         match s.to_lowercase().as_str() {
             "production" => Environment::Production,
             "staging" => Environment::Staging,
@@ -26,7 +34,17 @@ impl Environment {
             _ => Environment::Custom(s.to_string()),
         }
     }
+}
+
+impl std::str::FromStr for Environment {
+    type Err = std::convert::Infallible;
     
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Environment::from(s))
+    }
+}
+
+impl Environment {
     /// Get environment name
     pub fn name(&self) -> String {
         match self {
@@ -39,7 +57,7 @@ impl Environment {
 }
 
 /// Deployment provider
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DeploymentProvider {
     AWS,
     GCP,
@@ -56,6 +74,12 @@ pub struct DeploymentConfig {
     pub id: String,
     /// Agent ID
     pub agent_id: String,
+    /// Version of the agent definition that was deployed
+    #[serde(default)]
+    pub agent_version: Option<String>,
+    /// Model resolved for the deployment's environment
+    #[serde(default)]
+    pub model: String,
     /// Environment
     pub environment: Environment,
     /// Provider
@@ -64,8 +88,14 @@ pub struct DeploymentConfig {
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Region
     pub region: String,
-    /// Token ID
+    /// ID of the deployment's token in the token store
     pub token_id: Option<String>,
+    /// Token string, only on the deployment returned by the deploy that issued it
+    ///
+    /// The token is a credential, so it's never serialized or kept by the
+    /// manager; `token_id` is what identifies it afterwards.
+    #[serde(skip)]
+    pub token: Option<String>,
     /// Site ID
     pub site_id: Option<String>,
     /// Status
@@ -74,10 +104,142 @@ pub struct DeploymentConfig {
     pub endpoint: Option<String>,
     /// Custom configuration
     pub config: HashMap<String, String>,
+    /// Deployment this one was promoted from
+    #[serde(default)]
+    pub promoted_from: Option<String>,
+    /// Error from the last failed deploy step
+    #[serde(default)]
+    pub last_error: Option<String>,
 }
 
-/// Deployment status
+/// Check run against a freshly deployed agent before it is marked active
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeTest {
+    /// Prompt sent to the agent
+    pub prompt: String,
+    /// Text the response must contain
+    pub expect_contains: String,
+}
+
+impl SmokeTest {
+    /// Create a new smoke test
+    pub fn new(prompt: &str, expect_contains: &str) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+            expect_contains: expect_contains.to_string(),
+        }
+    }
+}
+
+/// Optional parts of a deploy made with `DeploymentManager::deploy_agent`
+///
+/// The default deploys the agent alone: no token, no site and no smoke test.
+#[derive(Debug, Clone, Default)]
+pub struct DeployOptions {
+    /// Whether to issue a deployment token from the manager's token store
+    pub issue_token: bool,
+    /// Site generated and registered for the agent
+    pub site_generator: Option<SiteGenerator>,
+    /// Check the agent must pass before the deployment is marked active
+    pub smoke_test: Option<SmokeTest>,
+}
+
+impl DeployOptions {
+    /// Create options for a plain deploy
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Issue a deployment token for the agent from the manager's token store
+    pub fn with_token(mut self) -> Self {
+        self.issue_token = true;
+        self
+    }
+    
+    /// Generate a site for the agent
+    pub fn with_site(mut self, site_generator: SiteGenerator) -> Self {
+        self.site_generator = Some(site_generator);
+        self
+    }
+    
+    /// Run a smoke test before marking the deployment active
+    pub fn with_smoke_test(mut self, smoke_test: SmokeTest) -> Self {
+        self.smoke_test = Some(smoke_test);
+        self
+    }
+}
+
+/// Deployment with its token and site details resolved, for display
+///
+/// Token details come from the token store's metadata; the token secret
+/// itself is never included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentDetail {
+    /// Deployment ID
+    pub id: String,
+    /// Agent ID
+    pub agent_id: String,
+    /// Model used by the deployment
+    pub model: String,
+    /// Environment name
+    pub environment: String,
+    /// Provider
+    pub provider: DeploymentProvider,
+    /// Region
+    pub region: String,
+    /// Status
+    pub status: DeploymentStatus,
+    /// Endpoint URL
+    pub endpoint: Option<String>,
+    /// Creation time
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Token ID
+    pub token_id: Option<String>,
+    /// Token type
+    pub token_type: Option<TokenType>,
+    /// Token expiration time
+    pub token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Site ID
+    pub site_id: Option<String>,
+    /// Site domain
+    pub site_domain: Option<String>,
+    /// Site theme
+    pub site_theme: Option<Theme>,
+    /// Site authentication method
+    pub site_auth_method: Option<AuthMethod>,
+}
+
+/// Agent in a fleet that couldn't be deployed
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetFailure {
+    /// Agent name
+    pub agent: String,
+    /// Why the deployment failed
+    pub reason: String,
+}
+
+/// Outcome of deploying a fleet with `DeploymentManager::deploy_fleet`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FleetDeployment {
+    /// Deployments that are in place
+    pub deployed: Vec<DeploymentConfig>,
+    /// Agents whose deployment failed
+    pub failed: Vec<FleetFailure>,
+    /// Agents that weren't attempted because an atomic deploy had already failed
+    pub skipped: Vec<String>,
+    /// Deployments that were removed again after an atomic deploy failed
+    pub rolled_back: Vec<DeploymentConfig>,
+}
+
+impl FleetDeployment {
+    /// Whether every agent in the fleet was deployed
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// Deployment status
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DeploymentStatus {
     Pending,
     Deploying,
@@ -86,46 +248,439 @@ pub enum DeploymentStatus {
     Stopped,
 }
 
+/// Criteria for `DeploymentManager::list_deployments`
+///
+/// Unset fields match every deployment. Without a limit every match is
+/// returned in one page.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentFilter {
+    /// Only deployments of this agent
+    #[serde(default)]
+    pub agent_id: Option<String>,
+    /// Only deployments in this environment
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Only deployments in this status
+    #[serde(default)]
+    pub status: Option<DeploymentStatus>,
+    /// Only deployments with this provider
+    #[serde(default)]
+    pub provider: Option<DeploymentProvider>,
+    /// Maximum number of deployments in the page
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// `next_cursor` from the previous page
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+impl DeploymentFilter {
+    /// Create a filter matching every deployment
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Only match deployments of an agent
+    pub fn with_agent(mut self, agent_id: &str) -> Self {
+        self.agent_id = Some(agent_id.to_string());
+        self
+    }
+    
+    /// Only match deployments in an environment
+    pub fn with_environment(mut self, environment: &str) -> Self {
+        self.environment = Some(environment.to_string());
+        self
+    }
+    
+    /// Only match deployments in a status
+    pub fn with_status(mut self, status: DeploymentStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+    
+    /// Only match deployments with a provider
+    pub fn with_provider(mut self, provider: DeploymentProvider) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+    
+    /// Return at most `limit` deployments per page
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+    
+    /// Continue from a previous page's `next_cursor`
+    pub fn with_cursor(mut self, cursor: &str) -> Self {
+        self.cursor = Some(cursor.to_string());
+        self
+    }
+    
+    /// Whether a deployment meets the criteria, ignoring paging
+    fn matches(&self, deployment: &DeploymentConfig) -> bool {
+        self.agent_id.as_ref().is_none_or(|agent_id| &deployment.agent_id == agent_id)
+            && self.environment.as_ref().is_none_or(|environment| {
+                deployment.environment.name() == Environment::from(environment.as_str()).name()
+            })
+            && self.status.as_ref().is_none_or(|status| &deployment.status == status)
+            && self.provider.as_ref().is_none_or(|provider| &deployment.provider == provider)
+    }
+}
+
+/// Page of deployments, newest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentPage {
+    /// Deployments in this page
+    pub deployments: Vec<DeploymentConfig>,
+    /// Cursor for the next page; `None` on the last one
+    pub next_cursor: Option<String>,
+}
+
+/// Position after a deployment in newest-first order, as an opaque string
+fn encode_cursor(deployment: &DeploymentConfig) -> String {
+    hex::encode(format!("{}|{}", deployment.created_at.to_rfc3339(), deployment.id))
+}
+
+/// Read a cursor written by `encode_cursor`
+fn decode_cursor(cursor: &str) -> Option<(chrono::DateTime<chrono::Utc>, String)> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    let (created_at, id) = decoded.split_once('|')?;
+    let created_at = chrono::DateTime::parse_from_rfc3339(created_at).ok()?.with_timezone(&chrono::Utc);
+    Some((created_at, id.to_string()))
+}
+
+/// Status change posted to deployment webhooks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentEvent {
+    /// Deployment ID
+    pub deployment_id: String,
+    /// Agent ID
+    pub agent_id: String,
+    /// Environment name
+    pub environment: String,
+    /// Status before the change; `None` when the deployment was just created
+    pub old_status: Option<DeploymentStatus>,
+    /// Status after the change
+    pub new_status: DeploymentStatus,
+    /// When the change happened
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Endpoint that receives deployment events
+#[derive(Debug, Clone)]
+struct Webhook {
+    url: String,
+    secret: Option<String>,
+}
+
+/// Attempts made to deliver an event before giving up
+const WEBHOOK_ATTEMPTS: u32 = 3;
+
+impl Webhook {
+    /// Post an event, retrying failed attempts with a growing delay
+    ///
+    /// With a secret, the body is signed with HMAC-SHA256 and the hex digest
+    /// is sent as `X-Bea-Signature: sha256=<digest>`.
+    async fn deliver(&self, body: Vec<u8>) {
+        let signature = self.secret.as_ref().map(|secret| {
+            use hmac::{Hmac, Mac};
+            
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(&body);
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+        });
+        
+        for attempt in 1..=WEBHOOK_ATTEMPTS {
+            let mut request = crate::http::client()
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header("X-Bea-Signature", signature);
+            }
+            
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => format!("status {}", response.status()),
+                Err(e) => e.to_string(),
+            };
+            
+            tracing::warn!(url = %self.url, attempt, error = %error, "webhook delivery failed");
+            if attempt < WEBHOOK_ATTEMPTS {
+                tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+            }
+        }
+    }
+}
+
+/// Superseded deployments per agent ID and environment name, oldest first
+type History = HashMap<(String, String), VecDeque<DeploymentConfig>>;
+
+/// Traffic splits per agent ID and environment name
+type TrafficSplits = HashMap<(String, String), Vec<(String, u8)>>;
+
 /// Deployment manager
 pub struct DeploymentManager {
     deployments: Arc<Mutex<HashMap<String, DeploymentConfig>>>,
+    agents: Arc<Mutex<HashMap<String, Agent>>>,
+    sites: Option<Arc<SiteStore>>,
+    registry: Option<Arc<ModelRegistry>>,
+    environments: Option<Arc<EnvironmentConfigs>>,
+    tokens: Option<Arc<TokenStore>>,
+    history: Arc<Mutex<History>>,
+    history_limit: usize,
+    splits: Arc<Mutex<TrafficSplits>>,
+    webhooks: Vec<Webhook>,
 }
 
+/// Number of superseded deployments kept per agent and environment
+const DEFAULT_HISTORY_LIMIT: usize = 5;
+
 impl DeploymentManager {
     /// Create a new deployment manager
     pub fn new() -> Self {
         Self {
             deployments: Arc::new(Mutex::new(HashMap::new())),
+            agents: Arc::new(Mutex::new(HashMap::new())),
+            sites: None,
+            registry: None,
+            environments: None,
+            tokens: None,
+            history: Arc::new(Mutex::new(HashMap::new())),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            splits: Arc::new(Mutex::new(HashMap::new())),
+            webhooks: Vec::new(),
+        }
+    }
+    
+    /// Set how many superseded deployments are kept for rollback
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+    
+    /// Register generated sites in the given store on deploy
+    pub fn with_site_store(mut self, sites: Arc<SiteStore>) -> Self {
+        self.sites = Some(sites);
+        self
+    }
+    
+    /// Use the given model registry to run deploy-time smoke tests
+    pub fn with_registry(mut self, registry: Arc<ModelRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+    
+    /// Use the given base config and environment overlays on deploy
+    pub fn with_environment_configs(mut self, environments: EnvironmentConfigs) -> Self {
+        self.environments = Some(Arc::new(environments));
+        self
+    }
+    
+    /// Issue deployment tokens from the given store
+    pub fn with_token_store(mut self, tokens: Arc<TokenStore>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+    
+    /// Post a `DeploymentEvent` to `url` whenever a deployment changes status
+    ///
+    /// Events are delivered in the background and retried a couple of times;
+    /// a webhook that can't be reached never holds up the change itself.
+    /// With a secret, each body is signed in an `X-Bea-Signature` header.
+    pub fn with_webhook(mut self, url: String, secret: Option<String>) -> Self {
+        self.webhooks.push(Webhook { url, secret });
+        self
+    }
+    
+    /// Send a status change to the webhooks without waiting for delivery
+    fn notify(&self, deployment: &DeploymentConfig, old_status: Option<DeploymentStatus>) {
+        if self.webhooks.is_empty() || old_status.as_ref() == Some(&deployment.status) {
+            return;
+        }
+        
+        let event = DeploymentEvent {
+            deployment_id: deployment.id.clone(),
+            agent_id: deployment.agent_id.clone(),
+            environment: deployment.environment.name(),
+            old_status,
+            new_status: deployment.status.clone(),
+            timestamp: chrono::Utc::now(),
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!(error = %e, "cannot serialize deployment event");
+                return;
+            }
+        };
+        
+        for webhook in &self.webhooks {
+            let webhook = webhook.clone();
+            let body = body.clone();
+            tokio::spawn(async move { webhook.deliver(body).await });
+        }
+    }
+    
+    /// Get the resolved config for an environment
+    ///
+    /// Empty when the manager has no environment configs.
+    pub fn environment_config(&self, environment: &str) -> EnvironmentConfig {
+        self.environments
+            .as_ref()
+            .map(|environments| environments.resolve(environment))
+            .unwrap_or_default()
+    }
+    
+    /// Load a deployment manager from a state file written by `save`
+    ///
+    /// A missing file gives an empty manager. Only the deployments are
+    /// stored; agent definitions and rollback history don't outlive the
+    /// process that created them. Older state files kept token strings in
+    /// `token_id`; those are dropped on load, so the next `save` removes them.
+    pub async fn load(path: &Path) -> Result<Self, BeaError> {
+        let manager = Self::new();
+        
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(manager),
+            Err(e) => return Err(e.into()),
+        };
+        
+        let mut stored: Vec<DeploymentConfig> = serde_json::from_str(&contents)?;
+        for deployment in &mut stored {
+            if deployment.token_id.as_deref().is_some_and(|id| id.starts_with("bea_")) {
+                deployment.token_id = None;
+            }
+        }
+        manager.deployments.lock().await.extend(stored.into_iter().map(|d| (d.id.clone(), d)));
+        Ok(manager)
+    }
+    
+    /// Write the deployments to a state file, replacing it atomically
+    ///
+    /// The file is created readable only by its owner.
+    pub async fn save(&self, path: &Path) -> Result<(), BeaError> {
+        let mut stored = self.list_deployments(DeploymentFilter::new()).await.deployments;
+        stored.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        crate::config::write_state_file(path, &serde_json::to_vec_pretty(&stored)?).await
+    }
+    
+    /// Generate an unused deployment ID
+    ///
+    /// IDs are "dep_" followed by the 32 hex digits of a UUID v4. A collision
+    /// with an existing deployment is astronomically unlikely, but is retried.
+    async fn new_deployment_id(&self) -> String {
+        let deployments = self.deployments.lock().await;
+        loop {
+            let id = format!("dep_{}", Uuid::new_v4().simple());
+            if !deployments.contains_key(&id) {
+                return id;
+            }
         }
     }
     
     /// Deploy an agent
+    ///
+    /// `options` can attach a token, a site and a smoke test. With a smoke
+    /// test the deployment only becomes `Active` once the agent answers the
+    /// test prompt as expected; otherwise it is marked `Failed` with
+    /// `last_error` set and an error is returned.
+    ///
+    /// A token is issued from the manager's token store, which is then
+    /// required. Only the returned deployment carries the token string; its
+    /// `token_id` names the token in the store. A failed deploy revokes it.
+    ///
+    /// With environment configs, the base config merged with the
+    /// environment's overlay supplies the region and provider, overriding
+    /// the ones passed in. Its token expiry and rate limits are recorded in
+    /// the deployment's `config`.
     pub async fn deploy_agent(
         &self,
         agent: Agent,
         environment: &str,
         region: &str,
         provider: DeploymentProvider,
-        token_manager: Option<TokenManager>,
-        site_generator: Option<SiteGenerator>,
-    ) -> Result<DeploymentConfig, Box<dyn Error>> {
-        let deployment_id = format!("dep_{}", Uuid::new_v4().to_string().replace("-", ""));
+        options: DeployOptions,
+    ) -> Result<DeploymentConfig, BeaError> {
+        self.deploy_and_supersede(agent, environment, region, provider, options)
+            .await
+            .map(|(deployment, _)| deployment)
+    }
+    
+    /// Deploy an agent, also returning the ID of the deployment it superseded
+    #[tracing::instrument(
+        name = "deploy_agent",
+        skip_all,
+        fields(agent_id = %agent.name(), environment = %environment, provider = ?provider, deployment_id = tracing::field::Empty),
+        err(Display)
+    )]
+    async fn deploy_and_supersede(
+        &self,
+        agent: Agent,
+        environment: &str,
+        region: &str,
+        provider: DeploymentProvider,
+        options: DeployOptions,
+    ) -> Result<(DeploymentConfig, Option<String>), BeaError> {
+        let DeployOptions { issue_token, site_generator, smoke_test } = options;
+        agent.validate().map_err(BeaError::InvalidAgent)?;
+        
+        let deployment_id = self.new_deployment_id().await;
         let agent_id = agent.name().to_string();
+        tracing::Span::current().record("deployment_id", deployment_id.as_str());
         
-        // Generate token if provided
-        let token_id = if let Some(tm) = token_manager {
-            let token = tm.get_token(environment);
-            token.map(|t| t.to_string())
+        let settings = self.environment_config(environment);
+        let region = settings.region.as_deref().unwrap_or(region);
+        let provider = settings.provider.clone().unwrap_or(provider);
+        
+        let mut config = HashMap::new();
+        if let Some(expiry) = settings.token_expiry_secs {
+            config.insert("token_expiry_secs".to_string(), expiry.to_string());
+        }
+        if let Some(rpm) = settings.requests_per_minute {
+            config.insert("requests_per_minute".to_string(), rpm.to_string());
+        }
+        if let Some(burst) = settings.burst {
+            config.insert("burst".to_string(), burst.to_string());
+        }
+        
+        let (token_id, token) = if issue_token {
+            let tokens = self.tokens.as_ref().ok_or_else(|| {
+                BeaError::InvalidConfig("a deployment token needs a token store".to_string())
+            })?;
+            let metadata = HashMap::from([("deployment_id".to_string(), deployment_id.clone())]);
+            let (token_id, token) = tokens
+                .generate_token_with_id(
+                    TokenType::Deployment,
+                    environment,
+                    settings.token_expiry_secs.map(|secs| chrono::Duration::seconds(secs as i64)),
+                    Some(&agent_id),
+                    None,
+                    Some(metadata),
+                )
+                .await?;
+            (Some(token_id), Some(token))
         } else {
-            None
+            (None, None)
         };
         
         // Generate site if provided
         let (site_id, endpoint) = if let Some(sg) = site_generator {
-            let site_id = sg.config.id.clone();
-            let endpoint = format!("https://{}", sg.config.domain.clone().unwrap_or_else(|| {
+            let mut site = sg.into_config();
+            if site.agent_id.is_none() {
+                site.agent_id = Some(agent_id.clone());
+            }
+            
+            let endpoint = format!("https://{}", site.domain.clone().unwrap_or_else(|| {
                 format!("{}.{}.bea-bot.app", agent_id, environment)
             }));
+            
+            let site_id = match &self.sites {
+                Some(sites) => sites.register(site).await,
+                None => site.id,
+            };
             (Some(site_id), Some(endpoint))
         } else {
             let endpoint = format!("https://{}.{}.bea-bot.app/api", agent_id, environment);
@@ -135,78 +690,730 @@ impl DeploymentManager {
         let deployment = DeploymentConfig {
             id: deployment_id.clone(),
             agent_id,
-            environment: Environment::from_str(environment),
+            agent_version: agent.version().map(|v| v.to_string()),
+            model: agent.model_for_env(environment).to_string(),
+            environment: Environment::from(environment),
             provider,
             created_at: chrono::Utc::now(),
             region: region.to_string(),
             token_id,
+            token: None,
             site_id,
             status: DeploymentStatus::Pending,
             endpoint,
-            config: HashMap::new(),
+            config,
+            promoted_from: None,
+            last_error: None,
         };
         
+        // Keep the agent definition so the deployment can be promoted later
+        self.agents.lock().await.insert(deployment_id.clone(), agent.clone());
+        
         // Store deployment
-        let mut deployments = self.deployments.lock().await;
-        deployments.insert(deployment_id.clone(), deployment.clone());
+        self.deployments.lock().await.insert(deployment_id.clone(), deployment.clone());
+        self.notify(&deployment, None);
         
         // In a real implementation, this would actually deploy the agent
         
-        // For demonstration purposes, we'll just update the status
         let mut updated_deployment = deployment.clone();
-        updated_deployment.status = DeploymentStatus::Active;
-        deployments.insert(deployment_id, updated_deployment.clone());
+        updated_deployment.status = DeploymentStatus::Deploying;
+        self.deployments.lock().await.insert(deployment_id.clone(), updated_deployment.clone());
+        self.notify(&updated_deployment, Some(DeploymentStatus::Pending));
+        
+        let smoke_result = match &smoke_test {
+            Some(test) => self.run_smoke_test(&agent, &deployment.model, test).await,
+            None => Ok(()),
+        };
         
-        Ok(updated_deployment)
+        match smoke_result {
+            Ok(()) => {
+                updated_deployment.status = DeploymentStatus::Active;
+                let superseded = self.supersede(&updated_deployment).await;
+                self.deployments.lock().await.insert(deployment_id, updated_deployment.clone());
+                self.notify(&updated_deployment, Some(DeploymentStatus::Deploying));
+                tracing::info!(model = %updated_deployment.model, "agent deployed");
+                Ok((DeploymentConfig { token, ..updated_deployment }, superseded))
+            }
+            Err(reason) => {
+                updated_deployment.status = DeploymentStatus::Failed;
+                updated_deployment.last_error = Some(reason.clone());
+                self.deployments.lock().await.insert(deployment_id.clone(), updated_deployment.clone());
+                self.notify(&updated_deployment, Some(DeploymentStatus::Deploying));
+                
+                // Nothing is serving with the token, and the caller never sees it
+                if let (Some(tokens), Some(token_id)) = (&self.tokens, &updated_deployment.token_id) {
+                    let _ = tokens.revoke_token(token_id).await;
+                }
+                Err(BeaError::DeploymentFailed { id: deployment_id, reason })
+            }
+        }
     }
     
-    /// Get deployment by ID
-    pub async fn get_deployment(&self, deployment_id: &str) -> Option<DeploymentConfig> {
+    /// History key for a deployment's agent and environment
+    fn history_key(agent_id: &str, environment: &str) -> (String, String) {
+        (agent_id.to_string(), Environment::from(environment).name())
+    }
+    
+    /// Stop the active deployment that `replacement` takes over from, if any
+    ///
+    /// The stopped deployment is pushed onto the history for its agent and
+    /// environment, dropping the oldest entry once the limit is reached.
+    /// While a traffic split is set for them nothing is stopped: the
+    /// replacement runs alongside the split's deployments until a split
+    /// gives it a share. Returns the ID of the stopped deployment.
+    async fn supersede(&self, replacement: &DeploymentConfig) -> Option<String> {
+        let environment = replacement.environment.name();
+        let key = Self::history_key(&replacement.agent_id, &environment);
+        
+        if self.splits.lock().await.contains_key(&key) {
+            return None;
+        }
+        
+        let mut deployments = self.deployments.lock().await;
+        let previous = deployments.values_mut().find(|d| {
+            d.id != replacement.id
+                && d.agent_id == replacement.agent_id
+                && d.environment.name() == environment
+                && d.status == DeploymentStatus::Active
+        })?;
+        previous.status = DeploymentStatus::Stopped;
+        let previous = previous.clone();
+        drop(deployments);
+        self.notify(&previous, Some(DeploymentStatus::Active));
+        
+        let previous_id = previous.id.clone();
+        let mut history = self.history.lock().await;
+        let entries = history.entry(key).or_default();
+        entries.push_back(previous);
+        while entries.len() > self.history_limit {
+            entries.pop_front();
+        }
+        Some(previous_id)
+    }
+    
+    /// Undo `supersede`: make a deployment it stopped active again
+    ///
+    /// The deployment is taken back out of the history. One that has since
+    /// been deleted or is no longer `Stopped` is left alone.
+    async fn restore_superseded(&self, deployment_id: &str) {
+        let mut deployments = self.deployments.lock().await;
+        let Some(previous) = deployments
+            .get_mut(deployment_id)
+            .filter(|d| d.status == DeploymentStatus::Stopped)
+        else {
+            return;
+        };
+        previous.status = DeploymentStatus::Active;
+        let previous = previous.clone();
+        drop(deployments);
+        self.notify(&previous, Some(DeploymentStatus::Stopped));
+        
+        let key = Self::history_key(&previous.agent_id, &previous.environment.name());
+        if let Some(entries) = self.history.lock().await.get_mut(&key) {
+            entries.retain(|d| d.id != deployment_id);
+        }
+    }
+    
+    /// Roll an agent's environment back to its previous deployment
+    ///
+    /// The current active deployment is marked `Stopped` and the most recent
+    /// superseded one is made `Active` again and returned. If that one has
+    /// since been deleted, it is dropped from the history and
+    /// `DeploymentNotFound` is returned.
+    #[tracing::instrument(skip(self), err(Display))]
+    pub async fn rollback(&self, agent_id: &str, environment: &str) -> Result<DeploymentConfig, BeaError> {
+        let key = Self::history_key(agent_id, environment);
+        
+        let previous_id = self.history.lock().await
+            .get_mut(&key)
+            .and_then(|entries| entries.pop_back())
+            .map(|previous| previous.id)
+            .ok_or_else(|| BeaError::NoPreviousDeployment {
+                agent_id: agent_id.to_string(),
+                environment: key.1.clone(),
+            })?;
+        
+        let mut deployments = self.deployments.lock().await;
+        let mut previous = deployments.get(&previous_id).cloned()
+            .ok_or(BeaError::DeploymentNotFound(previous_id))?;
+        
+        for deployment in deployments.values_mut() {
+            if deployment.agent_id == key.0
+                && deployment.environment.name() == key.1
+                && deployment.status == DeploymentStatus::Active
+            {
+                deployment.status = DeploymentStatus::Stopped;
+                self.notify(deployment, Some(DeploymentStatus::Active));
+            }
+        }
+        
+        let old_status = previous.status.clone();
+        previous.status = DeploymentStatus::Active;
+        previous.last_error = None;
+        deployments.insert(previous.id.clone(), previous.clone());
+        self.notify(&previous, Some(old_status));
+        tracing::info!(deployment_id = %previous.id, "rolled back");
+        
+        Ok(previous)
+    }
+    
+    /// Get the superseded deployments for an agent's environment, oldest first
+    pub async fn deployment_history(&self, agent_id: &str, environment: &str) -> Vec<DeploymentConfig> {
+        let key = Self::history_key(agent_id, environment);
+        self.history.lock().await
+            .get(&key)
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+    
+    /// Split an agent's traffic in an environment between deployments
+    ///
+    /// Each entry is a deployment ID and the percentage of requests it gets;
+    /// the percentages must add up to 100. Every deployment must exist, be
+    /// `Active` or `Deploying`, and belong to the agent and environment.
+    /// Replaces any split already set.
+    ///
+    /// To canary a new version, give the current deployment 100% before
+    /// deploying the new one, which then runs alongside it instead of
+    /// replacing it. Set a split between the two, then `promote_in_place`
+    /// the one that should take over.
+    #[tracing::instrument(skip(self), err(Display))]
+    pub async fn set_traffic_split(
+        &self,
+        agent_id: &str,
+        environment: &str,
+        splits: Vec<(String, u8)>,
+    ) -> Result<(), BeaError> {
+        let key = Self::history_key(agent_id, environment);
+        
+        if splits.is_empty() {
+            return Err(BeaError::InvalidTrafficSplit("no deployments given".to_string()));
+        }
+        
+        let total: u32 = splits.iter().map(|(_, percent)| *percent as u32).sum();
+        if total != 100 {
+            return Err(BeaError::InvalidTrafficSplit(format!(
+                "percentages add up to {}, expected 100",
+                total
+            )));
+        }
+        
         let deployments = self.deployments.lock().await;
-        deployments.get(deployment_id).cloned()
+        for (deployment_id, _) in &splits {
+            let deployment = deployments.get(deployment_id)
+                .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.clone()))?;
+            
+            if !matches!(deployment.status, DeploymentStatus::Active | DeploymentStatus::Deploying) {
+                return Err(BeaError::InvalidDeploymentState {
+                    id: deployment.id.clone(),
+                    status: format!("{:?}", deployment.status),
+                    expected: "Active".to_string(),
+                });
+            }
+            
+            if deployment.agent_id != key.0 || deployment.environment.name() != key.1 {
+                return Err(BeaError::InvalidTrafficSplit(format!(
+                    "deployment {} is for {} in {}, not {} in {}",
+                    deployment.id, deployment.agent_id, deployment.environment.name(), key.0, key.1
+                )));
+            }
+        }
+        drop(deployments);
+        
+        self.splits.lock().await.insert(key, splits);
+        Ok(())
     }
     
-    /// List deployments for an agent
-    pub async fn list_deployments_for_agent(&self, agent_id: &str) -> Vec<DeploymentConfig> {
+    /// Get the traffic split for an agent's environment, if one is set
+    pub async fn traffic_split(&self, agent_id: &str, environment: &str) -> Option<Vec<(String, u8)>> {
+        let key = Self::history_key(agent_id, environment);
+        self.splits.lock().await.get(&key).cloned()
+    }
+    
+    /// Remove the traffic split for an agent's environment
+    pub async fn clear_traffic_split(&self, agent_id: &str, environment: &str) {
+        let key = Self::history_key(agent_id, environment);
+        self.splits.lock().await.remove(&key);
+    }
+    
+    /// Pick the deployment that should serve a request
+    ///
+    /// With a traffic split, a deployment is drawn at random weighted by its
+    /// percentage. Deployments in the split that aren't `Active` are left
+    /// out and their share goes to the rest. Without a split, or when none
+    /// of its deployments is active, the active deployment is used. Returns
+    /// `None` when there's nothing to route to.
+    pub async fn route(&self, agent_id: &str, environment: &str) -> Option<String> {
+        use rand::Rng;
+        
+        let key = Self::history_key(agent_id, environment);
+        
+        let splits = self.splits.lock().await.get(&key).cloned();
+        if let Some(splits) = splits {
+            let deployments = self.deployments.lock().await;
+            let active: Vec<(String, u32)> = splits
+                .into_iter()
+                .filter(|(id, percent)| {
+                    *percent > 0
+                        && deployments.get(id).is_some_and(|d| d.status == DeploymentStatus::Active)
+                })
+                .map(|(id, percent)| (id, percent as u32))
+                .collect();
+            drop(deployments);
+            
+            let total: u32 = active.iter().map(|(_, percent)| percent).sum();
+            if total > 0 {
+                let mut draw = rand::thread_rng().gen_range(0..total);
+                for (deployment_id, percent) in active {
+                    if draw < percent {
+                        return Some(deployment_id);
+                    }
+                    draw -= percent;
+                }
+            }
+        }
+        
+        self.active_deployment(agent_id, environment).await.map(|d| d.id)
+    }
+    
+    /// Send the smoke test prompt to the agent and check its reply
+    async fn run_smoke_test(&self, agent: &Agent, model: &str, test: &SmokeTest) -> Result<(), String> {
+        let registry = self.registry.as_ref()
+            .ok_or_else(|| "smoke test requires a model registry".to_string())?;
+        
+        let request = ModelRequest::from_agent(agent, vec![Message::user(&test.prompt)])
+            .with_model(model);
+        
+        let response = registry.generate(request).await
+            .map_err(|e| format!("smoke test request failed: {}", e))?;
+        
+        let content = response.message().content();
+        if content.contains(&test.expect_contains) {
+            Ok(())
+        } else {
+            Err(format!(
+                "smoke test response did not contain {:?}: {:?}",
+                test.expect_contains, content
+            ))
+        }
+    }
+    
+    /// Deploy an agent to several environments concurrently
+    ///
+    /// Returns one result per environment, in the order given. A failure in
+    /// one environment does not stop the others.
+    pub async fn deploy_to_all(
+        &self,
+        agent: Agent,
+        environments: Vec<&str>,
+        provider: DeploymentProvider,
+        region: &str,
+    ) -> Vec<Result<DeploymentConfig, BeaError>> {
+        let deploys = environments.into_iter().map(|environment| {
+            let agent = agent.clone();
+            let provider = provider.clone();
+            
+            async move {
+                self.deploy_agent(agent, environment, region, provider, DeployOptions::new()).await
+            }
+        });
+        
+        join_all(deploys).await
+    }
+    
+    /// Make a deployment the sole active one for its agent and environment
+    ///
+    /// The deployment being promoted becomes "green": it is marked `Active`
+    /// and any traffic split for its environment is cleared. Every other
+    /// active deployment becomes "blue": it is marked `Stopped` and pushed
+    /// onto the history, so `rollback` switches straight back to it.
+    /// Promoting a stopped deployment takes it back out of the history.
+    /// Only `Active` and `Stopped` deployments can be promoted; one that is
+    /// still deploying or has failed is refused.
+    ///
+    /// Use `promote` to deploy to another environment instead.
+    #[tracing::instrument(skip(self), err(Display))]
+    pub async fn promote_in_place(&self, deployment_id: &str) -> Result<DeploymentConfig, BeaError> {
+        let mut deployments = self.deployments.lock().await;
+        let target = deployments.get(deployment_id)
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?
+            .clone();
+        
+        if !matches!(target.status, DeploymentStatus::Active | DeploymentStatus::Stopped) {
+            return Err(BeaError::InvalidDeploymentState {
+                id: target.id,
+                status: format!("{:?}", target.status),
+                expected: "Active or Stopped".to_string(),
+            });
+        }
+        
+        let environment = target.environment.name();
+        let key = Self::history_key(&target.agent_id, &environment);
+        
+        let mut blue = Vec::new();
+        for deployment in deployments.values_mut() {
+            if deployment.id != target.id
+                && deployment.agent_id == target.agent_id
+                && deployment.environment.name() == environment
+                && deployment.status == DeploymentStatus::Active
+            {
+                deployment.status = DeploymentStatus::Stopped;
+                self.notify(deployment, Some(DeploymentStatus::Active));
+                blue.push(deployment.clone());
+            }
+        }
+        
+        let old_status = target.status.clone();
+        let mut green = target;
+        green.status = DeploymentStatus::Active;
+        green.last_error = None;
+        deployments.insert(green.id.clone(), green.clone());
+        drop(deployments);
+        self.notify(&green, Some(old_status));
+        
+        let mut history = self.history.lock().await;
+        let entries = history.entry(key.clone()).or_default();
+        entries.retain(|d| d.id != green.id);
+        blue.sort_by_key(|d| d.created_at);
+        entries.extend(blue);
+        while entries.len() > self.history_limit {
+            entries.pop_front();
+        }
+        drop(history);
+        
+        self.splits.lock().await.remove(&key);
+        tracing::info!(deployment_id = %green.id, "promoted");
+        
+        Ok(green)
+    }
+    
+    /// Get the active deployment for an agent's environment
+    ///
+    /// After `promote_in_place` this is the promoted deployment. If several are
+    /// active, e.g. while a traffic split is in place, the newest is returned.
+    pub async fn active_deployment(&self, agent_id: &str, environment: &str) -> Option<DeploymentConfig> {
+        let key = Self::history_key(agent_id, environment);
         let deployments = self.deployments.lock().await;
         deployments
             .values()
-            .filter(|d| d.agent_id == agent_id)
+            .filter(|d| {
+                d.agent_id == key.0
+                    && d.environment.name() == key.1
+                    && d.status == DeploymentStatus::Active
+            })
+            .max_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)))
             .cloned()
-            .collect()
     }
     
-    /// List deployments for an environment
-    pub async fn list_deployments_for_environment(&self, environment: &str) -> Vec<DeploymentConfig> {
-        let env = Environment::from_str(environment);
+    /// Promote an active deployment to another environment
+    ///
+    /// Deploys the source deployment's agent to `to` in the same region and
+    /// provider, and links the new deployment back via `promoted_from`.
+    #[tracing::instrument(skip(self), err(Display))]
+    pub async fn promote(&self, deployment_id: &str, to: &str) -> Result<DeploymentConfig, BeaError> {
+        let source = self.get_deployment(deployment_id).await
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
+        
+        if !matches!(source.status, DeploymentStatus::Active) {
+            return Err(BeaError::InvalidDeploymentState {
+                id: source.id,
+                status: format!("{:?}", source.status),
+                expected: "Active".to_string(),
+            });
+        }
+        
+        let agent = self.agents.lock().await.get(deployment_id).cloned()
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
+        
+        let mut promoted = self
+            .deploy_agent(agent, to, &source.region, source.provider.clone(), DeployOptions::new())
+            .await?;
+        promoted.promoted_from = Some(source.id);
+        
+        let mut deployments = self.deployments.lock().await;
+        deployments.insert(promoted.id.clone(), promoted.clone());
+        
+        Ok(promoted)
+    }
+    
+    /// Describe a deployment, resolving its token and site details
+    pub async fn describe(
+        &self,
+        deployment_id: &str,
+        tokens: &TokenStore,
+        sites: &SiteStore,
+    ) -> Result<DeploymentDetail, BeaError> {
+        let deployment = self.get_deployment(deployment_id).await
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
+        
+        let token = match &deployment.token_id {
+            Some(token_id) => tokens.get_token_metadata(token_id).await,
+            None => None,
+        };
+        
+        let site = match &deployment.site_id {
+            Some(site_id) => sites.get(site_id).await,
+            None => None,
+        };
+        
+        Ok(DeploymentDetail {
+            id: deployment.id,
+            agent_id: deployment.agent_id,
+            model: deployment.model,
+            environment: deployment.environment.name(),
+            provider: deployment.provider,
+            region: deployment.region,
+            status: deployment.status,
+            endpoint: deployment.endpoint,
+            created_at: deployment.created_at,
+            token_id: token.as_ref().map(|t| t.id.clone()),
+            token_type: token.as_ref().map(|t| t.token_type.clone()),
+            token_expires_at: token.as_ref().and_then(|t| t.expires_at),
+            site_id: site.as_ref().map(|s| s.id.clone()),
+            site_domain: site.as_ref().and_then(|s| s.domain.clone()),
+            site_theme: site.as_ref().map(|s| s.theme.clone()),
+            site_auth_method: site.as_ref().and_then(|s| s.auth.as_ref().map(|a| a.method.clone())),
+        })
+    }
+    
+    /// Apply a function to every deployment without cloning them
+    ///
+    /// The deployments lock is held while `f` runs, so keep it quick and
+    /// don't call back into the manager from it.
+    pub async fn for_each_deployment<F: FnMut(&DeploymentConfig)>(&self, mut f: F) {
         let deployments = self.deployments.lock().await;
-        deployments
+        for deployment in deployments.values() {
+            f(deployment);
+        }
+    }
+    
+    /// Count all deployments
+    pub async fn count(&self) -> usize {
+        let deployments = self.deployments.lock().await;
+        deployments.len()
+    }
+    
+    /// Count deployments in each status
+    pub async fn count_by_status(&self) -> HashMap<DeploymentStatus, usize> {
+        let deployments = self.deployments.lock().await;
+        let mut counts = HashMap::new();
+        for deployment in deployments.values() {
+            *counts.entry(deployment.status.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+    
+    /// Get deployment by ID
+    pub async fn get_deployment(&self, deployment_id: &str) -> Option<DeploymentConfig> {
+        let deployments = self.deployments.lock().await;
+        deployments.get(deployment_id).cloned()
+    }
+    
+    /// List deployments matching a filter, newest first
+    ///
+    /// Deployments created at the same time are ordered by ID. When a limit
+    /// cuts the list short, `next_cursor` is set; pass it back in the filter
+    /// to get the following page. A cursor that can't be read gives an
+    /// empty page.
+    pub async fn list_deployments(&self, filter: DeploymentFilter) -> DeploymentPage {
+        let after = match &filter.cursor {
+            Some(cursor) => match decode_cursor(cursor) {
+                Some(after) => Some(after),
+                None => return DeploymentPage::default(),
+            },
+            None => None,
+        };
+        
+        let deployments = self.deployments.lock().await;
+        let mut matches: Vec<&DeploymentConfig> = deployments
             .values()
-            .filter(|d| d.environment.name() == env.name())
-            .cloned()
-            .collect()
+            .filter(|d| filter.matches(d))
+            .filter(|d| match &after {
+                Some((created_at, id)) => (d.created_at, &d.id) < (*created_at, id),
+                None => true,
+            })
+            .collect();
+        matches.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+        
+        let limit = filter.limit.unwrap_or(usize::MAX);
+        let next_cursor = match matches.get(limit) {
+            Some(_) if limit > 0 => Some(encode_cursor(matches[limit - 1])),
+            _ => None,
+        };
+        matches.truncate(limit);
+        
+        DeploymentPage {
+            deployments: matches.into_iter().cloned().collect(),
+            next_cursor,
+        }
+    }
+    
+    /// List deployments for an agent, newest first
+    pub async fn list_deployments_for_agent(&self, agent_id: &str) -> Vec<DeploymentConfig> {
+        self.list_deployments(DeploymentFilter::new().with_agent(agent_id)).await.deployments
+    }
+    
+    /// List deployments for an environment, newest first
+    pub async fn list_deployments_for_environment(&self, environment: &str) -> Vec<DeploymentConfig> {
+        self.list_deployments(DeploymentFilter::new().with_environment(environment)).await.deployments
     }
     
     /// Stop deployment
-    pub async fn stop_deployment(&self, deployment_id: &str) -> Result<(), Box<dyn Error>> {
+    ///
+    /// Any traffic split that includes it is cleared, so its agent's
+    /// requests go back to the active deployment.
+    pub async fn stop_deployment(&self, deployment_id: &str) -> Result<(), BeaError> {
         let mut deployments = self.deployments.lock().await;
-        let deployment = deployments.get_mut(deployment_id).ok_or("Deployment not found")?;
+        let deployment = deployments
+            .get_mut(deployment_id)
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
         
         // In a real implementation, this would actually stop the deployment
         
+        let old_status = deployment.status.clone();
         deployment.status = DeploymentStatus::Stopped;
+        self.notify(deployment, Some(old_status));
+        drop(deployments);
+        
+        self.splits.lock().await.retain(|_, splits| splits.iter().all(|(id, _)| id != deployment_id));
         Ok(())
     }
     
     /// Delete deployment
-    pub async fn delete_deployment(&self, deployment_id: &str) -> Result<(), Box<dyn Error>> {
+    ///
+    /// The deployment is also dropped from the rollback history, and any
+    /// traffic split that includes it is cleared.
+    pub async fn delete_deployment(&self, deployment_id: &str) -> Result<(), BeaError> {
         let mut deployments = self.deployments.lock().await;
         
         // In a real implementation, this would actually delete the deployment
         
-        deployments.remove(deployment_id).ok_or_else(|| "Deployment not found".into())?;
+        deployments
+            .remove(deployment_id)
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
+        drop(deployments);
+        self.agents.lock().await.remove(deployment_id);
+        
+        for entries in self.history.lock().await.values_mut() {
+            entries.retain(|d| d.id != deployment_id);
+        }
+        self.splits.lock().await.retain(|_, splits| splits.iter().all(|(id, _)| id != deployment_id));
         Ok(())
     }
+    
+    /// Tear down a deployment: stop it, delete it and revoke its token
+    ///
+    /// The token is revoked in `tokens`, or the manager's own token store if
+    /// none is given. A deployment with a token and no store to revoke it in
+    /// is left alone and an error returned. A token the store no longer has
+    /// was already revoked or swept. Returns the removed deployment.
+    #[tracing::instrument(skip(self, tokens), err(Display))]
+    pub async fn destroy_deployment(
+        &self,
+        deployment_id: &str,
+        tokens: Option<&TokenStore>,
+    ) -> Result<DeploymentConfig, BeaError> {
+        let deployment = self
+            .get_deployment(deployment_id)
+            .await
+            .ok_or_else(|| BeaError::DeploymentNotFound(deployment_id.to_string()))?;
+        let tokens = tokens.or(self.tokens.as_deref());
+        if deployment.token_id.is_some() && tokens.is_none() {
+            return Err(BeaError::InvalidConfig(format!(
+                "deployment {} has a token but there is no token store to revoke it in",
+                deployment_id
+            )));
+        }
+        
+        self.stop_deployment(deployment_id).await?;
+        self.delete_deployment(deployment_id).await?;
+        
+        if let (Some(tokens), Some(token_id)) = (tokens, &deployment.token_id) {
+            if let Err(BeaError::TokenNotFound) = tokens.revoke_token(token_id).await {
+                tracing::debug!(token_id = %token_id, "token already gone from the store");
+            }
+        }
+        
+        Ok(deployment)
+    }
+    
+    /// Deploy several agents to the same environment
+    ///
+    /// Every agent is deployed with a copy of `options`, so each gets its own
+    /// token when one is asked for. By default a failure is
+    /// recorded and the remaining agents are still deployed. With `atomic`,
+    /// the first failure stops the fleet and the agents deployed so far are
+    /// undone: the new deployment is destroyed and, if it superseded one,
+    /// that one is made active again. Any that can't be removed stay in
+    /// `deployed`.
+    #[tracing::instrument(
+        skip_all,
+        fields(environment = %environment, provider = ?provider, agents = agents.len(), atomic = atomic)
+    )]
+    pub async fn deploy_fleet(
+        &self,
+        agents: Vec<Agent>,
+        environment: &str,
+        region: &str,
+        provider: DeploymentProvider,
+        options: DeployOptions,
+        atomic: bool,
+    ) -> FleetDeployment {
+        let mut outcome = FleetDeployment::default();
+        let mut superseded = HashMap::new();
+        let mut agents = agents.into_iter();
+        
+        for agent in agents.by_ref() {
+            let name = agent.name().to_string();
+            let result = self
+                .deploy_and_supersede(agent, environment, region, provider.clone(), options.clone())
+                .await;
+            
+            match result {
+                Ok((deployment, previous)) => {
+                    if let Some(previous) = previous {
+                        superseded.insert(deployment.id.clone(), previous);
+                    }
+                    outcome.deployed.push(deployment);
+                }
+                Err(e) => {
+                    tracing::warn!(agent_id = %name, error = %e, "fleet agent failed to deploy");
+                    outcome.failed.push(FleetFailure { agent: name, reason: e.to_string() });
+                    if atomic {
+                        break;
+                    }
+                }
+            }
+        }
+        
+        if atomic && !outcome.failed.is_empty() {
+            outcome.skipped = agents.map(|agent| agent.name().to_string()).collect();
+            
+            for deployment in std::mem::take(&mut outcome.deployed) {
+                match self.destroy_deployment(&deployment.id, None).await {
+                    Ok(removed) => {
+                        // Only bring back what this deploy itself stopped
+                        if let Some(previous) = superseded.get(&removed.id) {
+                            self.restore_superseded(previous).await;
+                        }
+                        outcome.rolled_back.push(removed);
+                    }
+                    Err(e) => {
+                        tracing::warn!(deployment_id = %deployment.id, error = %e, "cannot remove fleet deployment");
+                        outcome.deployed.push(deployment);
+                    }
+                }
+            }
+        }
+        
+        outcome
+    }
+}
+
+impl Default for DeploymentManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// AWS deployment provider
@@ -292,3 +1499,340 @@ impl AzureDeploymentProvider {
         Ok(format!("https://{}.{}.bea-bot.azure.app", agent.name(), environment))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    async fn deploy(manager: &DeploymentManager, agent: &str) -> DeploymentConfig {
+        manager
+            .deploy_agent(Agent::new(agent), "production", "us-east-1", DeploymentProvider::AWS, DeployOptions::default())
+            .await
+            .unwrap()
+    }
+    
+    async fn status(manager: &DeploymentManager, deployment_id: &str) -> DeploymentStatus {
+        manager.get_deployment(deployment_id).await.unwrap().status
+    }
+    
+    /// Manager with seven active deployments, two of which are then stopped
+    async fn manager_with_deployments() -> DeploymentManager {
+        let manager = DeploymentManager::new();
+        for i in 0..7 {
+            let deployment = deploy(&manager, &format!("agent-{}", i)).await;
+            if i % 3 == 1 {
+                manager.stop_deployment(&deployment.id).await.unwrap();
+            }
+        }
+        manager
+    }
+    
+    #[tokio::test]
+    async fn pages_cover_every_match_once_in_order() {
+        let manager = manager_with_deployments().await;
+        let all: Vec<String> = manager
+            .list_deployments(DeploymentFilter::new().with_status(DeploymentStatus::Active))
+            .await
+            .deployments
+            .into_iter()
+            .map(|d| d.id)
+            .collect();
+        assert_eq!(all.len(), 5);
+        
+        let mut paged = Vec::new();
+        let mut sizes = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut filter = DeploymentFilter::new().with_status(DeploymentStatus::Active).with_limit(2);
+            if let Some(cursor) = &cursor {
+                filter = filter.with_cursor(cursor);
+            }
+            let page = manager.list_deployments(filter).await;
+            assert!(page.deployments.iter().all(|d| d.status == DeploymentStatus::Active));
+            sizes.push(page.deployments.len());
+            paged.extend(page.deployments.into_iter().map(|d| d.id));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(paged, all);
+    }
+    
+    #[tokio::test]
+    async fn exact_final_page_has_no_cursor() {
+        let manager = manager_with_deployments().await;
+        let page = manager
+            .list_deployments(DeploymentFilter::new().with_status(DeploymentStatus::Stopped).with_limit(2))
+            .await;
+        
+        assert_eq!(page.deployments.len(), 2);
+        assert!(page.next_cursor.is_none());
+    }
+    
+    #[tokio::test]
+    async fn unreadable_cursor_gives_an_empty_page() {
+        let manager = manager_with_deployments().await;
+        let page = manager
+            .list_deployments(DeploymentFilter::new().with_limit(2).with_cursor("not-a-cursor"))
+            .await;
+        
+        assert!(page.deployments.is_empty());
+        assert!(page.next_cursor.is_none());
+    }
+    
+    #[tokio::test]
+    async fn unknown_deployments_are_not_found() {
+        let manager = DeploymentManager::new();
+        
+        assert!(matches!(manager.stop_deployment("missing").await, Err(BeaError::DeploymentNotFound(id)) if id == "missing"));
+        assert!(matches!(manager.delete_deployment("missing").await, Err(BeaError::DeploymentNotFound(_))));
+        assert!(matches!(manager.destroy_deployment("missing", None).await, Err(BeaError::DeploymentNotFound(_))));
+    }
+    
+    #[tokio::test]
+    async fn new_versions_join_a_split_instead_of_replacing_it() {
+        let manager = DeploymentManager::new();
+        let v1 = deploy(&manager, "support").await;
+        manager.set_traffic_split("support", "production", vec![(v1.id.clone(), 100)]).await.unwrap();
+        
+        let v2 = deploy(&manager, "support").await;
+        assert_eq!(status(&manager, &v1.id).await, DeploymentStatus::Active);
+        assert!(manager.deployment_history("support", "production").await.is_empty());
+        
+        let canary = vec![(v1.id.clone(), 90), (v2.id.clone(), 10)];
+        manager.set_traffic_split("support", "production", canary.clone()).await.unwrap();
+        assert_eq!(manager.traffic_split("support", "production").await, Some(canary));
+    }
+    
+    #[tokio::test]
+    async fn routing_skips_split_deployments_that_are_not_active() {
+        let manager = DeploymentManager::new();
+        let v1 = deploy(&manager, "support").await;
+        manager.set_traffic_split("support", "production", vec![(v1.id.clone(), 100)]).await.unwrap();
+        let v2 = deploy(&manager, "support").await;
+        manager
+            .set_traffic_split("support", "production", vec![(v1.id.clone(), 90), (v2.id.clone(), 10)])
+            .await
+            .unwrap();
+        
+        manager.deployments.lock().await.get_mut(&v1.id).unwrap().status = DeploymentStatus::Failed;
+        
+        for _ in 0..50 {
+            assert_eq!(manager.route("support", "production").await, Some(v2.id.clone()));
+        }
+    }
+    
+    #[tokio::test]
+    async fn stopping_a_split_deployment_clears_the_split() {
+        let manager = DeploymentManager::new();
+        let v1 = deploy(&manager, "support").await;
+        manager.set_traffic_split("support", "production", vec![(v1.id.clone(), 100)]).await.unwrap();
+        let v2 = deploy(&manager, "support").await;
+        manager
+            .set_traffic_split("support", "production", vec![(v1.id.clone(), 50), (v2.id.clone(), 50)])
+            .await
+            .unwrap();
+        
+        manager.stop_deployment(&v2.id).await.unwrap();
+        
+        assert!(manager.traffic_split("support", "production").await.is_none());
+        for _ in 0..50 {
+            assert_eq!(manager.route("support", "production").await, Some(v1.id.clone()));
+        }
+    }
+    
+    async fn failing_fleet(manager: &DeploymentManager) -> FleetDeployment {
+        let agents = vec![Agent::new("support"), Agent::new("Not Valid")];
+        manager
+            .deploy_fleet(agents, "production", "us-east-1", DeploymentProvider::AWS, DeployOptions::default(), true)
+            .await
+    }
+    
+    #[tokio::test]
+    async fn failed_atomic_fleets_bring_back_what_they_superseded() {
+        let manager = DeploymentManager::new();
+        let previous = deploy(&manager, "support").await;
+        
+        let outcome = failing_fleet(&manager).await;
+        
+        assert!(outcome.deployed.is_empty());
+        assert_eq!(outcome.rolled_back.len(), 1);
+        assert_eq!(manager.active_deployment("support", "production").await.map(|d| d.id), Some(previous.id));
+        assert!(manager.deployment_history("support", "production").await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn failed_atomic_fleets_leave_stale_deployments_stopped() {
+        let manager = DeploymentManager::new();
+        let stale = deploy(&manager, "support").await;
+        let stopped = deploy(&manager, "support").await;
+        manager.stop_deployment(&stopped.id).await.unwrap();
+        
+        let outcome = failing_fleet(&manager).await;
+        
+        assert_eq!(outcome.rolled_back.len(), 1);
+        assert_eq!(status(&manager, &stale.id).await, DeploymentStatus::Stopped);
+        assert_eq!(status(&manager, &stopped.id).await, DeploymentStatus::Stopped);
+        assert!(manager.active_deployment("support", "production").await.is_none());
+        assert_eq!(manager.deployment_history("support", "production").await.len(), 1);
+    }
+    
+    #[tokio::test]
+    async fn rollback_restores_the_previous_deployment() {
+        let manager = DeploymentManager::new();
+        let v1 = deploy(&manager, "support").await;
+        let v2 = deploy(&manager, "support").await;
+        assert_eq!(status(&manager, &v1.id).await, DeploymentStatus::Stopped);
+        
+        let restored = manager.rollback("support", "production").await.unwrap();
+        
+        assert_eq!(restored.id, v1.id);
+        assert_eq!(status(&manager, &v1.id).await, DeploymentStatus::Active);
+        assert_eq!(status(&manager, &v2.id).await, DeploymentStatus::Stopped);
+        assert_eq!(manager.active_deployment("support", "production").await.map(|d| d.id), Some(v1.id));
+        assert!(manager.deployment_history("support", "production").await.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn rollback_without_history_fails() {
+        let manager = DeploymentManager::new();
+        let only = deploy(&manager, "support").await;
+        
+        let result = manager.rollback("support", "production").await;
+        
+        assert!(matches!(
+            result,
+            Err(BeaError::NoPreviousDeployment { agent_id, environment })
+                if agent_id == "support" && environment == "production"
+        ));
+        assert_eq!(status(&manager, &only.id).await, DeploymentStatus::Active);
+    }
+    
+    #[tokio::test]
+    async fn promoting_in_place_swaps_blue_for_green() {
+        let manager = DeploymentManager::new();
+        let blue = deploy(&manager, "support").await;
+        manager.set_traffic_split("support", "production", vec![(blue.id.clone(), 100)]).await.unwrap();
+        let green = deploy(&manager, "support").await;
+        manager
+            .set_traffic_split("support", "production", vec![(blue.id.clone(), 90), (green.id.clone(), 10)])
+            .await
+            .unwrap();
+        
+        let promoted = manager.promote_in_place(&green.id).await.unwrap();
+        
+        assert_eq!(promoted.id, green.id);
+        assert_eq!(status(&manager, &green.id).await, DeploymentStatus::Active);
+        assert_eq!(status(&manager, &blue.id).await, DeploymentStatus::Stopped);
+        assert!(manager.traffic_split("support", "production").await.is_none());
+        let history: Vec<String> = manager
+            .deployment_history("support", "production")
+            .await
+            .into_iter()
+            .map(|d| d.id)
+            .collect();
+        assert_eq!(history, vec![blue.id.clone()]);
+        
+        assert_eq!(manager.rollback("support", "production").await.unwrap().id, blue.id);
+        assert_eq!(status(&manager, &green.id).await, DeploymentStatus::Stopped);
+    }
+    
+    #[tokio::test]
+    async fn promoting_a_stopped_deployment_takes_it_out_of_the_history() {
+        let manager = DeploymentManager::new();
+        let v1 = deploy(&manager, "support").await;
+        let v2 = deploy(&manager, "support").await;
+        
+        manager.promote_in_place(&v1.id).await.unwrap();
+        
+        assert_eq!(status(&manager, &v1.id).await, DeploymentStatus::Active);
+        assert_eq!(status(&manager, &v2.id).await, DeploymentStatus::Stopped);
+        let history: Vec<String> = manager
+            .deployment_history("support", "production")
+            .await
+            .into_iter()
+            .map(|d| d.id)
+            .collect();
+        assert_eq!(history, vec![v2.id]);
+    }
+    
+    #[tokio::test]
+    async fn promoting_an_unknown_deployment_in_place_fails() {
+        let manager = DeploymentManager::new();
+        
+        assert!(matches!(
+            manager.promote_in_place("missing").await,
+            Err(BeaError::DeploymentNotFound(id)) if id == "missing"
+        ));
+    }
+    
+    #[tokio::test]
+    async fn webhook_events_are_signed_over_the_body() {
+        use hmac::{Hmac, Mac};
+        
+        let (sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().fallback(move |headers: axum::http::HeaderMap, body: axum::body::Bytes| {
+            let sender = sender.clone();
+            async move {
+                let signature = headers.get("X-Bea-Signature").and_then(|v| v.to_str().ok()).map(String::from);
+                let _ = sender.send((signature, body));
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        let manager = DeploymentManager::new().with_webhook(url, Some("hook-secret".to_string()));
+        let deployment = deploy(&manager, "support").await;
+        
+        let (signature, body) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"hook-secret").unwrap();
+        mac.update(&body);
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(signature, Some(expected));
+        
+        let event: DeploymentEvent = serde_json::from_slice(&body).unwrap();
+        assert_eq!(event.deployment_id, deployment.id);
+        assert_eq!(event.agent_id, "support");
+    }
+    
+    #[tokio::test]
+    async fn promoting_deploys_the_agent_to_the_other_environment() {
+        let manager = DeploymentManager::new();
+        let source = deploy(&manager, "support").await;
+        
+        let promoted = manager.promote(&source.id, "staging").await.unwrap();
+        
+        assert_ne!(promoted.id, source.id);
+        assert_eq!(promoted.agent_id, "support");
+        assert_eq!(promoted.environment.name(), "staging");
+        assert_eq!(promoted.region, source.region);
+        assert_eq!(promoted.promoted_from.as_deref(), Some(source.id.as_str()));
+        assert_eq!(manager.get_deployment(&promoted.id).await.unwrap().promoted_from, Some(source.id.clone()));
+        assert_eq!(status(&manager, &source.id).await, DeploymentStatus::Active);
+    }
+    
+    #[tokio::test]
+    async fn only_active_deployments_can_be_promoted() {
+        let manager = DeploymentManager::new();
+        let source = deploy(&manager, "support").await;
+        manager.stop_deployment(&source.id).await.unwrap();
+        
+        assert!(matches!(
+            manager.promote(&source.id, "staging").await,
+            Err(BeaError::InvalidDeploymentState { id, .. }) if id == source.id
+        ));
+        assert!(matches!(
+            manager.promote("missing", "staging").await,
+            Err(BeaError::DeploymentNotFound(id)) if id == "missing"
+        ));
+        assert!(manager.active_deployment("support", "staging").await.is_none());
+    }
+}
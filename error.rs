@@ -0,0 +1,261 @@
+use thiserror::Error;
+
+/// Errors returned by Bea Bot operations
+#[derive(Debug, Error)]
+pub enum BeaError {
+    /// No deployment exists with the given ID
+    #[error("Deployment not found: {0}")]
+    DeploymentNotFound(String),
+    
+    /// The deployment is not in a state that allows the operation
+    #[error("Deployment {id} is {status}, expected {expected}")]
+    InvalidDeploymentState {
+        id: String,
+        status: String,
+        expected: String,
+    },
+    
+    /// Deploying an agent failed
+    #[error("Deployment {id} failed: {reason}")]
+    DeploymentFailed { id: String, reason: String },
+    
+    /// The agent version is already registered
+    #[error("Agent {name} already has a version {version}")]
+    AgentVersionExists { name: String, version: String },
+    
+    /// The agent has too many requests in flight and queued
+    #[error("Agent {0} is too busy, try again later")]
+    AgentBusy(String),
+    
+    /// A tool definition could not be converted
+    #[error("Invalid tool schema: {0}")]
+    InvalidToolSchema(String),
+    
+    /// An I/O operation failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    
+    /// The token has consumed more than its token budget
+    #[error("Token {token_id} has exceeded its budget of {budget} tokens ({consumed} consumed)")]
+    QuotaExceeded {
+        token_id: String,
+        budget: u64,
+        consumed: u64,
+    },
+    
+    /// An uploaded file was rejected
+    #[error("File upload rejected: {0}")]
+    UploadRejected(String),
+    
+    /// An HTTP client could not be built or a request failed
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    
+    /// The tool choice does not match the request's tools
+    #[error("Invalid tool choice: {0}")]
+    InvalidToolChoice(String),
+    
+    /// Streaming was requested but the model's provider can't stream
+    #[error("Streaming is not supported for model {0}")]
+    StreamingUnsupported(String),
+    
+    /// A dataset could not be imported
+    #[error("Invalid dataset: {0}")]
+    InvalidDataset(String),
+    
+    /// No provider is registered for the model's prefix
+    #[error("Provider not found: {0}")]
+    ProviderNotFound(String),
+    
+    /// A provider's API returned an error
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+    
+    /// A response stream closed without producing a response
+    #[error("Stream ended without a response")]
+    EmptyStream,
+    
+    /// JSON could not be serialized or parsed
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    /// The token is not in the `bea_<type>_<jwt>` format
+    #[error("Invalid token format")]
+    InvalidTokenFormat,
+    
+    /// The token's signature or claims are invalid
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+    
+    /// The token is not in the token store
+    #[error("Token not found")]
+    TokenNotFound,
+    
+    /// The token has expired
+    #[error("Token expired")]
+    TokenExpired,
+    
+    /// There is no earlier deployment to roll back to
+    #[error("No previous deployment of {agent_id} in {environment}")]
+    NoPreviousDeployment { agent_id: String, environment: String },
+    
+    /// A content filter refused the text
+    #[error("Content blocked: {0}")]
+    ContentBlocked(String),
+    
+    /// The request was cancelled before it finished
+    #[error("Request cancelled")]
+    Cancelled,
+    
+    /// The traffic split can't be applied
+    #[error("Invalid traffic split: {0}")]
+    InvalidTrafficSplit(String),
+    
+    /// The agent definition failed validation
+    #[error("Invalid agent: {}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+    InvalidAgent(Vec<ValidationError>),
+    
+    /// The token's prefix names a different type than its JWT `type` claim
+    #[error("Token prefix is for {prefix} tokens but the token claims to be {claim}")]
+    InconsistentTokenType { prefix: String, claim: String },
+    
+    /// The embedding can't be stored in the vector store
+    #[error("Invalid embedding: {0}")]
+    InvalidEmbedding(String),
+    
+    /// The caller lacks permissions the tool requires
+    #[error("Permission denied for tool {tool}: missing {}", .missing.join(", "))]
+    PermissionDenied { tool: String, missing: Vec<String> },
+    
+    /// No agent is registered with the given name
+    #[error("Agent not found: {0}")]
+    AgentNotFound(String),
+    
+    /// The site's authentication settings are incomplete
+    #[error("Invalid site auth config: {0}")]
+    InvalidAuthConfig(String),
+    
+    /// The request didn't include a bearer token
+    #[error("Missing bearer token")]
+    MissingToken,
+    
+    /// An agent spec file could not be read or written
+    #[error("Invalid agent config: {0}")]
+    InvalidConfig(String),
+}
+
+/// Error returned by a model provider's API
+///
+/// Built from the provider's JSON error body when there is one, so the
+/// provider's own explanation reaches the caller.
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    /// HTTP status code
+    pub status: u16,
+    /// Provider error code or type, e.g. `invalid_request_error`
+    pub code: Option<String>,
+    /// Provider error message
+    pub message: String,
+    /// `X-Request-Id` sent with the failed request
+    pub request_id: Option<String>,
+}
+
+impl ProviderError {
+    /// Parse a provider error response body
+    ///
+    /// Understands Anthropic's `{"error": {"type", "message"}}` and OpenAI's
+    /// `{"error": {"message", "code"}}`. Bodies that aren't JSON are used as
+    /// the message verbatim.
+    pub fn from_body(status: u16, body: &str) -> Self {
+        let error = serde_json::from_str::<serde_json::Value>(body)
+            .ok()
+            .and_then(|json| json.get("error").cloned());
+        
+        match error {
+            Some(error) => {
+                let message = error.get("message")
+                    .and_then(|m| m.as_str())
+                    .or_else(|| error.as_str())
+                    .unwrap_or(body)
+                    .to_string();
+                
+                let code = ["code", "type"]
+                    .iter()
+                    .find_map(|key| match error.get(*key) {
+                        Some(serde_json::Value::String(s)) => Some(s.clone()),
+                        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+                        _ => None,
+                    });
+                
+                Self { status, code, message, request_id: None }
+            }
+            None => Self {
+                status,
+                code: None,
+                message: if body.trim().is_empty() {
+                    "request failed".to_string()
+                } else {
+                    body.trim().to_string()
+                },
+                request_id: None,
+            },
+        }
+    }
+}
+
+impl ProviderError {
+    /// Attach the request ID sent with the failed request
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "Provider error ({} {}): {}", self.status, code, self.message)?,
+            None => write!(f, "Provider error ({}): {}", self.status, self.message)?,
+        }
+        
+        if let Some(request_id) = &self.request_id {
+            write!(f, " [request {}]", request_id)?;
+        }
+        
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// Problem found when validating an agent definition
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ValidationError {
+    /// The agent has no name
+    #[error("agent name is empty")]
+    EmptyName,
+    
+    /// The agent name can't be used as a DNS label
+    #[error("agent name {0:?} must be lowercase letters, digits and hyphens")]
+    InvalidName(String),
+    
+    /// The performance tier is not one we know
+    #[error("unknown performance tier {0:?}, expected standard, high or ultra")]
+    UnknownPerformanceTier(String),
+    
+    /// The model string doesn't say which provider serves it
+    #[error("model {0:?} has no provider prefix, e.g. \"anthropic/\"")]
+    MissingProviderPrefix(String),
+    
+    /// The sampling temperature is outside 0.0..=2.0
+    #[error("temperature {0} must be between 0.0 and 2.0")]
+    TemperatureOutOfRange(f32),
+    
+    /// The nucleus sampling probability is outside 0.0..=1.0
+    #[error("top_p {0} must be between 0.0 and 1.0")]
+    TopPOutOfRange(f32),
+    
+    /// The token limit would stop the model producing anything
+    #[error("max_tokens must be at least 1")]
+    ZeroMaxTokens,
+}
@@ -0,0 +1,331 @@
+use std::sync::Arc;
+use regex::{Captures, Regex};
+
+use crate::BeaError;
+use crate::models::{Message, MessageRole, ModelResponse};
+
+/// What a content filter wants done with a piece of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Pass the text through unchanged
+    Allow,
+    /// Replace the text with this one
+    Redact(String),
+    /// Refuse the text, for this reason
+    Block(String),
+}
+
+/// Inspects text going to and coming from a model
+///
+/// Input is the user's text before it is sent; output is the model's reply
+/// before it is returned. The default for both is to allow everything.
+pub trait ContentFilter: Send + Sync {
+    /// Inspect user text before it is sent to the model
+    fn inspect_input(&self, text: &str) -> FilterDecision {
+        let _ = text;
+        FilterDecision::Allow
+    }
+    
+    /// Inspect model output before it is returned
+    fn inspect_output(&self, text: &str) -> FilterDecision {
+        let _ = text;
+        FilterDecision::Allow
+    }
+}
+
+/// Redacts text matching a set of regular expressions
+///
+/// `new` covers email addresses and card numbers: runs of 13 to 19 digits,
+/// which may be separated by spaces or hyphens, that start like a card
+/// network's numbers and pass the Luhn check. Input and output are redacted
+/// alike.
+#[derive(Debug, Clone)]
+pub struct RegexRedactionFilter {
+    patterns: Vec<Redaction>,
+}
+
+/// Pattern replaced by a `RegexRedactionFilter`
+#[derive(Debug, Clone)]
+struct Redaction {
+    regex: Regex,
+    replacement: String,
+    /// Further check a match must pass to be replaced
+    check: Option<fn(&str) -> bool>,
+}
+
+/// Whether digits read like a card number
+///
+/// Card numbers start with 2 to 6, unlike order ids and millisecond
+/// timestamps of the same length, and end in a Luhn check digit.
+fn is_card_number(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !matches!(digits.first(), Some(2..=6)) {
+        return false;
+    }
+    
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+impl RegexRedactionFilter {
+    /// Create a filter redacting emails and card numbers
+    pub fn new() -> Self {
+        Self {
+            patterns: vec![
+                Redaction {
+                    regex: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").expect("valid email pattern"),
+                    replacement: "[REDACTED EMAIL]".to_string(),
+                    check: None,
+                },
+                Redaction {
+                    regex: Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").expect("valid card pattern"),
+                    replacement: "[REDACTED CARD]".to_string(),
+                    check: Some(is_card_number),
+                },
+            ],
+        }
+    }
+    
+    /// Create a filter with no patterns
+    pub fn empty() -> Self {
+        Self { patterns: Vec::new() }
+    }
+    
+    /// Also replace matches of `pattern` with `replacement`
+    pub fn with_pattern(mut self, pattern: &str, replacement: &str) -> Result<Self, BeaError> {
+        let regex = Regex::new(pattern)
+            .map_err(|e| BeaError::InvalidConfig(format!("redaction pattern {:?}: {}", pattern, e)))?;
+        self.patterns.push(Redaction {
+            regex,
+            replacement: replacement.to_string(),
+            check: None,
+        });
+        Ok(self)
+    }
+    
+    /// Apply every pattern, redacting if anything matched
+    fn redact(&self, text: &str) -> FilterDecision {
+        let mut redacted = text.to_string();
+        for Redaction { regex, replacement, check } in &self.patterns {
+            redacted = regex
+                .replace_all(&redacted, |captures: &Captures| match check {
+                    Some(check) if !check(&captures[0]) => captures[0].to_string(),
+                    _ => replacement.clone(),
+                })
+                .into_owned();
+        }
+        
+        if redacted == text {
+            FilterDecision::Allow
+        } else {
+            FilterDecision::Redact(redacted)
+        }
+    }
+}
+
+impl Default for RegexRedactionFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentFilter for RegexRedactionFilter {
+    fn inspect_input(&self, text: &str) -> FilterDecision {
+        self.redact(text)
+    }
+    
+    fn inspect_output(&self, text: &str) -> FilterDecision {
+        self.redact(text)
+    }
+}
+
+/// Content filters applied one after another
+///
+/// Each filter sees the text as left by the ones before it. The first
+/// filter to block stops the chain with `BeaError::ContentBlocked`.
+#[derive(Clone, Default)]
+pub struct FilterChain {
+    filters: Vec<Arc<dyn ContentFilter>>,
+}
+
+impl FilterChain {
+    /// Create an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Add a filter to the end of the chain
+    pub fn with_filter(mut self, filter: Arc<dyn ContentFilter>) -> Self {
+        self.filters.push(filter);
+        self
+    }
+    
+    /// Whether the chain has no filters
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+    
+    /// Run user text through the chain's input filters
+    pub fn filter_input(&self, text: &str) -> Result<String, BeaError> {
+        self.run(text, |filter, text| filter.inspect_input(text))
+    }
+    
+    /// Run model output through the chain's output filters
+    pub fn filter_output(&self, text: &str) -> Result<String, BeaError> {
+        self.run(text, |filter, text| filter.inspect_output(text))
+    }
+    
+    /// Filter the user messages in a request
+    ///
+    /// System, assistant and tool messages aren't user input and are left
+    /// alone.
+    pub fn filter_messages(&self, messages: &mut [Message]) -> Result<(), BeaError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        
+        for message in messages.iter_mut().filter(|m| matches!(m.role(), MessageRole::User)) {
+            let filtered = self.filter_input(message.content())?;
+            message.set_content(filtered);
+        }
+        Ok(())
+    }
+    
+    /// Filter the message in a model response
+    pub fn filter_response(&self, response: &mut ModelResponse) -> Result<(), BeaError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        
+        let message = response.message_mut();
+        let filtered = self.filter_output(message.content())?;
+        message.set_content(filtered);
+        Ok(())
+    }
+    
+    /// Run text through every filter with `inspect`
+    fn run(
+        &self,
+        text: &str,
+        inspect: impl Fn(&dyn ContentFilter, &str) -> FilterDecision,
+    ) -> Result<String, BeaError> {
+        let mut text = text.to_string();
+        for filter in &self.filters {
+            match inspect(filter.as_ref(), &text) {
+                FilterDecision::Allow => {}
+                FilterDecision::Redact(redacted) => text = redacted,
+                FilterDecision::Block(reason) => return Err(BeaError::ContentBlocked(reason)),
+            }
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Filter that blocks text containing a word
+    struct BlockWord(&'static str);
+    
+    impl ContentFilter for BlockWord {
+        fn inspect_input(&self, text: &str) -> FilterDecision {
+            if text.contains(self.0) {
+                FilterDecision::Block(format!("mentions {}", self.0))
+            } else {
+                FilterDecision::Allow
+            }
+        }
+    }
+    
+    #[test]
+    fn emails_and_card_numbers_are_redacted() {
+        let filter = RegexRedactionFilter::new();
+        
+        let decision = filter.inspect_input("Mail ada@example.com, card 4111 1111 1111 1111 or 5500-0000-0000-0004.");
+        assert_eq!(
+            decision,
+            FilterDecision::Redact("Mail [REDACTED EMAIL], card [REDACTED CARD] or [REDACTED CARD].".to_string())
+        );
+        assert_eq!(
+            filter.inspect_output("Amex 378282246310005"),
+            FilterDecision::Redact("Amex [REDACTED CARD]".to_string())
+        );
+    }
+    
+    #[test]
+    fn numbers_that_are_not_cards_are_left_alone() {
+        let filter = RegexRedactionFilter::new();
+        
+        for text in [
+            // Millisecond timestamp, even one that passes the Luhn check
+            "Created at 1718000000000",
+            "Created at 1718000000006",
+            // Card-like but fails the Luhn check
+            "Order 4111 1111 1111 1112",
+            // Passes the Luhn check but no card starts with 9
+            "Tracking 9000000000001",
+            // Too short to be a card
+            "Call 555 0100 200",
+            "Nothing to see here",
+        ] {
+            assert_eq!(filter.inspect_input(text), FilterDecision::Allow, "{}", text);
+        }
+    }
+    
+    #[test]
+    fn custom_patterns_are_applied_after_the_defaults() {
+        let filter = RegexRedactionFilter::empty().with_pattern(r"ACME-\d+", "[ACCOUNT]").unwrap();
+        assert_eq!(
+            filter.inspect_input("Account ACME-42, mail ada@example.com"),
+            FilterDecision::Redact("Account [ACCOUNT], mail ada@example.com".to_string())
+        );
+        
+        assert!(matches!(
+            RegexRedactionFilter::empty().with_pattern("(", "x"),
+            Err(BeaError::InvalidConfig(_))
+        ));
+    }
+    
+    #[test]
+    fn chains_run_filters_in_order() {
+        // The block sees the text as already redacted, so the address no longer trips it
+        let redact_first = FilterChain::new()
+            .with_filter(Arc::new(RegexRedactionFilter::new()))
+            .with_filter(Arc::new(BlockWord("@example.com")));
+        assert_eq!(
+            redact_first.filter_input("Write to ada@example.com").unwrap(),
+            "Write to [REDACTED EMAIL]"
+        );
+        
+        let block_first = FilterChain::new()
+            .with_filter(Arc::new(BlockWord("@example.com")))
+            .with_filter(Arc::new(RegexRedactionFilter::new()));
+        assert!(matches!(
+            block_first.filter_input("Write to ada@example.com"),
+            Err(BeaError::ContentBlocked(reason)) if reason == "mentions @example.com"
+        ));
+        
+        // Output filters only redact, since the block only inspects input
+        assert_eq!(block_first.filter_output("Write to ada@example.com").unwrap(), "Write to [REDACTED EMAIL]");
+    }
+    
+    #[test]
+    fn only_user_messages_are_filtered() {
+        let chain = FilterChain::new().with_filter(Arc::new(RegexRedactionFilter::new()));
+        let mut messages = vec![Message::system("Escalate to ops@example.com"), Message::user("I'm ada@example.com")];
+        
+        chain.filter_messages(&mut messages).unwrap();
+        assert_eq!(messages[0].content(), "Escalate to ops@example.com");
+        assert_eq!(messages[1].content(), "I'm [REDACTED EMAIL]");
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::BeaError;
+
+/// Default `User-Agent` sent with every request
+pub const DEFAULT_USER_AGENT: &str = concat!("bea-bot/", env!("CARGO_PKG_VERSION"));
+
+/// Default time allowed to establish a connection
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builder for the HTTP clients used for outbound requests
+///
+/// Providers and HTTP-based tools build their clients here so proxy and
+/// timeout settings are the same everywhere. Pass the result to a provider's
+/// `with_client` to use non-default settings. Without an explicit proxy the
+/// standard `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables
+/// are honored.
+#[derive(Debug, Clone)]
+pub struct HttpClientBuilder {
+    proxy: Option<String>,
+    timeout: Option<Duration>,
+    connect_timeout: Duration,
+    user_agent: String,
+}
+
+impl Default for HttpClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpClientBuilder {
+    /// Create a new builder with the default settings
+    pub fn new() -> Self {
+        Self {
+            proxy: None,
+            timeout: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+    
+    /// Send all requests through the given proxy
+    ///
+    /// Hosts listed in `NO_PROXY` still bypass it.
+    pub fn with_proxy(mut self, url: &str) -> Self {
+        self.proxy = Some(url.to_string());
+        self
+    }
+    
+    /// Set the overall timeout for each request
+    ///
+    /// This covers reading the whole response body, so leave it unset for
+    /// clients used with long streamed responses.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Set the timeout for establishing a connection
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+    
+    /// Set the `User-Agent` header sent with every request
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+    
+    /// Build the client
+    pub fn build(&self) -> Result<reqwest::Client, BeaError> {
+        Ok(self.client_builder()?.build()?)
+    }
+    
+    /// Get a reqwest builder with these settings applied
+    ///
+    /// For callers that need to adjust the client further, such as turning
+    /// off redirects, before building it.
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder, BeaError> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .user_agent(self.user_agent.as_str());
+        
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy.as_str())?.no_proxy(reqwest::NoProxy::from_env());
+            builder = builder.proxy(proxy);
+        }
+        
+        Ok(builder)
+    }
+}
+
+/// Get the shared client built with the default settings
+///
+/// Clones share one connection pool, so providers created with `new` reuse
+/// connections instead of each opening their own. Falls back to reqwest's
+/// defaults if the client can't be configured.
+pub fn client() -> reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    
+    CLIENT
+        .get_or_init(|| {
+            HttpClientBuilder::new()
+                .build()
+                .unwrap_or_else(|_| reqwest::Client::new())
+        })
+        .clone()
+}
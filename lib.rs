@@ -1,14 +1,59 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 
+use crate::models::TokenUsage;
+
+pub mod clock;
 pub mod config;
+pub mod conversation;
+pub mod dataset;
+pub mod error;
+pub mod filters;
+pub mod http;
+pub mod logs;
+pub mod metrics;
 pub mod models;
+pub mod rate_limiter;
+pub mod runtime;
 pub mod tokens;
 pub mod sites;
 pub mod deploy;
 pub mod tools;
+#[path = "aws/vector_store.rs"]
+pub mod vector_store;
+
+pub use error::{BeaError, ProviderError, ValidationError};
+
+/// Install a tracing subscriber that writes to stderr, for binaries
+///
+/// `RUST_LOG` picks what is shown, with the same `target=level` directives
+/// as `env_logger`, e.g. `bea_bot=debug,info`. Without it only warnings
+/// and errors are shown. Spans never carry token strings or API keys. Does
+/// nothing if a subscriber is already installed.
+pub fn init_tracing() {
+    use std::io::IsTerminal;
+    use tracing_subscriber::filter::{LevelFilter, Targets};
+    use tracing_subscriber::prelude::*;
+
+    let filter = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|directives| directives.parse::<Targets>().ok())
+        .unwrap_or_else(|| Targets::new().with_default(LevelFilter::WARN));
+
+    let _ = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_ansi(std::io::stderr().is_terminal()),
+        )
+        .with(filter)
+        .try_init();
+}
 
 /// Represents an AI agent with configurable parameters
 #[derive(Debug, Clone)]
@@ -16,25 +61,46 @@ pub struct Agent {
     name: String,
     model: String,
     memory_enabled: bool,
-    context: String,
+    system_blocks: Vec<String>,
     tools: Vec<Tool>,
     dataset_path: Option<String>,
     performance_tier: String,
     parameters: HashMap<String, String>,
+    version: Option<String>,
+    model_overrides: HashMap<String, String>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
 }
 
 impl Agent {
     /// Create a new agent with the specified name
+    ///
+    /// This and the `with_*` methods are the unchecked way to configure an
+    /// agent: nothing is validated until `validate` is called. Prefer
+    /// `Agent::builder`, whose `build` won't return an invalid agent.
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
             model: "anthropic/claude-3-haiku".to_string(),
             memory_enabled: false,
-            context: "You are a helpful assistant.".to_string(),
+            system_blocks: vec!["You are a helpful assistant.".to_string()],
             tools: Vec::new(),
             dataset_path: None,
             performance_tier: "standard".to_string(),
             parameters: HashMap::new(),
+            version: None,
+            model_overrides: HashMap::new(),
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+        }
+    }
+
+    /// Start configuring an agent that is validated when it's built
+    pub fn builder(name: &str) -> AgentBuilder {
+        AgentBuilder {
+            agent: Agent::new(name),
         }
     }
 
@@ -44,6 +110,12 @@ impl Agent {
         self
     }
 
+    /// Use a different model when deployed to the given environment
+    pub fn with_model_for_env(mut self, environment: &str, model: &str) -> Self {
+        self.model_overrides.insert(environment.to_lowercase(), model.to_string());
+        self
+    }
+
     /// Enable or disable agent memory
     pub fn with_memory(mut self, enabled: bool) -> Self {
         self.memory_enabled = enabled;
@@ -52,7 +124,17 @@ impl Agent {
 
     /// Set the context/system prompt for the agent
     pub fn with_context(mut self, context: &str) -> Self {
-        self.context = context.to_string();
+        self.system_blocks = vec![context.to_string()];
+        self
+    }
+
+    /// Set the system prompt as several ordered blocks
+    ///
+    /// Blocks are kept separate so providers can cache the static ones
+    /// independently, and are joined with blank lines wherever a single
+    /// prompt is needed.
+    pub fn with_system_blocks(mut self, blocks: Vec<&str>) -> Self {
+        self.system_blocks = blocks.into_iter().map(|b| b.to_string()).collect();
         self
     }
 
@@ -80,6 +162,30 @@ impl Agent {
         self
     }
 
+    /// Set the version of this agent definition
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Set the sampling temperature, between 0.0 and 2.0
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the nucleus sampling probability, between 0.0 and 1.0
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Limit the number of tokens generated per response
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
     /// Get the agent's name
     pub fn name(&self) -> &str {
         &self.name
@@ -89,10 +195,460 @@ impl Agent {
     pub fn model(&self) -> &str {
         &self.model
     }
+
+    /// Get the model to use in the given environment
+    ///
+    /// Falls back to the agent's base model when no override is set.
+    pub fn model_for_env(&self, environment: &str) -> &str {
+        self.model_overrides
+            .get(&environment.to_lowercase())
+            .map(|m| m.as_str())
+            .unwrap_or(&self.model)
+    }
+
+    /// Get the agent's context/system prompt
+    pub fn context(&self) -> String {
+        self.system_blocks.join("\n\n")
+    }
+
+    /// Get the agent's system prompt blocks, in order
+    pub fn system_blocks(&self) -> &[String] {
+        &self.system_blocks
+    }
+
+    /// Whether the agent has memory enabled
+    pub fn memory_enabled(&self) -> bool {
+        self.memory_enabled
+    }
+
+    /// Get the agent's tools
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    /// Get the agent's dataset path
+    pub fn dataset_path(&self) -> Option<&str> {
+        self.dataset_path.as_deref()
+    }
+
+    /// Get the agent's performance tier
+    pub fn performance_tier(&self) -> &str {
+        &self.performance_tier
+    }
+
+    /// Get the agent's custom parameters
+    pub fn parameters(&self) -> &HashMap<String, String> {
+        &self.parameters
+    }
+
+    /// Get the agent's version
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Get the agent's sampling temperature, if set
+    pub fn temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    /// Get the agent's nucleus sampling probability, if set
+    pub fn top_p(&self) -> Option<f32> {
+        self.top_p
+    }
+
+    /// Get the agent's per-response token limit, if set
+    pub fn max_tokens(&self) -> Option<u32> {
+        self.max_tokens
+    }
+
+    /// Create a new agent from this one with the given overrides applied
+    ///
+    /// Every field set in `overrides` replaces the base value; unset fields
+    /// keep it. Tools are unioned by name, with an override tool replacing a
+    /// base tool of the same name. Parameters and per-environment models are
+    /// merged key by key, with override values winning.
+    pub fn apply_overrides(&self, overrides: &AgentOverrides) -> Agent {
+        let mut agent = self.clone();
+
+        if let Some(model) = &overrides.model {
+            agent.model = model.clone();
+        }
+        if let Some(memory_enabled) = overrides.memory_enabled {
+            agent.memory_enabled = memory_enabled;
+        }
+        if let Some(context) = &overrides.context {
+            agent.system_blocks = vec![context.clone()];
+        }
+        if let Some(dataset_path) = &overrides.dataset_path {
+            agent.dataset_path = Some(dataset_path.clone());
+        }
+        if let Some(performance_tier) = &overrides.performance_tier {
+            agent.performance_tier = performance_tier.clone();
+        }
+        if let Some(version) = &overrides.version {
+            agent.version = Some(version.clone());
+        }
+        if let Some(temperature) = overrides.temperature {
+            agent.temperature = Some(temperature);
+        }
+        if let Some(top_p) = overrides.top_p {
+            agent.top_p = Some(top_p);
+        }
+        if let Some(max_tokens) = overrides.max_tokens {
+            agent.max_tokens = Some(max_tokens);
+        }
+
+        for tool in &overrides.tools {
+            match agent.tools.iter_mut().find(|t| t.name == tool.name) {
+                Some(existing) => *existing = tool.clone(),
+                None => agent.tools.push(tool.clone()),
+            }
+        }
+
+        agent.parameters.extend(overrides.parameters.clone());
+        agent.model_overrides.extend(
+            overrides.model_overrides
+                .iter()
+                .map(|(env, model)| (env.to_lowercase(), model.clone())),
+        );
+
+        agent
+    }
+
+    /// Check that the agent can be deployed
+    ///
+    /// The name becomes part of the deployment's subdomain, so it must be a
+    /// valid DNS label. All problems are collected rather than stopping at
+    /// the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push(ValidationError::EmptyName);
+        } else if !self.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            || self.name.starts_with('-')
+            || self.name.ends_with('-')
+            || self.name.len() > 63
+        {
+            errors.push(ValidationError::InvalidName(self.name.clone()));
+        }
+
+        if !matches!(self.performance_tier.as_str(), "standard" | "high" | "ultra") {
+            errors.push(ValidationError::UnknownPerformanceTier(self.performance_tier.clone()));
+        }
+
+        match self.model.split_once('/') {
+            Some((provider, model)) if !provider.is_empty() && !model.is_empty() => {}
+            _ => errors.push(ValidationError::MissingProviderPrefix(self.model.clone())),
+        }
+
+        if let Some(temperature) = self.temperature.filter(|t| !(0.0..=2.0).contains(t)) {
+            errors.push(ValidationError::TemperatureOutOfRange(temperature));
+        }
+        if let Some(top_p) = self.top_p.filter(|p| !(0.0..=1.0).contains(p)) {
+            errors.push(ValidationError::TopPOutOfRange(top_p));
+        }
+        if self.max_tokens == Some(0) {
+            errors.push(ValidationError::ZeroMaxTokens);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Get a serializable copy of the agent's full configuration
+    pub fn to_config(&self) -> AgentConfig {
+        AgentConfig {
+            name: self.name.clone(),
+            model: self.model.clone(),
+            memory_enabled: self.memory_enabled,
+            system_blocks: self.system_blocks.clone(),
+            tools: self.tools.clone(),
+            dataset_path: self.dataset_path.clone(),
+            performance_tier: self.performance_tier.clone(),
+            parameters: self.parameters.clone(),
+            version: self.version.clone(),
+            model_overrides: self.model_overrides.clone(),
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+        }
+    }
+
+    /// Rebuild an agent from a configuration produced by `to_config`
+    pub fn from_config(config: AgentConfig) -> Self {
+        Self {
+            name: config.name,
+            model: config.model,
+            memory_enabled: config.memory_enabled,
+            system_blocks: config.system_blocks,
+            tools: config.tools,
+            dataset_path: config.dataset_path,
+            performance_tier: config.performance_tier,
+            parameters: config.parameters,
+            version: config.version,
+            model_overrides: config.model_overrides,
+            temperature: config.temperature,
+            top_p: config.top_p,
+            max_tokens: config.max_tokens,
+        }
+    }
 }
 
-/// Represents a tool that can be used by an agent
+/// Builder for an `Agent` that validates it once configuration is done
+///
+/// Created with `Agent::builder`. The defaults are the same as `Agent::new`.
 #[derive(Debug, Clone)]
+pub struct AgentBuilder {
+    agent: Agent,
+}
+
+impl AgentBuilder {
+    /// Set the AI model
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.agent = self.agent.with_model(model);
+        self
+    }
+
+    /// Use a different model when deployed to the given environment
+    pub fn with_model_for_env(mut self, environment: &str, model: &str) -> Self {
+        self.agent = self.agent.with_model_for_env(environment, model);
+        self
+    }
+
+    /// Enable or disable agent memory
+    pub fn with_memory(mut self, enabled: bool) -> Self {
+        self.agent = self.agent.with_memory(enabled);
+        self
+    }
+
+    /// Set the context/system prompt
+    pub fn with_context(mut self, context: &str) -> Self {
+        self.agent = self.agent.with_context(context);
+        self
+    }
+
+    /// Set the system prompt as several ordered blocks
+    pub fn with_system_blocks(mut self, blocks: Vec<&str>) -> Self {
+        self.agent = self.agent.with_system_blocks(blocks);
+        self
+    }
+
+    /// Set the agent's tools
+    pub fn with_tools(mut self, tools: Vec<Tool>) -> Self {
+        self.agent = self.agent.with_tools(tools);
+        self
+    }
+
+    /// Add a dataset for the agent to use
+    pub fn with_dataset(mut self, path: &str) -> Self {
+        self.agent = self.agent.with_dataset(path);
+        self
+    }
+
+    /// Set the performance tier
+    pub fn with_performance_tier(mut self, tier: &str) -> Self {
+        self.agent = self.agent.with_performance_tier(tier);
+        self
+    }
+
+    /// Set a custom parameter
+    pub fn with_parameter(mut self, key: &str, value: &str) -> Self {
+        self.agent = self.agent.with_parameter(key, value);
+        self
+    }
+
+    /// Set the version of the agent definition
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.agent = self.agent.with_version(version);
+        self
+    }
+
+    /// Set the sampling temperature, between 0.0 and 2.0
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.agent = self.agent.with_temperature(temperature);
+        self
+    }
+
+    /// Set the nucleus sampling probability, between 0.0 and 1.0
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.agent = self.agent.with_top_p(top_p);
+        self
+    }
+
+    /// Limit the number of tokens generated per response
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.agent = self.agent.with_max_tokens(max_tokens);
+        self
+    }
+
+    /// Finish configuring the agent
+    ///
+    /// Fails with every problem `Agent::validate` finds.
+    pub fn build(self) -> Result<Agent, Vec<ValidationError>> {
+        self.agent.validate()?;
+        Ok(self.agent)
+    }
+}
+
+/// Serializable form of an agent, for persisting and inspecting agents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    /// Agent name
+    pub name: String,
+    /// Model used by default
+    pub model: String,
+    /// Whether memory is enabled
+    #[serde(default, alias = "memory")]
+    pub memory_enabled: bool,
+    /// System prompt blocks, in order
+    #[serde(default, alias = "context")]
+    pub system_blocks: Vec<String>,
+    /// Tools available to the agent
+    #[serde(default)]
+    pub tools: Vec<Tool>,
+    /// Dataset path
+    #[serde(default, alias = "dataset")]
+    pub dataset_path: Option<String>,
+    /// Performance tier
+    #[serde(default = "default_performance_tier")]
+    pub performance_tier: String,
+    /// Custom parameters
+    #[serde(default)]
+    pub parameters: HashMap<String, String>,
+    /// Version of the agent definition
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Per-environment models
+    #[serde(default)]
+    pub model_overrides: HashMap<String, String>,
+    /// Sampling temperature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Per-response token limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+fn default_performance_tier() -> String {
+    "standard".to_string()
+}
+
+/// Overrides layered on top of a base agent with `Agent::apply_overrides`
+///
+/// Fields left as `None` (or empty, for the collections) keep the base
+/// agent's value, so there is no ambiguity about which values were set.
+#[derive(Debug, Clone, Default)]
+pub struct AgentOverrides {
+    /// Model to use instead of the base model
+    pub model: Option<String>,
+    /// Whether memory is enabled
+    pub memory_enabled: Option<bool>,
+    /// Context/system prompt
+    pub context: Option<String>,
+    /// Tools to add to, or replace in, the base agent's tools
+    pub tools: Vec<Tool>,
+    /// Dataset path
+    pub dataset_path: Option<String>,
+    /// Performance tier
+    pub performance_tier: Option<String>,
+    /// Parameters merged over the base parameters
+    pub parameters: HashMap<String, String>,
+    /// Per-environment models merged over the base overrides
+    pub model_overrides: HashMap<String, String>,
+    /// Version of the resulting agent definition
+    pub version: Option<String>,
+    /// Sampling temperature
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability
+    pub top_p: Option<f32>,
+    /// Per-response token limit
+    pub max_tokens: Option<u32>,
+}
+
+/// Registry of agent definitions with their version history
+#[derive(Debug)]
+pub struct AgentRegistry {
+    versions: Arc<Mutex<HashMap<String, Vec<Agent>>>>,
+}
+
+impl AgentRegistry {
+    /// Create a new agent registry
+    pub fn new() -> Self {
+        Self {
+            versions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Register a new version of an agent, returning its version
+    ///
+    /// Agents without an explicit version are numbered sequentially
+    /// ("1", "2", ...). Registering a version that already exists fails.
+    pub async fn register_version(&self, agent: Agent) -> Result<String, BeaError> {
+        let mut versions = self.versions.lock().await;
+        let history = versions.entry(agent.name().to_string()).or_default();
+
+        let version = agent
+            .version
+            .clone()
+            .unwrap_or_else(|| (history.len() + 1).to_string());
+
+        if history.iter().any(|a| a.version() == Some(version.as_str())) {
+            return Err(BeaError::AgentVersionExists {
+                name: agent.name().to_string(),
+                version,
+            });
+        }
+
+        history.push(agent.with_version(&version));
+        Ok(version)
+    }
+
+    /// Get a specific version of an agent
+    pub async fn get_version(&self, name: &str, version: &str) -> Option<Agent> {
+        let versions = self.versions.lock().await;
+        versions
+            .get(name)?
+            .iter()
+            .find(|a| a.version() == Some(version))
+            .cloned()
+    }
+
+    /// Get the most recently registered version of an agent
+    pub async fn latest(&self, name: &str) -> Option<Agent> {
+        let versions = self.versions.lock().await;
+        versions.get(name)?.last().cloned()
+    }
+
+    /// List the versions of an agent, oldest first
+    pub async fn list_versions(&self, name: &str) -> Vec<String> {
+        let versions = self.versions.lock().await;
+        versions
+            .get(name)
+            .map(|history| {
+                history
+                    .iter()
+                    .filter_map(|a| a.version.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+impl Default for AgentRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents a tool that can be used by an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     name: String,
     description: Option<String>,
@@ -126,6 +682,16 @@ impl Tool {
 #[derive(Debug, Clone)]
 pub struct TokenManager {
     tokens: HashMap<String, String>,
+    info: HashMap<String, TokenInfo>,
+}
+
+/// Audit details recorded when a token is generated
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// Creation time
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Scopes granted to the token
+    pub scopes: Vec<String>,
 }
 
 impl TokenManager {
@@ -133,11 +699,17 @@ impl TokenManager {
     pub fn new() -> Self {
         Self {
             tokens: HashMap::new(),
+            info: HashMap::new(),
         }
     }
 
     /// Generate a new token for the specified environment
-    pub fn generate_token(mut self, environment: &str) -> Self {
+    pub fn generate_token(self, environment: &str) -> Self {
+        self.generate_token_with_scopes(environment, Vec::new())
+    }
+
+    /// Generate a new token for the specified environment, granting the given scopes
+    pub fn generate_token_with_scopes(mut self, environment: &str, scopes: Vec<String>) -> Self {
         use rand::{thread_rng, Rng};
         use rand::distributions::Alphanumeric;
         
@@ -148,6 +720,10 @@ impl TokenManager {
             .collect();
         
         self.tokens.insert(environment.to_string(), format!("bea_{}", token));
+        self.info.insert(environment.to_string(), TokenInfo {
+            created_at: chrono::Utc::now(),
+            scopes,
+        });
         self
     }
 
@@ -155,6 +731,17 @@ impl TokenManager {
     pub fn get_token(&self, environment: &str) -> Option<&String> {
         self.tokens.get(environment)
     }
+
+    /// Get when the environment's token was created and what it grants
+    pub fn token_info(&self, environment: &str) -> Option<&TokenInfo> {
+        self.info.get(environment)
+    }
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Generates web interfaces for agents
@@ -210,6 +797,12 @@ impl SiteGenerator {
     }
 }
 
+impl Default for SiteGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Authentication methods for site access
 #[derive(Debug, Clone)]
 pub enum Auth {
@@ -224,6 +817,8 @@ pub enum Auth {
 #[derive(Debug)]
 pub struct AgentStateManager {
     agents: Arc<Mutex<HashMap<String, AgentState>>>,
+    limits: HashMap<String, Arc<ConcurrencyLimit>>,
+    max_queue_depth: Option<usize>,
 }
 
 impl AgentStateManager {
@@ -231,15 +826,248 @@ impl AgentStateManager {
     pub fn new() -> Self {
         Self {
             agents: Arc::new(Mutex::new(HashMap::new())),
+            limits: HashMap::new(),
+            max_queue_depth: None,
         }
     }
 
-    /// Register a new agent
-    pub async fn register_agent(&self, agent: &Agent) -> Result<(), Box<dyn Error>> {
+    /// Load usage counts from a state file written by `save`
+    ///
+    /// A missing file gives an empty manager. Only the counts are stored, so
+    /// each agent comes back with a default definition until it is
+    /// registered again, which keeps its counts.
+    pub async fn load(path: &Path) -> Result<Self, BeaError> {
+        let manager = Self::new();
+
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(manager),
+            Err(e) => return Err(e.into()),
+        };
+
+        let stored: Vec<StoredAgentState> = serde_json::from_str(&contents)?;
+        manager.agents.lock().await.extend(stored.into_iter().map(|stored| {
+            let state = AgentState {
+                agent: Agent::new(&stored.name),
+                created_at: stored.created_at,
+                request_count: stored.request_count,
+                last_active: stored.last_active,
+                prompt_tokens: stored.prompt_tokens,
+                completion_tokens: stored.completion_tokens,
+            };
+            (stored.name, state)
+        }));
+        Ok(manager)
+    }
+
+    /// Write the agents' usage counts to a state file only its owner can read
+    pub async fn save(&self, path: &Path) -> Result<(), BeaError> {
+        let mut stored: Vec<StoredAgentState> = self
+            .agents
+            .lock()
+            .await
+            .iter()
+            .map(|(name, state)| StoredAgentState {
+                name: name.clone(),
+                created_at: state.created_at,
+                last_active: state.last_active,
+                request_count: state.request_count,
+                prompt_tokens: state.prompt_tokens,
+                completion_tokens: state.completion_tokens,
+            })
+            .collect();
+        stored.sort_by(|a, b| a.name.cmp(&b.name));
+        crate::config::write_state_file(path, &serde_json::to_vec_pretty(&stored)?).await
+    }
+
+    /// Cap the number of in-flight model calls for an agent
+    pub fn with_max_concurrency(mut self, agent: &str, max: usize) -> Self {
+        self.limits.insert(
+            agent.to_string(),
+            Arc::new(ConcurrencyLimit {
+                semaphore: Arc::new(Semaphore::new(max)),
+                queued: AtomicUsize::new(0),
+            }),
+        );
+        self
+    }
+
+    /// Cap how many requests may wait for a slot before being rejected as busy
+    pub fn with_max_queue_depth(mut self, depth: usize) -> Self {
+        self.max_queue_depth = Some(depth);
+        self
+    }
+
+    /// Wait for a request slot for an agent
+    ///
+    /// Call this before each model call and hold the returned permit until
+    /// the call finishes. Agents without a concurrency limit get a slot
+    /// immediately. When all slots are taken the request queues, and fails
+    /// with `BeaError::AgentBusy` if the queue is already at its maximum depth.
+    pub async fn acquire_slot(&self, agent: &str) -> Result<AgentPermit, BeaError> {
+        let Some(limit) = self.limits.get(agent) else {
+            return Ok(AgentPermit { _permit: None });
+        };
+
+        if let Ok(permit) = limit.semaphore.clone().try_acquire_owned() {
+            return Ok(AgentPermit { _permit: Some(permit) });
+        }
+
+        let waiting = limit.queued.fetch_add(1, Ordering::SeqCst) + 1;
+        let _queued = QueueGuard(&limit.queued);
+        if self.max_queue_depth.is_some_and(|max| waiting > max) {
+            return Err(BeaError::AgentBusy(agent.to_string()));
+        }
+
+        let permit = limit.semaphore.clone().acquire_owned().await
+            .map_err(|_| BeaError::AgentBusy(agent.to_string()))?;
+
+        Ok(AgentPermit { _permit: Some(permit) })
+    }
+
+    /// Register an agent
+    ///
+    /// Registering a name that is already registered replaces its agent
+    /// definition but keeps its creation time and usage counts.
+    pub async fn register_agent(&self, agent: &Agent) -> Result<(), BeaError> {
         let mut agents = self.agents.lock().await;
-        agents.insert(agent.name().to_string(), AgentState::new(agent));
+        agents
+            .entry(agent.name().to_string())
+            .and_modify(|state| state.agent = agent.clone())
+            .or_insert_with(|| AgentState::new(agent));
         Ok(())
     }
+
+    /// Remove a registered agent and its usage counts
+    pub async fn unregister_agent(&self, name: &str) -> Result<(), BeaError> {
+        let mut agents = self.agents.lock().await;
+        agents
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| BeaError::AgentNotFound(name.to_string()))
+    }
+
+    /// List the names of registered agents, sorted
+    pub async fn list_agents(&self) -> Vec<String> {
+        let agents = self.agents.lock().await;
+        let mut names: Vec<String> = agents.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Get a copy of an agent's current state
+    pub async fn get_state_snapshot(&self, name: &str) -> Option<AgentStateSnapshot> {
+        let agents = self.agents.lock().await;
+        let state = agents.get(name)?;
+
+        Some(AgentStateSnapshot {
+            agent: state.agent.clone(),
+            created_at: state.created_at,
+            last_active: state.last_active,
+            request_count: state.request_count,
+            prompt_tokens: state.prompt_tokens,
+            completion_tokens: state.completion_tokens,
+        })
+    }
+
+    /// Record a completed model call for an agent
+    pub async fn record_request(&self, agent_name: &str, usage: &TokenUsage) -> Result<(), BeaError> {
+        let mut agents = self.agents.lock().await;
+        let state = agents
+            .get_mut(agent_name)
+            .ok_or_else(|| BeaError::AgentNotFound(agent_name.to_string()))?;
+
+        state.request_count += 1;
+        state.last_active = chrono::Utc::now();
+        state.prompt_tokens += u64::from(usage.prompt_tokens());
+        state.completion_tokens += u64::from(usage.completion_tokens());
+        Ok(())
+    }
+
+    /// Get request and token counts for an agent
+    pub async fn stats(&self, agent_name: &str) -> Option<AgentStats> {
+        let agents = self.agents.lock().await;
+        let state = agents.get(agent_name)?;
+
+        Some(AgentStats {
+            request_count: state.request_count,
+            prompt_tokens: state.prompt_tokens,
+            completion_tokens: state.completion_tokens,
+            created_at: state.created_at,
+            last_active: state.last_active,
+            uptime: chrono::Utc::now() - state.created_at,
+        })
+    }
+}
+
+impl Default for AgentStateManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Point-in-time copy of a registered agent's state
+#[derive(Debug, Clone)]
+pub struct AgentStateSnapshot {
+    /// Agent definition
+    pub agent: Agent,
+    /// When the agent was registered
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the agent last handled a request
+    pub last_active: chrono::DateTime<chrono::Utc>,
+    /// Number of model calls recorded
+    pub request_count: u64,
+    /// Total prompt tokens used
+    pub prompt_tokens: u64,
+    /// Total completion tokens used
+    pub completion_tokens: u64,
+}
+
+/// Usage statistics for a registered agent
+#[derive(Debug, Clone)]
+pub struct AgentStats {
+    /// Number of model calls recorded
+    pub request_count: u64,
+    /// Total prompt tokens used
+    pub prompt_tokens: u64,
+    /// Total completion tokens used
+    pub completion_tokens: u64,
+    /// When the agent was registered
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When the agent last handled a request
+    pub last_active: chrono::DateTime<chrono::Utc>,
+    /// Time since the agent was registered
+    pub uptime: chrono::Duration,
+}
+
+impl AgentStats {
+    /// Get the total tokens used
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// Request slot for an agent, released when dropped
+#[derive(Debug)]
+pub struct AgentPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Concurrency limit for a single agent
+#[derive(Debug)]
+struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+/// Decrements the queue depth when a waiting request leaves the queue,
+/// including when its future is dropped
+struct QueueGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 /// Represents the runtime state of a deployed agent
@@ -249,6 +1077,8 @@ struct AgentState {
     created_at: chrono::DateTime<chrono::Utc>,
     request_count: u64,
     last_active: chrono::DateTime<chrono::Utc>,
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 impl AgentState {
@@ -260,16 +1090,31 @@ impl AgentState {
             created_at: now,
             request_count: 0,
             last_active: now,
+            prompt_tokens: 0,
+            completion_tokens: 0,
         }
     }
 }
 
+/// Usage counts of an agent as kept in a state file
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredAgentState {
+    name: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_active: chrono::DateTime<chrono::Utc>,
+    request_count: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
 /// Deploy an agent with optional token manager and site
 pub async fn deploy(
     agent: Agent, 
-    token_manager: Option<TokenManager>, 
+    _token_manager: Option<TokenManager>, 
     site: Option<SiteGenerator>
 ) -> Result<String, Box<dyn Error>> {
+    agent.validate().map_err(BeaError::InvalidAgent)?;
+    
     // This would contain actual deployment logic
     // For now, we'll just return a mock endpoint
     
@@ -287,6 +1132,8 @@ pub async fn deploy_to_env(
     agent: Agent,
     environment: &str,
 ) -> Result<String, Box<dyn Error>> {
+    agent.validate().map_err(BeaError::InvalidAgent)?;
+    
     // This would contain environment-specific deployment logic
     
     let domain = match environment {
@@ -298,3 +1145,112 @@ pub async fn deploy_to_env(
     
     Ok(format!("https://{}", domain))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_name_fails_validation() {
+        let errors = Agent::new("").validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::EmptyName]);
+    }
+
+    #[test]
+    fn uppercase_name_with_spaces_fails_validation() {
+        let errors = Agent::new("Support Bot").validate().unwrap_err();
+        assert_eq!(errors, vec![ValidationError::InvalidName("Support Bot".to_string())]);
+    }
+
+    #[test]
+    fn validation_collects_every_problem() {
+        let agent = Agent::new("-bad").with_performance_tier("turbo").with_model("claude");
+        let errors = agent.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::InvalidName("-bad".to_string()),
+                ValidationError::UnknownPerformanceTier("turbo".to_string()),
+                ValidationError::MissingProviderPrefix("claude".to_string()),
+            ]
+        );
+        assert!(Agent::new("support-bot-2").validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn deploy_rejects_invalid_agents_before_deploying() {
+        assert!(deploy(Agent::new(""), None, None).await.is_err());
+        assert_eq!(
+            deploy(Agent::new("support"), None, None).await.unwrap(),
+            "https://support.bea-bot.app"
+        );
+    }
+
+    #[tokio::test]
+    async fn registering_an_agent_twice_updates_it() {
+        let manager = AgentStateManager::new();
+        manager.register_agent(&Agent::new("support")).await.unwrap();
+        manager.record_request("support", &TokenUsage::new(10, 5)).await.unwrap();
+        let first = manager.get_state_snapshot("support").await.unwrap();
+
+        let updated = Agent::new("support").with_model("openai/gpt-4o");
+        manager.register_agent(&updated).await.unwrap();
+
+        assert_eq!(manager.list_agents().await, vec!["support".to_string()]);
+        let snapshot = manager.get_state_snapshot("support").await.unwrap();
+        assert_eq!(snapshot.agent.model(), "openai/gpt-4o");
+        assert_eq!(snapshot.created_at, first.created_at);
+        assert_eq!(snapshot.request_count, 1);
+        assert_eq!(snapshot.prompt_tokens, 10);
+    }
+
+    #[tokio::test]
+    async fn unregistered_agents_are_gone() {
+        let manager = AgentStateManager::new();
+        manager.register_agent(&Agent::new("support")).await.unwrap();
+        manager.register_agent(&Agent::new("billing")).await.unwrap();
+        assert_eq!(manager.list_agents().await, vec!["billing".to_string(), "support".to_string()]);
+
+        manager.unregister_agent("support").await.unwrap();
+        assert_eq!(manager.list_agents().await, vec!["billing".to_string()]);
+        assert!(manager.get_state_snapshot("support").await.is_none());
+        assert!(matches!(
+            manager.unregister_agent("support").await,
+            Err(BeaError::AgentNotFound(name)) if name == "support"
+        ));
+    }
+
+    #[tokio::test]
+    async fn saved_usage_counts_load_back() {
+        let path = std::env::temp_dir().join(format!("bea-agents-{}.json", uuid::Uuid::new_v4().simple()));
+        let manager = AgentStateManager::new();
+        manager.register_agent(&Agent::new("support")).await.unwrap();
+        manager.record_request("support", &TokenUsage::new(10, 5)).await.unwrap();
+        manager.record_request("support", &TokenUsage::new(3, 2)).await.unwrap();
+        manager.save(&path).await.unwrap();
+
+        let loaded = AgentStateManager::load(&path).await.unwrap();
+        let stats = loaded.stats("support").await.unwrap();
+        assert_eq!(stats.request_count, 2);
+        assert_eq!(stats.prompt_tokens, 13);
+        assert_eq!(stats.completion_tokens, 7);
+
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert!(AgentStateManager::load(&path).await.unwrap().list_agents().await.is_empty());
+    }
+
+    #[test]
+    fn overrides_replace_sampling_settings_that_are_set() {
+        let base = Agent::new("support").with_temperature(0.2).with_top_p(0.9).with_max_tokens(512);
+
+        let agent = base.apply_overrides(&AgentOverrides {
+            temperature: Some(0.7),
+            max_tokens: Some(1024),
+            ..AgentOverrides::default()
+        });
+
+        assert_eq!(agent.temperature(), Some(0.7));
+        assert_eq!(agent.top_p(), Some(0.9));
+        assert_eq!(agent.max_tokens(), Some(1024));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::Mutex;
+
+/// Number of entries kept by `InMemoryLogStore` by default
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Number of entries buffered for each subscriber
+const SUBSCRIBER_BUFFER: usize = 100;
+
+/// Severity of a log entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Info,
+    Error,
+}
+
+/// Event recorded by a running agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// When the event happened
+    pub timestamp: DateTime<Utc>,
+    /// Agent that handled the request
+    pub agent: String,
+    /// Severity
+    pub level: LogLevel,
+    /// Kind of event, e.g. `request` or `response`
+    pub event: String,
+    /// Human-readable details
+    pub message: String,
+}
+
+impl LogEntry {
+    /// Create a new entry timestamped now
+    pub fn new(agent: &str, level: LogLevel, event: &str, message: &str) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            agent: agent.to_string(),
+            level,
+            event: event.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Backend that agent logs are written to and read from
+#[async_trait]
+pub trait LogStore: Send + Sync {
+    /// Record an entry
+    async fn append(&self, entry: LogEntry);
+    
+    /// Get an agent's last `n` entries, oldest first
+    async fn tail(&self, agent: &str, n: usize) -> Vec<LogEntry>;
+    
+    /// Receive an agent's entries as they are appended
+    ///
+    /// The stream ends when the receiver is dropped.
+    async fn subscribe(&self, agent: &str) -> Receiver<LogEntry>;
+}
+
+/// Log store that keeps the most recent entries in memory
+///
+/// Entries don't survive a restart. Subscribers that fall more than
+/// `SUBSCRIBER_BUFFER` entries behind miss the overflow rather than slowing
+/// down the agent.
+pub struct InMemoryLogStore {
+    entries: Mutex<VecDeque<LogEntry>>,
+    capacity: usize,
+    subscribers: Mutex<Vec<(String, Sender<LogEntry>)>>,
+}
+
+impl InMemoryLogStore {
+    /// Create a new, empty log store
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            capacity: DEFAULT_CAPACITY,
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+    
+    /// Set the number of entries kept before the oldest are dropped
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+}
+
+impl Default for InMemoryLogStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LogStore for InMemoryLogStore {
+    async fn append(&self, entry: LogEntry) {
+        {
+            let mut subscribers = self.subscribers.lock().await;
+            subscribers.retain(|(_, tx)| !tx.is_closed());
+            for (agent, tx) in subscribers.iter() {
+                if *agent == entry.agent {
+                    let _ = tx.try_send(entry.clone());
+                }
+            }
+        }
+        
+        let mut entries = self.entries.lock().await;
+        entries.push_back(entry);
+        while entries.len() > self.capacity {
+            entries.pop_front();
+        }
+    }
+    
+    async fn tail(&self, agent: &str, n: usize) -> Vec<LogEntry> {
+        let entries = self.entries.lock().await;
+        let mut tail: Vec<LogEntry> = entries
+            .iter()
+            .rev()
+            .filter(|e| e.agent == agent)
+            .take(n)
+            .cloned()
+            .collect();
+        tail.reverse();
+        tail
+    }
+    
+    async fn subscribe(&self, agent: &str) -> Receiver<LogEntry> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers.lock().await.push((agent.to_string(), tx));
+        rx
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::models::TokenUsage;
+use crate::BeaError;
+
+/// Content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Label used for tools that aren't registered, so model-chosen names can't
+/// grow the number of series
+const UNKNOWN_TOOL: &str = "unknown";
+
+const REQUESTS: &str = "bea_requests_total";
+const REQUEST_ERRORS: &str = "bea_request_errors_total";
+const REQUEST_DURATION: &str = "bea_request_duration_seconds";
+const TOKENS: &str = "bea_tokens_total";
+const TOOL_EXECUTIONS: &str = "bea_tool_executions_total";
+
+/// Buckets for `bea_request_duration_seconds`, from 5ms to 10s
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Get the handle that renders Bea Bot's metrics, installing the Prometheus
+/// recorder on first use
+///
+/// Metrics are recorded through the `metrics` facade. An application that
+/// installed its own recorder first keeps it and receives the metrics there,
+/// in which case this handle renders nothing.
+pub fn handle() -> &'static PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    
+    HANDLE.get_or_init(|| {
+        let recorder = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(REQUEST_DURATION.to_string()), DURATION_BUCKETS)
+            .expect("duration buckets are not empty")
+            .build_recorder();
+        let handle = recorder.handle();
+        let _ = metrics::set_global_recorder(recorder);
+        
+        metrics::describe_counter!(REQUESTS, "Model requests made");
+        metrics::describe_counter!(REQUEST_ERRORS, "Model requests that failed");
+        metrics::describe_histogram!(REQUEST_DURATION, metrics::Unit::Seconds, "Time taken by model requests");
+        metrics::describe_counter!(TOKENS, "Tokens used by model requests");
+        metrics::describe_counter!(TOOL_EXECUTIONS, "Tool executions");
+        handle
+    })
+}
+
+/// Record a finished model request, with its usage if it succeeded
+pub fn record_request(
+    provider: &str,
+    model: &str,
+    elapsed: Duration,
+    outcome: Result<&TokenUsage, &BeaError>,
+) {
+    handle();
+    let labels = [("model", model.to_string()), ("provider", provider.to_string())];
+    
+    metrics::counter!(REQUESTS, &labels).increment(1);
+    metrics::histogram!(REQUEST_DURATION, &labels).record(elapsed.as_secs_f64());
+    
+    match outcome {
+        Ok(usage) => {
+            metrics::counter!(TOKENS, "kind" => "prompt").increment(u64::from(usage.prompt_tokens()));
+            metrics::counter!(TOKENS, "kind" => "completion").increment(u64::from(usage.completion_tokens()));
+        }
+        Err(_) => metrics::counter!(REQUEST_ERRORS, &labels).increment(1),
+    }
+}
+
+/// Record a tool execution
+///
+/// `tool` is `None` for calls to tools that aren't registered.
+pub fn record_tool_execution(tool: Option<&str>, success: bool) {
+    handle();
+    let status = if success { "success" } else { "error" };
+    metrics::counter!(
+        TOOL_EXECUTIONS,
+        "status" => status,
+        "tool" => tool.unwrap_or(UNKNOWN_TOOL).to_string()
+    )
+    .increment(1);
+}
+
+/// Render every metric in the Prometheus text format
+pub fn render() -> String {
+    handle().render()
+}
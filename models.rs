@@ -1,16 +1,85 @@
-use std::collections::HashMap;
-use std::error::Error;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::Agent;
+use crate::config::{ProviderKeys, DEFAULT_AZURE_API_VERSION};
+use crate::error::{BeaError, ProviderError};
+use crate::http;
+use crate::metrics;
+
+/// Default marker appended to truncated content; `{n}` is the number of
+/// characters removed
+pub const TRUNCATION_MARKER: &str = "… [truncated {n} chars]";
+
+/// Estimate the number of tokens in a piece of text
+///
+/// Uses the common ~4 characters per token heuristic. Real tokenizers vary
+/// by model, so treat this as an approximation.
+pub fn count_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimate the number of tokens in a list of messages
+///
+/// Sums `count_tokens` over each message's content, so it agrees with the
+/// estimate shown by generated sites.
+pub fn count_message_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(|m| count_tokens(&m.content)).sum()
+}
+
+/// Image attached to a message, for models that accept vision input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Image {
+    media_type: String,
+    data: String,
+}
+
+impl Image {
+    /// Create an image from its MIME type and base64-encoded bytes
+    pub fn new(media_type: &str, data: &str) -> Self {
+        Self {
+            media_type: media_type.to_string(),
+            data: data.to_string(),
+        }
+    }
+    
+    /// Get the MIME type, e.g. `image/png`
+    pub fn media_type(&self) -> &str {
+        &self.media_type
+    }
+    
+    /// Get the base64-encoded bytes
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+    
+    /// Render as a `data:` URL
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
 
 /// Message role for conversation history
+///
+/// Roles are written in lowercase. The capitalized names written before
+/// are still read, so persisted conversations keep loading.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MessageRole {
+    #[serde(alias = "System")]
     System,
+    #[serde(alias = "User")]
     User,
+    #[serde(alias = "Assistant")]
     Assistant,
+    #[serde(alias = "Tool")]
     Tool,
 }
 
@@ -23,6 +92,10 @@ pub struct Message {
     name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<Image>,
 }
 
 impl Message {
@@ -33,6 +106,8 @@ impl Message {
             content: content.to_string(),
             name: None,
             tool_calls: None,
+            tool_call_id: None,
+            images: Vec::new(),
         }
     }
 
@@ -43,6 +118,8 @@ impl Message {
             content: content.to_string(),
             name: None,
             tool_calls: None,
+            tool_call_id: None,
+            images: Vec::new(),
         }
     }
 
@@ -53,6 +130,8 @@ impl Message {
             content: content.to_string(),
             name: None,
             tool_calls: None,
+            tool_call_id: None,
+            images: Vec::new(),
         }
     }
 
@@ -63,6 +142,93 @@ impl Message {
             content: content.to_string(),
             name: Some(name.to_string()),
             tool_calls: None,
+            tool_call_id: None,
+            images: Vec::new(),
+        }
+    }
+    
+    /// Create a tool message answering a specific tool call
+    pub fn tool_result(content: &str, name: &str, tool_call_id: &str) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Self::tool(content, name)
+        }
+    }
+    
+    /// Attach tool calls to this message
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = Some(tool_calls);
+        self
+    }
+    
+    /// Mark this message as the answer to a specific tool call
+    pub fn with_tool_call_id(mut self, tool_call_id: &str) -> Self {
+        self.tool_call_id = Some(tool_call_id.to_string());
+        self
+    }
+    
+    /// Attach an image for the model to look at
+    ///
+    /// Images are only sent with user messages.
+    pub fn with_image(mut self, image: Image) -> Self {
+        self.images.push(image);
+        self
+    }
+    
+    /// Get the message role
+    pub fn role(&self) -> &MessageRole {
+        &self.role
+    }
+    
+    /// Get the message content
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+    
+    /// Replace the message content
+    pub fn set_content(&mut self, content: String) {
+        self.content = content;
+    }
+    
+    /// Get the tool calls requested by this message
+    pub fn tool_calls(&self) -> Option<&[ToolCall]> {
+        self.tool_calls.as_deref()
+    }
+    
+    /// Get the ID of the tool call this message answers
+    pub fn tool_call_id(&self) -> Option<&str> {
+        self.tool_call_id.as_deref()
+    }
+    
+    /// Get the images attached to this message
+    pub fn images(&self) -> &[Image] {
+        &self.images
+    }
+    
+    /// Clip the content to at most `max_chars` characters and append `marker`
+    ///
+    /// `{n}` in the marker is replaced with the number of characters removed
+    /// (see [`TRUNCATION_MARKER`]). Content within the limit is left as is.
+    pub fn truncate_content(&mut self, max_chars: usize, marker: &str) {
+        let total = self.content.chars().count();
+        if total <= max_chars {
+            return;
+        }
+        
+        let cut = self.content
+            .char_indices()
+            .nth(max_chars)
+            .map(|(i, _)| i)
+            .unwrap_or(self.content.len());
+        
+        self.content.truncate(cut);
+        self.content.push_str(&marker.replace("{n}", &(total - max_chars).to_string()));
+    }
+    
+    /// Clip the content to roughly `max_tokens` tokens, as estimated by [`count_tokens`]
+    pub fn truncate_content_tokens(&mut self, max_tokens: usize, marker: &str) {
+        if count_tokens(&self.content) > max_tokens {
+            self.truncate_content(max_tokens * 4, marker);
         }
     }
 }
@@ -75,6 +241,37 @@ pub struct ToolCall {
     arguments: String,
 }
 
+impl ToolCall {
+    /// Create a new tool call with JSON-encoded arguments
+    pub fn new(id: &str, name: &str, arguments: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+    
+    /// Get the tool call ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    
+    /// Get the name of the tool to call
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    /// Get the JSON-encoded arguments
+    pub fn arguments(&self) -> &str {
+        &self.arguments
+    }
+    
+    /// Parse the arguments, falling back to an empty object if they aren't valid JSON
+    pub fn arguments_json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.arguments).unwrap_or_else(|_| serde_json::json!({}))
+    }
+}
+
 /// Model request parameters
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelRequest {
@@ -83,10 +280,129 @@ pub struct ModelRequest {
     temperature: Option<f32>,
     max_tokens: Option<u32>,
     tools: Option<Vec<ToolDefinition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice>,
     top_p: Option<f32>,
     stream: Option<bool>,
 }
 
+impl ModelRequest {
+    /// Create a new request for the given model and messages
+    pub fn new(model: &str, messages: Vec<Message>) -> Self {
+        Self {
+            messages,
+            model: model.to_string(),
+            temperature: None,
+            max_tokens: None,
+            tools: None,
+            tool_choice: None,
+            top_p: None,
+            stream: None,
+        }
+    }
+    
+    /// Create a request for an agent, prefixing its system blocks
+    ///
+    /// Each of the agent's system blocks becomes its own system message, in
+    /// order, ahead of `messages`. The agent's sampling parameters are copied
+    /// onto the request.
+    pub fn from_agent(agent: &Agent, messages: Vec<Message>) -> Self {
+        let mut all = Vec::with_capacity(agent.system_blocks().len() + messages.len());
+        all.extend(agent.system_blocks().iter().map(|b| Message::system(b)));
+        all.extend(messages);
+        
+        Self {
+            temperature: agent.temperature(),
+            top_p: agent.top_p(),
+            max_tokens: agent.max_tokens(),
+            ..Self::new(agent.model(), all)
+        }
+    }
+    
+    /// Get the model identifier
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+    
+    /// Get the messages sent with the request
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+    
+    /// Get mutable access to the messages, e.g. to trim history to a budget
+    pub fn messages_mut(&mut self) -> &mut Vec<Message> {
+        &mut self.messages
+    }
+    
+    /// Get whether a streamed response was requested
+    pub fn stream(&self) -> Option<bool> {
+        self.stream
+    }
+    
+    /// Set the tools the model may call
+    pub fn with_tools(mut self, tools: Vec<ToolDefinition>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+    
+    /// Control whether and which tool the model must call
+    pub fn with_tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+    
+    /// Check that the tool choice is consistent with the request's tools
+    ///
+    /// `Required` needs at least one tool, and `Specific` must name one of
+    /// the tools in the request.
+    pub fn validate_tool_choice(&self) -> Result<(), BeaError> {
+        let tools = self.tools.as_deref().unwrap_or(&[]);
+        
+        match &self.tool_choice {
+            Some(ToolChoice::Required) if tools.is_empty() => Err(BeaError::InvalidToolChoice(
+                "a tool is required but the request has no tools".to_string(),
+            )),
+            Some(ToolChoice::Specific(name)) if !tools.iter().any(|t| &t.name == name) => {
+                Err(BeaError::InvalidToolChoice(format!(
+                    "tool {} is not in the request's tools",
+                    name
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// Use a different model for this request
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+    
+    /// Request a streamed response
+    pub fn with_stream(mut self, stream: bool) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+    
+    /// Set the sampling temperature
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+    
+    /// Set the nucleus sampling probability
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+    
+    /// Limit the number of tokens generated
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+}
+
 /// Tool definition for model request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolDefinition {
@@ -95,195 +411,2192 @@ pub struct ToolDefinition {
     parameters: serde_json::Value,
 }
 
-/// Model response
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ModelResponse {
-    message: Message,
-    model: String,
-    usage: TokenUsage,
-}
-
-/// Token usage information
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenUsage {
-    prompt_tokens: u32,
-    completion_tokens: u32,
-    total_tokens: u32,
-}
-
-/// Trait for AI model providers
-#[async_trait]
-pub trait ModelProvider: Send + Sync {
-    /// Get the provider name
-    fn provider_name(&self) -> &str;
-    
-    /// Get available models
-    async fn available_models(&self) -> Result<Vec<String>, Box<dyn Error>>;
-    
-    /// Generate a completion for the given request
-    async fn generate(
-        &self,
-        request: ModelRequest,
-    ) -> Result<ModelResponse, Box<dyn Error>>;
-    
-    /// Stream a completion for the given request
-    async fn generate_stream(
-        &self,
-        request: ModelRequest,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<ModelResponse, Box<dyn Error>>>, Box<dyn Error>>;
-}
-
-/// Anthropic Claude model provider
-pub struct AnthropicProvider {
-    api_key: String,
-    client: reqwest::Client,
-}
-
-impl AnthropicProvider {
-    /// Create a new Anthropic provider with the given API key
-    pub fn new(api_key: &str) -> Self {
+impl ToolDefinition {
+    /// Create a new tool definition
+    pub fn new(name: &str, description: Option<&str>, parameters: serde_json::Value) -> Self {
         Self {
-            api_key: api_key.to_string(),
-            client: reqwest::Client::new(),
+            name: name.to_string(),
+            description: description.map(|d| d.to_string()),
+            parameters,
         }
     }
     
-    /// Convert our message format to Anthropic's message format
-    fn convert_messages(&self, messages: Vec<Message>) -> Vec<serde_json::Value> {
-        messages
-            .into_iter()
-            .map(|msg| {
-                let role = match msg.role {
-                    MessageRole::System => "system",
-                    MessageRole::User => "user",
-                    MessageRole::Assistant => "assistant",
-                    MessageRole::Tool => "tool",
-                };
-                
-                let mut message = serde_json::json!({
-                    "role": role,
-                    "content": msg.content,
-                });
-                
-                if let Some(name) = msg.name {
-                    message["name"] = serde_json::json!(name);
-                }
-                
-                if let Some(tool_calls) = msg.tool_calls {
-                    message["tool_calls"] = serde_json::json!(tool_calls);
-                }
-                
-                message
-            })
-            .collect()
+    /// Get the tool name
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
-
-#[async_trait]
-impl ModelProvider for AnthropicProvider {
-    fn provider_name(&self) -> &str {
-        "anthropic"
+    
+    /// Get the tool description
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
     }
     
-    async fn available_models(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        // In a real implementation, this would query the Anthropic API
-        Ok(vec![
-            "claude-3-opus-20240229".to_string(),
-            "claude-3-sonnet-20240229".to_string(),
-            "claude-3-haiku-20240307".to_string(),
-            "claude-3.5-sonnet-20240613".to_string(),
-            "claude-3.7-sonnet-20240519".to_string(),
-        ])
+    /// Get the JSON Schema for the tool parameters
+    pub fn parameters(&self) -> &serde_json::Value {
+        &self.parameters
     }
     
-    async fn generate(
-        &self,
-        request: ModelRequest,
-    ) -> Result<ModelResponse, Box<dyn Error>> {
-        let anthropic_messages = self.convert_messages(request.messages);
+    /// Convert a LangChain-style tool (`name`, `description`, `args_schema`)
+    pub fn from_langchain(value: serde_json::Value) -> Result<Self, BeaError> {
+        let name = value.get("name")
+            .and_then(|n| n.as_str())
+            .filter(|n| !n.is_empty())
+            .ok_or_else(|| BeaError::InvalidToolSchema("missing tool name".to_string()))?;
         
-        let mut payload = serde_json::json!({
-            "model": request.model,
-            "messages": anthropic_messages,
-        });
+        let description = value.get("description").and_then(|d| d.as_str());
         
-        if let Some(temperature) = request.temperature {
-            payload["temperature"] = serde_json::json!(temperature);
+        let parameters = match value.get("args_schema") {
+            Some(schema) => schema.clone(),
+            None => serde_json::json!({ "type": "object", "properties": {} }),
+        };
+        
+        Self::validate_schema(name, &parameters)?;
+        
+        Ok(Self::new(name, description, parameters))
+    }
+    
+    /// Convert to a LangChain-style tool (`name`, `description`, `args_schema`)
+    pub fn to_langchain(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "description": self.description.as_deref().unwrap_or(""),
+            "args_schema": self.parameters,
+        })
+    }
+    
+    /// Convert to OpenAI's function tool format
+    fn to_openai(&self) -> serde_json::Value {
+        let mut function = serde_json::json!({
+            "name": self.name,
+            "parameters": self.parameters,
+        });
+        if let Some(description) = &self.description {
+            function["description"] = serde_json::json!(description);
+        }
+        serde_json::json!({ "type": "function", "function": function })
+    }
+    
+    /// Convert to Anthropic's tool format
+    fn to_anthropic(&self) -> serde_json::Value {
+        let mut tool = serde_json::json!({
+            "name": self.name,
+            "input_schema": self.parameters,
+        });
+        if let Some(description) = &self.description {
+            tool["description"] = serde_json::json!(description);
         }
+        tool
+    }
+    
+    /// Check that a parameters schema is a JSON Schema object
+    fn validate_schema(name: &str, schema: &serde_json::Value) -> Result<(), BeaError> {
+        let invalid = |reason: &str| BeaError::InvalidToolSchema(format!("{}: {}", name, reason));
         
-        if let Some(max_tokens) = request.max_tokens {
-            payload["max_tokens"] = serde_json::json!(max_tokens);
+        let schema = schema.as_object()
+            .ok_or_else(|| invalid("args_schema must be a JSON object"))?;
+        
+        if let Some(schema_type) = schema.get("type") {
+            if schema_type.as_str() != Some("object") {
+                return Err(invalid("args_schema type must be \"object\""));
+            }
         }
         
-        if let Some(tools) = request.tools {
-            payload["tools"] = serde_json::json!(tools);
+        if let Some(properties) = schema.get("properties") {
+            if !properties.is_object() {
+                return Err(invalid("args_schema properties must be an object"));
+            }
         }
         
-        // In a real implementation, this would call the Anthropic API
-        // and parse the response
+        if let Some(required) = schema.get("required") {
+            let valid = required.as_array()
+                .map(|r| r.iter().all(|v| v.is_string()))
+                .unwrap_or(false);
+            
+            if !valid {
+                return Err(invalid("args_schema required must be an array of strings"));
+            }
+        }
         
-        // Mock response for demonstration purposes
-        let response = ModelResponse {
-            message: Message::assistant("This is a response from Claude."),
-            model: request.model,
-            usage: TokenUsage {
-                prompt_tokens: 128,
-                completion_tokens: 64,
-                total_tokens: 192,
-            },
+        Ok(())
+    }
+}
+
+/// Whether and which tool the model must call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    Auto,
+    /// The model must not call a tool
+    None,
+    /// The model must call at least one tool
+    Required,
+    /// The model must call the named tool
+    Specific(String),
+}
+
+impl ToolChoice {
+    /// Convert to OpenAI's `tool_choice` format
+    fn to_openai(&self) -> serde_json::Value {
+        match self {
+            ToolChoice::Auto => serde_json::json!("auto"),
+            ToolChoice::None => serde_json::json!("none"),
+            ToolChoice::Required => serde_json::json!("required"),
+            ToolChoice::Specific(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            }),
+        }
+    }
+    
+    /// Convert to Gemini's `toolConfig` format
+    fn to_google(&self) -> serde_json::Value {
+        let config = match self {
+            ToolChoice::Auto => serde_json::json!({ "mode": "AUTO" }),
+            ToolChoice::None => serde_json::json!({ "mode": "NONE" }),
+            ToolChoice::Required => serde_json::json!({ "mode": "ANY" }),
+            ToolChoice::Specific(name) => serde_json::json!({
+                "mode": "ANY",
+                "allowedFunctionNames": [name],
+            }),
         };
         
-        Ok(response)
+        serde_json::json!({ "functionCallingConfig": config })
     }
     
-    async fn generate_stream(
-        &self,
-        request: ModelRequest,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<ModelResponse, Box<dyn Error>>>, Box<dyn Error>> {
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
-        
-        // In a real implementation, this would stream responses from the Anthropic API
-        
-        // For demonstration, we'll just send a single response
-        let cloned_request = request.clone();
-        tokio::spawn(async move {
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-            
-            let response = ModelResponse {
-                message: Message::assistant("This is a streamed response from Claude."),
-                model: cloned_request.model,
-                usage: TokenUsage {
-                    prompt_tokens: 128,
-                    completion_tokens: 64,
-                    total_tokens: 192,
-                },
-            };
-            
-            let _ = tx.send(Ok(response)).await;
-        });
-        
-        Ok(rx)
+    /// Convert to Anthropic's `tool_choice` format
+    fn to_anthropic(&self) -> serde_json::Value {
+        match self {
+            ToolChoice::Auto => serde_json::json!({ "type": "auto" }),
+            ToolChoice::None => serde_json::json!({ "type": "none" }),
+            ToolChoice::Required => serde_json::json!({ "type": "any" }),
+            ToolChoice::Specific(name) => serde_json::json!({
+                "type": "tool",
+                "name": name,
+            }),
+        }
     }
 }
 
-/// OpenAI model provider
-pub struct OpenAIProvider {
-    api_key: String,
-    organization: Option<String>,
-    client: reqwest::Client,
+/// Model response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelResponse {
+    message: Message,
+    model: String,
+    usage: TokenUsage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_limit: Option<RateLimitInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
-impl OpenAIProvider {
-    /// Create a new OpenAI provider with the given API key
-    pub fn new(api_key: &str, organization: Option<&str>) -> Self {
+impl ModelResponse {
+    /// Create a response carrying a message from a model
+    ///
+    /// For providers and test doubles; usage starts at zero.
+    pub fn new(message: Message, model: &str) -> Self {
         Self {
-            api_key: api_key.to_string(),
-            organization: organization.map(|s| s.to_string()),
-            client: reqwest::Client::new(),
+            message,
+            model: model.to_string(),
+            usage: TokenUsage::default(),
+            rate_limit: None,
+            stop_reason: None,
+            request_id: None,
         }
     }
     
-    /// Convert our message format to OpenAI's message format
+    /// Set the token usage
+    pub fn with_usage(mut self, usage: TokenUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+    
+    /// Set the reason the model stopped generating
+    pub fn with_stop_reason(mut self, stop_reason: &str) -> Self {
+        self.stop_reason = Some(stop_reason.to_string());
+        self
+    }
+    
+    /// Get the response message
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+    
+    /// Get the response message for modification
+    pub fn message_mut(&mut self) -> &mut Message {
+        &mut self.message
+    }
+    
+    /// Get the token usage for this response
+    pub fn usage(&self) -> &TokenUsage {
+        &self.usage
+    }
+    
+    /// Get the rate limit information reported by the provider, if any
+    pub fn rate_limit(&self) -> Option<&RateLimitInfo> {
+        self.rate_limit.as_ref()
+    }
+    
+    /// Get the reason the model stopped generating, as reported by the provider
+    pub fn stop_reason(&self) -> Option<&str> {
+        self.stop_reason.as_deref()
+    }
+    
+    /// Get the `X-Request-Id` sent with the provider call, if any
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+    
+    /// Split a partial response from a provider that streams whole chunks into events
+    ///
+    /// Tool calls arrive complete, so each is started, given its arguments
+    /// and ended in one go. Usage is left to the caller.
+    fn into_events(self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        
+        if !self.message.content.is_empty() {
+            events.push(StreamEvent::ContentDelta(self.message.content));
+        }
+        
+        for call in self.message.tool_calls.into_iter().flatten() {
+            events.push(StreamEvent::ToolCallStart { id: call.id.clone(), name: call.name });
+            events.push(StreamEvent::ToolCallArgsDelta { id: call.id.clone(), json_fragment: call.arguments });
+            events.push(StreamEvent::ToolCallEnd { id: call.id });
+        }
+        
+        if let Some(reason) = self.stop_reason {
+            events.push(StreamEvent::Stop(reason));
+        }
+        
+        events
+    }
+    
+    /// Create a response carrying a message, with no usage reported yet
+    fn delta(message: Message, model: &str, request_id: &str) -> Self {
+        Self {
+            message,
+            model: model.to_string(),
+            usage: TokenUsage {
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+            },
+            rate_limit: None,
+            stop_reason: None,
+            request_id: Some(request_id.to_string()),
+        }
+    }
+}
+
+/// Rate limit information reported by a provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateLimitInfo {
+    /// Requests remaining in the current window
+    pub remaining_requests: Option<u64>,
+    /// Tokens remaining in the current window
+    pub remaining_tokens: Option<u64>,
+    /// Seconds to wait before retrying
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimitInfo {
+    /// Parse rate limit headers from a provider response
+    ///
+    /// Understands OpenAI's `x-ratelimit-remaining-*`, Anthropic's
+    /// `anthropic-ratelimit-*-remaining` and the standard `retry-after`.
+    /// Returns `None` when none of the headers are present.
+    pub fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let header = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                headers
+                    .get(*name)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse::<f64>().ok())
+                    .map(|v| v.ceil() as u64)
+            })
+        };
+        
+        let info = Self {
+            remaining_requests: header(&[
+                "x-ratelimit-remaining-requests",
+                "anthropic-ratelimit-requests-remaining",
+                "x-ratelimit-remaining",
+            ]),
+            remaining_tokens: header(&[
+                "x-ratelimit-remaining-tokens",
+                "anthropic-ratelimit-tokens-remaining",
+            ]),
+            retry_after: header(&["retry-after"]),
+        };
+        
+        if info.remaining_requests.is_none() && info.remaining_tokens.is_none() && info.retry_after.is_none() {
+            None
+        } else {
+            Some(info)
+        }
+    }
+    
+    /// Parse rate limit headers from a `reqwest::Response`
+    pub fn from_response(response: &reqwest::Response) -> Option<Self> {
+        Self::from_headers(response.headers())
+    }
+}
+
+/// Token usage information
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+impl TokenUsage {
+    /// Create usage from prompt and completion token counts
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+    
+    /// Get the number of prompt tokens
+    pub fn prompt_tokens(&self) -> u32 {
+        self.prompt_tokens
+    }
+    
+    /// Get the number of completion tokens
+    pub fn completion_tokens(&self) -> u32 {
+        self.completion_tokens
+    }
+    
+    /// Get the total number of tokens
+    pub fn total_tokens(&self) -> u32 {
+        self.total_tokens
+    }
+}
+
+/// Combines the usage reported on a streamed response's chunks
+///
+/// Providers report usage on a stream as running totals: the prompt count
+/// once it's known, and the completion count so far, with plain deltas
+/// carrying zeros. Summing the chunks would count the prompt once per
+/// report, so the largest of each count seen is kept instead.
+#[derive(Debug, Clone, Default)]
+pub struct UsageAccumulator {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl UsageAccumulator {
+    /// Create an accumulator that has seen no chunks
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Take a streamed chunk's usage into account
+    pub fn observe(&mut self, response: &ModelResponse) {
+        self.prompt_tokens = self.prompt_tokens.max(response.usage.prompt_tokens);
+        self.completion_tokens = self.completion_tokens.max(response.usage.completion_tokens);
+    }
+    
+    /// Get the usage of the whole response
+    pub fn finish(self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.prompt_tokens + self.completion_tokens,
+        }
+    }
+}
+
+/// Event emitted while an agent works on a turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AgentEvent {
+    /// Incremental assistant text
+    TextDelta(String),
+    /// A tool call is starting
+    ToolCallStart { name: String, arguments: String },
+    /// A tool call has finished
+    ToolCallResult { name: String, summary: String, success: bool },
+    /// The turn is complete
+    Done(TokenUsage),
+}
+
+/// Event in a streamed completion
+///
+/// Text arrives as `ContentDelta`s. Each tool call is bracketed by
+/// `ToolCallStart` and `ToolCallEnd`, with its argument JSON arriving in
+/// between as fragments that only parse once joined. Fragments of different
+/// calls may interleave; the `id` says which call each belongs to. `Stop`
+/// carries the provider's stop reason when it reports one, and `Done` ends
+/// the stream with the final token totals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StreamEvent {
+    /// Incremental assistant text
+    ContentDelta(String),
+    /// A tool call is starting
+    ToolCallStart { id: String, name: String },
+    /// A piece of a tool call's argument JSON
+    ToolCallArgsDelta { id: String, json_fragment: String },
+    /// A tool call's arguments are complete
+    ToolCallEnd { id: String },
+    /// Why the model stopped generating
+    Stop(String),
+    /// The response is complete
+    Done(TokenUsage),
+}
+
+/// Streamed completion from a provider
+///
+/// Carries the events along with what is known about the response before
+/// the first event arrives. Use `collect` to reassemble the events into a
+/// `ModelResponse` when the deltas themselves don't matter.
+#[derive(Debug)]
+pub struct ModelStream {
+    events: tokio::sync::mpsc::Receiver<Result<StreamEvent, BeaError>>,
+    model: String,
+    request_id: Option<String>,
+    rate_limit: Option<RateLimitInfo>,
+    timer: Option<StreamTimer>,
+}
+
+/// Request a stream reports to the metrics once it finishes
+#[derive(Debug)]
+struct StreamTimer {
+    provider: String,
+    started: std::time::Instant,
+}
+
+impl ModelStream {
+    /// Create a stream of events for a model
+    pub fn new(model: &str, events: tokio::sync::mpsc::Receiver<Result<StreamEvent, BeaError>>) -> Self {
+        Self {
+            events,
+            model: model.to_string(),
+            request_id: None,
+            rate_limit: None,
+            timer: None,
+        }
+    }
+    
+    /// Set the `X-Request-Id` sent with the provider call
+    pub fn with_request_id(mut self, request_id: &str) -> Self {
+        self.request_id = Some(request_id.to_string());
+        self
+    }
+    
+    /// Set the rate limit information reported by the provider
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimitInfo>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+    
+    /// Get the model generating the response
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+    
+    /// Get the `X-Request-Id` sent with the provider call, if any
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+    
+    /// Get the rate limit information reported by the provider, if any
+    pub fn rate_limit(&self) -> Option<&RateLimitInfo> {
+        self.rate_limit.as_ref()
+    }
+    
+    /// Wait for the next event; `None` once the stream has ended
+    pub async fn recv(&mut self) -> Option<Result<StreamEvent, BeaError>> {
+        let event = self.events.recv().await;
+        match &event {
+            Some(Ok(StreamEvent::Done(usage))) => self.record(Ok(usage)),
+            Some(Err(e)) => self.record(Err(e)),
+            // Streams that end without totals still count as requests
+            None => self.record(Ok(&TokenUsage::default())),
+            Some(Ok(_)) => {}
+        }
+        event
+    }
+    
+    /// Report the request to the metrics, once, if it was started by a registry
+    fn record(&mut self, outcome: Result<&TokenUsage, &BeaError>) {
+        if let Some(timer) = self.timer.take() {
+            metrics::record_request(&timer.provider, &self.model, timer.started.elapsed(), outcome);
+        }
+    }
+    
+    /// Swap in another source of events, returning the current one
+    ///
+    /// Lets a wrapper transform the events while keeping the metadata.
+    pub fn replace_events(
+        &mut self,
+        events: tokio::sync::mpsc::Receiver<Result<StreamEvent, BeaError>>,
+    ) -> tokio::sync::mpsc::Receiver<Result<StreamEvent, BeaError>> {
+        std::mem::replace(&mut self.events, events)
+    }
+    
+    /// Consume the stream into a single response
+    pub async fn collect(self) -> Result<ModelResponse, BeaError> {
+        collect_stream_with(self, |_| {}).await
+    }
+}
+
+/// Default number of deltas buffered for a streamed response
+const DEFAULT_STREAM_BUFFER: usize = 100;
+
+/// What a provider does when a streaming consumer falls behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StreamBackpressure {
+    /// Wait for the consumer to catch up; every delta is delivered
+    #[default]
+    Block,
+    /// Keep reading upstream and drop the oldest undelivered text delta
+    ///
+    /// Suited to real-time UIs that only care about the latest tokens.
+    /// Consumers that reassemble the full text must use `Block`, since
+    /// dropped deltas are lost. Tool calls, stop reasons, the final usage
+    /// and errors are never dropped; when only those are waiting, the
+    /// upstream read blocks as it would under `Block`.
+    DropOldest,
+}
+
+/// Streaming options for a provider
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// Channel buffer size
+    pub buffer: usize,
+    /// Backpressure policy
+    pub backpressure: StreamBackpressure,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            buffer: DEFAULT_STREAM_BUFFER,
+            backpressure: StreamBackpressure::Block,
+        }
+    }
+}
+
+/// Item sent over a `ModelStream`'s channel
+type StreamItem = Result<StreamEvent, BeaError>;
+
+/// Forwards stream events to a channel according to a backpressure policy
+///
+/// Under `DropOldest`, events that don't fit in the channel wait in a local
+/// queue of the same size. When that queue overflows, its oldest text delta
+/// is dropped so the upstream reader can carry on. Any other event is only
+/// ever delivered late, never dropped.
+struct StreamForwarder {
+    tx: tokio::sync::mpsc::Sender<StreamItem>,
+    backpressure: StreamBackpressure,
+    pending: VecDeque<StreamItem>,
+    capacity: usize,
+}
+
+impl StreamForwarder {
+    /// Create a forwarder and the receiving end of its channel
+    fn channel(options: StreamOptions) -> (Self, tokio::sync::mpsc::Receiver<StreamItem>) {
+        let capacity = options.buffer.max(1);
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        
+        let forwarder = Self {
+            tx,
+            backpressure: options.backpressure,
+            pending: VecDeque::new(),
+            capacity,
+        };
+        
+        (forwarder, rx)
+    }
+    
+    /// Forward an item, returning `false` once the receiver has gone away
+    async fn push(&mut self, item: StreamItem) -> bool {
+        match self.backpressure {
+            StreamBackpressure::Block => self.tx.send(item).await.is_ok(),
+            StreamBackpressure::DropOldest => {
+                self.pending.push_back(item);
+                
+                while let Some(next) = self.pending.pop_front() {
+                    match self.tx.try_send(next) {
+                        Ok(()) => {}
+                        Err(tokio::sync::mpsc::error::TrySendError::Full(next)) => {
+                            self.pending.push_front(next);
+                            break;
+                        }
+                        Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => return false,
+                    }
+                }
+                
+                while self.pending.len() > self.capacity {
+                    match self.pending.iter().position(|item| matches!(item, Ok(StreamEvent::ContentDelta(_)))) {
+                        Some(index) => {
+                            self.pending.remove(index);
+                        }
+                        // Nothing left that may be dropped, so wait for the consumer
+                        None => {
+                            let next = self.pending.pop_front().expect("queue is over capacity");
+                            if self.tx.send(next).await.is_err() {
+                                return false;
+                            }
+                        }
+                    }
+                }
+                
+                true
+            }
+        }
+    }
+    
+    /// Deliver anything still held back, waiting for the consumer if needed
+    async fn finish(mut self) {
+        while let Some(item) = self.pending.pop_front() {
+            if self.tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Event read from a `text/event-stream` body
+struct SseEvent {
+    /// Name from the `event:` field, if the event had one
+    event: Option<String>,
+    /// Data lines, joined with newlines
+    data: String,
+}
+
+/// Incremental parser for `text/event-stream` bodies
+///
+/// Body chunks can split lines (and UTF-8 sequences) anywhere, so bytes are
+/// buffered until a full line arrives.
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed a chunk of the body, returning the events it completes
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take(),
+                        data: self.data.join("\n"),
+                    });
+                    self.data.clear();
+                }
+                self.event = None;
+                continue;
+            }
+            
+            // Lines starting with a colon are comments
+            if line.starts_with(':') {
+                continue;
+            }
+            
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        
+        events
+    }
+}
+
+/// Header carrying the ID generated for each provider call
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Generate an ID to tag a provider call with
+fn new_request_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Strip the `provider/` routing prefix from a model identifier
+fn model_id(model: &str) -> &str {
+    model.split_once('/').map(|(_, id)| id).unwrap_or(model)
+}
+
+/// Run a provider call until it finishes or `cancel` fires
+///
+/// Cancelling drops the call, which aborts any HTTP request it has in flight.
+async fn cancellable<T>(
+    cancel: CancellationToken,
+    call: impl std::future::Future<Output = Result<T, BeaError>>,
+) -> Result<T, BeaError> {
+    tokio::select! {
+        _ = cancel.cancelled() => Err(BeaError::Cancelled),
+        result = call => result,
+    }
+}
+
+/// Consume a response stream into a single response
+///
+/// Content deltas are concatenated, and each tool call's argument fragments
+/// are joined; calls appear in the order they started. The token usage
+/// comes from the `Done` event, which carries the final totals.
+pub async fn collect_stream(stream: ModelStream) -> Result<ModelResponse, BeaError> {
+    stream.collect().await
+}
+
+/// Stream a completion, calling `on_delta` with each piece of content as it arrives
+///
+/// Returns the assembled response once the stream ends, as `collect_stream`
+/// would. The first error in the stream stops it and is returned.
+pub async fn generate_with_callback<P: ModelProvider + ?Sized>(
+    provider: &P,
+    request: ModelRequest,
+    on_delta: impl FnMut(&str),
+) -> Result<ModelResponse, BeaError> {
+    let stream = provider.generate_stream(request, CancellationToken::new()).await?;
+    collect_stream_with(stream, on_delta).await
+}
+
+/// Consume a response stream, passing each non-empty content delta to `on_delta`
+async fn collect_stream_with(
+    mut stream: ModelStream,
+    mut on_delta: impl FnMut(&str),
+) -> Result<ModelResponse, BeaError> {
+    let mut content = String::new();
+    let mut tool_calls: Vec<ToolCall> = Vec::new();
+    let mut stop_reason = None;
+    let mut usage = TokenUsage::default();
+    let mut received = false;
+    
+    while let Some(event) = stream.recv().await {
+        received = true;
+        match event? {
+            StreamEvent::ContentDelta(text) => {
+                if !text.is_empty() {
+                    on_delta(&text);
+                }
+                content.push_str(&text);
+            }
+            StreamEvent::ToolCallStart { id, name } => tool_calls.push(ToolCall::new(&id, &name, "")),
+            StreamEvent::ToolCallArgsDelta { id, json_fragment } => {
+                if let Some(call) = tool_calls.iter_mut().rev().find(|call| call.id == id) {
+                    call.arguments.push_str(&json_fragment);
+                }
+            }
+            StreamEvent::ToolCallEnd { .. } => {}
+            StreamEvent::Stop(reason) => stop_reason = Some(reason),
+            StreamEvent::Done(totals) => usage = totals,
+        }
+    }
+    
+    if !received {
+        return Err(BeaError::EmptyStream);
+    }
+    
+    // Calls without arguments still need a JSON object
+    for call in &mut tool_calls {
+        if call.arguments.trim().is_empty() {
+            call.arguments = "{}".to_string();
+        }
+    }
+    
+    let mut message = Message::assistant(&content);
+    if !tool_calls.is_empty() {
+        message.tool_calls = Some(tool_calls);
+    }
+    
+    Ok(ModelResponse {
+        message,
+        model: stream.model,
+        usage,
+        rate_limit: stream.rate_limit,
+        stop_reason,
+        request_id: stream.request_id,
+    })
+}
+
+/// Trait for AI model providers
+#[async_trait]
+pub trait ModelProvider: Send + Sync {
+    /// Get the provider name
+    fn provider_name(&self) -> &str;
+    
+    /// Get available models
+    async fn available_models(&self) -> Result<Vec<String>, BeaError>;
+    
+    /// Generate a completion for the given request
+    ///
+    /// Cancelling `cancel` aborts the call, including any HTTP request in
+    /// flight, and fails it with `BeaError::Cancelled`.
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError>;
+    
+    /// Whether this provider can stream completions
+    fn supports_streaming(&self) -> bool {
+        true
+    }
+    
+    /// Stream a completion for the given request
+    ///
+    /// Cancelling `cancel` before the stream starts fails the call with
+    /// `BeaError::Cancelled`; cancelling it later stops reading the response
+    /// and closes the stream.
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError>;
+}
+
+/// Default Anthropic API base URL
+const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1";
+
+/// Anthropic API version sent in the `anthropic-version` header
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic requires `max_tokens`; used when the request doesn't set it
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Anthropic Claude model provider
+pub struct AnthropicProvider {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+    stream_options: StreamOptions,
+}
+
+impl AnthropicProvider {
+    /// Create a new Anthropic provider with the given API key
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            base_url: ANTHROPIC_API_URL.to_string(),
+            client: http::client(),
+            stream_options: StreamOptions::default(),
+        }
+    }
+    
+    /// Use a different API base URL, e.g. for a proxy or gateway
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+    
+    /// Set the number of deltas buffered for streamed responses
+    pub fn with_stream_buffer(mut self, size: usize) -> Self {
+        self.stream_options.buffer = size;
+        self
+    }
+    
+    /// Set the backpressure policy for streamed responses
+    pub fn with_backpressure(mut self, backpressure: StreamBackpressure) -> Self {
+        self.stream_options.backpressure = backpressure;
+        self
+    }
+    
+    /// Build a POST request with the API key, version and request ID headers attached
+    fn post(&self, path: &str, request_id: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.base_url, path))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header(REQUEST_ID_HEADER, request_id)
+    }
+    
+    /// Convert our message format to Anthropic's message format
+    ///
+    /// Anthropic rejects system-role entries in `messages`, so system
+    /// messages are split out and returned separately, joined with blank
+    /// lines, for the top-level `system` field. Assistant tool calls become
+    /// `tool_use` content blocks, and tool results are sent back as
+    /// `tool_result` blocks in a user turn.
+    fn convert_messages(&self, messages: Vec<Message>) -> (Option<String>, Vec<serde_json::Value>) {
+        let (system, messages): (Vec<Message>, Vec<Message>) = messages
+            .into_iter()
+            .partition(|m| matches!(m.role, MessageRole::System));
+        
+        let system = if system.is_empty() {
+            None
+        } else {
+            let blocks: Vec<&str> = system.iter().map(|m| m.content.as_str()).collect();
+            Some(blocks.join("\n\n"))
+        };
+        
+        let messages = messages
+            .into_iter()
+            .map(|msg| {
+                match (msg.role, msg.tool_calls) {
+                    (MessageRole::Assistant, Some(tool_calls)) => {
+                        let mut content = Vec::new();
+                        if !msg.content.is_empty() {
+                            content.push(serde_json::json!({
+                                "type": "text",
+                                "text": msg.content,
+                            }));
+                        }
+                        
+                        for call in &tool_calls {
+                            content.push(serde_json::json!({
+                                "type": "tool_use",
+                                "id": call.id,
+                                "name": call.name,
+                                "input": call.arguments_json(),
+                            }));
+                        }
+                        
+                        serde_json::json!({
+                            "role": "assistant",
+                            "content": content,
+                        })
+                    },
+                    (MessageRole::Tool, _) => serde_json::json!({
+                        "role": "user",
+                        "content": [{
+                            "type": "tool_result",
+                            "tool_use_id": msg.tool_call_id.unwrap_or_default(),
+                            "content": msg.content,
+                        }],
+                    }),
+                    (MessageRole::User, _) if !msg.images.is_empty() => {
+                        let mut content: Vec<serde_json::Value> = msg
+                            .images
+                            .iter()
+                            .map(|image| serde_json::json!({
+                                "type": "image",
+                                "source": {
+                                    "type": "base64",
+                                    "media_type": image.media_type,
+                                    "data": image.data,
+                                },
+                            }))
+                            .collect();
+                        content.push(serde_json::json!({ "type": "text", "text": msg.content }));
+                        
+                        serde_json::json!({
+                            "role": "user",
+                            "content": content,
+                        })
+                    },
+                    (role, _) => {
+                        let role = match role {
+                            MessageRole::User => "user",
+                            _ => "assistant",
+                        };
+                        
+                        serde_json::json!({
+                            "role": role,
+                            "content": msg.content,
+                        })
+                    },
+                }
+            })
+            .collect();
+        
+        (system, messages)
+    }
+    
+    /// Build the Messages API payload for a request
+    fn build_payload(&self, request: &ModelRequest) -> serde_json::Value {
+        let (system, anthropic_messages) = self.convert_messages(request.messages.clone());
+        
+        let mut payload = serde_json::json!({
+            "model": model_id(&request.model),
+            "messages": anthropic_messages,
+            "max_tokens": request.max_tokens.unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+        });
+        
+        if let Some(system) = system {
+            payload["system"] = serde_json::json!(system);
+        }
+        
+        if let Some(temperature) = request.temperature {
+            payload["temperature"] = serde_json::json!(temperature);
+        }
+        
+        if let Some(top_p) = request.top_p {
+            payload["top_p"] = serde_json::json!(top_p);
+        }
+        
+        if let Some(tools) = &request.tools {
+            payload["tools"] = tools.iter().map(ToolDefinition::to_anthropic).collect();
+        }
+        
+        if let Some(tool_choice) = &request.tool_choice {
+            payload["tool_choice"] = tool_choice.to_anthropic();
+        }
+        
+        payload
+    }
+}
+
+#[async_trait]
+impl ModelProvider for AnthropicProvider {
+    fn provider_name(&self) -> &str {
+        "anthropic"
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        // In a real implementation, this would query the Anthropic API
+        Ok(vec![
+            "claude-3-opus-20240229".to_string(),
+            "claude-3-sonnet-20240229".to_string(),
+            "claude-3-haiku-20240307".to_string(),
+            "claude-3.5-sonnet-20240613".to_string(),
+            "claude-3.7-sonnet-20240519".to_string(),
+        ])
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        cancellable(cancel, async move {
+            request.validate_tool_choice()?;
+            
+            let payload = self.build_payload(&request);
+            
+            let request_id = new_request_id();
+            let response = self.post("/messages", &request_id).json(&payload).send().await?;
+            let rate_limit = RateLimitInfo::from_response(&response);
+            
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ProviderError::from_body(status.as_u16(), &body)
+                    .with_request_id(&request_id)
+                    .into());
+            }
+            
+            let body: serde_json::Value = response.json().await?;
+            
+            let mut text = String::new();
+            let mut tool_calls = Vec::new();
+            for block in body["content"].as_array().into_iter().flatten() {
+                match block["type"].as_str() {
+                    Some("text") => text.push_str(block["text"].as_str().unwrap_or("")),
+                    Some("tool_use") => tool_calls.push(ToolCall {
+                        id: block["id"].as_str().unwrap_or("").to_string(),
+                        name: block["name"].as_str().unwrap_or("").to_string(),
+                        arguments: block["input"].to_string(),
+                    }),
+                    _ => {}
+                }
+            }
+            
+            let mut message = Message::assistant(&text);
+            if !tool_calls.is_empty() {
+                message.tool_calls = Some(tool_calls);
+            }
+            
+            let usage = &body["usage"];
+            let prompt_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as u32;
+            let completion_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as u32;
+            
+            Ok(ModelResponse {
+                message,
+                model: request.model,
+                usage: TokenUsage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                },
+                rate_limit,
+                stop_reason: body["stop_reason"].as_str().map(|r| r.to_string()),
+                request_id: Some(request_id),
+            })
+        })
+        .await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        cancellable(cancel.clone(), async move {
+            request.validate_tool_choice()?;
+            
+            let mut payload = self.build_payload(&request);
+            payload["stream"] = serde_json::json!(true);
+            
+            let request_id = new_request_id();
+            let mut response = self.post("/messages", &request_id).json(&payload).send().await?;
+            let rate_limit = RateLimitInfo::from_response(&response);
+            
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ProviderError::from_body(status.as_u16(), &body)
+                    .with_request_id(&request_id)
+                    .into());
+            }
+            
+            let (mut forwarder, rx) = StreamForwarder::channel(self.stream_options);
+            let stream = ModelStream::new(&request.model, rx)
+                .with_request_id(&request_id)
+                .with_rate_limit(rate_limit);
+            
+            tokio::spawn(async move {
+                let mut parser = SseParser::default();
+                let mut prompt_tokens = 0;
+                let mut tool_calls: HashMap<u64, String> = HashMap::new();
+                
+                'read: loop {
+                    let chunk = tokio::select! {
+                        _ = cancel.cancelled() => return,
+                        chunk = response.chunk() => chunk,
+                    };
+                    let bytes = match chunk {
+                        Ok(Some(bytes)) => bytes,
+                        Ok(None) => break,
+                        Err(e) => {
+                            forwarder.push(Err(e.into())).await;
+                            break;
+                        }
+                    };
+                    
+                    for event in parser.feed(&bytes) {
+                        let data: serde_json::Value = match serde_json::from_str(&event.data) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                forwarder.push(Err(e.into())).await;
+                                break 'read;
+                            }
+                        };
+                        let index = data["index"].as_u64().unwrap_or(0);
+                        
+                        // Anthropic names each event after its type; the data's `type` covers unnamed ones
+                        let kind = event.event.as_deref().or_else(|| data["type"].as_str()).unwrap_or("");
+                        let events = match kind {
+                            "message_start" => {
+                                prompt_tokens = data["message"]["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32;
+                                Vec::new()
+                            }
+                            "content_block_start" => {
+                                let block = &data["content_block"];
+                                if block["type"] == "tool_use" {
+                                    let id = block["id"].as_str().unwrap_or("").to_string();
+                                    tool_calls.insert(index, id.clone());
+                                    vec![StreamEvent::ToolCallStart {
+                                        id,
+                                        name: block["name"].as_str().unwrap_or("").to_string(),
+                                    }]
+                                } else {
+                                    Vec::new()
+                                }
+                            }
+                            "content_block_delta" => {
+                                let delta = &data["delta"];
+                                match delta["type"].as_str() {
+                                    Some("text_delta") => {
+                                        vec![StreamEvent::ContentDelta(delta["text"].as_str().unwrap_or("").to_string())]
+                                    }
+                                    Some("input_json_delta") => match tool_calls.get(&index) {
+                                        Some(id) => vec![StreamEvent::ToolCallArgsDelta {
+                                            id: id.clone(),
+                                            json_fragment: delta["partial_json"].as_str().unwrap_or("").to_string(),
+                                        }],
+                                        None => Vec::new(),
+                                    },
+                                    _ => Vec::new(),
+                                }
+                            }
+                            "content_block_stop" => tool_calls
+                                .remove(&index)
+                                .map(|id| StreamEvent::ToolCallEnd { id })
+                                .into_iter()
+                                .collect(),
+                            "message_delta" => {
+                                let completion_tokens = data["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32;
+                                let usage = TokenUsage {
+                                    prompt_tokens,
+                                    completion_tokens,
+                                    total_tokens: prompt_tokens + completion_tokens,
+                                };
+                                
+                                let mut events = Vec::new();
+                                if let Some(reason) = data["delta"]["stop_reason"].as_str() {
+                                    events.push(StreamEvent::Stop(reason.to_string()));
+                                }
+                                events.push(StreamEvent::Done(usage));
+                                events
+                            }
+                            "message_stop" => break 'read,
+                            "error" => {
+                                let error = ProviderError::from_body(status.as_u16(), &event.data)
+                                    .with_request_id(&request_id);
+                                forwarder.push(Err(error.into())).await;
+                                break 'read;
+                            }
+                            _ => Vec::new(),
+                        };
+                        
+                        for event in events {
+                            if !forwarder.push(Ok(event)).await {
+                                break 'read;
+                            }
+                        }
+                    }
+                }
+                
+                forwarder.finish().await;
+            });
+            
+            Ok(stream)
+        })
+        .await
+    }
+}
+
+/// Default OpenAI API base URL
+const OPENAI_API_URL: &str = "https://api.openai.com/v1";
+
+/// OpenAI model provider
+pub struct OpenAIProvider {
+    api_key: String,
+    organization: Option<String>,
+    project: Option<String>,
+    base_url: String,
+    client: reqwest::Client,
+    stream_options: StreamOptions,
+}
+
+impl OpenAIProvider {
+    /// Create a new OpenAI provider with the given API key
+    pub fn new(api_key: &str, organization: Option<&str>) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            organization: organization.map(|s| s.to_string()),
+            project: None,
+            base_url: OPENAI_API_URL.to_string(),
+            client: http::client(),
+            stream_options: StreamOptions::default(),
+        }
+    }
+    
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+    
+    /// Set the number of deltas buffered for streamed responses
+    pub fn with_stream_buffer(mut self, size: usize) -> Self {
+        self.stream_options.buffer = size;
+        self
+    }
+    
+    /// Set the backpressure policy for streamed responses
+    pub fn with_backpressure(mut self, backpressure: StreamBackpressure) -> Self {
+        self.stream_options.backpressure = backpressure;
+        self
+    }
+    
+    /// Set the organization sent in the `OpenAI-Organization` header
+    pub fn with_organization(mut self, organization: &str) -> Self {
+        self.organization = Some(organization.to_string());
+        self
+    }
+    
+    /// Set the project sent in the `OpenAI-Project` header
+    pub fn with_project(mut self, project: &str) -> Self {
+        self.project = Some(project.to_string());
+        self
+    }
+    
+    /// Override the API base URL (e.g. for a proxy or a mock server)
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    /// Build a POST request with authentication, org/project and request ID headers attached
+    fn post(&self, path: &str, request_id: &str) -> reqwest::RequestBuilder {
+        let mut builder = self.client
+            .post(format!("{}{}", self.base_url, path))
+            .bearer_auth(&self.api_key)
+            .header(REQUEST_ID_HEADER, request_id);
+        
+        if let Some(organization) = &self.organization {
+            builder = builder.header("OpenAI-Organization", organization);
+        }
+        
+        if let Some(project) = &self.project {
+            builder = builder.header("OpenAI-Project", project);
+        }
+        
+        builder
+    }
+    
+    /// Convert our message format to OpenAI's message format
+    ///
+    /// Unlike Anthropic, OpenAI accepts system messages inline, so they keep
+    /// their position in the conversation.
+    fn convert_messages(messages: Vec<Message>) -> Vec<serde_json::Value> {
+        messages
+            .into_iter()
+            .map(|msg| {
+                let role = match msg.role {
+                    MessageRole::System => "system",
+                    MessageRole::User => "user",
+                    MessageRole::Assistant => "assistant",
+                    MessageRole::Tool => "tool",
+                };
+                
+                let mut message = serde_json::json!({
+                    "role": role,
+                    "content": msg.content,
+                });
+                
+                if matches!(msg.role, MessageRole::User) && !msg.images.is_empty() {
+                    let mut parts = vec![serde_json::json!({ "type": "text", "text": msg.content })];
+                    parts.extend(msg.images.iter().map(|image| serde_json::json!({
+                        "type": "image_url",
+                        "image_url": { "url": image.data_url() },
+                    })));
+                    message["content"] = serde_json::json!(parts);
+                }
+                
+                if let Some(name) = msg.name {
+                    message["name"] = serde_json::json!(name);
+                }
+                
+                if let Some(tool_calls) = msg.tool_calls {
+                    let tool_calls: Vec<serde_json::Value> = tool_calls
+                        .into_iter()
+                        .map(|call| serde_json::json!({
+                            "id": call.id,
+                            "type": "function",
+                            "function": {
+                                "name": call.name,
+                                "arguments": call.arguments,
+                            },
+                        }))
+                        .collect();
+                    message["tool_calls"] = serde_json::json!(tool_calls);
+                }
+                
+                if let Some(tool_call_id) = msg.tool_call_id {
+                    message["tool_call_id"] = serde_json::json!(tool_call_id);
+                }
+                
+                message
+            })
+            .collect()
+    }
+    
+    /// Build the chat completions payload for a request
+    fn build_payload(request: &ModelRequest) -> serde_json::Value {
+        let openai_messages = Self::convert_messages(request.messages.clone());
+        
+        let mut payload = serde_json::json!({
+            "model": model_id(&request.model),
+            "messages": openai_messages,
+        });
+        
+        if let Some(temperature) = request.temperature {
+            payload["temperature"] = serde_json::json!(temperature);
+        }
+        
+        if let Some(max_tokens) = request.max_tokens {
+            payload["max_tokens"] = serde_json::json!(max_tokens);
+        }
+        
+        if let Some(top_p) = request.top_p {
+            payload["top_p"] = serde_json::json!(top_p);
+        }
+        
+        if let Some(tools) = &request.tools {
+            payload["tools"] = tools.iter().map(ToolDefinition::to_openai).collect();
+        }
+        
+        if let Some(tool_choice) = &request.tool_choice {
+            payload["tool_choice"] = tool_choice.to_openai();
+        }
+        
+        payload
+    }
+    
+    /// Send a chat completions request and parse the response
+    ///
+    /// Shared with `AzureOpenAIProvider`, which speaks the same protocol at a
+    /// different URL.
+    async fn send_chat(
+        builder: reqwest::RequestBuilder,
+        payload: &serde_json::Value,
+        model: String,
+        request_id: String,
+    ) -> Result<ModelResponse, BeaError> {
+        let response = builder.json(payload).send().await?;
+        let rate_limit = RateLimitInfo::from_response(&response);
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body)
+                .with_request_id(&request_id)
+                .into());
+        }
+        
+        let body: serde_json::Value = response.json().await?;
+        let choice = &body["choices"][0]["message"];
+        let stop_reason = body["choices"][0]["finish_reason"].as_str().map(|r| r.to_string());
+        
+        let mut message = Message::assistant(choice["content"].as_str().unwrap_or(""));
+        if let Some(calls) = choice["tool_calls"].as_array() {
+            message.tool_calls = Some(
+                calls
+                    .iter()
+                    .map(|call| ToolCall {
+                        id: call["id"].as_str().unwrap_or("").to_string(),
+                        name: call["function"]["name"].as_str().unwrap_or("").to_string(),
+                        arguments: call["function"]["arguments"].as_str().unwrap_or("{}").to_string(),
+                    })
+                    .collect(),
+            );
+        }
+        
+        let usage = &body["usage"];
+        let prompt_tokens = usage["prompt_tokens"].as_u64().unwrap_or(0) as u32;
+        let completion_tokens = usage["completion_tokens"].as_u64().unwrap_or(0) as u32;
+        
+        Ok(ModelResponse {
+            message,
+            model,
+            usage: TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            },
+            rate_limit,
+            stop_reason,
+            request_id: Some(request_id),
+        })
+    }
+    
+    /// Send a streamed chat completions request and forward its deltas
+    async fn stream_chat(
+        builder: reqwest::RequestBuilder,
+        mut payload: serde_json::Value,
+        model: String,
+        request_id: String,
+        stream_options: StreamOptions,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        payload["stream"] = serde_json::json!(true);
+        payload["stream_options"] = serde_json::json!({ "include_usage": true });
+        
+        let mut response = builder.json(&payload).send().await?;
+        let rate_limit = RateLimitInfo::from_response(&response);
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body)
+                .with_request_id(&request_id)
+                .into());
+        }
+        
+        let (mut forwarder, rx) = StreamForwarder::channel(stream_options);
+        let stream = ModelStream::new(&model, rx)
+            .with_request_id(&request_id)
+            .with_rate_limit(rate_limit);
+        
+        tokio::spawn(async move {
+            let mut parser = SseParser::default();
+            // IDs of the calls started so far, by index, until they end
+            let mut tool_calls: Vec<Option<String>> = Vec::new();
+            let mut usage = TokenUsage::default();
+            
+            'read: loop {
+                let chunk = tokio::select! {
+                    _ = cancel.cancelled() => return,
+                    chunk = response.chunk() => chunk,
+                };
+                let bytes = match chunk {
+                    Ok(Some(bytes)) => bytes,
+                    Ok(None) => break,
+                    Err(e) => {
+                        forwarder.push(Err(e.into())).await;
+                        forwarder.finish().await;
+                        return;
+                    }
+                };
+                
+                for event in parser.feed(&bytes) {
+                    if event.data == "[DONE]" {
+                        break 'read;
+                    }
+                    
+                    let data: serde_json::Value = match serde_json::from_str(&event.data) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            forwarder.push(Err(e.into())).await;
+                            forwarder.finish().await;
+                            return;
+                        }
+                    };
+                    
+                    if data.get("error").is_some() {
+                        let error = ProviderError::from_body(status.as_u16(), &event.data)
+                            .with_request_id(&request_id);
+                        forwarder.push(Err(error.into())).await;
+                        forwarder.finish().await;
+                        return;
+                    }
+                    
+                    if let Some(reported) = data.get("usage").filter(|u| !u.is_null()) {
+                        let prompt_tokens = reported["prompt_tokens"].as_u64().unwrap_or(0) as u32;
+                        let completion_tokens = reported["completion_tokens"].as_u64().unwrap_or(0) as u32;
+                        usage = TokenUsage {
+                            prompt_tokens,
+                            completion_tokens,
+                            total_tokens: prompt_tokens + completion_tokens,
+                        };
+                    }
+                    
+                    let choice = &data["choices"][0];
+                    let mut events = Vec::new();
+                    
+                    if let Some(text) = choice["delta"]["content"].as_str() {
+                        events.push(StreamEvent::ContentDelta(text.to_string()));
+                    }
+                    
+                    // Tool calls arrive in fragments keyed by index; the first carries the ID and name
+                    for fragment in choice["delta"]["tool_calls"].as_array().into_iter().flatten() {
+                        let index = fragment["index"].as_u64().unwrap_or(0) as usize;
+                        while tool_calls.len() <= index {
+                            tool_calls.push(None);
+                        }
+                        
+                        if let Some(id) = fragment["id"].as_str() {
+                            tool_calls[index] = Some(id.to_string());
+                            events.push(StreamEvent::ToolCallStart {
+                                id: id.to_string(),
+                                name: fragment["function"]["name"].as_str().unwrap_or("").to_string(),
+                            });
+                        }
+                        
+                        let arguments = fragment["function"]["arguments"].as_str().unwrap_or("");
+                        if let (Some(id), false) = (&tool_calls[index], arguments.is_empty()) {
+                            events.push(StreamEvent::ToolCallArgsDelta {
+                                id: id.clone(),
+                                json_fragment: arguments.to_string(),
+                            });
+                        }
+                    }
+                    
+                    // OpenAI doesn't mark the end of each call, only of the whole message
+                    if let Some(reason) = choice["finish_reason"].as_str() {
+                        events.extend(tool_calls.iter_mut().filter_map(Option::take).map(|id| StreamEvent::ToolCallEnd { id }));
+                        events.push(StreamEvent::Stop(reason.to_string()));
+                    }
+                    
+                    for event in events {
+                        if !forwarder.push(Ok(event)).await {
+                            return;
+                        }
+                    }
+                }
+            }
+            
+            // Usage arrives in a chunk of its own after the finish reason
+            for id in tool_calls.into_iter().flatten() {
+                forwarder.push(Ok(StreamEvent::ToolCallEnd { id })).await;
+            }
+            forwarder.push(Ok(StreamEvent::Done(usage))).await;
+            forwarder.finish().await;
+        });
+        
+        Ok(stream)
+    }
+}
+
+#[async_trait]
+impl ModelProvider for OpenAIProvider {
+    fn provider_name(&self) -> &str {
+        "openai"
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        // In a real implementation, this would query the OpenAI API
+        Ok(vec![
+            "gpt-4o".to_string(),
+            "gpt-4-turbo".to_string(),
+            "gpt-3.5-turbo".to_string(),
+        ])
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        cancellable(cancel, async move {
+            request.validate_tool_choice()?;
+            let payload = Self::build_payload(&request);
+            
+            let request_id = new_request_id();
+            let builder = self.post("/chat/completions", &request_id);
+            Self::send_chat(builder, &payload, request.model, request_id).await
+        })
+        .await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        cancellable(cancel.clone(), async move {
+            request.validate_tool_choice()?;
+            let payload = Self::build_payload(&request);
+            
+            let request_id = new_request_id();
+            let builder = self.post("/chat/completions", &request_id);
+            Self::stream_chat(builder, payload, request.model, request_id, self.stream_options, cancel).await
+        })
+        .await
+    }
+}
+
+/// Azure OpenAI model provider
+///
+/// Azure serves OpenAI models from per-resource deployments, so requests go
+/// to `{endpoint}/openai/deployments/{deployment}/chat/completions` with an
+/// `api-key` header instead of a bearer token.
+pub struct AzureOpenAIProvider {
+    endpoint: String,
+    api_key: String,
+    api_version: String,
+    deployments: HashMap<String, String>,
+    client: reqwest::Client,
+    stream_options: StreamOptions,
+}
+
+impl AzureOpenAIProvider {
+    /// Create a new Azure OpenAI provider
+    ///
+    /// `deployment_map` maps model names, as in `azure/gpt-4o`, to the Azure
+    /// deployment serving them. Models without an entry are sent to a
+    /// deployment of the same name.
+    pub fn new(endpoint: &str, api_key: &str, api_version: &str, deployment_map: HashMap<String, String>) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            api_version: api_version.to_string(),
+            deployments: deployment_map,
+            client: http::client(),
+            stream_options: StreamOptions::default(),
+        }
+    }
+    
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+    
+    /// Set the number of deltas buffered for streamed responses
+    pub fn with_stream_buffer(mut self, size: usize) -> Self {
+        self.stream_options.buffer = size;
+        self
+    }
+    
+    /// Set the backpressure policy for streamed responses
+    pub fn with_backpressure(mut self, backpressure: StreamBackpressure) -> Self {
+        self.stream_options.backpressure = backpressure;
+        self
+    }
+    
+    /// Get the deployment that serves a model
+    pub fn deployment<'a>(&'a self, model: &'a str) -> &'a str {
+        let model = model_id(model);
+        self.deployments.get(model).map(|d| d.as_str()).unwrap_or(model)
+    }
+    
+    /// Get the chat completions URL for a model's deployment
+    pub fn chat_url(&self, model: &str) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint,
+            self.deployment(model),
+            self.api_version
+        )
+    }
+    
+    /// Build a POST request for a model with the API key and request ID headers attached
+    fn post(&self, model: &str, request_id: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(self.chat_url(model))
+            .header("api-key", &self.api_key)
+            .header(REQUEST_ID_HEADER, request_id)
+    }
+    
+    /// Build the chat completions payload for a request
+    ///
+    /// The deployment in the URL picks the model, so the body doesn't name one.
+    fn build_payload(request: &ModelRequest) -> serde_json::Value {
+        let mut payload = OpenAIProvider::build_payload(request);
+        if let Some(payload) = payload.as_object_mut() {
+            payload.remove("model");
+        }
+        payload
+    }
+}
+
+#[async_trait]
+impl ModelProvider for AzureOpenAIProvider {
+    fn provider_name(&self) -> &str {
+        "azure"
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        let mut models: Vec<String> = self.deployments.keys().cloned().collect();
+        models.sort();
+        Ok(models)
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        cancellable(cancel, async move {
+            request.validate_tool_choice()?;
+            let payload = Self::build_payload(&request);
+            
+            let request_id = new_request_id();
+            let builder = self.post(&request.model, &request_id);
+            OpenAIProvider::send_chat(builder, &payload, request.model, request_id).await
+        })
+        .await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        cancellable(cancel.clone(), async move {
+            request.validate_tool_choice()?;
+            let payload = Self::build_payload(&request);
+            
+            let request_id = new_request_id();
+            let builder = self.post(&request.model, &request_id);
+            OpenAIProvider::stream_chat(builder, payload, request.model, request_id, self.stream_options, cancel).await
+        })
+        .await
+    }
+}
+
+/// Default Gemini API base URL
+const GOOGLE_API_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Google Gemini model provider
+pub struct GoogleProvider {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+    stream_options: StreamOptions,
+}
+
+impl GoogleProvider {
+    /// Create a new Google provider with the given API key
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            base_url: GOOGLE_API_URL.to_string(),
+            client: http::client(),
+            stream_options: StreamOptions::default(),
+        }
+    }
+    
+    /// Use a different API base URL, e.g. for a proxy or gateway
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+    
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+    
+    /// Set the number of deltas buffered for streamed responses
+    pub fn with_stream_buffer(mut self, size: usize) -> Self {
+        self.stream_options.buffer = size;
+        self
+    }
+    
+    /// Set the backpressure policy for streamed responses
+    pub fn with_backpressure(mut self, backpressure: StreamBackpressure) -> Self {
+        self.stream_options.backpressure = backpressure;
+        self
+    }
+    
+    /// Build a POST request for a model method with the API key and request ID headers attached
+    fn post(&self, model: &str, method: &str, request_id: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}/models/{}:{}", self.base_url, model_id(model), method))
+            .header("x-goog-api-key", &self.api_key)
+            .header(REQUEST_ID_HEADER, request_id)
+    }
+    
+    /// Convert our message format to Gemini's `contents` format
+    ///
+    /// Gemini only has `user` and `model` roles: system messages are returned
+    /// separately for `systemInstruction`, assistant turns become `model`, and
+    /// tool results are sent as `functionResponse` parts in a user turn.
+    fn convert_messages(&self, messages: Vec<Message>) -> (Option<String>, Vec<serde_json::Value>) {
+        let (system, messages): (Vec<Message>, Vec<Message>) = messages
+            .into_iter()
+            .partition(|m| matches!(m.role, MessageRole::System));
+        
+        let system = if system.is_empty() {
+            None
+        } else {
+            let blocks: Vec<&str> = system.iter().map(|m| m.content.as_str()).collect();
+            Some(blocks.join("\n\n"))
+        };
+        
+        let contents = messages
+            .into_iter()
+            .map(|msg| match msg.role {
+                MessageRole::Assistant => {
+                    let mut parts = Vec::new();
+                    if !msg.content.is_empty() {
+                        parts.push(serde_json::json!({ "text": msg.content }));
+                    }
+                    
+                    for call in msg.tool_calls.iter().flatten() {
+                        parts.push(serde_json::json!({
+                            "functionCall": {
+                                "name": call.name,
+                                "args": call.arguments_json(),
+                            },
+                        }));
+                    }
+                    
+                    serde_json::json!({ "role": "model", "parts": parts })
+                }
+                MessageRole::Tool => serde_json::json!({
+                    "role": "user",
+                    "parts": [{
+                        "functionResponse": {
+                            "name": msg.name.unwrap_or_default(),
+                            "response": { "content": msg.content },
+                        },
+                    }],
+                }),
+                _ => {
+                    let mut parts = vec![serde_json::json!({ "text": msg.content })];
+                    parts.extend(msg.images.iter().map(|image| serde_json::json!({
+                        "inlineData": {
+                            "mimeType": image.media_type,
+                            "data": image.data,
+                        },
+                    })));
+                    
+                    serde_json::json!({ "role": "user", "parts": parts })
+                }
+            })
+            .collect();
+        
+        (system, contents)
+    }
+    
+    /// Build the generateContent payload for a request
+    fn build_payload(&self, request: &ModelRequest) -> serde_json::Value {
+        let (system, contents) = self.convert_messages(request.messages.clone());
+        
+        let mut payload = serde_json::json!({
+            "contents": contents,
+        });
+        
+        if let Some(system) = system {
+            payload["systemInstruction"] = serde_json::json!({ "parts": [{ "text": system }] });
+        }
+        
+        let mut generation_config = serde_json::Map::new();
+        if let Some(temperature) = request.temperature {
+            generation_config.insert("temperature".to_string(), serde_json::json!(temperature));
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            generation_config.insert("maxOutputTokens".to_string(), serde_json::json!(max_tokens));
+        }
+        if let Some(top_p) = request.top_p {
+            generation_config.insert("topP".to_string(), serde_json::json!(top_p));
+        }
+        if !generation_config.is_empty() {
+            payload["generationConfig"] = serde_json::Value::Object(generation_config);
+        }
+        
+        if let Some(tools) = &request.tools {
+            let declarations: Vec<serde_json::Value> = tools
+                .iter()
+                .map(|tool| serde_json::json!({
+                    "name": tool.name,
+                    "description": tool.description.as_deref().unwrap_or(""),
+                    "parameters": tool.parameters,
+                }))
+                .collect();
+            payload["tools"] = serde_json::json!([{ "functionDeclarations": declarations }]);
+        }
+        
+        if let Some(tool_choice) = &request.tool_choice {
+            payload["toolConfig"] = tool_choice.to_google();
+        }
+        
+        payload
+    }
+    
+    /// Map a GenerateContentResponse into our response type
+    fn parse_response(body: &serde_json::Value, model: &str, request_id: &str) -> ModelResponse {
+        let candidate = &body["candidates"][0];
+        
+        let mut text = String::new();
+        let mut tool_calls = Vec::new();
+        for part in candidate["content"]["parts"].as_array().into_iter().flatten() {
+            if let Some(part_text) = part["text"].as_str() {
+                text.push_str(part_text);
+            }
+            
+            if let Some(call) = part.get("functionCall") {
+                tool_calls.push(ToolCall {
+                    // Gemini doesn't assign IDs to function calls
+                    id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                    name: call["name"].as_str().unwrap_or("").to_string(),
+                    arguments: call["args"].to_string(),
+                });
+            }
+        }
+        
+        let mut message = Message::assistant(&text);
+        if !tool_calls.is_empty() {
+            message.tool_calls = Some(tool_calls);
+        }
+        
+        let mut response = ModelResponse::delta(message, model, request_id);
+        
+        let usage = &body["usageMetadata"];
+        let prompt_tokens = usage["promptTokenCount"].as_u64().unwrap_or(0) as u32;
+        let completion_tokens = usage["candidatesTokenCount"].as_u64().unwrap_or(0) as u32;
+        response.usage = TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        };
+        response.stop_reason = candidate["finishReason"].as_str().map(|r| r.to_string());
+        
+        response
+    }
+}
+
+#[async_trait]
+impl ModelProvider for GoogleProvider {
+    fn provider_name(&self) -> &str {
+        "google"
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        // In a real implementation, this would query the Gemini API
+        Ok(vec![
+            "gemini-1.5-pro".to_string(),
+            "gemini-1.5-flash".to_string(),
+            "gemini-1.5-flash-8b".to_string(),
+            "gemini-1.0-pro".to_string(),
+        ])
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        cancellable(cancel, async move {
+            request.validate_tool_choice()?;
+            let payload = self.build_payload(&request);
+            
+            let request_id = new_request_id();
+            let response = self.post(&request.model, "generateContent", &request_id)
+                .json(&payload)
+                .send()
+                .await?;
+            let rate_limit = RateLimitInfo::from_response(&response);
+            
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ProviderError::from_body(status.as_u16(), &body)
+                    .with_request_id(&request_id)
+                    .into());
+            }
+            
+            let body: serde_json::Value = response.json().await?;
+            
+            let mut response = Self::parse_response(&body, &request.model, &request_id);
+            response.rate_limit = rate_limit;
+            Ok(response)
+        })
+        .await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        cancellable(cancel.clone(), async move {
+            request.validate_tool_choice()?;
+            let payload = self.build_payload(&request);
+            
+            let request_id = new_request_id();
+            let mut response = self.post(&request.model, "streamGenerateContent", &request_id)
+                .query(&[("alt", "sse")])
+                .json(&payload)
+                .send()
+                .await?;
+            let rate_limit = RateLimitInfo::from_response(&response);
+            
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ProviderError::from_body(status.as_u16(), &body)
+                    .with_request_id(&request_id)
+                    .into());
+            }
+            
+            let (mut forwarder, rx) = StreamForwarder::channel(self.stream_options);
+            let stream = ModelStream::new(&request.model, rx)
+                .with_request_id(&request_id)
+                .with_rate_limit(rate_limit);
+            let model = request.model;
+            
+            tokio::spawn(async move {
+                let mut parser = SseParser::default();
+                let mut usage = UsageAccumulator::new();
+                
+                'read: loop {
+                    let chunk = tokio::select! {
+                        _ = cancel.cancelled() => return,
+                        chunk = response.chunk() => chunk,
+                    };
+                    let bytes = match chunk {
+                        Ok(Some(bytes)) => bytes,
+                        Ok(None) => break,
+                        Err(e) => {
+                            forwarder.push(Err(e.into())).await;
+                            break;
+                        }
+                    };
+                    
+                    for event in parser.feed(&bytes) {
+                        let data: serde_json::Value = match serde_json::from_str(&event.data) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                forwarder.push(Err(e.into())).await;
+                                break 'read;
+                            }
+                        };
+                        
+                        if data.get("error").is_some() {
+                            let error = ProviderError::from_body(status.as_u16(), &event.data)
+                                .with_request_id(&request_id);
+                            forwarder.push(Err(error.into())).await;
+                            break 'read;
+                        }
+                        
+                        // Each event is a partial response; usage totals are cumulative
+                        let chunk = Self::parse_response(&data, &model, &request_id);
+                        usage.observe(&chunk);
+                        
+                        for event in chunk.into_events() {
+                            if !forwarder.push(Ok(event)).await {
+                                break 'read;
+                            }
+                        }
+                    }
+                }
+                
+                forwarder.push(Ok(StreamEvent::Done(usage.finish()))).await;
+                forwarder.finish().await;
+            });
+            
+            Ok(stream)
+        })
+        .await
+    }
+}
+
+/// Default address of a local Ollama server
+pub const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+/// Ollama provider for locally hosted models
+///
+/// Talks to Ollama's native `/api/chat` endpoint, so no API key is needed.
+/// Models are routed as `ollama/<name>`, e.g. `ollama/llama3`.
+pub struct OllamaProvider {
+    base_url: String,
+    client: reqwest::Client,
+    stream_options: StreamOptions,
+}
+
+impl OllamaProvider {
+    /// Create a new Ollama provider for the server at `base_url`
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: http::client(),
+            stream_options: StreamOptions::default(),
+        }
+    }
+    
+    /// Use the given HTTP client, e.g. one built with `HttpClientBuilder`
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+    
+    /// Set the number of deltas buffered for streamed responses
+    pub fn with_stream_buffer(mut self, size: usize) -> Self {
+        self.stream_options.buffer = size;
+        self
+    }
+    
+    /// Set the backpressure policy for streamed responses
+    pub fn with_backpressure(mut self, backpressure: StreamBackpressure) -> Self {
+        self.stream_options.backpressure = backpressure;
+        self
+    }
+    
+    /// Convert our message format to Ollama's chat format
     fn convert_messages(&self, messages: Vec<Message>) -> Vec<serde_json::Value> {
         messages
             .into_iter()
@@ -300,108 +2613,524 @@ impl OpenAIProvider {
                     "content": msg.content,
                 });
                 
-                if let Some(name) = msg.name {
-                    message["name"] = serde_json::json!(name);
+                if matches!(msg.role, MessageRole::User) && !msg.images.is_empty() {
+                    message["images"] = msg.images.iter().map(|image| serde_json::json!(image.data)).collect();
                 }
                 
-                if let Some(tool_calls) = msg.tool_calls {
-                    message["tool_calls"] = serde_json::json!(tool_calls);
+                if let Some(tool_calls) = &msg.tool_calls {
+                    message["tool_calls"] = tool_calls
+                        .iter()
+                        .map(|call| serde_json::json!({
+                            "function": {
+                                "name": call.name,
+                                "arguments": call.arguments_json(),
+                            },
+                        }))
+                        .collect();
                 }
                 
                 message
             })
             .collect()
     }
+    
+    /// Build the `/api/chat` payload for a request
+    fn build_payload(&self, request: &ModelRequest, stream: bool) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "model": model_id(&request.model),
+            "messages": self.convert_messages(request.messages.clone()),
+            "stream": stream,
+        });
+        
+        let mut options = serde_json::Map::new();
+        if let Some(temperature) = request.temperature {
+            options.insert("temperature".to_string(), serde_json::json!(temperature));
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            options.insert("num_predict".to_string(), serde_json::json!(max_tokens));
+        }
+        if let Some(top_p) = request.top_p {
+            options.insert("top_p".to_string(), serde_json::json!(top_p));
+        }
+        if !options.is_empty() {
+            payload["options"] = serde_json::Value::Object(options);
+        }
+        
+        // Ollama has no tool_choice; tools are always optional for the model
+        if let Some(tools) = &request.tools {
+            payload["tools"] = tools
+                .iter()
+                .map(|tool| serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description.as_deref().unwrap_or(""),
+                        "parameters": tool.parameters,
+                    },
+                }))
+                .collect();
+        }
+        
+        payload
+    }
+    
+    /// Map an `/api/chat` response, or one line of a streamed one, into our response type
+    fn parse_response(body: &serde_json::Value, model: &str, request_id: &str) -> ModelResponse {
+        let message = &body["message"];
+        
+        let tool_calls: Vec<ToolCall> = message["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|call| ToolCall {
+                // Ollama doesn't assign IDs to tool calls
+                id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+                name: call["function"]["name"].as_str().unwrap_or("").to_string(),
+                arguments: call["function"]["arguments"].to_string(),
+            })
+            .collect();
+        
+        let mut message = Message::assistant(message["content"].as_str().unwrap_or(""));
+        if !tool_calls.is_empty() {
+            message.tool_calls = Some(tool_calls);
+        }
+        
+        let mut response = ModelResponse::delta(message, model, request_id);
+        
+        // Counts are only reported on the final response
+        if body["done"].as_bool().unwrap_or(false) {
+            let prompt_tokens = body["prompt_eval_count"].as_u64().unwrap_or(0) as u32;
+            let completion_tokens = body["eval_count"].as_u64().unwrap_or(0) as u32;
+            response.usage = TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            };
+            response.stop_reason = Some(body["done_reason"].as_str().unwrap_or("stop").to_string());
+        }
+        
+        response
+    }
+    
+    /// Send a chat request, returning the response once the status is checked
+    async fn chat(&self, payload: &serde_json::Value, request_id: &str) -> Result<reqwest::Response, BeaError> {
+        let response = self.client
+            .post(format!("{}/api/chat", self.base_url))
+            .header(REQUEST_ID_HEADER, request_id)
+            .json(payload)
+            .send()
+            .await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body)
+                .with_request_id(request_id)
+                .into());
+        }
+        
+        Ok(response)
+    }
 }
 
 #[async_trait]
-impl ModelProvider for OpenAIProvider {
+impl ModelProvider for OllamaProvider {
     fn provider_name(&self) -> &str {
-        "openai"
+        "ollama"
     }
     
-    async fn available_models(&self) -> Result<Vec<String>, Box<dyn Error>> {
-        // In a real implementation, this would query the OpenAI API
-        Ok(vec![
-            "gpt-4o".to_string(),
-            "gpt-4-turbo".to_string(),
-            "gpt-3.5-turbo".to_string(),
-        ])
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        let response = self.client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body).into());
+        }
+        
+        let body: serde_json::Value = response.json().await?;
+        Ok(body["models"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|m| m["name"].as_str().map(|name| name.to_string()))
+            .collect())
     }
     
     async fn generate(
         &self,
         request: ModelRequest,
-    ) -> Result<ModelResponse, Box<dyn Error>> {
-        let openai_messages = self.convert_messages(request.messages);
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        cancellable(cancel, async move {
+            let payload = self.build_payload(&request, false);
+            
+            let request_id = new_request_id();
+            let body: serde_json::Value = self.chat(&payload, &request_id).await?.json().await?;
+            
+            Ok(Self::parse_response(&body, &request.model, &request_id))
+        })
+        .await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        cancellable(cancel.clone(), async move {
+            let payload = self.build_payload(&request, true);
+            
+            let request_id = new_request_id();
+            let mut response = self.chat(&payload, &request_id).await?;
+            
+            let (mut forwarder, rx) = StreamForwarder::channel(self.stream_options);
+            let stream = ModelStream::new(&request.model, rx).with_request_id(&request_id);
+            let model = request.model;
+            
+            tokio::spawn(async move {
+                // Ollama streams newline-delimited JSON rather than SSE
+                let mut buffer = Vec::new();
+                
+                'read: loop {
+                    let chunk = tokio::select! {
+                        _ = cancel.cancelled() => return,
+                        chunk = response.chunk() => chunk,
+                    };
+                    let bytes = match chunk {
+                        Ok(Some(bytes)) => bytes,
+                        Ok(None) => break,
+                        Err(e) => {
+                            forwarder.push(Err(e.into())).await;
+                            break;
+                        }
+                    };
+                    buffer.extend_from_slice(&bytes);
+                    
+                    while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=end).collect();
+                        if line.iter().all(u8::is_ascii_whitespace) {
+                            continue;
+                        }
+                        
+                        let data: serde_json::Value = match serde_json::from_slice(&line) {
+                            Ok(data) => data,
+                            Err(e) => {
+                                forwarder.push(Err(e.into())).await;
+                                break 'read;
+                            }
+                        };
+                        
+                        if data.get("error").is_some() {
+                            let error = ProviderError::from_body(200, &data.to_string())
+                                .with_request_id(&request_id);
+                            forwarder.push(Err(error.into())).await;
+                            break 'read;
+                        }
+                        
+                        // Counts only come with the final line, which also ends the stream
+                        let done = data["done"].as_bool().unwrap_or(false);
+                        let chunk = Self::parse_response(&data, &model, &request_id);
+                        let usage = chunk.usage.clone();
+                        
+                        let mut events = chunk.into_events();
+                        if done {
+                            events.push(StreamEvent::Done(usage));
+                        }
+                        
+                        for event in events {
+                            if !forwarder.push(Ok(event)).await {
+                                break 'read;
+                            }
+                        }
+                    }
+                }
+                
+                forwarder.finish().await;
+            });
+            
+            Ok(stream)
+        })
+        .await
+    }
+}
+
+/// Provider wrapper that records each interaction to a JSONL cassette
+///
+/// Every successful `generate` call appends one line of the form
+/// `{"request": ..., "response": ...}`. The line is written and flushed
+/// before `generate` returns, so once a call has completed its interaction
+/// can be read back from the cassette immediately. Lines appear in the order
+/// calls complete. Streamed responses are passed through without recording.
+pub struct RecordingProvider<P: ModelProvider> {
+    inner: P,
+    path: PathBuf,
+    cassette: std::sync::Mutex<BufWriter<File>>,
+}
+
+impl<P: ModelProvider> RecordingProvider<P> {
+    /// Create a new recording provider appending to the cassette at `path`
+    pub fn new(inner: P, path: impl AsRef<Path>) -> Result<Self, BeaError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
         
-        let mut payload = serde_json::json!({
-            "model": request.model,
-            "messages": openai_messages,
+        Ok(Self {
+            inner,
+            path,
+            cassette: std::sync::Mutex::new(BufWriter::new(file)),
+        })
+    }
+    
+    /// Get the cassette path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Flush any buffered interactions to the cassette
+    pub fn flush(&self) -> Result<(), BeaError> {
+        let mut cassette = self.cassette.lock().unwrap_or_else(|e| e.into_inner());
+        cassette.flush()?;
+        Ok(())
+    }
+    
+    /// Append one interaction to the cassette and flush it
+    fn record(&self, request: &ModelRequest, response: &ModelResponse) -> Result<(), BeaError> {
+        let line = serde_json::json!({
+            "request": request,
+            "response": response,
         });
         
-        if let Some(temperature) = request.temperature {
-            payload["temperature"] = serde_json::json!(temperature);
-        }
-        
-        if let Some(max_tokens) = request.max_tokens {
-            payload["max_tokens"] = serde_json::json!(max_tokens);
-        }
-        
-        if let Some(tools) = request.tools {
-            payload["tools"] = serde_json::json!(tools);
+        let mut cassette = self.cassette.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(cassette, "{}", line)?;
+        cassette.flush()?;
+        Ok(())
+    }
+}
+
+impl<P: ModelProvider> Drop for RecordingProvider<P> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[async_trait]
+impl<P: ModelProvider> ModelProvider for RecordingProvider<P> {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        self.inner.available_models().await
+    }
+    
+    fn supports_streaming(&self) -> bool {
+        self.inner.supports_streaming()
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        let response = self.inner.generate(request.clone(), cancel).await?;
+        self.record(&request, &response)?;
+        Ok(response)
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        self.inner.generate_stream(request, cancel).await
+    }
+}
+
+/// Number of characters in each content delta streamed by `MockProvider` by default
+const DEFAULT_MOCK_CHUNK_CHARS: usize = 8;
+
+/// Where `MockProvider` gets its responses from
+enum MockResponses {
+    Script(std::sync::Mutex<VecDeque<ModelResponse>>),
+    Function(Box<dyn Fn(&ModelRequest) -> ModelResponse + Send + Sync>),
+}
+
+/// Provider returning canned responses, for tests that shouldn't touch the network
+///
+/// Responses come either from a script, one per call in order, or from a
+/// function of the request. Calling a scripted provider after its script is
+/// used up fails with a provider error. Streamed responses are split into
+/// small content deltas, and tool calls are streamed with their arguments
+/// in pieces, so consumers see the same kind of events a real provider
+/// sends. Responses without a model name get the requested one.
+///
+/// It answers to `"mock"` unless renamed, e.g. to `"anthropic"` to stand
+/// in for a real provider:
+///
+/// ```ignore
+/// let provider = MockProvider::with_responses(vec![
+///     ModelResponse::new(Message::assistant("Hello!"), ""),
+/// ])
+/// .with_name("anthropic");
+/// registry.register_provider(provider).await?;
+/// ```
+pub struct MockProvider {
+    name: String,
+    models: Vec<String>,
+    responses: MockResponses,
+    chunk_chars: usize,
+    requests: std::sync::Mutex<Vec<ModelRequest>>,
+}
+
+impl MockProvider {
+    /// Create a provider returning these responses in order, one per call
+    pub fn with_responses(responses: Vec<ModelResponse>) -> Self {
+        Self::from_source(MockResponses::Script(std::sync::Mutex::new(responses.into())))
+    }
+    
+    /// Create a provider answering each request with `respond`
+    pub fn from_fn(respond: impl Fn(&ModelRequest) -> ModelResponse + Send + Sync + 'static) -> Self {
+        Self::from_source(MockResponses::Function(Box::new(respond)))
+    }
+    
+    /// Create a provider with the default settings around a source of responses
+    fn from_source(responses: MockResponses) -> Self {
+        Self {
+            name: "mock".to_string(),
+            models: Vec::new(),
+            responses,
+            chunk_chars: DEFAULT_MOCK_CHUNK_CHARS,
+            requests: std::sync::Mutex::new(Vec::new()),
         }
+    }
+    
+    /// Set the provider name the registry routes by
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+    
+    /// Set the models reported by `available_models`
+    pub fn with_models(mut self, models: Vec<&str>) -> Self {
+        self.models = models.into_iter().map(|m| m.to_string()).collect();
+        self
+    }
+    
+    /// Set how many characters each streamed delta carries
+    pub fn with_chunk_chars(mut self, chunk_chars: usize) -> Self {
+        self.chunk_chars = chunk_chars.max(1);
+        self
+    }
+    
+    /// Get the requests received so far, in order
+    pub fn requests(&self) -> Vec<ModelRequest> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    /// Record a request and produce its response
+    fn respond(&self, request: &ModelRequest) -> Result<ModelResponse, BeaError> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).push(request.clone());
         
-        // In a real implementation, this would call the OpenAI API
-        // and parse the response
-        
-        // Mock response for demonstration purposes
-        let response = ModelResponse {
-            message: Message::assistant("This is a response from GPT."),
-            model: request.model,
-            usage: TokenUsage {
-                prompt_tokens: 128,
-                completion_tokens: 64,
-                total_tokens: 192,
-            },
+        let mut response = match &self.responses {
+            MockResponses::Script(script) => script
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .pop_front()
+                .ok_or_else(|| ProviderError {
+                    status: 500,
+                    code: Some("mock_exhausted".to_string()),
+                    message: "mock provider has no scripted responses left".to_string(),
+                    request_id: None,
+                })?,
+            MockResponses::Function(respond) => respond(request),
         };
         
+        if response.model.is_empty() {
+            response.model = request.model.clone();
+        }
+        if response.request_id.is_none() {
+            response.request_id = Some(new_request_id());
+        }
         Ok(response)
     }
     
+    /// Split text into pieces of at most `chunk_chars` characters
+    fn chunks(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        chars.chunks(self.chunk_chars).map(|chunk| chunk.iter().collect()).collect()
+    }
+}
+
+#[async_trait]
+impl ModelProvider for MockProvider {
+    fn provider_name(&self) -> &str {
+        &self.name
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        Ok(self.models.clone())
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        if cancel.is_cancelled() {
+            return Err(BeaError::Cancelled);
+        }
+        self.respond(&request)
+    }
+    
     async fn generate_stream(
         &self,
         request: ModelRequest,
-    ) -> Result<tokio::sync::mpsc::Receiver<Result<ModelResponse, Box<dyn Error>>>, Box<dyn Error>> {
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        if cancel.is_cancelled() {
+            return Err(BeaError::Cancelled);
+        }
+        let response = self.respond(&request)?;
         
-        // In a real implementation, this would stream responses from the OpenAI API
+        let mut events: Vec<StreamEvent> = self.chunks(&response.message.content)
+            .into_iter()
+            .map(StreamEvent::ContentDelta)
+            .collect();
+        for call in response.message.tool_calls.iter().flatten() {
+            events.push(StreamEvent::ToolCallStart { id: call.id.clone(), name: call.name.clone() });
+            events.extend(self.chunks(&call.arguments).into_iter().map(|json_fragment| {
+                StreamEvent::ToolCallArgsDelta { id: call.id.clone(), json_fragment }
+            }));
+            events.push(StreamEvent::ToolCallEnd { id: call.id.clone() });
+        }
+        if let Some(reason) = &response.stop_reason {
+            events.push(StreamEvent::Stop(reason.clone()));
+        }
+        events.push(StreamEvent::Done(response.usage.clone()));
         
-        // For demonstration, we'll just send a single response
-        let cloned_request = request.clone();
-        tokio::spawn(async move {
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-            
-            let response = ModelResponse {
-                message: Message::assistant("This is a streamed response from GPT."),
-                model: cloned_request.model,
-                usage: TokenUsage {
-                    prompt_tokens: 128,
-                    completion_tokens: 64,
-                    total_tokens: 192,
-                },
-            };
-            
-            let _ = tx.send(Ok(response)).await;
-        });
+        // Everything is known up front, so the channel holds the whole stream
+        let (tx, rx) = tokio::sync::mpsc::channel(events.len());
+        for event in events {
+            let _ = tx.try_send(Ok(event));
+        }
         
-        Ok(rx)
+        let mut stream = ModelStream::new(&response.model, rx).with_rate_limit(response.rate_limit.clone());
+        if let Some(request_id) = &response.request_id {
+            stream = stream.with_request_id(request_id);
+        }
+        Ok(stream)
     }
 }
 
 /// Model registry for managing providers
 pub struct ModelRegistry {
-    providers: Arc<Mutex<HashMap<String, Box<dyn ModelProvider>>>>,
+    providers: Arc<Mutex<HashMap<String, Arc<dyn ModelProvider>>>>,
 }
 
 impl ModelRegistry {
@@ -412,26 +3141,522 @@ impl ModelRegistry {
         }
     }
     
+    /// Create a registry with every provider that has credentials
+    ///
+    /// Azure deployments are assumed to be named after their models. Ollama
+    /// needs no key, so it's always registered and only contacted for
+    /// `ollama/` models.
+    pub async fn with_default_providers(keys: &ProviderKeys) -> Result<Self, BeaError> {
+        let registry = Self::new();
+        
+        if let Some(key) = &keys.anthropic_api_key {
+            registry.register_provider(AnthropicProvider::new(key)).await?;
+        }
+        if let Some(key) = &keys.openai_api_key {
+            registry.register_provider(OpenAIProvider::new(key, keys.openai_org.as_deref())).await?;
+        }
+        if let Some(key) = &keys.google_api_key {
+            registry.register_provider(GoogleProvider::new(key)).await?;
+        }
+        if let (Some(endpoint), Some(key)) = (&keys.azure_openai_endpoint, &keys.azure_openai_api_key) {
+            let api_version = keys.azure_openai_api_version.as_deref().unwrap_or(DEFAULT_AZURE_API_VERSION);
+            registry.register_provider(AzureOpenAIProvider::new(endpoint, key, api_version, HashMap::new())).await?;
+        }
+        
+        let ollama_url = keys.ollama_host.as_deref().unwrap_or(OLLAMA_DEFAULT_URL);
+        registry.register_provider(OllamaProvider::new(ollama_url)).await?;
+        
+        Ok(registry)
+    }
+    
     /// Register a new provider
-    pub async fn register_provider<P: ModelProvider + 'static>(&self, provider: P) -> Result<(), Box<dyn Error>> {
+    pub async fn register_provider<P: ModelProvider + 'static>(&self, provider: P) -> Result<(), BeaError> {
         let mut providers = self.providers.lock().await;
-        providers.insert(provider.provider_name().to_string(), Box::new(provider));
+        providers.insert(provider.provider_name().to_string(), Arc::new(provider));
         Ok(())
     }
     
-    /// Get a provider by name
-    pub async fn get_provider(&self, provider_name: &str) -> Option<Box<dyn ModelProvider>> {
+    /// Get a shared handle to a provider by name
+    pub async fn get_provider(&self, provider_name: &str) -> Option<Arc<dyn ModelProvider>> {
         let providers = self.providers.lock().await;
-        providers.get(provider_name).map(|p| dyn_clone::clone_box(&**p))
+        providers.get(provider_name).cloned()
     }
     
     /// Generate a completion using the appropriate provider
-    pub async fn generate(&self, request: ModelRequest) -> Result<ModelResponse, Box<dyn Error>> {
-        let provider_name = request.model.split('/').next().unwrap_or("anthropic");
+    pub async fn generate(&self, request: ModelRequest) -> Result<ModelResponse, BeaError> {
+        self.generate_cancellable(request, CancellationToken::new()).await
+    }
+    
+    /// Generate a completion, giving up when `cancel` fires
+    #[tracing::instrument(
+        skip_all,
+        fields(model = %request.model, provider = request.model.split('/').next().unwrap_or("anthropic")),
+        err(Display)
+    )]
+    pub async fn generate_cancellable(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        let model = request.model.clone();
+        let provider_name = model.split('/').next().unwrap_or("anthropic");
+        
+        let provider = self.get_provider(provider_name).await
+            .ok_or_else(|| BeaError::ProviderNotFound(provider_name.to_string()))?;
+        
+        let started = std::time::Instant::now();
+        let result = provider.generate(request, cancel).await;
+        metrics::record_request(provider_name, &model, started.elapsed(), result.as_ref().map(ModelResponse::usage));
+        result
+    }
+    
+    /// Whether the provider for a model can stream completions
+    pub async fn supports_streaming(&self, model: &str) -> bool {
+        let provider_name = model.split('/').next().unwrap_or("anthropic");
+        let providers = self.providers.lock().await;
+        providers
+            .get(provider_name)
+            .map(|p| p.supports_streaming())
+            .unwrap_or(false)
+    }
+    
+    /// Stream a completion using the appropriate provider
+    pub async fn generate_stream(
+        &self,
+        request: ModelRequest,
+    ) -> Result<ModelStream, BeaError> {
+        self.generate_stream_cancellable(request, CancellationToken::new()).await
+    }
+    
+    /// Stream a completion, stopping the stream when `cancel` fires
+    ///
+    /// Cancelling aborts the HTTP request, ends the forwarding task and
+    /// closes the stream's channel. The request is recorded in the metrics
+    /// when the stream delivers its totals, an error, or its end.
+    #[tracing::instrument(
+        skip_all,
+        fields(model = %request.model, provider = request.model.split('/').next().unwrap_or("anthropic")),
+        err(Display)
+    )]
+    pub async fn generate_stream_cancellable(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        let model = request.model.clone();
+        let provider_name = model.split('/').next().unwrap_or("anthropic");
         
         let provider = self.get_provider(provider_name).await
-            .ok_or_else(|| format!("Provider not found: {}", provider_name))?;
+            .ok_or_else(|| BeaError::ProviderNotFound(provider_name.to_string()))?;
+        
+        let started = std::time::Instant::now();
+        match provider.generate_stream(request, cancel).await {
+            Ok(mut stream) => {
+                stream.timer = Some(StreamTimer { provider: provider_name.to_string(), started });
+                Ok(stream)
+            }
+            Err(e) => {
+                metrics::record_request(provider_name, &model, started.elapsed(), Err(&e));
+                Err(e)
+            }
+        }
+    }
+    
+    /// Stream a completion using the appropriate provider, calling `on_delta` with each piece of content
+    pub async fn generate_with_callback(
+        &self,
+        request: ModelRequest,
+        on_delta: impl FnMut(&str),
+    ) -> Result<ModelResponse, BeaError> {
+        let stream = self.generate_stream(request).await?;
+        collect_stream_with(stream, on_delta).await
+    }
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Request seen by a mock HTTP server
+    #[derive(Debug, Clone)]
+    struct Captured {
+        uri: String,
+        headers: axum::http::HeaderMap,
+    }
+    
+    /// Serve `reply` to every request on a local port, recording each request
+    async fn mock_server(reply: serde_json::Value) -> (String, Arc<std::sync::Mutex<Vec<Captured>>>) {
+        let captured = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = captured.clone();
+        let app = axum::Router::new().fallback(move |uri: axum::http::Uri, headers: axum::http::HeaderMap| {
+            let seen = seen.clone();
+            let reply = reply.clone();
+            async move {
+                seen.lock().unwrap().push(Captured { uri: uri.to_string(), headers });
+                axum::Json(reply)
+            }
+        });
+        
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), captured)
+    }
+    
+    /// Push `items` through a `DropOldest` forwarder with a buffer of 2
+    ///
+    /// Nothing is read until the producer has run as far as it can, so
+    /// everything beyond the buffer is subject to the drop policy.
+    async fn forward_to_a_slow_consumer(items: Vec<StreamItem>) -> Vec<Result<StreamEvent, String>> {
+        let options = StreamOptions { buffer: 2, backpressure: StreamBackpressure::DropOldest };
+        let (mut forwarder, mut rx) = StreamForwarder::channel(options);
+        tokio::spawn(async move {
+            for item in items {
+                if !forwarder.push(item).await {
+                    return;
+                }
+            }
+            forwarder.finish().await;
+        });
+        tokio::task::yield_now().await;
+        
+        let mut received = Vec::new();
+        while let Some(item) = rx.recv().await {
+            received.push(item.map_err(|e| e.to_string()));
+        }
+        received
+    }
+    
+    fn deltas(count: usize) -> Vec<StreamItem> {
+        (0..count).map(|i| Ok(StreamEvent::ContentDelta(i.to_string()))).collect()
+    }
+    
+    #[tokio::test]
+    async fn drop_oldest_only_drops_text_deltas() {
+        let mut items = deltas(4);
+        items.push(Ok(StreamEvent::Stop("end_turn".to_string())));
+        items.push(Ok(StreamEvent::ContentDelta("late".to_string())));
+        items.push(Ok(StreamEvent::Done(TokenUsage::new(3, 6))));
+        
+        let received = forward_to_a_slow_consumer(items).await;
+        
+        assert_eq!(
+            received,
+            vec![
+                Ok(StreamEvent::ContentDelta("0".to_string())),
+                Ok(StreamEvent::ContentDelta("1".to_string())),
+                Ok(StreamEvent::Stop("end_turn".to_string())),
+                Ok(StreamEvent::Done(TokenUsage::new(3, 6))),
+            ]
+        );
+    }
+    
+    #[tokio::test]
+    async fn tool_calls_and_errors_survive_drop_oldest() {
+        let mut items = deltas(4);
+        items.push(Ok(StreamEvent::ToolCallStart { id: "call_1".to_string(), name: "lookup".to_string() }));
+        items.push(Ok(StreamEvent::ToolCallArgsDelta { id: "call_1".to_string(), json_fragment: "{\"q\":".to_string() }));
+        items.push(Ok(StreamEvent::ContentDelta("late".to_string())));
+        items.push(Ok(StreamEvent::ToolCallArgsDelta { id: "call_1".to_string(), json_fragment: "\"x\"}".to_string() }));
+        items.push(Ok(StreamEvent::ToolCallEnd { id: "call_1".to_string() }));
+        items.push(Err(BeaError::Cancelled));
+        
+        let received = forward_to_a_slow_consumer(items).await;
+        
+        assert_eq!(
+            received,
+            vec![
+                Ok(StreamEvent::ContentDelta("0".to_string())),
+                Ok(StreamEvent::ContentDelta("1".to_string())),
+                Ok(StreamEvent::ToolCallStart { id: "call_1".to_string(), name: "lookup".to_string() }),
+                Ok(StreamEvent::ToolCallArgsDelta { id: "call_1".to_string(), json_fragment: "{\"q\":".to_string() }),
+                Ok(StreamEvent::ToolCallArgsDelta { id: "call_1".to_string(), json_fragment: "\"x\"}".to_string() }),
+                Ok(StreamEvent::ToolCallEnd { id: "call_1".to_string() }),
+                Err(BeaError::Cancelled.to_string()),
+            ]
+        );
+    }
+    
+    #[tokio::test]
+    async fn tool_calls_reassemble_after_drop_oldest() {
+        let mut items = deltas(6);
+        items.push(Ok(StreamEvent::ToolCallStart { id: "call_1".to_string(), name: "lookup".to_string() }));
+        items.push(Ok(StreamEvent::ToolCallArgsDelta { id: "call_1".to_string(), json_fragment: "{\"q\":\"x\"}".to_string() }));
+        items.push(Ok(StreamEvent::ToolCallEnd { id: "call_1".to_string() }));
+        items.push(Ok(StreamEvent::Stop("tool_use".to_string())));
+        items.push(Ok(StreamEvent::Done(TokenUsage::new(3, 6))));
+        
+        let options = StreamOptions { buffer: 2, backpressure: StreamBackpressure::DropOldest };
+        let (mut forwarder, rx) = StreamForwarder::channel(options);
+        tokio::spawn(async move {
+            for item in items {
+                forwarder.push(item).await;
+            }
+            forwarder.finish().await;
+        });
+        tokio::task::yield_now().await;
+        
+        let response = ModelStream::new("anthropic/claude", rx).collect().await.unwrap();
+        let calls = response.message().tool_calls().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name(), "lookup");
+        assert_eq!(calls[0].arguments_json(), serde_json::json!({ "q": "x" }));
+        assert_eq!(response.usage().completion_tokens(), 6);
+    }
+    
+    /// A minimal chat completions response
+    fn openai_reply() -> serde_json::Value {
+        serde_json::json!({
+            "choices": [{ "message": { "role": "assistant", "content": "hi" }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 3, "completion_tokens": 1 },
+        })
+    }
+    
+    #[tokio::test]
+    async fn openai_sends_organization_and_project_headers() {
+        let (url, captured) = mock_server(openai_reply()).await;
+        let provider = OpenAIProvider::new("sk-test", None)
+            .with_organization("org-123")
+            .with_project("proj-456")
+            .with_base_url(&url);
+        
+        let request = ModelRequest::new("openai/gpt-4o", vec![Message::user("hello")]);
+        let response = provider.generate(request, CancellationToken::new()).await.unwrap();
+        assert_eq!(response.message().content(), "hi");
+        
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured[0].uri, "/chat/completions");
+        let headers = &captured[0].headers;
+        assert_eq!(headers["openai-organization"], "org-123");
+        assert_eq!(headers["openai-project"], "proj-456");
+        assert_eq!(headers["authorization"], "Bearer sk-test");
+    }
+    
+    #[tokio::test]
+    async fn openai_omits_unset_organization_and_project() {
+        let (url, captured) = mock_server(openai_reply()).await;
+        let provider = OpenAIProvider::new("sk-test", None).with_base_url(&url);
+        
+        let request = ModelRequest::new("openai/gpt-4o", vec![Message::user("hello")]);
+        provider.generate(request, CancellationToken::new()).await.unwrap();
+        
+        let captured = captured.lock().unwrap();
+        assert!(!captured[0].headers.contains_key("openai-organization"));
+        assert!(!captured[0].headers.contains_key("openai-project"));
+    }
+    
+    /// An assistant turn calling a tool, followed by the tool's answer
+    fn tool_round_trip() -> Vec<Message> {
+        vec![
+            Message::user("What's 2 + 2?"),
+            Message::assistant("").with_tool_calls(vec![ToolCall::new("call_1", "calculator", r#"{"expression":"2 + 2"}"#)]),
+            Message::tool_result("4", "calculator", "call_1"),
+        ]
+    }
+    
+    #[test]
+    fn openai_converts_tool_calls_to_function_calls() {
+        let messages = OpenAIProvider::convert_messages(tool_round_trip());
+        
+        assert_eq!(
+            messages[1]["tool_calls"],
+            serde_json::json!([{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "calculator", "arguments": r#"{"expression":"2 + 2"}"# },
+            }])
+        );
+        assert_eq!(messages[2]["role"], "tool");
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[2]["content"], "4");
+    }
+    
+    #[test]
+    fn anthropic_converts_tool_calls_to_tool_use_blocks() {
+        let (_, messages) = AnthropicProvider::new("key").convert_messages(tool_round_trip());
+        
+        assert_eq!(
+            messages[1],
+            serde_json::json!({
+                "role": "assistant",
+                "content": [{
+                    "type": "tool_use",
+                    "id": "call_1",
+                    "name": "calculator",
+                    "input": { "expression": "2 + 2" },
+                }],
+            })
+        );
+        assert_eq!(
+            messages[2],
+            serde_json::json!({
+                "role": "user",
+                "content": [{ "type": "tool_result", "tool_use_id": "call_1", "content": "4" }],
+            })
+        );
+    }
+    
+    #[test]
+    fn anthropic_lifts_system_messages_out_of_the_conversation() {
+        let messages = vec![Message::system("Be brief."), Message::user("Hi")];
+        let (system, messages) = AnthropicProvider::new("key").convert_messages(messages);
+        
+        assert_eq!(system.as_deref(), Some("Be brief."));
+        assert_eq!(messages, vec![serde_json::json!({ "role": "user", "content": "Hi" })]);
+    }
+    
+    #[test]
+    fn openai_keeps_system_messages_inline() {
+        let messages = OpenAIProvider::convert_messages(vec![Message::system("Be brief."), Message::user("Hi")]);
+        
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "Be brief.");
+        assert_eq!(messages[1]["role"], "user");
+    }
+    
+    /// Request offering one `lookup_order` tool
+    fn request_with_tool(model: &str) -> ModelRequest {
+        let schema = serde_json::json!({ "type": "object", "properties": { "id": { "type": "string" } } });
+        ModelRequest::new(model, vec![Message::user("Where is order 7?")])
+            .with_tools(vec![ToolDefinition::new("lookup_order", Some("Find an order"), schema)])
+    }
+    
+    #[test]
+    fn anthropic_sends_tools_with_an_input_schema() {
+        let payload = AnthropicProvider::new("key").build_payload(&request_with_tool("anthropic/claude-3-haiku-20240307"));
+        
+        assert_eq!(payload["tools"], serde_json::json!([{
+            "name": "lookup_order",
+            "description": "Find an order",
+            "input_schema": { "type": "object", "properties": { "id": { "type": "string" } } },
+        }]));
+    }
+    
+    #[test]
+    fn openai_sends_tools_as_functions() {
+        let payload = OpenAIProvider::build_payload(&request_with_tool("openai/gpt-4o"));
+        
+        assert_eq!(payload["tools"], serde_json::json!([{
+            "type": "function",
+            "function": {
+                "name": "lookup_order",
+                "description": "Find an order",
+                "parameters": { "type": "object", "properties": { "id": { "type": "string" } } },
+            },
+        }]));
+    }
+    
+    #[test]
+    fn sampling_settings_reach_anthropic_and_openai() {
+        let request = |model: &str| {
+            ModelRequest::new(model, vec![Message::user("Hi")])
+                .with_temperature(0.25)
+                .with_top_p(0.5)
+                .with_max_tokens(256)
+        };
+        
+        let anthropic = AnthropicProvider::new("key").build_payload(&request("anthropic/claude-3-haiku-20240307"));
+        let openai = OpenAIProvider::build_payload(&request("openai/gpt-4o"));
+        for payload in [anthropic, openai] {
+            assert_eq!(payload["temperature"], 0.25);
+            assert_eq!(payload["top_p"], 0.5);
+            assert_eq!(payload["max_tokens"], 256);
+        }
+    }
+    
+    #[tokio::test]
+    async fn registry_hands_out_the_same_provider_instance() {
+        let registry = ModelRegistry::new();
+        registry.register_provider(OpenAIProvider::new("sk-test", None)).await.unwrap();
+        
+        let first = registry.get_provider("openai").await.unwrap();
+        let second = registry.get_provider("openai").await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(registry.get_provider("missing").await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn azure_routes_to_the_mapped_deployment_with_an_api_key() {
+        let (url, captured) = mock_server(openai_reply()).await;
+        let deployments = HashMap::from([("gpt-4o".to_string(), "prod-gpt4o".to_string())]);
+        let provider = AzureOpenAIProvider::new(&format!("{}/", url), "azure-key", "2024-06-01", deployments);
+        assert_eq!(
+            provider.chat_url("azure/gpt-4o"),
+            format!("{}/openai/deployments/prod-gpt4o/chat/completions?api-version=2024-06-01", url)
+        );
+        assert_eq!(provider.deployment("azure/gpt-4o-mini"), "gpt-4o-mini");
+        
+        let registry = ModelRegistry::new();
+        registry.register_provider(provider).await.unwrap();
+        let request = ModelRequest::new("azure/gpt-4o", vec![Message::user("hello")]);
+        let response = registry.generate(request).await.unwrap();
+        assert_eq!(response.message().content(), "hi");
+        
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured[0].uri, "/openai/deployments/prod-gpt4o/chat/completions?api-version=2024-06-01");
+        assert_eq!(captured[0].headers["api-key"], "azure-key");
+        assert!(!captured[0].headers.contains_key("authorization"));
+    }
+    
+    #[test]
+    fn streamed_usage_counts_the_prompt_once() {
+        let chunk = |prompt, completion| ModelResponse::new(Message::assistant(""), "").with_usage(TokenUsage::new(prompt, completion));
+        
+        let mut usage = UsageAccumulator::new();
+        usage.observe(&chunk(120, 0));
+        usage.observe(&chunk(0, 0));
+        usage.observe(&chunk(120, 45));
+        
+        assert_eq!(usage.finish(), TokenUsage::new(120, 45));
+        assert_eq!(UsageAccumulator::new().finish().total_tokens(), 0);
+    }
+    
+    #[tokio::test]
+    async fn recorded_sessions_can_be_read_back_and_replayed() {
+        let path = std::env::temp_dir().join(format!("bea-cassette-{}.jsonl", uuid::Uuid::new_v4().simple()));
+        let provider = MockProvider::with_responses(vec![
+            ModelResponse::new(Message::assistant("Hello!"), "").with_usage(TokenUsage::new(5, 2)),
+            ModelResponse::new(Message::assistant("Goodbye!"), "").with_usage(TokenUsage::new(9, 3)),
+        ]);
+        let recorder = RecordingProvider::new(provider, &path).unwrap();
+        for prompt in ["Hi", "Bye"] {
+            let request = ModelRequest::new("mock/echo", vec![Message::user(prompt)]);
+            recorder.generate(request, CancellationToken::new()).await.unwrap();
+        }
+        recorder.flush().unwrap();
+        
+        let cassette = std::fs::read_to_string(recorder.path()).unwrap();
+        let interactions: Vec<(ModelRequest, ModelResponse)> = cassette
+            .lines()
+            .map(|line| {
+                let mut line: serde_json::Value = serde_json::from_str(line).unwrap();
+                (
+                    serde_json::from_value(line["request"].take()).unwrap(),
+                    serde_json::from_value(line["response"].take()).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(interactions.len(), 2);
+        assert_eq!(interactions[0].0.messages[0].content(), "Hi");
+        assert_eq!(interactions[1].0.messages[0].content(), "Bye");
+        assert_eq!(interactions[1].1.message().content(), "Goodbye!");
+        assert_eq!(*interactions[1].1.usage(), TokenUsage::new(9, 3));
+        
+        // The recorded responses answer the same requests again
+        let (requests, responses): (Vec<_>, Vec<_>) = interactions.into_iter().unzip();
+        let replay = MockProvider::with_responses(responses);
+        let replayed = replay.generate(requests[0].clone(), CancellationToken::new()).await.unwrap();
+        assert_eq!(replayed.message().content(), "Hello!");
+        assert_eq!(replayed.model, "mock/echo");
         
-        provider.generate(request).await
+        drop(recorder);
+        std::fs::remove_file(path).unwrap();
     }
 }
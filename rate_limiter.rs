@@ -1,220 +1,211 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc, Duration};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
-use uuid::Uuid;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-/// Token type
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum TokenType {
-    Bearer,
-    API,
-    Deployment,
-    Session,
-}
-
-/// Token metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenMetadata {
-    /// Token ID
-    pub id: String,
-    /// Token type
-    pub token_type: TokenType,
-    /// Environment (production, staging, development)
-    pub environment: String,
-    /// Creation time
-    pub created_at: DateTime<Utc>,
-    /// Expiration time (None for non-expiring tokens)
-    pub expires_at: Option<DateTime<Utc>>,
-    /// Associated agent ID
-    pub agent_id: Option<String>,
-    /// Associated user ID
-    pub user_id: Option<String>,
-    /// Custom metadata
-    pub metadata: HashMap<String, String>,
-}
+use crate::error::BeaError;
+use crate::models::{ModelProvider, ModelRequest, ModelResponse, ModelStream};
 
-/// Claims for JWT tokens
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    /// Subject (token ID)
-    sub: String,
-    /// Issuer
-    iss: String,
-    /// Issued at
-    iat: i64,
-    /// Expiration time
-    exp: Option<i64>,
-    /// Token type
-    #[serde(rename = "type")]
-    token_type: String,
-    /// Environment
-    env: String,
-    /// Agent ID
-    #[serde(skip_serializing_if = "Option::is_none")]
-    aid: Option<String>,
-    /// User ID
-    #[serde(skip_serializing_if = "Option::is_none")]
-    uid: Option<String>,
-    /// Custom metadata
-    #[serde(skip_serializing_if = "Option::is_none")]
-    meta: Option<HashMap<String, String>>,
+/// Token-bucket rate limiter
+///
+/// The bucket holds up to `burst` tokens and refills continuously at
+/// `requests_per_minute`. Callers that find it empty reserve the next token
+/// and sleep until it is due, so waiters are served in arrival order. A
+/// caller that stops waiting gives its reserved token back.
+#[derive(Debug)]
+pub struct RateLimiter {
+    refill_per_sec: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
 }
 
-/// Token store for managing tokens
-pub struct TokenStore {
-    tokens: Arc<Mutex<HashMap<String, TokenMetadata>>>,
-    jwt_secret: String,
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
 }
 
-impl TokenStore {
-    /// Create a new token store
-    pub fn new(jwt_secret: &str) -> Self {
+impl RateLimiter {
+    /// Create a new rate limiter allowing `requests_per_minute` with up to `burst` at once
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        
         Self {
-            tokens: Arc::new(Mutex::new(HashMap::new())),
-            jwt_secret: jwt_secret.to_string(),
+            refill_per_sec: requests_per_minute.max(1) as f64 / 60.0,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
         }
     }
     
-    /// Generate a new token
-    pub async fn generate_token(
-        &self,
-        token_type: TokenType,
-        environment: &str,
-        duration: Option<Duration>,
-        agent_id: Option<&str>,
-        user_id: Option<&str>,
-        metadata: Option<HashMap<String, String>>,
-    ) -> Result<String, Box<dyn Error>> {
-        let token_id = format!("tok_{}", Uuid::new_v4().to_string().replace("-", ""));
-        
-        let now = Utc::now();
-        let expires_at = duration.map(|d| now + d);
-        
-        let token_metadata = TokenMetadata {
-            id: token_id.clone(),
-            token_type: token_type.clone(),
-            environment: environment.to_string(),
-            created_at: now,
-            expires_at,
-            agent_id: agent_id.map(|s| s.to_string()),
-            user_id: user_id.map(|s| s.to_string()),
-            metadata: metadata.unwrap_or_default(),
-        };
-        
-        // Create JWT claims
-        let claims = Claims {
-            sub: token_id.clone(),
-            iss: "bea-bot".to_string(),
-            iat: now.timestamp(),
-            exp: expires_at.map(|exp| exp.timestamp()),
-            token_type: match token_type {
-                TokenType::Bearer => "bearer",
-                TokenType::API => "api",
-                TokenType::Deployment => "deployment",
-                TokenType::Session => "session",
-            }.to_string(),
-            env: environment.to_string(),
-            aid: agent_id.map(|s| s.to_string()),
-            uid: user_id.map(|s| s.to_string()),
-            meta: if token_metadata.metadata.is_empty() {
-                None
-            } else {
-                Some(token_metadata.metadata.clone())
-            },
-        };
-        
-        // Generate JWT token
-        let token = encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
-        )?;
-        
-        // Store token metadata
-        let mut tokens = self.tokens.lock().await;
-        tokens.insert(token_id, token_metadata);
-        
-        // Generate Bea Bot token format
-        let token_prefix = match token_type {
-            TokenType::Bearer => "bea_b",
-            TokenType::API => "bea_a",
-            TokenType::Deployment => "bea_d",
-            TokenType::Session => "bea_s",
+    /// Wait until a request is allowed
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.burst);
+            bucket.last_refill = now;
+            
+            // Take the token now, even if that leaves the bucket in debt
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            
+            Duration::from_secs_f64(-bucket.tokens / self.refill_per_sec)
         };
         
-        Ok(format!("{}_{}", token_prefix, token))
+        let reservation = Reservation { limiter: self, kept: false };
+        tracing::debug!(wait_ms = wait.as_millis() as u64, "rate limited, waiting");
+        tokio::time::sleep(wait).await;
+        reservation.keep();
+    }
+}
+
+/// Token reserved by a waiting caller, returned to the bucket if the wait is dropped
+struct Reservation<'a> {
+    limiter: &'a RateLimiter,
+    kept: bool,
+}
+
+impl Reservation<'_> {
+    /// The wait finished, so the token is spent
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.kept {
+            let mut bucket = self.limiter.bucket.lock().unwrap();
+            bucket.tokens = (bucket.tokens + 1.0).min(self.limiter.burst);
+        }
+    }
+}
+
+/// Provider wrapper that waits on a rate limiter before each request
+pub struct RateLimitedProvider<P: ModelProvider> {
+    inner: P,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<P: ModelProvider> RateLimitedProvider<P> {
+    /// Create a new rate-limited provider with its own limiter
+    pub fn new(inner: P, requests_per_minute: u32, burst: u32) -> Self {
+        Self::with_limiter(inner, Arc::new(RateLimiter::new(requests_per_minute, burst)))
+    }
+    
+    /// Create a new rate-limited provider sharing an existing limiter
+    pub fn with_limiter(inner: P, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+    
+    /// Get the limiter used by this provider
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
     }
     
-    /// Validate a token
-    pub async fn validate_token(&self, token: &str) -> Result<TokenMetadata, Box<dyn Error>> {
-        // Extract token type and JWT
-        let parts: Vec<&str> = token.split('_').collect();
-        if parts.len() < 3 || parts[0] != "bea" {
-            return Err("Invalid token format".into());
+    /// Wait on the limiter, giving up if `cancel` fires first
+    async fn acquire(&self, cancel: &CancellationToken) -> Result<(), BeaError> {
+        tokio::select! {
+            _ = cancel.cancelled() => Err(BeaError::Cancelled),
+            _ = self.limiter.acquire() => Ok(()),
         }
+    }
+}
+
+#[async_trait]
+impl<P: ModelProvider> ModelProvider for RateLimitedProvider<P> {
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+    
+    async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+        self.inner.available_models().await
+    }
+    
+    fn supports_streaming(&self) -> bool {
+        self.inner.supports_streaming()
+    }
+    
+    async fn generate(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelResponse, BeaError> {
+        self.acquire(&cancel).await?;
+        self.inner.generate(request, cancel).await
+    }
+    
+    async fn generate_stream(
+        &self,
+        request: ModelRequest,
+        cancel: CancellationToken,
+    ) -> Result<ModelStream, BeaError> {
+        self.acquire(&cancel).await?;
+        self.inner.generate_stream(request, cancel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Tokio's timer rounds sleeps up to the next millisecond, so a wait can
+    /// end slightly after it was due, but never before
+    fn assert_waited(actual: Duration, expected: Duration) {
+        assert!(
+            actual >= expected && actual <= expected + Duration::from_millis(5),
+            "waited {:?}, expected {:?}",
+            actual,
+            expected
+        );
+    }
+    
+    #[tokio::test(start_paused = true)]
+    async fn requests_beyond_the_burst_wait_for_refills() {
+        // 60 a minute refills one token a second
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
         
-        let token_type = match parts[1] {
-            "b" => TokenType::Bearer,
-            "a" => TokenType::API,
-            "d" => TokenType::Deployment,
-            "s" => TokenType::Session,
-            _ => return Err("Invalid token type".into()),
-        };
-        
-        let jwt = parts[2..].join("_");
-        
-        // Validate JWT
-        let validation = Validation::default();
-        let token_data = decode::<Claims>(
-            &jwt,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &validation,
-        )?;
-        
-        let claims = token_data.claims;
-        
-        // Check if token exists in store
-        let tokens = self.tokens.lock().await;
-        let token_metadata = tokens.get(&claims.sub).ok_or("Token not found")?;
-        
-        // Check if token is expired
-        if let Some(expires_at) = token_metadata.expires_at {
-            if expires_at < Utc::now() {
-                return Err("Token expired".into());
-            }
+        let mut finished = Vec::new();
+        for _ in 0..10 {
+            limiter.acquire().await;
+            finished.push(start.elapsed());
         }
         
-        Ok(token_metadata.clone())
-    }
-    
-    /// Revoke a token
-    pub async fn revoke_token(&self, token_id: &str) -> Result<(), Box<dyn Error>> {
-        let mut tokens = self.tokens.lock().await;
-        tokens.remove(token_id).ok_or_else(|| "Token not found".into())?;
-        Ok(())
+        // The burst goes straight through, then one request a second
+        for (i, waited) in finished.into_iter().enumerate() {
+            assert_waited(waited, Duration::from_secs((i as u64).saturating_sub(1)));
+        }
     }
     
-    /// List tokens for an agent
-    pub async fn list_tokens_for_agent(&self, agent_id: &str) -> Vec<TokenMetadata> {
-        let tokens = self.tokens.lock().await;
-        tokens
-            .values()
-            .filter(|t| t.agent_id.as_deref() == Some(agent_id))
-            .cloned()
-            .collect()
+    #[tokio::test]
+    async fn cancelled_requests_stop_waiting() {
+        let provider = RateLimitedProvider::new(crate::models::MockProvider::with_responses(Vec::new()), 1, 1);
+        provider.limiter().acquire().await;
+        
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let request = ModelRequest::new("mock/model", Vec::new());
+        assert!(matches!(provider.generate(request, cancel).await, Err(BeaError::Cancelled)));
     }
     
-    /// List tokens for a user
-    pub async fn list_tokens_for_user(&self, user_id: &str) -> Vec<TokenMetadata> {
-        let tokens = self.tokens.lock().await;
-        tokens
-            .values()
-            .filter(|t| t.user_id.as_deref() == Some(user_id))
-            .cloned()
-            .collect()
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_waits_give_their_token_back() {
+        let limiter = RateLimiter::new(60, 1);
+        let start = Instant::now();
+        limiter.acquire().await;
+        
+        // Gives up half way through its one second wait
+        assert!(tokio::time::timeout(Duration::from_millis(500), limiter.acquire()).await.is_err());
+        
+        limiter.acquire().await;
+        assert_waited(start.elapsed(), Duration::from_secs(1));
     }
 }
@@ -0,0 +1,1733 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::{Agent, AgentPermit, AgentStateManager, BeaError};
+use crate::filters::{ContentFilter, FilterChain};
+use crate::logs::{InMemoryLogStore, LogEntry, LogLevel, LogStore};
+use crate::models::{collect_stream, count_message_tokens, Image, Message, MessageRole, ModelRegistry, ModelRequest, ModelResponse, ModelStream, StreamEvent, TokenUsage, ToolCall};
+use crate::sites::FileUploadConfig;
+use crate::tokens::{TokenMetadata, TokenStore};
+
+/// Shared state for the runtime's request handlers
+#[derive(Clone)]
+pub struct AppState {
+    agent: Arc<Agent>,
+    registry: Arc<ModelRegistry>,
+    tokens: Option<Arc<TokenStore>>,
+    logs: Option<Arc<dyn LogStore>>,
+    filters: FilterChain,
+    agent_states: Option<Arc<AgentStateManager>>,
+    file_upload: Option<FileUploadConfig>,
+    uploads: Arc<Mutex<UploadStore>>,
+    shutdown: CancellationToken,
+}
+
+impl AppState {
+    /// Create the state for serving an agent
+    pub fn new(agent: Agent, registry: Arc<ModelRegistry>) -> Self {
+        Self {
+            agent: Arc::new(agent),
+            registry,
+            tokens: None,
+            logs: None,
+            filters: FilterChain::new(),
+            agent_states: None,
+            file_upload: None,
+            uploads: Arc::new(Mutex::new(UploadStore::new(UPLOAD_TTL, UPLOAD_STORE_BYTES))),
+            shutdown: CancellationToken::new(),
+        }
+    }
+    
+    /// Require a valid `Authorization: Bearer bea_...` token from this store on API routes
+    ///
+    /// Without a token store, API routes are open.
+    pub fn with_token_store(mut self, tokens: Arc<TokenStore>) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+    
+    /// Record request and response events in this log store, and serve them at `/v1/logs`
+    pub fn with_log_store(mut self, logs: Arc<dyn LogStore>) -> Self {
+        self.logs = Some(logs);
+        self
+    }
+    
+    /// Take a request slot from this manager before each model call
+    ///
+    /// Requests over the agent's concurrency limit queue, and are rejected
+    /// with 429 Too Many Requests once the queue is full.
+    pub fn with_agent_states(mut self, agent_states: Arc<AgentStateManager>) -> Self {
+        self.agent_states = Some(agent_states);
+        self
+    }
+    
+    /// Accept file uploads at `/v1/agents/{name}/files` within these limits
+    ///
+    /// Uploads are kept in memory for `UPLOAD_TTL`, and the oldest are
+    /// dropped once they add up to `UPLOAD_STORE_BYTES`. Chat requests refer
+    /// to them by the returned reference in `attachments`.
+    pub fn with_file_upload(mut self, file_upload: FileUploadConfig) -> Self {
+        self.file_upload = Some(file_upload);
+        self
+    }
+    
+    /// Run chat requests and responses through a content filter, after any already added
+    ///
+    /// User messages are filtered before they reach the model, and blocked
+    /// input is rejected without calling it. Streamed responses are filtered
+    /// one delta at a time, so a pattern split across deltas isn't caught.
+    pub fn with_content_filter(mut self, filter: Arc<dyn ContentFilter>) -> Self {
+        self.filters = self.filters.with_filter(filter);
+        self
+    }
+    
+    /// End `events` once the server stops waiting for in-flight requests
+    fn until_shutdown<S: Stream>(&self, events: S) -> impl Stream<Item = S::Item> {
+        events.take_until(self.shutdown.clone().cancelled_owned())
+    }
+    
+    /// Run streamed content deltas through the output filters
+    ///
+    /// A blocked delta is replaced with the error and ends the stream.
+    fn filter_stream(&self, mut stream: ModelStream) -> ModelStream {
+        if self.filters.is_empty() {
+            return stream;
+        }
+        
+        let filters = self.filters.clone();
+        let (tx, filtered) = tokio::sync::mpsc::channel(STREAM_FILTER_BUFFER);
+        let mut rx = stream.replace_events(filtered);
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                let event = match event {
+                    Ok(StreamEvent::ContentDelta(text)) => filters.filter_output(&text).map(StreamEvent::ContentDelta),
+                    other => other,
+                };
+                let failed = event.is_err();
+                if tx.send(event).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        stream
+    }
+    
+    /// Write an event to the log store, if one is configured
+    async fn log(&self, level: LogLevel, event: &str, message: &str) {
+        if let Some(logs) = &self.logs {
+            logs.append(LogEntry::new(self.agent.name(), level, event, message)).await;
+        }
+    }
+    
+    /// Log the outcome of a model call
+    async fn log_outcome(&self, result: &Result<ModelResponse, BeaError>) {
+        match result {
+            Ok(response) => self.log_response(response.usage(), response.stop_reason()).await,
+            Err(e) => self.log(LogLevel::Error, "error", &e.to_string()).await,
+        }
+    }
+    
+    /// Log a completed response's token count and stop reason
+    async fn log_response(&self, usage: &TokenUsage, stop_reason: Option<&str>) {
+        let message = format!(
+            "{} tokens, stop reason {}",
+            usage.total_tokens(),
+            stop_reason.unwrap_or("unknown")
+        );
+        self.log(LogLevel::Info, "response", &message).await;
+    }
+    
+    /// Charge and log a streamed response once it ends
+    ///
+    /// Usage comes from the stream's `Done` event and is counted against the
+    /// caller's budget; the outcome is logged when the stream ends, including
+    /// when the client disconnects first.
+    fn account_stream(&self, caller: Option<TokenMetadata>, mut stream: ModelStream) -> ModelStream {
+        let state = self.clone();
+        let (tx, accounted) = tokio::sync::mpsc::channel(STREAM_FILTER_BUFFER);
+        let mut rx = stream.replace_events(accounted);
+        tokio::spawn(async move {
+            let mut usage = None;
+            let mut stop_reason = None;
+            let mut error = None;
+            while let Some(event) = rx.recv().await {
+                match &event {
+                    Ok(StreamEvent::Done(totals)) => usage = Some(totals.clone()),
+                    Ok(StreamEvent::Stop(reason)) => stop_reason = Some(reason.clone()),
+                    Err(e) => error = Some(e.to_string()),
+                    _ => {}
+                }
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+            
+            if let Some(usage) = &usage {
+                state.record_usage(caller.as_ref(), usage).await;
+            }
+            match (error, usage) {
+                (Some(e), _) => state.log(LogLevel::Error, "error", &e).await,
+                (None, Some(usage)) => state.log_response(&usage, stop_reason.as_deref()).await,
+                (None, None) => state.log(LogLevel::Error, "error", "stream ended before completing").await,
+            }
+        });
+        stream
+    }
+    
+    /// Log an incoming chat request
+    async fn log_request(&self, messages: usize, mode: ResponseMode) {
+        let mode = match mode {
+            ResponseMode::Json => "json",
+            ResponseMode::EventStream => "stream",
+        };
+        let message = format!("{} messages for {} ({})", messages, self.agent.model(), mode);
+        self.log(LogLevel::Info, "request", &message).await;
+    }
+    
+    /// Validate the request's bearer token, if a token store is configured
+    async fn authorize(&self, headers: &HeaderMap) -> Result<Option<TokenMetadata>, BeaError> {
+        let Some(tokens) = &self.tokens else {
+            return Ok(None);
+        };
+        
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(BeaError::MissingToken)?;
+        
+        tokens.validate_token(token.trim()).await.map(Some)
+    }
+    
+    /// Count a response's tokens against the caller's budget
+    async fn record_usage(&self, caller: Option<&TokenMetadata>, usage: &TokenUsage) {
+        if let (Some(tokens), Some(caller)) = (&self.tokens, caller) {
+            let used = u64::from(usage.total_tokens());
+            // A token revoked mid-request has nothing left to charge, and one
+            // over budget is refused the next time it is validated
+            if let Err(BeaError::QuotaExceeded { budget, .. }) = tokens.record_usage(&caller.id, used).await {
+                tracing::debug!(token_id = %caller.id, budget, "token has gone over its budget");
+            }
+        }
+    }
+    
+    /// Wait for a request slot for the agent, if a state manager is configured
+    async fn acquire_slot(&self) -> Result<Option<AgentPermit>, BeaError> {
+        match &self.agent_states {
+            Some(agent_states) => agent_states.acquire_slot(self.agent.name()).await.map(Some),
+            None => Ok(None),
+        }
+    }
+    
+    /// Add the uploads referenced by `attachments` to the last user message
+    ///
+    /// Text files are put in front of the message's content as context;
+    /// images are attached for the model to look at.
+    async fn attach_uploads(&self, messages: &mut [Message], attachments: &[String]) -> Result<(), BeaError> {
+        if attachments.is_empty() {
+            return Ok(());
+        }
+        
+        let message = messages
+            .iter_mut()
+            .rev()
+            .find(|message| matches!(message.role(), MessageRole::User))
+            .ok_or_else(|| BeaError::UploadRejected("attachments need a user message".to_string()))?;
+        
+        let uploads = self.uploads.lock().await;
+        let now = Instant::now();
+        let mut context = String::new();
+        let mut images = Vec::new();
+        for reference in attachments {
+            let upload = uploads
+                .get(reference, now)
+                .ok_or_else(|| BeaError::UploadRejected(format!("unknown attachment {}", reference)))?;
+            match &upload.content {
+                UploadContent::Text(text) => {
+                    context.push_str(&format!("Attached file {}:\n{}\n\n", upload.name, text));
+                }
+                UploadContent::Image(image) => images.push(image.clone()),
+            }
+        }
+        
+        if !context.is_empty() {
+            let content = format!("{}{}", context, message.content());
+            message.set_content(content);
+        }
+        if !images.is_empty() {
+            *message = images.into_iter().fold(message.clone(), Message::with_image);
+        }
+        Ok(())
+    }
+    
+    /// Get the agent being served
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+    
+    /// Get the model registry
+    pub fn registry(&self) -> &Arc<ModelRegistry> {
+        &self.registry
+    }
+}
+
+/// Number of filtered deltas buffered between the model and the client
+const STREAM_FILTER_BUFFER: usize = 32;
+
+/// Build the runtime's routes
+pub fn router(state: AppState) -> Router {
+    // Let oversized uploads through to `FileUploadConfig::check`, which reports the limit
+    let upload_limit = state
+        .file_upload
+        .as_ref()
+        .map_or(0, |upload| usize::try_from(upload.max_size).unwrap_or(usize::MAX).saturating_add(1));
+    
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/chat", post(chat))
+        .route("/v1/agents/:name/chat", post(agent_chat))
+        .route("/v1/agents/:name/files", post(upload_file).layer(DefaultBodyLimit::max(upload_limit)))
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/tokens/estimate", post(estimate_tokens))
+        .route("/v1/logs", get(logs))
+        .with_state(state)
+}
+
+/// Time in-flight requests get to finish after a shutdown signal by default
+pub const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+/// Serve an agent over HTTP on `addr` until Ctrl-C or SIGTERM
+///
+/// API routes are open and logs are kept in memory; use `serve_with_state`
+/// to require tokens or use another log store.
+pub async fn serve(agent: Agent, registry: Arc<ModelRegistry>, addr: SocketAddr) -> Result<(), BeaError> {
+    let state = AppState::new(agent, registry).with_log_store(Arc::new(InMemoryLogStore::new()));
+    serve_with_state(state, addr).await
+}
+
+/// Serve the runtime with the given state on `addr` until Ctrl-C or SIGTERM
+///
+/// In-flight requests get `DEFAULT_SHUTDOWN_GRACE` to finish.
+pub async fn serve_with_state(state: AppState, addr: SocketAddr) -> Result<(), BeaError> {
+    serve_with_shutdown(state, addr, shutdown_signal(), DEFAULT_SHUTDOWN_GRACE).await
+}
+
+/// Serve the runtime on `addr` until `shutdown` completes
+///
+/// Once it does, the listener is closed so new connections are refused,
+/// and in-flight requests, including open event streams, keep running for
+/// up to `grace`. After that, model calls still running are cancelled and
+/// open event streams are ended, which closes their connections.
+pub async fn serve_with_shutdown<F>(
+    state: AppState,
+    addr: SocketAddr,
+    shutdown: F,
+    grace: Duration,
+) -> Result<(), BeaError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let cut_off = state.shutdown.clone();
+    
+    let (signalled_tx, signalled_rx) = oneshot::channel();
+    let signal = async move {
+        shutdown.await;
+        let _ = signalled_tx.send(());
+    };
+    
+    let server = axum::serve(listener, router(state)).with_graceful_shutdown(signal).into_future();
+    tokio::pin!(server);
+    
+    tokio::select! {
+        result = &mut server => return Ok(result?),
+        Ok(()) = signalled_rx => {}
+    }
+    
+    tracing::info!(grace_secs = grace.as_secs_f64(), "shutting down, draining in-flight requests");
+    if let Ok(result) = tokio::time::timeout(grace, &mut server).await {
+        return Ok(result?);
+    }
+    
+    // Connections are served on their own tasks, which outlive the server
+    // future, so end their responses instead of just dropping it
+    tracing::warn!("grace period over, closing remaining connections");
+    cut_off.cancel();
+    Ok(())
+}
+
+/// Wait for Ctrl-C, or SIGTERM on Unix
+pub async fn shutdown_signal() {
+    // A handler that can't be installed must not count as a signal
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+impl BeaError {
+    /// HTTP status to report this error with
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BeaError::MissingToken
+            | BeaError::InvalidTokenFormat
+            | BeaError::InvalidToken(_)
+            | BeaError::InconsistentTokenType { .. }
+            | BeaError::TokenNotFound
+            | BeaError::TokenExpired => StatusCode::UNAUTHORIZED,
+            BeaError::PermissionDenied { .. } => StatusCode::FORBIDDEN,
+            BeaError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+            BeaError::QuotaExceeded { .. } | BeaError::AgentBusy(_) => StatusCode::TOO_MANY_REQUESTS,
+            BeaError::StreamingUnsupported(_)
+            | BeaError::InvalidToolChoice(_)
+            | BeaError::InvalidToolSchema(_)
+            | BeaError::UploadRejected(_)
+            | BeaError::Json(_) => StatusCode::BAD_REQUEST,
+            BeaError::ContentBlocked(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            BeaError::ProviderNotFound(_) => StatusCode::SERVICE_UNAVAILABLE,
+            BeaError::Provider(_) | BeaError::Http(_) | BeaError::EmptyStream => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for BeaError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.to_string() });
+        (self.status_code(), Json(body)).into_response()
+    }
+}
+
+/// Body of a `POST /chat` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatRequest {
+    /// Conversation so far; the agent's system prompt is added in front
+    pub messages: Vec<Message>,
+    /// Whether to stream the response as server-sent events
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// References returned by `POST /v1/agents/{name}/files` to add to the last user message
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+/// Handler for `POST /chat`
+///
+/// Sends the messages to the agent's model and returns the `ModelResponse`
+/// as JSON, or streams `StreamEvent`s as server-sent events when negotiated
+/// (see `negotiate_response_mode`).
+pub async fn chat(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatRequest>,
+) -> Result<Response, BeaError> {
+    run_chat(state, &headers, request, false).await
+}
+
+/// Handler for `POST /v1/agents/{name}/chat`
+///
+/// Like `POST /chat`, but negotiated on the `Accept` header alone:
+/// `text/event-stream` streams, anything else gets the whole `ModelResponse`,
+/// final `TokenUsage` included, as JSON. Models that can stream are
+/// streamed and collected even for JSON. 404 for any agent but the one
+/// being served.
+pub async fn agent_chat(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<ChatRequest>,
+) -> Result<Response, BeaError> {
+    if name != state.agent.name() {
+        return Err(BeaError::AgentNotFound(name));
+    }
+    let request = ChatRequest { stream: None, ..request };
+    run_chat(state, &headers, request, true).await
+}
+
+/// Answer a chat request as JSON or server-sent events
+///
+/// With `collect`, JSON responses are streamed from the model and folded
+/// into one response when its provider can stream.
+async fn run_chat(
+    state: AppState,
+    headers: &HeaderMap,
+    request: ChatRequest,
+    collect: bool,
+) -> Result<Response, BeaError> {
+    let caller = state.authorize(headers).await?;
+    
+    let model = state.agent.model();
+    let accept = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok());
+    let supports_streaming = state.registry.supports_streaming(model).await;
+    let mode = negotiate_response_mode(model, request.stream, accept, supports_streaming)?;
+    
+    state.log_request(request.messages.len(), mode).await;
+    
+    let mut messages = request.messages;
+    state.attach_uploads(&mut messages, &request.attachments).await?;
+    state.filters.filter_messages(&mut messages)?;
+    
+    let permit = state.acquire_slot().await?;
+    let model_request = ModelRequest::from_agent(&state.agent, messages);
+    match mode {
+        ResponseMode::Json => {
+            let response = if collect && supports_streaming {
+                let stream = state.registry
+                    .generate_stream_cancellable(model_request.with_stream(true), state.shutdown.child_token())
+                    .await;
+                match stream {
+                    Ok(stream) => collect_stream(stream).await,
+                    Err(e) => Err(e),
+                }
+            } else {
+                state.registry.generate_cancellable(model_request, state.shutdown.child_token()).await
+            };
+            state.log_outcome(&response).await;
+            let mut response = response?;
+            state.record_usage(caller.as_ref(), response.usage()).await;
+            state.filters.filter_response(&mut response)?;
+            Ok(Json(response).into_response())
+        }
+        ResponseMode::EventStream => {
+            let cancel = state.shutdown.child_token();
+            let rx = state.registry.generate_stream_cancellable(model_request, cancel.clone()).await;
+            if let Err(e) = &rx {
+                state.log(LogLevel::Error, "error", &e.to_string()).await;
+            }
+            let events = event_stream(state.filter_stream(state.account_stream(caller, rx?)));
+            let events = state.until_shutdown(cancel_on_drop(events, cancel));
+            Ok(Sse::new(hold_while_open(events, permit)).into_response())
+        }
+    }
+}
+
+/// Cancel `cancel` when `events` is dropped
+///
+/// Axum drops the SSE body when the client disconnects, so this stops the
+/// provider request instead of letting it run to completion unread.
+fn cancel_on_drop<S: Stream>(events: S, cancel: CancellationToken) -> impl Stream<Item = S::Item> {
+    hold_while_open(events, cancel.drop_guard())
+}
+
+/// Keep `held` alive until `events` is dropped
+///
+/// Used to hold an agent's request slot for as long as a response streams.
+fn hold_while_open<S: Stream, T>(events: S, held: T) -> impl Stream<Item = S::Item> {
+    events.map(move |event| {
+        let _ = &held;
+        event
+    })
+}
+
+/// Turn a streamed response into SSE events
+///
+/// Each `StreamEvent` is sent as a JSON `data` event. A provider error
+/// mid-stream is sent as an `error` event and ends the stream.
+fn event_stream(stream: ModelStream) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(Some(stream), |stream| async move {
+        let mut stream = stream?;
+        let event = match stream.recv().await? {
+            Ok(delta) => match Event::default().json_data(&delta) {
+                Ok(event) => return Some((Ok(event), Some(stream))),
+                Err(e) => Event::default().event("error").data(e.to_string()),
+            },
+            Err(e) => Event::default().event("error").data(e.to_string()),
+        };
+        Some((Ok(event), None))
+    })
+}
+
+/// How long an upload can be attached after it is stored
+pub const UPLOAD_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Total size of the uploads kept at once, in bytes
+pub const UPLOAD_STORE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// An uploaded file, ready to go to the model
+#[derive(Debug, Clone)]
+struct Upload {
+    name: String,
+    content: UploadContent,
+    stored_at: Instant,
+}
+
+impl Upload {
+    /// Bytes held in memory for the upload
+    fn size(&self) -> u64 {
+        match &self.content {
+            UploadContent::Text(text) => text.len() as u64,
+            UploadContent::Image(image) => image.data().len() as u64,
+        }
+    }
+}
+
+/// Uploads waiting to be attached to chat requests
+///
+/// Uploads expire `ttl` after they are stored. When a new upload would take
+/// the total past `max_bytes`, the oldest are dropped to make room.
+#[derive(Debug)]
+struct UploadStore {
+    uploads: HashMap<String, Upload>,
+    total_bytes: u64,
+    ttl: Duration,
+    max_bytes: u64,
+}
+
+impl UploadStore {
+    /// Create an empty store
+    fn new(ttl: Duration, max_bytes: u64) -> Self {
+        Self {
+            uploads: HashMap::new(),
+            total_bytes: 0,
+            ttl,
+            max_bytes,
+        }
+    }
+    
+    /// Store an upload, dropping expired and then the oldest uploads to fit it
+    fn insert(&mut self, reference: String, upload: Upload) {
+        let ttl = self.ttl;
+        let now = upload.stored_at;
+        self.uploads.retain(|_, stored| now.duration_since(stored.stored_at) < ttl);
+        self.total_bytes = self.uploads.values().map(Upload::size).sum();
+        
+        while self.total_bytes + upload.size() > self.max_bytes {
+            let Some(oldest) = self
+                .uploads
+                .iter()
+                .min_by_key(|(_, stored)| stored.stored_at)
+                .map(|(reference, _)| reference.clone())
+            else {
+                break;
+            };
+            if let Some(removed) = self.uploads.remove(&oldest) {
+                self.total_bytes -= removed.size();
+            }
+        }
+        
+        self.total_bytes += upload.size();
+        self.uploads.insert(reference, upload);
+    }
+    
+    /// Get an upload that hasn't expired by `now`
+    fn get(&self, reference: &str, now: Instant) -> Option<&Upload> {
+        self.uploads
+            .get(reference)
+            .filter(|upload| now.duration_since(upload.stored_at) < self.ttl)
+    }
+}
+
+/// What an upload is sent to the model as
+#[derive(Debug, Clone)]
+enum UploadContent {
+    /// Text put in front of the message
+    Text(String),
+    /// Image attached to the message
+    Image(Image),
+}
+
+/// Query parameters of `POST /v1/agents/{name}/files`
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadQuery {
+    /// Original file name, shown to the model with text files
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Body of a `POST /v1/agents/{name}/files` response
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadResponse {
+    /// Reference to pass in a chat request's `attachments`
+    pub reference: String,
+    /// MIME type the file was stored as
+    pub content_type: String,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// Handler for `POST /v1/agents/{name}/files`
+///
+/// The body is the file itself, typed by its `Content-Type` header. It is
+/// checked against the configured `FileUploadConfig`; images are kept for
+/// vision input, and anything else must be UTF-8 text, which is added to the
+/// message as context. 400 when the upload is rejected.
+pub async fn upload_file(
+    State(state): State<AppState>,
+    Path(agent): Path<String>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<UploadResponse>, BeaError> {
+    state.authorize(&headers).await?;
+    if agent != state.agent.name() {
+        return Err(BeaError::AgentNotFound(agent));
+    }
+    
+    let Some(config) = &state.file_upload else {
+        return Err(BeaError::UploadRejected("file uploads are disabled".to_string()));
+    };
+    
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    let size = body.len() as u64;
+    config.check(content_type, size)?;
+    
+    let content = if FileUploadConfig::is_image(&media_type) {
+        UploadContent::Image(Image::new(&media_type, &base64_encode(&body)))
+    } else {
+        let text = String::from_utf8(body.to_vec())
+            .map_err(|_| BeaError::UploadRejected(format!("can't read text from a {} file", media_type)))?;
+        UploadContent::Text(text)
+    };
+    
+    let reference = format!("file-{}", uuid::Uuid::new_v4().simple());
+    let upload = Upload {
+        name: query.name.unwrap_or_else(|| reference.clone()),
+        content,
+        stored_at: Instant::now(),
+    };
+    state.uploads.lock().await.insert(reference.clone(), upload);
+    
+    Ok(Json(UploadResponse {
+        reference,
+        content_type: media_type,
+        size,
+    }))
+}
+
+/// Encode bytes as standard, padded base64
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Number of log entries returned by `GET /v1/logs` by default
+const DEFAULT_LOG_TAIL: usize = 100;
+
+/// Query parameters of `GET /v1/logs`
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogsQuery {
+    /// Number of recent entries to return
+    #[serde(default = "default_log_tail")]
+    pub tail: usize,
+    /// Keep the connection open and stream new entries as server-sent events
+    #[serde(default)]
+    pub follow: bool,
+}
+
+fn default_log_tail() -> usize {
+    DEFAULT_LOG_TAIL
+}
+
+/// Handler for `GET /v1/logs`
+///
+/// Returns the agent's recent log entries as a JSON array, or with
+/// `follow=true` streams them as SSE `data` events followed by new entries
+/// as they are written. 404 when the server has no log store.
+pub async fn logs(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<LogsQuery>,
+) -> Result<Response, BeaError> {
+    state.authorize(&headers).await?;
+    
+    let Some(logs) = &state.logs else {
+        let body = serde_json::json!({ "error": "logging is not enabled" });
+        return Ok((StatusCode::NOT_FOUND, Json(body)).into_response());
+    };
+    
+    let agent = state.agent.name();
+    if !query.follow {
+        return Ok(Json(logs.tail(agent, query.tail).await).into_response());
+    }
+    
+    // Subscribe first so nothing written between the two calls is missed
+    let rx = logs.subscribe(agent).await;
+    let recent = logs.tail(agent, query.tail).await;
+    
+    let entries = stream::iter(recent).chain(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|entry| (entry, rx))
+    }));
+    let events = entries.map(|entry| {
+        Ok::<_, Infallible>(Event::default().json_data(&entry).unwrap_or_else(|e| {
+            Event::default().event("error").data(e.to_string())
+        }))
+    });
+    
+    Ok(Sse::new(state.until_shutdown(events)).into_response())
+}
+
+/// Body of a `GET /readyz` response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    /// Whether the agent can serve requests
+    pub ready: bool,
+    /// Why the agent isn't ready
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Handler for `GET /healthz`
+///
+/// Liveness only: answers as long as the server is running.
+pub async fn healthz() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Handler for `GET /metrics`
+///
+/// Renders the process's metrics in the Prometheus text format.
+pub async fn metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, crate::metrics::CONTENT_TYPE)], crate::metrics::render())
+}
+
+/// Handler for `GET /readyz`
+///
+/// Ready when the provider for the agent's model is registered and answers
+/// `available_models`; otherwise 503 with the reason.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let model = state.agent.model();
+    let provider_name = model.split('/').next().unwrap_or(model);
+    
+    let result = match state.registry.get_provider(provider_name).await {
+        Some(provider) => provider.available_models().await.map(|_| ()),
+        None => Err(BeaError::ProviderNotFound(provider_name.to_string())),
+    };
+    
+    match result {
+        Ok(()) => (StatusCode::OK, Json(Readiness { ready: true, reason: None })),
+        Err(e) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(Readiness { ready: false, reason: Some(e.to_string()) }),
+        ),
+    }
+}
+
+/// How a chat response is delivered to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseMode {
+    /// A single JSON body with the complete response
+    Json,
+    /// Server-sent events carrying response deltas
+    EventStream,
+}
+
+/// Media type for server-sent events
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// Decide how to deliver a chat response
+///
+/// The rules, in order of precedence:
+///
+/// 1. An explicit `stream` field in the request body wins: `true` streams,
+///    `false` returns JSON, whatever the `Accept` header says.
+/// 2. Without a `stream` field, the `Accept` header decides. The response
+///    streams when `text/event-stream` is acceptable and ranked at least as
+///    high as `application/json` (or `*/*`) by quality value.
+/// 3. With neither, the response is JSON.
+///
+/// Asking to stream a model whose provider can't stream is an error,
+/// which the server reports as 400 Bad Request.
+pub fn negotiate_response_mode(
+    model: &str,
+    stream: Option<bool>,
+    accept: Option<&str>,
+    supports_streaming: bool,
+) -> Result<ResponseMode, BeaError> {
+    let wants_stream = match stream {
+        Some(stream) => stream,
+        None => accept.map(prefers_event_stream).unwrap_or(false),
+    };
+    
+    if !wants_stream {
+        return Ok(ResponseMode::Json);
+    }
+    
+    if !supports_streaming {
+        return Err(BeaError::StreamingUnsupported(model.to_string()));
+    }
+    
+    Ok(ResponseMode::EventStream)
+}
+
+/// Whether an `Accept` header ranks event streams at least as high as JSON
+fn prefers_event_stream(accept: &str) -> bool {
+    let mut event_stream: Option<f32> = None;
+    let mut json: Option<f32> = None;
+    
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_lowercase();
+        let quality = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        
+        let slot = match media_type.as_str() {
+            EVENT_STREAM => &mut event_stream,
+            "application/json" | "application/*" | "*/*" => &mut json,
+            _ => continue,
+        };
+        *slot = Some(slot.map_or(quality, |q: f32| q.max(quality)));
+    }
+    
+    match event_stream {
+        Some(q) if q > 0.0 => q >= json.unwrap_or(0.0),
+        _ => false,
+    }
+}
+
+/// Body of a `POST /v1/tokens/estimate` request
+#[derive(Debug, Clone, Deserialize)]
+pub struct EstimateRequest {
+    /// Messages to estimate
+    pub messages: Vec<Message>,
+}
+
+/// Body of a `POST /v1/tokens/estimate` response
+#[derive(Debug, Clone, Serialize)]
+pub struct EstimateResponse {
+    /// Estimated number of tokens
+    pub tokens: usize,
+}
+
+/// Handler for `POST /v1/tokens/estimate`
+///
+/// Uses the same heuristic as `count_tokens`, so the count shown in the
+/// chat page matches the server's own estimates. Needs the same bearer
+/// token as the other `/v1` routes.
+pub async fn estimate_tokens(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<EstimateRequest>,
+) -> Result<Json<EstimateResponse>, BeaError> {
+    state.authorize(&headers).await?;
+    
+    Ok(Json(EstimateResponse {
+        tokens: count_message_tokens(&request.messages),
+    }))
+}
+
+/// Body of an OpenAI-style `POST /v1/chat/completions` request
+///
+/// Only the fields Bea can honour are read; the rest of OpenAI's request
+/// body is ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionRequest {
+    /// Model the client asked for; the agent's own model is always used
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Conversation in OpenAI's message format
+    pub messages: Vec<ChatCompletionMessage>,
+    /// Sampling temperature
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Maximum number of tokens to generate
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Whether to stream `chat.completion.chunk` events
+    #[serde(default)]
+    pub stream: Option<bool>,
+}
+
+/// Message in OpenAI's chat format
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionMessage {
+    /// `system`, `developer`, `user`, `assistant` or `tool`
+    pub role: String,
+    /// Either a string or a list of content parts; only text parts are kept
+    #[serde(default)]
+    pub content: serde_json::Value,
+    /// Name of the tool, for tool messages
+    #[serde(default)]
+    pub name: Option<String>,
+    /// ID of the call a tool message answers
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    /// Tool calls made by an assistant message
+    #[serde(default)]
+    pub tool_calls: Vec<serde_json::Value>,
+}
+
+impl ChatCompletionMessage {
+    /// Convert to Bea's message format
+    fn into_message(self) -> Result<Message, BeaError> {
+        let content = match &self.content {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(parts) => parts
+                .iter()
+                .filter(|part| part["type"] == "text")
+                .filter_map(|part| part["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        };
+        
+        let message = match self.role.as_str() {
+            "system" | "developer" => Message::system(&content),
+            "user" => Message::user(&content),
+            "assistant" if self.tool_calls.is_empty() => Message::assistant(&content),
+            "assistant" => {
+                let tool_calls = self
+                    .tool_calls
+                    .iter()
+                    .map(|call| {
+                        ToolCall::new(
+                            call["id"].as_str().unwrap_or(""),
+                            call["function"]["name"].as_str().unwrap_or(""),
+                            call["function"]["arguments"].as_str().unwrap_or("{}"),
+                        )
+                    })
+                    .collect();
+                Message::assistant(&content).with_tool_calls(tool_calls)
+            }
+            "tool" => Message::tool_result(
+                &content,
+                self.name.as_deref().unwrap_or(""),
+                self.tool_call_id.as_deref().unwrap_or(""),
+            ),
+            role => {
+                return Err(BeaError::Json(serde::de::Error::custom(format!(
+                    "unknown message role: {}",
+                    role
+                ))))
+            }
+        };
+        
+        Ok(message)
+    }
+}
+
+/// Handler for `POST /v1/chat/completions`
+///
+/// Speaks enough of OpenAI's chat completions API for the official client
+/// libraries to talk to a deployed agent by changing only their base URL.
+/// Responses use the `chat.completion` envelope, or `chat.completion.chunk`
+/// server-sent events ending with `data: [DONE]` when streaming.
+pub async fn chat_completions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatCompletionRequest>,
+) -> Result<Response, BeaError> {
+    let caller = state.authorize(&headers).await?;
+    
+    let model = state.agent.model();
+    let supports_streaming = state.registry.supports_streaming(model).await;
+    let mode = negotiate_response_mode(model, Some(request.stream.unwrap_or(false)), None, supports_streaming)?;
+    
+    let mut messages = request
+        .messages
+        .into_iter()
+        .map(ChatCompletionMessage::into_message)
+        .collect::<Result<Vec<_>, _>>()?;
+    state.log_request(messages.len(), mode).await;
+    state.filters.filter_messages(&mut messages)?;
+    
+    let mut model_request = ModelRequest::from_agent(&state.agent, messages);
+    if let Some(temperature) = request.temperature {
+        model_request = model_request.with_temperature(temperature);
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        model_request = model_request.with_max_tokens(max_tokens);
+    }
+    
+    let permit = state.acquire_slot().await?;
+    let id = format!("chatcmpl-{}", uuid::Uuid::new_v4().simple());
+    let created = chrono::Utc::now().timestamp();
+    
+    match mode {
+        ResponseMode::Json => {
+            let response = state.registry.generate_cancellable(model_request, state.shutdown.child_token()).await;
+            state.log_outcome(&response).await;
+            let mut response = response?;
+            state.record_usage(caller.as_ref(), response.usage()).await;
+            state.filters.filter_response(&mut response)?;
+            
+            let usage = response.usage();
+            Ok(Json(serde_json::json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": created,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": openai_message(response.message()),
+                    "finish_reason": finish_reason(&response),
+                }],
+                "usage": {
+                    "prompt_tokens": usage.prompt_tokens(),
+                    "completion_tokens": usage.completion_tokens(),
+                    "total_tokens": usage.total_tokens(),
+                },
+            }))
+            .into_response())
+        }
+        ResponseMode::EventStream => {
+            let cancel = state.shutdown.child_token();
+            let rx = state.registry
+                .generate_stream_cancellable(model_request.with_stream(true), cancel.clone())
+                .await;
+            if let Err(e) = &rx {
+                state.log(LogLevel::Error, "error", &e.to_string()).await;
+            }
+            let stream = state.filter_stream(state.account_stream(caller, rx?));
+            let chunk = ChunkTemplate { id, created, model: model.to_string() };
+            let events = state.until_shutdown(cancel_on_drop(chunk_stream(stream, chunk), cancel));
+            Ok(Sse::new(hold_while_open(events, permit)).into_response())
+        }
+    }
+}
+
+/// Fields shared by every chunk of a streamed completion
+struct ChunkTemplate {
+    id: String,
+    created: i64,
+    model: String,
+}
+
+impl ChunkTemplate {
+    /// Build a `chat.completion.chunk` event
+    fn event(&self, delta: serde_json::Value, finish_reason: Option<&str>) -> Event {
+        let chunk = serde_json::json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        });
+        Event::default().data(chunk.to_string())
+    }
+}
+
+/// Turn a streamed response into OpenAI chunk events
+///
+/// The first chunk carries the assistant role, as OpenAI's does. Tool calls
+/// are numbered in the order they start, and their argument fragments are
+/// passed on as they arrive. The stream always ends with `data: [DONE]`,
+/// after an `error` event if the provider failed mid-stream.
+fn chunk_stream(
+    stream: ModelStream,
+    chunk: ChunkTemplate,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let first = chunk.event(serde_json::json!({ "role": "assistant", "content": "" }), None);
+    
+    let deltas = stream::unfold(Some((stream, chunk, Vec::<String>::new())), |state| async move {
+        let (mut stream, chunk, mut calls) = state?;
+        loop {
+            let delta = match stream.recv().await? {
+                Ok(StreamEvent::ContentDelta(text)) => serde_json::json!({ "content": text }),
+                Ok(StreamEvent::ToolCallStart { id, name }) => {
+                    calls.push(id.clone());
+                    serde_json::json!({
+                        "tool_calls": [{
+                            "index": calls.len() - 1,
+                            "id": id,
+                            "type": "function",
+                            "function": { "name": name, "arguments": "" },
+                        }],
+                    })
+                }
+                Ok(StreamEvent::ToolCallArgsDelta { id, json_fragment }) => {
+                    let Some(index) = calls.iter().position(|call| *call == id) else {
+                        continue;
+                    };
+                    serde_json::json!({
+                        "tool_calls": [{
+                            "index": index,
+                            "function": { "arguments": json_fragment },
+                        }],
+                    })
+                }
+                Ok(StreamEvent::Stop(reason)) => {
+                    let event = chunk.event(serde_json::json!({}), Some(map_finish_reason(Some(&reason), !calls.is_empty())));
+                    return Some((Ok(event), Some((stream, chunk, calls))));
+                }
+                Ok(StreamEvent::ToolCallEnd { .. } | StreamEvent::Done(_)) => continue,
+                Err(e) => {
+                    let event = Event::default().event("error").data(e.to_string());
+                    return Some((Ok(event), None));
+                }
+            };
+            return Some((Ok(chunk.event(delta, None)), Some((stream, chunk, calls))));
+        }
+    });
+    
+    stream::once(async move { Ok(first) })
+        .chain(deltas)
+        .chain(stream::once(async { Ok(Event::default().data("[DONE]")) }))
+}
+
+/// Render a message in OpenAI's format
+fn openai_message(message: &Message) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "role": "assistant",
+        "content": message.content(),
+    });
+    
+    if let Some(tool_calls) = message.tool_calls() {
+        body["tool_calls"] = tool_calls
+            .iter()
+            .map(|call| {
+                serde_json::json!({
+                    "id": call.id(),
+                    "type": "function",
+                    "function": {
+                        "name": call.name(),
+                        "arguments": call.arguments(),
+                    },
+                })
+            })
+            .collect();
+    }
+    
+    body
+}
+
+/// Map a response's stop reason onto OpenAI's `finish_reason` values
+fn finish_reason(response: &ModelResponse) -> &'static str {
+    let has_tool_calls = response.message().tool_calls().is_some_and(|calls| !calls.is_empty());
+    map_finish_reason(response.stop_reason(), has_tool_calls)
+}
+
+/// Map a provider's stop reason onto OpenAI's `finish_reason` values
+fn map_finish_reason(stop_reason: Option<&str>, has_tool_calls: bool) -> &'static str {
+    match stop_reason.map(str::to_lowercase).as_deref() {
+        Some("max_tokens" | "length") => "length",
+        Some("safety" | "content_filter" | "recitation") => "content_filter",
+        Some("tool_use" | "tool_calls") => "tool_calls",
+        _ if has_tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MockProvider, TokenUsage};
+    use crate::tokens::TokenType;
+    
+    const JSON: &str = "application/json";
+    
+    /// Registry whose `anthropic` provider answers every request with `reply`
+    async fn registry_replying(reply: &'static str) -> Arc<ModelRegistry> {
+        let provider = MockProvider::from_fn(move |_| {
+            ModelResponse::new(Message::assistant(reply), "")
+                .with_usage(TokenUsage::new(12, 3))
+                .with_stop_reason("end_turn")
+        })
+        .with_name("anthropic");
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(provider).await.unwrap();
+        registry
+    }
+    
+    /// Serve the runtime on a local port, returning its base URL
+    async fn spawn(state: AppState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+        format!("http://{}", addr)
+    }
+    
+    /// State for a `support` agent behind a token store, with a token for it
+    async fn authorized_state(reply: &'static str) -> (AppState, Arc<TokenStore>, Arc<InMemoryLogStore>, String, String) {
+        let tokens = Arc::new(TokenStore::new("test-secret"));
+        let (id, token) = tokens
+            .generate_token_with_id(TokenType::API, "production", None, Some("support"), None, None)
+            .await
+            .unwrap();
+        let logs = Arc::new(InMemoryLogStore::new());
+        let state = AppState::new(Agent::new("support"), registry_replying(reply).await)
+            .with_token_store(tokens.clone())
+            .with_log_store(logs.clone());
+        (state, tokens, logs, id, token)
+    }
+    
+    #[tokio::test]
+    async fn chat_requires_a_token() {
+        let (state, ..) = authorized_state("Hello").await;
+        let url = spawn(state).await;
+        
+        let response = reqwest::Client::new()
+            .post(format!("{}/chat", url))
+            .json(&serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+    
+    #[tokio::test]
+    async fn token_estimates_require_a_token() {
+        let (state, _, _, _, token) = authorized_state("Hello").await;
+        let url = spawn(state).await;
+        let body = serde_json::json!({ "messages": [{ "role": "user", "content": "Hi there" }] });
+        
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/tokens/estimate", url))
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/tokens/estimate", url))
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        let estimate: serde_json::Value = response.json().await.unwrap();
+        assert!(estimate["tokens"].as_u64().unwrap() > 0);
+    }
+    
+    #[tokio::test]
+    async fn chat_returns_the_response_and_charges_the_caller() {
+        let (state, tokens, _, id, token) = authorized_state("Hello there").await;
+        let url = spawn(state).await;
+        
+        let response = reqwest::Client::new()
+            .post(format!("{}/chat", url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["message"]["content"], "Hello there");
+        assert_eq!(tokens.get_token_metadata(&id).await.unwrap().tokens_consumed, 15);
+    }
+    
+    #[tokio::test]
+    async fn streamed_chat_is_charged_and_logged() {
+        let (state, tokens, logs, id, token) = authorized_state("Hello there").await;
+        let url = spawn(state).await;
+        
+        let response = reqwest::Client::new()
+            .post(format!("{}/chat", url))
+            .bearer_auth(&token)
+            .json(&serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }], "stream": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], EVENT_STREAM);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("data: "), "{}", body);
+        assert!(body.contains("Hello"), "{}", body);
+        
+        // Usage is charged once the stream has been read to the end
+        let mut consumed = 0;
+        for _ in 0..50 {
+            consumed = tokens.get_token_metadata(&id).await.unwrap().tokens_consumed;
+            if consumed > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(consumed, 15);
+        
+        let events: Vec<String> = logs.tail("support", 10).await.into_iter().map(|entry| entry.event).collect();
+        assert_eq!(events, vec!["request", "response"]);
+    }
+    
+    #[test]
+    fn stream_field_wins_over_accept() {
+        let cases = [
+            (Some(true), Some(JSON), ResponseMode::EventStream),
+            (Some(true), None, ResponseMode::EventStream),
+            (Some(false), Some(EVENT_STREAM), ResponseMode::Json),
+            (Some(false), None, ResponseMode::Json),
+        ];
+        for (stream, accept, expected) in cases {
+            let mode = negotiate_response_mode("mock/model", stream, accept, true).unwrap();
+            assert_eq!(mode, expected, "stream {:?}, accept {:?}", stream, accept);
+        }
+    }
+    
+    #[test]
+    fn accept_decides_without_a_stream_field() {
+        let cases = [
+            (Some(EVENT_STREAM), ResponseMode::EventStream),
+            (Some(JSON), ResponseMode::Json),
+            (Some("*/*"), ResponseMode::Json),
+            (Some("text/event-stream, application/json"), ResponseMode::EventStream),
+            (Some("application/json, text/event-stream;q=0.5"), ResponseMode::Json),
+            (Some("application/json;q=0.5, text/event-stream"), ResponseMode::EventStream),
+            (Some("text/event-stream;q=0"), ResponseMode::Json),
+            (None, ResponseMode::Json),
+        ];
+        for (accept, expected) in cases {
+            let mode = negotiate_response_mode("mock/model", None, accept, true).unwrap();
+            assert_eq!(mode, expected, "accept {:?}", accept);
+        }
+    }
+    
+    #[test]
+    fn streaming_a_model_that_cannot_stream_is_a_bad_request() {
+        for (stream, accept) in [(Some(true), None), (None, Some(EVENT_STREAM))] {
+            let err = negotiate_response_mode("mock/model", stream, accept, false).unwrap_err();
+            assert!(matches!(err, BeaError::StreamingUnsupported(_)));
+            assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        }
+        
+        let mode = negotiate_response_mode("mock/model", None, Some(JSON), false).unwrap();
+        assert_eq!(mode, ResponseMode::Json);
+    }
+    
+    #[tokio::test]
+    async fn agent_chat_negotiates_on_accept() {
+        let state = AppState::new(Agent::new("support"), registry_replying("Hello there").await);
+        let url = spawn(state).await;
+        let client = reqwest::Client::new();
+        let body = serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }] });
+        
+        let response = client.post(format!("{}/v1/agents/support/chat", url)).json(&body).send().await.unwrap();
+        let json: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(json["message"]["content"], "Hello there");
+        assert_eq!(json["usage"]["prompt_tokens"], 12);
+        assert_eq!(json["usage"]["completion_tokens"], 3);
+        
+        let response = client
+            .post(format!("{}/v1/agents/support/chat", url))
+            .header("accept", EVENT_STREAM)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], EVENT_STREAM);
+        
+        let response = client.post(format!("{}/v1/agents/billing/chat", url)).json(&body).send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+    
+    #[tokio::test]
+    async fn uploaded_files_reach_the_model_as_context_and_images() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let provider = MockProvider::from_fn(move |request| {
+            recorded.lock().unwrap().push(request.messages().to_vec());
+            ModelResponse::new(Message::assistant("Got it"), "")
+        })
+        .with_name("anthropic");
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(provider).await.unwrap();
+        
+        let upload = FileUploadConfig {
+            enabled: true,
+            accepted_types: vec!["text/plain".to_string(), "image/*".to_string()],
+            max_size: 16,
+        };
+        let url = spawn(AppState::new(Agent::new("support"), registry).with_file_upload(upload)).await;
+        let client = reqwest::Client::new();
+        let files = format!("{}/v1/agents/support/files", url);
+        
+        let upload = |content_type: &'static str, body: &'static [u8]| {
+            client.post(format!("{}?name=notes.txt", files)).header("content-type", content_type).body(body).send()
+        };
+        let text: serde_json::Value = upload("text/plain", b"the answer is 42").await.unwrap().json().await.unwrap();
+        let image: serde_json::Value = upload("image/png", b"png").await.unwrap().json().await.unwrap();
+        
+        assert_eq!(upload("application/pdf", b"%PDF").await.unwrap().status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(upload("text/plain", b"this is seventeen").await.unwrap().status(), reqwest::StatusCode::BAD_REQUEST);
+        
+        let response = client
+            .post(format!("{}/chat", url))
+            .json(&serde_json::json!({
+                "messages": [{ "role": "user", "content": "What is the answer?" }],
+                "attachments": [text["reference"], image["reference"]],
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        
+        let sent = seen.lock().unwrap()[0].clone();
+        let question = sent.last().unwrap();
+        assert_eq!(question.content(), "Attached file notes.txt:\nthe answer is 42\n\nWhat is the answer?");
+        assert_eq!(question.images(), &[Image::new("image/png", "cG5n")]);
+    }
+    
+    /// Text upload of `size` bytes stored at `stored_at`
+    fn text_upload(size: usize, stored_at: Instant) -> Upload {
+        Upload {
+            name: "notes.txt".to_string(),
+            content: UploadContent::Text("x".repeat(size)),
+            stored_at,
+        }
+    }
+    
+    #[test]
+    fn uploads_expire_after_the_ttl() {
+        let start = Instant::now();
+        let mut store = UploadStore::new(Duration::from_secs(60), 1024);
+        store.insert("file-a".to_string(), text_upload(10, start));
+        
+        assert!(store.get("file-a", start + Duration::from_secs(59)).is_some());
+        assert!(store.get("file-a", start + Duration::from_secs(60)).is_none());
+        
+        // Expired uploads stop counting towards the size cap
+        store.insert("file-b".to_string(), text_upload(1024, start + Duration::from_secs(61)));
+        assert!(store.get("file-b", start + Duration::from_secs(61)).is_some());
+        assert_eq!(store.total_bytes, 1024);
+    }
+    
+    #[test]
+    fn the_oldest_uploads_make_room_past_the_size_cap() {
+        let start = Instant::now();
+        let mut store = UploadStore::new(Duration::from_secs(60), 100);
+        store.insert("file-a".to_string(), text_upload(40, start));
+        store.insert("file-b".to_string(), text_upload(40, start + Duration::from_secs(1)));
+        store.insert("file-c".to_string(), text_upload(40, start + Duration::from_secs(2)));
+        
+        let now = start + Duration::from_secs(2);
+        assert!(store.get("file-a", now).is_none());
+        assert!(store.get("file-b", now).is_some());
+        assert!(store.get("file-c", now).is_some());
+        assert_eq!(store.total_bytes, 80);
+    }
+    
+    #[test]
+    fn base64_matches_the_standard_alphabet_and_padding() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(&[0xfb, 0xff]), "+/8=");
+    }
+    
+    #[tokio::test]
+    async fn metrics_count_requests_made_through_the_runtime() {
+        let state = AppState::new(
+            Agent::new("support").with_model("anthropic/metrics-probe"),
+            registry_replying("Hello").await,
+        );
+        let url = spawn(state).await;
+        let client = reqwest::Client::new();
+        
+        let response = client
+            .post(format!("{}/chat", url))
+            .json(&serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        
+        let response = client.get(format!("{}/metrics", url)).send().await.unwrap();
+        assert_eq!(response.headers()["content-type"], crate::metrics::CONTENT_TYPE);
+        let body = response.text().await.unwrap();
+        assert!(
+            body.contains(r#"bea_requests_total{model="anthropic/metrics-probe",provider="anthropic"} 1"#),
+            "{}",
+            body
+        );
+        assert!(body.contains("bea_request_duration_seconds_bucket"), "{}", body);
+        assert!(body.contains(r#"bea_tokens_total{kind="prompt"}"#), "{}", body);
+    }
+    
+    #[tokio::test]
+    async fn metrics_count_streamed_requests() {
+        let state = AppState::new(
+            Agent::new("support").with_model("anthropic/metrics-stream-probe"),
+            registry_replying("Hello").await,
+        );
+        let url = spawn(state).await;
+        let client = reqwest::Client::new();
+        
+        let response = client
+            .post(format!("{}/chat", url))
+            .json(&serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }], "stream": true }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], EVENT_STREAM);
+        response.text().await.unwrap();
+        
+        let body = client.get(format!("{}/metrics", url)).send().await.unwrap().text().await.unwrap();
+        assert!(
+            body.contains(r#"bea_requests_total{model="anthropic/metrics-stream-probe",provider="anthropic"} 1"#),
+            "{}",
+            body
+        );
+        assert!(
+            !body.contains(r#"bea_request_errors_total{model="anthropic/metrics-stream-probe""#),
+            "{}",
+            body
+        );
+    }
+    
+    /// Provider that streams "Hello", then holds the stream open until released
+    struct HeldProvider {
+        release: Arc<tokio::sync::Notify>,
+    }
+    
+    #[async_trait::async_trait]
+    impl crate::models::ModelProvider for HeldProvider {
+        fn provider_name(&self) -> &str {
+            "anthropic"
+        }
+        
+        async fn available_models(&self) -> Result<Vec<String>, BeaError> {
+            Ok(Vec::new())
+        }
+        
+        async fn generate(&self, request: ModelRequest, _cancel: CancellationToken) -> Result<ModelResponse, BeaError> {
+            Ok(ModelResponse::new(Message::assistant("Hello world"), request.model()))
+        }
+        
+        async fn generate_stream(&self, request: ModelRequest, _cancel: CancellationToken) -> Result<ModelStream, BeaError> {
+            let (tx, rx) = tokio::sync::mpsc::channel(4);
+            let release = self.release.clone();
+            tokio::spawn(async move {
+                let _ = tx.send(Ok(StreamEvent::ContentDelta("Hello".to_string()))).await;
+                release.notified().await;
+                let _ = tx.send(Ok(StreamEvent::ContentDelta(" world".to_string()))).await;
+                let _ = tx.send(Ok(StreamEvent::Done(TokenUsage::new(1, 2)))).await;
+            });
+            Ok(ModelStream::new(request.model(), rx))
+        }
+    }
+    
+    /// Serve a `HeldProvider` with `grace`, and open a stream that has sent "Hello"
+    async fn open_held_stream(
+        grace: Duration,
+    ) -> (Arc<tokio::sync::Notify>, oneshot::Sender<()>, tokio::task::JoinHandle<Result<(), BeaError>>, reqwest::Response, SocketAddr) {
+        let release = Arc::new(tokio::sync::Notify::new());
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(HeldProvider { release: release.clone() }).await.unwrap();
+        let state = AppState::new(Agent::new("support"), registry);
+        
+        let addr = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_with_shutdown(
+            state,
+            addr,
+            async move {
+                let _ = shutdown_rx.await;
+            },
+            grace,
+        ));
+        
+        let client = reqwest::Client::new();
+        let body = serde_json::json!({ "messages": [{ "role": "user", "content": "Hi" }], "stream": true });
+        let mut response = None;
+        for _ in 0..50 {
+            match client.post(format!("http://{}/chat", addr)).json(&body).send().await {
+                Ok(sent) => {
+                    response = Some(sent);
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        }
+        let mut response = response.expect("server never started");
+        let first = response.chunk().await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&first).contains("Hello"));
+        
+        (release, shutdown_tx, server, response, addr)
+    }
+    
+    #[tokio::test]
+    async fn shutdown_refuses_new_connections_and_drains_open_streams() {
+        let (release, shutdown_tx, server, mut response, addr) = open_held_stream(Duration::from_secs(5)).await;
+        
+        shutdown_tx.send(()).unwrap();
+        let mut refused = false;
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(addr).await.is_err() {
+                refused = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(refused, "new connections were still accepted after shutdown");
+        assert!(!server.is_finished());
+        
+        release.notify_one();
+        let mut rest = String::new();
+        while let Some(chunk) = response.chunk().await.unwrap() {
+            rest.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        assert!(rest.contains(" world"), "{}", rest);
+        
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+    }
+    
+    #[tokio::test]
+    async fn streams_still_open_after_the_grace_period_are_cut_off() {
+        // The provider is never released, so the stream would otherwise run forever
+        let (_release, shutdown_tx, server, mut response, _) = open_held_stream(Duration::from_millis(100)).await;
+        
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+        
+        let rest = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut rest = String::new();
+            while let Ok(Some(chunk)) = response.chunk().await {
+                rest.push_str(&String::from_utf8_lossy(&chunk));
+            }
+            rest
+        })
+        .await
+        .expect("stream was still open after the grace period");
+        assert!(!rest.contains(" world"), "{}", rest);
+    }
+}
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
 use serde::{Serialize, Deserialize};
-use crate::Agent;
+use tokio::sync::Mutex;
+use crate::{Agent, BeaError};
 
 /// Theme for site generation
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,7 +14,54 @@ pub enum Theme {
     Dark,
     ModernLight,
     ModernDark,
-    Custom(String),
+    /// Custom theme styled by inline CSS and/or an external stylesheet
+    ///
+    /// When neither `css` nor `href` is provided the page falls back to the
+    /// default theme rather than rendering unstyled.
+    Custom {
+        /// CSS class applied to the page body
+        class: String,
+        /// Inline CSS for the theme
+        css: Option<String>,
+        /// URL of a stylesheet for the theme
+        href: Option<String>,
+    },
+}
+
+impl From<&str> for Theme {
+    /// Parse a theme from a string
+    ///
+    /// Unknown names become a custom theme with no styles, which renders
+    /// with the default theme.
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "default" => Theme::Default,
+            "light" => Theme::Light,
+            "dark" => Theme::Dark,
+            "modern-light" => Theme::ModernLight,
+            "modern-dark" => Theme::ModernDark,
+            other => {
+                let slug: String = other
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                    .collect();
+                
+                Theme::Custom {
+                    class: format!("bea-theme-{}", slug),
+                    css: None,
+                    href: None,
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Theme {
+    type Err = std::convert::Infallible;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Theme::from(s))
+    }
 }
 
 impl Theme {
@@ -23,20 +73,38 @@ impl Theme {
             Theme::Dark => "bea-theme-dark",
             Theme::ModernLight => "bea-theme-modern-light",
             Theme::ModernDark => "bea-theme-modern-dark",
-            Theme::Custom(_) => "bea-theme-custom",
+            Theme::Custom { class, .. } if self.has_custom_styles() => class,
+            Theme::Custom { .. } => Theme::Default.css_class(),
         }
     }
     
-    /// Parse a theme from a string
-    pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "default" => Theme::Default,
-            "light" => Theme::Light,
-            "dark" => Theme::Dark,
-            "modern-light" => Theme::ModernLight,
-            "modern-dark" => Theme::ModernDark,
-            _ => Theme::Custom(s.to_string()),
+    /// Whether this is a custom theme that provides its own styles
+    fn has_custom_styles(&self) -> bool {
+        matches!(self, Theme::Custom { css, href, .. } if css.is_some() || href.is_some())
+    }
+    
+    /// HTML for the custom theme's stylesheet link and inline styles
+    ///
+    /// The link is attribute-escaped. In the inline CSS every `</` becomes
+    /// `<\/`, which CSS reads the same but which can't close the `<style>`
+    /// element, so a theme can't inject markup.
+    pub fn stylesheet_html(&self) -> String {
+        let mut html = String::new();
+        
+        if let Theme::Custom { href, css, .. } = self {
+            if let Some(href) = href {
+                html.push_str(&format!(
+                    "<link rel=\"stylesheet\" href=\"{}\">",
+                    tera::escape_html(href)
+                ));
+            }
+            
+            if let Some(css) = css {
+                html.push_str(&format!("<style>{}</style>", css.replace("</", "<\\/")));
+            }
         }
+        
+        html
     }
 }
 
@@ -47,6 +115,8 @@ pub struct AuthConfig {
     pub method: AuthMethod,
     /// Redirect URL for OAuth
     pub redirect_url: Option<String>,
+    /// Provider's authorization endpoint for OAuth
+    pub authorization_url: Option<String>,
     /// Client ID for OAuth
     pub client_id: Option<String>,
     /// Client secret for OAuth
@@ -103,11 +173,132 @@ pub struct SiteConfig {
     pub custom_head: Option<String>,
     /// Site settings
     pub settings: HashMap<String, String>,
+    /// File upload configuration
+    #[serde(default)]
+    pub file_upload: Option<FileUploadConfig>,
+    /// Whether custom CSS, JS and head HTML are inserted into the page
+    #[serde(default)]
+    pub allow_raw_injection: bool,
+}
+
+/// File upload configuration for a site's chat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileUploadConfig {
+    /// Whether users can attach files
+    pub enabled: bool,
+    /// Accepted MIME types, e.g. `application/pdf` or `image/*`
+    pub accepted_types: Vec<String>,
+    /// Maximum file size in bytes
+    pub max_size: u64,
+}
+
+impl FileUploadConfig {
+    /// Check an upload against the configured limits
+    ///
+    /// Servers receiving uploads should call this rather than trusting the
+    /// `accept` attribute rendered in the page.
+    pub fn check(&self, content_type: &str, size: u64) -> Result<(), BeaError> {
+        if !self.enabled {
+            return Err(BeaError::UploadRejected("file uploads are disabled".to_string()));
+        }
+        
+        if size > self.max_size {
+            return Err(BeaError::UploadRejected(format!(
+                "file is {} bytes, the limit is {} bytes",
+                size, self.max_size
+            )));
+        }
+        
+        if !self.accepts(content_type) {
+            return Err(BeaError::UploadRejected(format!(
+                "file type {} is not accepted",
+                content_type
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    /// Whether a MIME type matches the accepted types
+    ///
+    /// An empty list accepts any type; `type/*` matches any subtype.
+    pub fn accepts(&self, content_type: &str) -> bool {
+        let content_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        
+        self.accepted_types.is_empty() || self.accepted_types.iter().any(|accepted| {
+            let accepted = accepted.to_lowercase();
+            match accepted.strip_suffix("/*") {
+                Some(prefix) => content_type.starts_with(&format!("{}/", prefix)),
+                None => content_type == accepted,
+            }
+        })
+    }
+    
+    /// Whether a MIME type should be sent to the model as an image
+    pub fn is_image(content_type: &str) -> bool {
+        content_type.trim().to_lowercase().starts_with("image/")
+    }
+}
+
+/// Store for generated site configurations
+pub struct SiteStore {
+    sites: Arc<Mutex<HashMap<String, SiteConfig>>>,
 }
 
+impl SiteStore {
+    /// Create a new site store
+    pub fn new() -> Self {
+        Self {
+            sites: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+    
+    /// Register a site configuration, returning its ID
+    pub async fn register(&self, config: SiteConfig) -> String {
+        let id = config.id.clone();
+        let mut sites = self.sites.lock().await;
+        sites.insert(id.clone(), config);
+        id
+    }
+    
+    /// Get a site configuration by ID
+    pub async fn get(&self, id: &str) -> Option<SiteConfig> {
+        let sites = self.sites.lock().await;
+        sites.get(id).cloned()
+    }
+    
+    /// List all site configurations
+    pub async fn list(&self) -> Vec<SiteConfig> {
+        let sites = self.sites.lock().await;
+        sites.values().cloned().collect()
+    }
+    
+    /// Delete a site configuration, returning it if it existed
+    pub async fn delete(&self, id: &str) -> Option<SiteConfig> {
+        let mut sites = self.sites.lock().await;
+        sites.remove(id)
+    }
+}
+
+impl Default for SiteStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Built-in page template for generated sites
+const SITE_TEMPLATE: &str = include_str!("templates/site.html");
+
 /// Site generator
+#[derive(Debug, Clone)]
 pub struct SiteGenerator {
     pub(crate) config: SiteConfig,
+    template: Option<PathBuf>,
 }
 
 impl SiteGenerator {
@@ -125,10 +316,30 @@ impl SiteGenerator {
                 custom_js: None,
                 custom_head: None,
                 settings: HashMap::new(),
+                file_upload: None,
+                allow_raw_injection: false,
             },
+            template: None,
         }
     }
     
+    /// Allow custom CSS, JS and head HTML to be inserted into the page
+    ///
+    /// These are inserted verbatim, so only enable this for trusted content.
+    /// When disabled (the default), they are left out of the generated HTML.
+    pub fn with_raw_injection(mut self, allow: bool) -> Self {
+        self.config.allow_raw_injection = allow;
+        self
+    }
+    
+    /// Render the site with a tera template read from `path`
+    ///
+    /// See `generate_html` for the variables available to the template.
+    pub fn with_template(mut self, path: impl Into<PathBuf>) -> Self {
+        self.template = Some(path.into());
+        self
+    }
+    
     /// Set the site name
     pub fn with_name(mut self, name: &str) -> Self {
         self.config.name = name.to_string();
@@ -144,7 +355,27 @@ impl SiteGenerator {
     
     /// Set the site theme
     pub fn with_theme(mut self, theme: &str) -> Self {
-        self.config.theme = Theme::from_str(theme);
+        self.config.theme = Theme::from(theme);
+        self
+    }
+    
+    /// Get the site configuration
+    pub fn config(&self) -> &SiteConfig {
+        &self.config
+    }
+    
+    /// Consume the generator, returning its site configuration
+    pub fn into_config(self) -> SiteConfig {
+        self.config
+    }
+    
+    /// Use a custom theme with inline CSS and/or an external stylesheet
+    pub fn with_custom_theme(mut self, class: &str, css: Option<&str>, href: Option<&str>) -> Self {
+        self.config.theme = Theme::Custom {
+            class: class.to_string(),
+            css: css.map(|s| s.to_string()),
+            href: href.map(|s| s.to_string()),
+        };
         self
     }
     
@@ -161,6 +392,7 @@ impl SiteGenerator {
         self.config.auth = Some(AuthConfig {
             method,
             redirect_url: None,
+            authorization_url: None,
             client_id: None,
             client_secret: None,
             allowed_domains: None,
@@ -170,6 +402,40 @@ impl SiteGenerator {
         self
     }
     
+    /// Set the OAuth client used by OAuth2 and OIDC site auth
+    ///
+    /// Defaults the auth method to OAuth2 if none has been set.
+    pub fn with_oauth_client(mut self, client_id: &str, redirect_url: &str) -> Self {
+        let auth = self.oauth_config();
+        auth.client_id = Some(client_id.to_string());
+        auth.redirect_url = Some(redirect_url.to_string());
+        self
+    }
+    
+    /// Set the provider endpoint OAuth2 and OIDC sites send users to sign in
+    ///
+    /// The callback at the redirect URL exchanges the code for an access
+    /// token and sends the user back to the site with `#token=...&state=...`,
+    /// echoing the `state` it was given. Defaults the auth method to OAuth2
+    /// if none has been set.
+    pub fn with_oauth_authorization_url(mut self, url: &str) -> Self {
+        self.oauth_config().authorization_url = Some(url.to_string());
+        self
+    }
+    
+    /// Auth config to set OAuth settings on, created as OAuth2 if missing
+    fn oauth_config(&mut self) -> &mut AuthConfig {
+        self.config.auth.get_or_insert(AuthConfig {
+            method: AuthMethod::OAuth2,
+            redirect_url: None,
+            authorization_url: None,
+            client_id: None,
+            client_secret: None,
+            allowed_domains: None,
+            headers: None,
+        })
+    }
+    
     /// Add custom CSS to the site
     pub fn with_custom_css(mut self, css: &str) -> Self {
         self.config.custom_css = Some(css.to_string());
@@ -194,220 +460,229 @@ impl SiteGenerator {
         self
     }
     
-    /// Generate the site HTML
-    pub fn generate_html(&self) -> Result<String, Box<dyn Error>> {
-        // In a real implementation, this would generate the HTML for the site
-        
-        // For demonstration purposes, we'll return a simple template
-        let html = format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>{}</title>
-    <link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/tailwindcss@2.2.19/dist/tailwind.min.css">
-    <script src="https://cdn.jsdelivr.net/npm/alpinejs@3.12.3/dist/cdn.min.js" defer></script>
-    {}
-    <style>
-        :root {{
-            --primary-color: #4f46e5;
-            --secondary-color: #7c3aed;
-            --text-color: #111827;
-            --bg-color: #ffffff;
-            --accent-color: #8b5cf6;
-        }}
-        
-        .dark {{
-            --primary-color: #818cf8;
-            --secondary-color: #a78bfa;
-            --text-color: #f9fafb;
-            --bg-color: #111827;
-            --accent-color: #c4b5fd;
-        }}
-        
-        body {{
-            background-color: var(--bg-color);
-            color: var(--text-color);
-            transition: background-color 0.3s, color 0.3s;
-        }}
-        
-        .chat-container {{
-            height: calc(100vh - 12rem);
-        }}
-        
-        .message {{
-            border-radius: 1rem;
-            padding: 1rem;
-            margin-bottom: 1rem;
-            max-width: 80%;
-        }}
-        
-        .user-message {{
-            background-color: var(--primary-color);
-            color: white;
-            align-self: flex-end;
-        }}
-        
-        .assistant-message {{
-            background-color: #f3f4f6;
-            color: var(--text-color);
-            align-self: flex-start;
-        }}
-        
-        .dark .assistant-message {{
-            background-color: #1f2937;
-            color: #f9fafb;
-        }}
-        
-        .typing-indicator span {{
-            animation: blink 1.4s infinite both;
-        }}
-        
-        .typing-indicator span:nth-child(2) {{
-            animation-delay: 0.2s;
-        }}
-        
-        .typing-indicator span:nth-child(3) {{
-            animation-delay: 0.4s;
-        }}
-        
-        @keyframes blink {{
-            0% {{ opacity: 0.1; }}
-            20% {{ opacity: 1; }}
-            100% {{ opacity: 0.1; }}
-        }}
-        
-        {}
-    </style>
-</head>
-<body class="{}">
-    <div x-data="chatApp()" class="flex flex-col h-screen">
-        <nav class="bg-gray-800 text-white p-4">
-            <div class="container mx-auto flex justify-between items-center">
-                <div class="flex items-center">
-                    <svg class="w-8 h-8 mr-2" viewBox="0 0 24 24" fill="none" xmlns="http://www.w3.org/2000/svg">
-                        <path d="M12 2C6.48 2 2 6.48 2 12C2 17.52 6.48 22 12 22C17.52 22 22 17.52 22 12C22 6.48 17.52 2 12 2ZM12 20C7.59 20 4 16.41 4 12C4 7.59 7.59 4 12 4C16.41 4 20 7.59 20 12C20 16.41 16.41 20 12 20Z" fill="currentColor"/>
-                        <path d="M12 17C14.7614 17 17 14.7614 17 12C17 9.23858 14.7614 7 12 7C9.23858 7 7 9.23858 7 12C7 14.7614 9.23858 17 12 17Z" fill="currentColor"/>
-                    </svg>
-                    <span class="text-xl font-bold">{}</span>
-                </div>
-                <div class="flex items-center">
-                    <button @click="toggleTheme()" class="p-2 rounded-full hover:bg-gray-700">
-                        <svg x-show="!darkMode" class="w-6 h-6" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M20.354 15.354A9 9 0 018.646 3.646 9.003 9.003 0 0012 21a9.003 9.003 0 008.354-5.646z"></path>
-                        </svg>
-                        <svg x-show="darkMode" class="w-6 h-6" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                            <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 3v1m0 16v1m9-9h-1M4 12H3m15.364 6.364l-.707-.707M6.343 6.343l-.707-.707m12.728 0l-.707.707M6.343 17.657l-.707.707M16 12a4 4 0 11-8 0 4 4 0 018 0z"></path>
-                        </svg>
-                    </button>
-                    <button class="ml-4 px-4 py-2 bg-indigo-600 rounded-md hover:bg-indigo-700">Settings</button>
-                </div>
-            </div>
-        </nav>
-        
-        <div class="flex-1 overflow-hidden flex flex-col">
-            <div class="flex-1 overflow-y-auto p-4">
-                <div class="container mx-auto max-w-4xl">
-                    <div class="flex flex-col">
-                        <template x-for="(message, index) in messages" :key="index">
-                            <div :class="{{'message': true, 'user-message': message.role === 'user', 'assistant-message': message.role === 'assistant'}}">
-                                <div x-text="message.content"></div>
-                            </div>
-                        </template>
-                        <div x-show="isTyping" class="message assistant-message typing-indicator">
-                            <span>.</span><span>.</span><span>.</span>
-                        </div>
-                    </div>
-                </div>
-            </div>
-            
-            <div class="border-t p-4">
-                <div class="container mx-auto max-w-4xl">
-                    <form @submit.prevent="sendMessage()" class="flex">
-                        <input 
-                            type="text" 
-                            x-model="userInput" 
-                            class="flex-1 rounded-l-lg border-2 border-gray-300 p-2 focus:outline-none focus:border-indigo-500 dark:bg-gray-700 dark:border-gray-600 dark:text-white"
-                            placeholder="Type your message..."
-                        >
-                        <button 
-                            type="submit" 
-                            class="bg-indigo-600 text-white px-4 py-2 rounded-r-lg hover:bg-indigo-700 focus:outline-none"
-                            :disabled="userInput.trim() === ''"
-                        >
+    /// Let users attach files to chat messages
+    ///
+    /// Files are uploaded to `/v1/agents/{agent}/files` and the returned
+    /// reference is sent along with the next message.
+    pub fn with_file_upload(mut self, enabled: bool, accepted_types: &[&str], max_size: u64) -> Self {
+        self.config.file_upload = Some(FileUploadConfig {
+            enabled,
+            accepted_types: accepted_types.iter().map(|t| t.to_string()).collect(),
+            max_size,
+        });
+        self
+    }
+    
+    /// HTML for the file input, if uploads are enabled
+    fn file_upload_input(&self) -> String {
+        match &self.config.file_upload {
+            Some(upload) if upload.enabled => format!(
+                r#"<label class="flex items-center px-3 border-2 border-r-0 border-gray-300 rounded-l-lg cursor-pointer hover:bg-gray-100 dark:border-gray-600 dark:hover:bg-gray-700" title="Attach a file">
+                            <input type="file" class="hidden" accept="{}" @change="uploadFile($event)">
                             <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M14 5l7 7m0 0l-7 7m7-7H3"></path>
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M15.172 7l-6.586 6.586a2 2 0 102.828 2.828l6.414-6.586a4 4 0 00-5.656-5.656l-6.415 6.585a6 6 0 108.486 8.486L20.5 13"></path>
                             </svg>
-                        </button>
-                    </form>
-                </div>
-            </div>
-        </div>
-    </div>
-
-    <script>
-        function chatApp() {{
-            return {{
-                darkMode: window.matchMedia('(prefers-color-scheme: dark)').matches,
-                userInput: '',
-                messages: [],
-                isTyping: false,
-                
-                init() {{
-                    if (this.darkMode) {{
-                        document.body.classList.add('dark');
+                        </label>"#,
+                upload.accepted_types.join(",").replace('"', "&quot;")
+            ),
+            _ => String::new(),
+        }
+    }
+    
+    /// Chat app methods for uploading files, if uploads are enabled
+    fn file_upload_script(&self) -> String {
+        match &self.config.file_upload {
+            Some(upload) if upload.enabled => {
+                let agent = self.config.agent_id.as_deref().unwrap_or(&self.config.id);
+                format!(
+                    r#"attachments: [],
+                uploadFile(event) {{
+                    const file = event.target.files[0];
+                    event.target.value = '';
+                    if (!file) return;
+                    if (file.size > {}) {{
+                        this.addMessage('assistant', 'That file is too large to upload.');
+                        return;
                     }}
-                    
-                    // Welcome message
-                    setTimeout(() => {{
-                        this.addMessage('assistant', 'Hello! I\'m your AI assistant. How can I help you today?');
-                    }}, a500);
-                }},
-                
-                toggleTheme() {{
-                    this.darkMode = !this.darkMode;
-                    document.body.classList.toggle('dark');
-                }},
-                
-                sendMessage() {{
-                    if (this.userInput.trim() === '') return;
-                    
-                    const userMessage = this.userInput;
-                    this.addMessage('user', userMessage);
-                    this.userInput = '';
-                    
-                    // Simulate typing
-                    this.isTyping = true;
-                    
-                    // Simulate API call to agent
-                    setTimeout(() => {{
-                        this.isTyping = false;
-                        this.addMessage('assistant', 'I\'m processing your request: "' + userMessage + '". This is a demo interface for the Bea Bot agent platform.');
-                    }}, 1500);
-                }},
-                
-                addMessage(role, content) {{
-                    this.messages.push({{ role, content }});
-                    // Scroll to bottom
-                    setTimeout(() => {{
-                        const container = document.querySelector('.overflow-y-auto');
-                        container.scrollTop = container.scrollHeight;
-                    }}, 50);
-                }}
-            }};
-        }}
-    </script>
-    {}
-</body>
-</html>"#,
-            self.config.name,
-            self.config.custom_head.as_deref().unwrap_or(""),
-            self.config.custom_css.as_deref().unwrap_or(""),
-            self.config.theme.css_class(),
-            self.config.name,
-            self.config.custom_js.as_deref().unwrap_or(""),
-        );
+                    fetch('/v1/agents/{}/files?name=' + encodeURIComponent(file.name), {{
+                        method: 'POST',
+                        headers: this.authHeaders({{ 'Content-Type': file.type || 'application/octet-stream' }}),
+                        body: file
+                    }})
+                        .then((res) => res.ok ? res.json() : Promise.reject(res.statusText))
+                        .then((data) => {{
+                            this.attachments.push(data.reference);
+                            this.addMessage('user', 'Attached ' + file.name);
+                        }})
+                        .catch(() => this.addMessage('assistant', 'Sorry, the upload failed.'));
+                }},"#,
+                    upload.max_size,
+                    urlencode(agent)
+                )
+            }
+            _ => String::new(),
+        }
+    }
+    
+    /// Auth config for methods the generated page signs users in for
+    ///
+    /// `Email` and `Custom` auth are handled entirely by whatever serves the
+    /// page, so it renders as if there were no auth.
+    fn page_auth(&self) -> Option<&AuthConfig> {
+        self.config.auth.as_ref().filter(|auth| {
+            matches!(auth.method, AuthMethod::Basic | AuthMethod::OAuth2 | AuthMethod::OIDC)
+        })
+    }
+    
+    /// Check that the auth config has what its method needs
+    fn validate_auth(&self) -> Result<(), BeaError> {
+        let Some(auth) = self.page_auth() else {
+            return Ok(());
+        };
+        
+        if matches!(auth.method, AuthMethod::OAuth2 | AuthMethod::OIDC) {
+            let missing: Vec<&str> = [
+                ("client_id", &auth.client_id),
+                ("redirect_url", &auth.redirect_url),
+                ("authorization_url", &auth.authorization_url),
+            ]
+                .into_iter()
+                .filter(|(_, value)| value.as_deref().is_none_or(str::is_empty))
+                .map(|(field, _)| field)
+                .collect();
+            
+            if !missing.is_empty() {
+                return Err(BeaError::InvalidAuthConfig(format!(
+                    "{:?} auth requires {}",
+                    auth.method,
+                    missing.join(" and ")
+                )));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// `data-auth-method` attribute for the body tag
+    fn auth_attribute(&self) -> String {
+        match self.page_auth().map(|auth| &auth.method) {
+            Some(AuthMethod::Basic) => r#" data-auth-method="basic""#.to_string(),
+            Some(AuthMethod::OAuth2) => r#" data-auth-method="oauth2""#.to_string(),
+            Some(AuthMethod::OIDC) => r#" data-auth-method="oidc""#.to_string(),
+            _ => String::new(),
+        }
+    }
+    
+    /// Sign-in overlay shown while the page has no accepted access token
+    ///
+    /// Basic sites ask for an access token; OAuth2 and OIDC sites send the
+    /// user to the provider to sign in.
+    fn auth_gate(&self) -> String {
+        let Some(auth) = self.page_auth() else {
+            return String::new();
+        };
+        
+        let prompt = match auth.method {
+            AuthMethod::Basic => r#"<form @submit.prevent="useToken()" class="bg-white dark:bg-gray-800 p-6 rounded-lg shadow-lg w-80">
+                <h2 class="text-lg font-bold mb-4">Sign in</h2>
+                <input type="password" x-model="tokenInput" placeholder="Access token" autocomplete="off" class="w-full mb-4 p-2 border-2 border-gray-300 rounded dark:bg-gray-700 dark:border-gray-600">
+                <button type="submit" class="w-full bg-indigo-600 text-white py-2 rounded hover:bg-indigo-700" :disabled="tokenInput.trim() === ''">Sign in</button>
+            </form>"#,
+            _ => r#"<div class="bg-white dark:bg-gray-800 p-6 rounded-lg shadow-lg w-80 text-center">
+                <h2 class="text-lg font-bold mb-4">Sign in to continue</h2>
+                <button @click="signIn()" class="w-full bg-indigo-600 text-white py-2 rounded hover:bg-indigo-700">Sign in</button>
+            </div>"#,
+        };
+        
+        format!(
+            r#"
+        <div x-show="signInRequired" class="fixed inset-0 z-50 flex items-center justify-center bg-gray-900 bg-opacity-75">
+            {}
+        </div>"#,
+            prompt
+        )
+    }
+    
+    /// Chat app methods behind the sign-in overlay
+    fn auth_script(&self) -> String {
+        let Some(auth) = self.page_auth() else {
+            return String::new();
+        };
+        
+        if matches!(auth.method, AuthMethod::Basic) {
+            return r#"tokenInput: '',
+                useToken() {
+                    this.accessToken = this.tokenInput.trim();
+                    this.tokenInput = '';
+                    sessionStorage.setItem('bea-access-token', this.accessToken);
+                    this.signInRequired = false;
+                },"#
+                .to_string();
+        }
+        
+        // Values are JSON strings, which can't close the script element once `</` is escaped
+        let js = |value: &Option<String>| {
+            serde_json::to_string(value.as_deref().unwrap_or_default())
+                .unwrap_or_default()
+                .replace("</", "<\\/")
+        };
+        let scope = if matches!(auth.method, AuthMethod::OIDC) { ", scope: 'openid'" } else { "" };
+        format!(
+            r#"oauth: true,
+                signIn() {{
+                    const state = crypto.randomUUID();
+                    sessionStorage.setItem('bea-auth-state', state);
+                    const url = new URL({});
+                    const params = {{ response_type: 'code', client_id: {}, redirect_uri: {}, state{} }};
+                    for (const [key, value] of Object.entries(params)) url.searchParams.set(key, value);
+                    location.assign(url);
+                }},"#,
+            js(&auth.authorization_url),
+            js(&auth.client_id),
+            js(&auth.redirect_url),
+            scope
+        )
+    }
+    
+    /// Generate the site HTML
+    ///
+    /// Renders the built-in template, or the one set with `with_template`.
+    /// Templates are rendered with tera and receive `title`, `name`,
+    /// `stylesheet`, `theme_class`, `custom_head`, `custom_css`, `custom_js`,
+    /// `file_upload_input`, `file_upload_script`, `auth_attribute`, `auth_gate`
+    /// and `auth_script`. The name and theme class are HTML-escaped, and a
+    /// custom theme's CSS can't break out of its `<style>` element. The custom
+    /// CSS, JS and head HTML are inserted as-is, and only when raw injection
+    /// is allowed.
+    pub fn generate_html(&self) -> Result<String, Box<dyn Error>> {
+        self.validate_auth()?;
+        
+        let template = match &self.template {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => SITE_TEMPLATE.to_string(),
+        };
+        
+        let name = tera::escape_html(&self.config.name);
+        let raw = |value: &Option<String>| match value {
+            Some(value) if self.config.allow_raw_injection => value.clone(),
+            _ => String::new(),
+        };
+        
+        let mut context = tera::Context::new();
+        context.insert("title", &name);
+        context.insert("name", &name);
+        context.insert("stylesheet", &self.config.theme.stylesheet_html());
+        context.insert("theme_class", &tera::escape_html(self.config.theme.css_class()));
+        context.insert("custom_head", &raw(&self.config.custom_head));
+        context.insert("custom_css", &raw(&self.config.custom_css));
+        context.insert("custom_js", &raw(&self.config.custom_js));
+        context.insert("file_upload_input", &self.file_upload_input());
+        context.insert("file_upload_script", &self.file_upload_script());
+        context.insert("auth_attribute", &self.auth_attribute());
+        context.insert("auth_gate", &self.auth_gate());
+        context.insert("auth_script", &self.auth_script());
+        
+        let html = tera::Tera::one_off(&template, &context, false)?;
         
         Ok(html)
     }
@@ -428,3 +703,189 @@ impl SiteGenerator {
         Ok(format!("https://{}", domain))
     }
 }
+
+impl Default for SiteGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Percent-encode a string for use in a URL query parameter or path segment
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn welcome_message_timeout_is_a_number() {
+        let html = SiteGenerator::new().generate_html().unwrap();
+        
+        assert!(!html.contains("a500"));
+        let timeout = regex::Regex::new(r"setTimeout\(\(\) => \{[^}]*\}, \d+\);").unwrap();
+        assert!(timeout.is_match(&html));
+    }
+    
+    #[test]
+    fn malicious_name_is_escaped() {
+        let html = SiteGenerator::new()
+            .with_name("</title><script>alert(1)</script>")
+            .generate_html()
+            .unwrap();
+        
+        assert!(!html.contains("<script>alert(1)</script>"));
+        assert!(html.contains("&lt;&#x2F;title&gt;&lt;script&gt;alert(1)&lt;&#x2F;script&gt;"));
+    }
+    
+    #[test]
+    fn custom_js_needs_raw_injection() {
+        let site = || SiteGenerator::new().with_custom_js("<script>window.tracked = true;</script>");
+        
+        let html = site().generate_html().unwrap();
+        assert!(!html.contains("window.tracked"));
+        
+        let html = site().with_raw_injection(true).generate_html().unwrap();
+        assert!(html.contains("<script>window.tracked = true;</script>"));
+    }
+    
+    /// Site whose OAuth client is fully configured
+    fn oauth_site(auth: crate::Auth) -> SiteGenerator {
+        SiteGenerator::new()
+            .with_auth(auth)
+            .with_oauth_client("client-1", "https://example.com/callback")
+            .with_oauth_authorization_url("https://login.example.com/authorize")
+    }
+    
+    #[test]
+    fn no_auth_renders_without_a_gate() {
+        let html = SiteGenerator::new().with_auth(crate::Auth::None).generate_html().unwrap();
+        
+        assert!(!html.contains("data-auth-method"));
+        assert!(!html.contains(r#"x-show="signInRequired""#));
+        assert!(!html.contains("signIn()"));
+    }
+    
+    #[test]
+    fn basic_auth_asks_for_an_access_token() {
+        let html = SiteGenerator::new().with_auth(crate::Auth::Basic).generate_html().unwrap();
+        
+        assert!(html.contains(r#"data-auth-method="basic""#));
+        assert!(html.contains(r#"x-show="signInRequired""#));
+        assert!(html.contains(r#"x-model="tokenInput" placeholder="Access token""#));
+        assert!(html.contains("sessionStorage.setItem('bea-access-token', this.accessToken);"));
+        assert!(!html.contains("signIn()"));
+    }
+    
+    #[test]
+    fn oauth2_auth_redirects_to_the_provider() {
+        let html = oauth_site(crate::Auth::OAuth2).generate_html().unwrap();
+        
+        assert!(html.contains(r#"data-auth-method="oauth2""#));
+        assert!(html.contains(r#"<button @click="signIn()""#));
+        assert!(html.contains(r#"const url = new URL("https://login.example.com/authorize");"#));
+        assert!(html.contains(r#"response_type: 'code', client_id: "client-1", redirect_uri: "https://example.com/callback", state }"#));
+    }
+    
+    #[test]
+    fn oidc_auth_redirects_to_the_provider_for_an_openid_scope() {
+        let html = oauth_site(crate::Auth::OIDC).generate_html().unwrap();
+        
+        assert!(html.contains(r#"data-auth-method="oidc""#));
+        assert!(html.contains(r#"<button @click="signIn()""#));
+        assert!(html.contains("state, scope: 'openid' }"));
+    }
+    
+    #[test]
+    fn oauth_settings_cannot_close_the_script_element() {
+        let html = oauth_site(crate::Auth::OAuth2)
+            .with_oauth_client("</script><script>alert(1)</script>", "https://example.com/callback")
+            .generate_html()
+            .unwrap();
+        
+        assert!(!html.contains("</script><script>alert(1)"));
+        assert!(html.contains(r#"client_id: "<\/script><script>alert(1)<\/script>""#));
+    }
+    
+    #[test]
+    fn oauth2_without_a_client_is_rejected() {
+        let err = SiteGenerator::new().with_auth(crate::Auth::OAuth2).generate_html().unwrap_err();
+        
+        assert!(err.to_string().contains("client_id and redirect_url and authorization_url"), "{}", err);
+    }
+    
+    #[test]
+    fn oidc_without_an_authorization_url_is_rejected() {
+        let err = SiteGenerator::new()
+            .with_auth(crate::Auth::OIDC)
+            .with_oauth_client("client-1", "https://example.com/callback")
+            .generate_html()
+            .unwrap_err();
+        
+        assert!(err.to_string().ends_with("requires authorization_url"), "{}", err);
+    }
+    
+    #[test]
+    fn custom_theme_css_cannot_close_its_style_element() {
+        let html = SiteGenerator::new()
+            .with_custom_theme("brand", Some("body { color: red; }</style><script>alert(1)</script>"), None)
+            .generate_html()
+            .unwrap();
+        
+        assert!(!html.contains("</style><script>alert(1)"));
+    }
+    
+    #[test]
+    fn upload_url_encodes_the_agent() {
+        let mut site = SiteGenerator::new().with_file_upload(true, &["text/plain"], 1024);
+        site.config.agent_id = Some("x'); alert(1); ('</script>".to_string());
+        let html = site.generate_html().unwrap();
+        
+        assert!(html.contains("fetch('/v1/agents/x%27%29%3B%20alert%281%29%3B%20%28%27%3C%2Fscript%3E/files?name="));
+        assert!(!html.contains("alert(1)"));
+    }
+    
+    #[test]
+    fn messages_are_sent_to_chat_with_pending_attachments() {
+        let html = SiteGenerator::new().with_file_upload(true, &["text/plain"], 1024).generate_html().unwrap();
+        
+        assert!(html.contains("fetch('/chat'"));
+        assert!(html.contains("JSON.stringify({ messages: turns, attachments })"));
+        assert!(!html.contains("This is a demo interface"));
+    }
+    
+    #[test]
+    fn chat_history_leaves_out_what_is_only_shown() {
+        let html = SiteGenerator::new().with_file_upload(true, &["text/plain"], 1024).generate_html().unwrap();
+        
+        assert!(html.contains("const turns = [...this.conversation, { role: 'user', content }];"));
+        assert!(html.contains("this.conversation = [...turns, { role: 'assistant', content: data.message.content }];"));
+        assert_eq!(html.matches("this.conversation =").count(), 1);
+    }
+    
+    #[test]
+    fn chat_and_uploads_send_the_access_token() {
+        let html = SiteGenerator::new().with_file_upload(true, &["text/plain"], 1024).generate_html().unwrap();
+        
+        assert!(html.contains("'Authorization': 'Bearer ' + this.accessToken"));
+        assert!(html.contains("headers: this.authHeaders({ 'Content-Type': 'application/json', 'Accept': 'application/json' })"));
+        assert!(html.contains("headers: this.authHeaders({ 'Content-Type': file.type || 'application/octet-stream' })"));
+    }
+    
+    #[test]
+    fn token_estimates_cover_the_conversation_and_send_the_access_token() {
+        let html = SiteGenerator::new().generate_html().unwrap();
+        
+        let estimate = &html[html.find("fetch('/v1/tokens/estimate'").unwrap()..];
+        let estimate = &estimate[..estimate.find(".then(").unwrap()];
+        assert!(estimate.contains("headers: this.authHeaders({ 'Content-Type': 'application/json' })"), "{}", estimate);
+        assert!(estimate.contains("JSON.stringify({ messages: this.conversation })"), "{}", estimate);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use serde::{Serialize, Deserialize};
@@ -7,6 +8,9 @@ use chrono::{DateTime, Utc, Duration};
 use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey};
 use uuid::Uuid;
 
+use crate::BeaError;
+use crate::clock::{Clock, SystemClock};
+
 /// Token type
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TokenType {
@@ -16,6 +20,28 @@ pub enum TokenType {
     Session,
 }
 
+impl TokenType {
+    /// Name used in the JWT `type` claim
+    fn claim(&self) -> &'static str {
+        match self {
+            TokenType::Bearer => "bearer",
+            TokenType::API => "api",
+            TokenType::Deployment => "deployment",
+            TokenType::Session => "session",
+        }
+    }
+    
+    /// Prefix of the Bea Bot token string
+    fn prefix(&self) -> &'static str {
+        match self {
+            TokenType::Bearer => "bea_b",
+            TokenType::API => "bea_a",
+            TokenType::Deployment => "bea_d",
+            TokenType::Session => "bea_s",
+        }
+    }
+}
+
 /// Token metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenMetadata {
@@ -35,6 +61,12 @@ pub struct TokenMetadata {
     pub user_id: Option<String>,
     /// Custom metadata
     pub metadata: HashMap<String, String>,
+    /// Maximum number of model tokens this token may consume (None for unlimited)
+    #[serde(default)]
+    pub max_tokens_budget: Option<u64>,
+    /// Model tokens consumed so far
+    #[serde(default)]
+    pub tokens_consumed: u64,
 }
 
 /// Claims for JWT tokens
@@ -68,6 +100,7 @@ struct Claims {
 pub struct TokenStore {
     tokens: Arc<Mutex<HashMap<String, TokenMetadata>>>,
     jwt_secret: String,
+    clock: Arc<dyn Clock>,
 }
 
 impl TokenStore {
@@ -76,9 +109,54 @@ impl TokenStore {
         Self {
             tokens: Arc::new(Mutex::new(HashMap::new())),
             jwt_secret: jwt_secret.to_string(),
+            clock: Arc::new(SystemClock),
         }
     }
     
+    /// Use a custom clock for token timestamps and expiry checks
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    
+    /// Generate a token ID that isn't in `tokens` yet
+    ///
+    /// Token IDs have the form `tok_<uuid>`, with the UUID v4 written as 32
+    /// hex digits. A fresh UUID is drawn if the ID is already in the store.
+    fn new_token_id(tokens: &HashMap<String, TokenMetadata>) -> String {
+        loop {
+            let id = format!("tok_{}", Uuid::new_v4().simple());
+            if !tokens.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+    
+    /// Load a token store from a state file written by `save`
+    ///
+    /// A missing file gives an empty store. The file holds token metadata
+    /// only; tokens are checked against it and signed with `jwt_secret`.
+    pub async fn load(path: &Path, jwt_secret: &str) -> Result<Self, BeaError> {
+        let store = Self::new(jwt_secret);
+        
+        let contents = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(e.into()),
+        };
+        
+        let stored: Vec<TokenMetadata> = serde_json::from_str(&contents)?;
+        store.tokens.lock().await.extend(stored.into_iter().map(|t| (t.id.clone(), t)));
+        Ok(store)
+    }
+    
+    /// Write the token metadata to a state file only its owner can read
+    pub async fn save(&self, path: &Path) -> Result<(), BeaError> {
+        let mut stored: Vec<TokenMetadata> = self.tokens.lock().await.values().cloned().collect();
+        stored.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        crate::config::write_state_file(path, &serde_json::to_vec_pretty(&stored)?).await
+    }
+    
     /// Generate a new token
     pub async fn generate_token(
         &self,
@@ -89,13 +167,40 @@ impl TokenStore {
         user_id: Option<&str>,
         metadata: Option<HashMap<String, String>>,
     ) -> Result<String, Box<dyn Error>> {
-        let token_id = format!("tok_{}", Uuid::new_v4().to_string().replace("-", ""));
+        let (_, token) = self
+            .generate_token_with_id(token_type, environment, duration, agent_id, user_id, metadata)
+            .await?;
+        Ok(token)
+    }
+    
+    /// Generate a new token, returning its ID along with the token string
+    ///
+    /// Only the token's ID is traced; the token string is a credential.
+    #[tracing::instrument(
+        skip_all,
+        fields(token_type = ?token_type, environment = %environment, agent_id = tracing::field::Empty, token_id = tracing::field::Empty),
+        err(Display)
+    )]
+    pub async fn generate_token_with_id(
+        &self,
+        token_type: TokenType,
+        environment: &str,
+        duration: Option<Duration>,
+        agent_id: Option<&str>,
+        user_id: Option<&str>,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<(String, String), BeaError> {
+        let span = tracing::Span::current();
+        if let Some(agent_id) = agent_id {
+            span.record("agent_id", agent_id);
+        }
         
-        let now = Utc::now();
+        let now = self.clock.now();
         let expires_at = duration.map(|d| now + d);
         
         let token_metadata = TokenMetadata {
-            id: token_id.clone(),
+            // Assigned by `issue`
+            id: String::new(),
             token_type: token_type.clone(),
             environment: environment.to_string(),
             created_at: now,
@@ -103,23 +208,33 @@ impl TokenStore {
             agent_id: agent_id.map(|s| s.to_string()),
             user_id: user_id.map(|s| s.to_string()),
             metadata: metadata.unwrap_or_default(),
+            max_tokens_budget: None,
+            tokens_consumed: 0,
         };
         
-        // Create JWT claims
+        let (token_id, token) = self.issue(token_metadata).await?;
+        span.record("token_id", token_id.as_str());
+        Ok((token_id, token))
+    }
+    
+    /// Give the token an unused ID, sign a JWT for it and store its metadata
+    ///
+    /// Returns the ID and the full token string. The ID is picked and the
+    /// metadata stored under one lock, so concurrent calls can't both claim
+    /// the same ID.
+    async fn issue(&self, mut token_metadata: TokenMetadata) -> Result<(String, String), jsonwebtoken::errors::Error> {
+        let mut tokens = self.tokens.lock().await;
+        token_metadata.id = Self::new_token_id(&tokens);
+        
         let claims = Claims {
-            sub: token_id.clone(),
+            sub: token_metadata.id.clone(),
             iss: "bea-bot".to_string(),
-            iat: now.timestamp(),
-            exp: expires_at.map(|exp| exp.timestamp()),
-            token_type: match token_type {
-                TokenType::Bearer => "bearer",
-                TokenType::API => "api",
-                TokenType::Deployment => "deployment",
-                TokenType::Session => "session",
-            }.to_string(),
-            env: environment.to_string(),
-            aid: agent_id.map(|s| s.to_string()),
-            uid: user_id.map(|s| s.to_string()),
+            iat: token_metadata.created_at.timestamp(),
+            exp: token_metadata.expires_at.map(|exp| exp.timestamp()),
+            token_type: token_metadata.token_type.claim().to_string(),
+            env: token_metadata.environment.clone(),
+            aid: token_metadata.agent_id.clone(),
+            uid: token_metadata.user_id.clone(),
             meta: if token_metadata.metadata.is_empty() {
                 None
             } else {
@@ -134,27 +249,56 @@ impl TokenStore {
             &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
         )?;
         
+        // Generate Bea Bot token format
+        let token = format!("{}_{}", token_metadata.token_type.prefix(), token);
+        
         // Store token metadata
-        let mut tokens = self.tokens.lock().await;
-        tokens.insert(token_id, token_metadata);
+        let token_id = token_metadata.id.clone();
+        tokens.insert(token_id.clone(), token_metadata);
         
-        // Generate Bea Bot token format
-        let token_prefix = match token_type {
-            TokenType::Bearer => "bea_b",
-            TokenType::API => "bea_a",
-            TokenType::Deployment => "bea_d",
-            TokenType::Session => "bea_s",
-        };
+        Ok((token_id, token))
+    }
+    
+    /// Replace a token with a new one carrying the same metadata
+    ///
+    /// The new token gets a fresh ID and issue time, and keeps the old token's
+    /// lifetime counted from now. Expired tokens can still be rotated; the new
+    /// token's metadata then records when the old one expired under
+    /// `rotated_after_expiry`. The old token is revoked.
+    pub async fn rotate_token(&self, old_token_id: &str) -> Result<String, BeaError> {
+        let old = self.get_token_metadata(old_token_id).await
+            .ok_or(BeaError::TokenNotFound)?;
         
-        Ok(format!("{}_{}", token_prefix, token))
+        let now = self.clock.now();
+        let mut token_metadata = old.clone();
+        token_metadata.created_at = now;
+        token_metadata.expires_at = old.expires_at.map(|exp| now + (exp - old.created_at));
+        token_metadata.metadata.insert("rotated_from".to_string(), old.id.clone());
+        match old.expires_at {
+            Some(expired_at) if expired_at < now => {
+                token_metadata.metadata.insert("rotated_after_expiry".to_string(), expired_at.to_rfc3339());
+            }
+            _ => {
+                token_metadata.metadata.remove("rotated_after_expiry");
+            }
+        }
+        
+        let (_, token) = self.issue(token_metadata).await?;
+        
+        self.tokens.lock().await.remove(&old.id);
+        
+        Ok(token)
     }
     
     /// Validate a token
-    pub async fn validate_token(&self, token: &str) -> Result<TokenMetadata, Box<dyn Error>> {
+    ///
+    /// The token itself is never traced, only the ID it resolves to.
+    #[tracing::instrument(skip_all, fields(token_id = tracing::field::Empty), err(Display))]
+    pub async fn validate_token(&self, token: &str) -> Result<TokenMetadata, BeaError> {
         // Extract token type and JWT
         let parts: Vec<&str> = token.split('_').collect();
         if parts.len() < 3 || parts[0] != "bea" {
-            return Err("Invalid token format".into());
+            return Err(BeaError::InvalidTokenFormat);
         }
         
         let token_type = match parts[1] {
@@ -162,13 +306,16 @@ impl TokenStore {
             "a" => TokenType::API,
             "d" => TokenType::Deployment,
             "s" => TokenType::Session,
-            _ => return Err("Invalid token type".into()),
+            _ => return Err(BeaError::InvalidTokenFormat),
         };
         
         let jwt = parts[2..].join("_");
         
-        // Validate JWT
-        let validation = Validation::default();
+        // Validate JWT; expiry is checked against the store's clock below
+        let mut validation = Validation::default();
+        validation.validate_exp = false;
+        validation.set_required_spec_claims(&["iss"]);
+        validation.set_issuer(&["bea-bot"]);
         let token_data = decode::<Claims>(
             &jwt,
             &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
@@ -176,28 +323,109 @@ impl TokenStore {
         )?;
         
         let claims = token_data.claims;
+        tracing::Span::current().record("token_id", claims.sub.as_str());
+        
+        // The prefix isn't signed, so it must agree with the signed claim
+        if claims.token_type != token_type.claim() {
+            return Err(BeaError::InconsistentTokenType {
+                prefix: token_type.claim().to_string(),
+                claim: claims.token_type,
+            });
+        }
         
         // Check if token exists in store
         let tokens = self.tokens.lock().await;
-        let token_metadata = tokens.get(&claims.sub).ok_or("Token not found")?;
+        let token_metadata = tokens.get(&claims.sub).ok_or(BeaError::TokenNotFound)?;
         
         // Check if token is expired
         if let Some(expires_at) = token_metadata.expires_at {
-            if expires_at < Utc::now() {
-                return Err("Token expired".into());
+            if expires_at < self.clock.now() {
+                return Err(BeaError::TokenExpired);
+            }
+        }
+        
+        // Check if token has gone over its budget
+        if let Some(budget) = token_metadata.max_tokens_budget {
+            if token_metadata.tokens_consumed > budget {
+                return Err(BeaError::QuotaExceeded {
+                    token_id: token_metadata.id.clone(),
+                    budget,
+                    consumed: token_metadata.tokens_consumed,
+                });
             }
         }
         
         Ok(token_metadata.clone())
     }
     
+    /// Get the metadata for a token by ID
+    pub async fn get_token_metadata(&self, token_id: &str) -> Option<TokenMetadata> {
+        let tokens = self.tokens.lock().await;
+        tokens.get(token_id).cloned()
+    }
+    
+    /// Set the maximum number of model tokens a token may consume
+    ///
+    /// Once `tokens_consumed` exceeds the budget, `validate_token` rejects the
+    /// token with `BeaError::QuotaExceeded`. Pass `None` to remove the limit.
+    pub async fn set_token_budget(&self, token_id: &str, budget: Option<u64>) -> Result<(), BeaError> {
+        let mut tokens = self.tokens.lock().await;
+        let token_metadata = tokens.get_mut(token_id).ok_or(BeaError::TokenNotFound)?;
+        token_metadata.max_tokens_budget = budget;
+        Ok(())
+    }
+    
+    /// Record model tokens consumed by requests made with a token
+    ///
+    /// The usage is always recorded. Returns `BeaError::QuotaExceeded` once
+    /// it takes the token over its budget, as `validate_token` will from then on.
+    pub async fn record_usage(&self, token_id: &str, used: u64) -> Result<(), BeaError> {
+        let mut tokens = self.tokens.lock().await;
+        let token_metadata = tokens.get_mut(token_id).ok_or(BeaError::TokenNotFound)?;
+        token_metadata.tokens_consumed = token_metadata.tokens_consumed.saturating_add(used);
+        
+        match token_metadata.max_tokens_budget {
+            Some(budget) if token_metadata.tokens_consumed > budget => Err(BeaError::QuotaExceeded {
+                token_id: token_metadata.id.clone(),
+                budget,
+                consumed: token_metadata.tokens_consumed,
+            }),
+            _ => Ok(()),
+        }
+    }
+    
     /// Revoke a token
-    pub async fn revoke_token(&self, token_id: &str) -> Result<(), Box<dyn Error>> {
+    pub async fn revoke_token(&self, token_id: &str) -> Result<(), BeaError> {
         let mut tokens = self.tokens.lock().await;
-        tokens.remove(token_id).ok_or_else(|| "Token not found".into())?;
+        tokens.remove(token_id).ok_or(BeaError::TokenNotFound)?;
         Ok(())
     }
     
+    /// Remove expired tokens from the store, returning how many were removed
+    ///
+    /// Swept tokens fail validation with `BeaError::TokenNotFound`.
+    pub async fn sweep_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut tokens = self.tokens.lock().await;
+        let before = tokens.len();
+        tokens.retain(|_, t| t.expires_at.is_none_or(|exp| exp >= now));
+        before - tokens.len()
+    }
+    
+    /// Sweep expired tokens on a background task every `interval`
+    ///
+    /// The task runs until the returned handle is aborted.
+    pub fn spawn_sweeper(self: Arc<Self>, interval: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                self.sweep_expired().await;
+            }
+        })
+    }
+    
     /// List tokens for an agent
     pub async fn list_tokens_for_agent(&self, agent_id: &str) -> Vec<TokenMetadata> {
         let tokens = self.tokens.lock().await;
@@ -218,3 +446,237 @@ impl TokenStore {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    
+    const SECRET: &str = "test-secret";
+    
+    /// Store whose time only moves when the returned clock is advanced
+    fn store_with_mock_clock() -> (TokenStore, MockClock) {
+        let clock = MockClock::default();
+        let store = TokenStore::new(SECRET).with_clock(Arc::new(clock.clone()));
+        (store, clock)
+    }
+    
+    #[tokio::test]
+    async fn issued_tokens_validate() {
+        let store = TokenStore::new(SECRET);
+        let token = store
+            .generate_token(TokenType::API, "production", None, Some("support"), None, None)
+            .await
+            .unwrap();
+        
+        let metadata = store.validate_token(&token).await.unwrap();
+        assert!(matches!(metadata.token_type, TokenType::API));
+        assert_eq!(metadata.agent_id.as_deref(), Some("support"));
+    }
+    
+    #[tokio::test]
+    async fn tampered_prefix_is_rejected() {
+        let store = TokenStore::new(SECRET);
+        let token = store
+            .generate_token(TokenType::Bearer, "production", None, None, None, None)
+            .await
+            .unwrap();
+        let tampered = token.replacen("bea_b_", "bea_a_", 1);
+        
+        let err = store.validate_token(&tampered).await.unwrap_err();
+        assert!(
+            matches!(&err, BeaError::InconsistentTokenType { prefix, claim } if prefix == "api" && claim == "bearer"),
+            "{:?}",
+            err
+        );
+    }
+    
+    #[tokio::test]
+    async fn wrong_issuer_is_rejected() {
+        let store = TokenStore::new(SECRET);
+        let token = store
+            .generate_token(TokenType::Bearer, "production", None, None, None, None)
+            .await
+            .unwrap();
+        let id = store.validate_token(&token).await.unwrap().id;
+        
+        // Correctly signed and naming a stored token, but issued by someone else
+        let claims = Claims {
+            sub: id,
+            iss: "someone-else".to_string(),
+            iat: chrono::Utc::now().timestamp(),
+            exp: None,
+            token_type: "bearer".to_string(),
+            env: "production".to_string(),
+            aid: None,
+            uid: None,
+            meta: None,
+        };
+        let jwt = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap();
+        
+        let err = store.validate_token(&format!("bea_b_{}", jwt)).await.unwrap_err();
+        assert!(matches!(err, BeaError::InvalidToken(_)), "{:?}", err);
+    }
+    
+    #[tokio::test]
+    async fn unknown_token_ids_are_not_found() {
+        let store = TokenStore::new(SECRET);
+        
+        assert!(matches!(store.set_token_budget("missing", Some(10)).await, Err(BeaError::TokenNotFound)));
+        assert!(matches!(store.record_usage("missing", 1).await, Err(BeaError::TokenNotFound)));
+        assert!(matches!(store.revoke_token("missing").await, Err(BeaError::TokenNotFound)));
+    }
+    
+    #[tokio::test]
+    async fn usage_over_the_budget_is_recorded_and_reported() {
+        let store = TokenStore::new(SECRET);
+        let (id, token) = store
+            .generate_token_with_id(TokenType::API, "production", None, Some("support"), None, None)
+            .await
+            .unwrap();
+        store.set_token_budget(&id, Some(100)).await.unwrap();
+        
+        store.record_usage(&id, 60).await.unwrap();
+        let over = store.record_usage(&id, 60).await;
+        assert!(matches!(over, Err(BeaError::QuotaExceeded { budget: 100, consumed: 120, .. })));
+        assert!(matches!(store.validate_token(&token).await, Err(BeaError::QuotaExceeded { .. })));
+    }
+    
+    #[tokio::test]
+    async fn spending_exactly_the_budget_is_allowed() {
+        let store = TokenStore::new(SECRET);
+        let (id, token) = store
+            .generate_token_with_id(TokenType::API, "production", None, Some("support"), None, None)
+            .await
+            .unwrap();
+        store.set_token_budget(&id, Some(100)).await.unwrap();
+        
+        store.record_usage(&id, 100).await.unwrap();
+        assert_eq!(store.validate_token(&token).await.unwrap().tokens_consumed, 100);
+        
+        let over = store.record_usage(&id, 1).await;
+        assert!(matches!(over, Err(BeaError::QuotaExceeded { budget: 100, consumed: 101, .. })));
+        assert!(matches!(store.validate_token(&token).await, Err(BeaError::QuotaExceeded { .. })));
+    }
+    
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_issues_each_store_their_own_token() {
+        let store = Arc::new(TokenStore::new(SECRET));
+        let issues = (0..64).map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                store
+                    .generate_token_with_id(TokenType::API, "production", None, Some("support"), None, None)
+                    .await
+                    .unwrap()
+            })
+        });
+        
+        let mut ids = Vec::new();
+        for issue in issues.collect::<Vec<_>>() {
+            let (id, token) = issue.await.unwrap();
+            assert_eq!(store.validate_token(&token).await.unwrap().id, id);
+            ids.push(id);
+        }
+        
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 64);
+        assert_eq!(store.list_tokens_for_agent("support").await.len(), 64);
+    }
+    
+    #[tokio::test]
+    async fn tokens_expire_once_their_lifetime_has_passed() {
+        let (store, clock) = store_with_mock_clock();
+        let token = store
+            .generate_token(TokenType::Session, "production", Some(Duration::hours(1)), None, Some("ada"), None)
+            .await
+            .unwrap();
+        
+        clock.advance(Duration::minutes(59));
+        assert!(store.validate_token(&token).await.is_ok());
+        
+        clock.advance(Duration::minutes(1));
+        assert!(store.validate_token(&token).await.is_ok());
+        
+        clock.advance(Duration::seconds(1));
+        assert!(matches!(store.validate_token(&token).await, Err(BeaError::TokenExpired)));
+    }
+    
+    #[tokio::test]
+    async fn swept_tokens_are_gone() {
+        let (store, clock) = store_with_mock_clock();
+        let (short_id, short) = store
+            .generate_token_with_id(TokenType::Session, "production", Some(Duration::hours(1)), None, None, None)
+            .await
+            .unwrap();
+        let long = store
+            .generate_token(TokenType::Session, "production", Some(Duration::hours(2)), None, None, None)
+            .await
+            .unwrap();
+        let forever = store
+            .generate_token(TokenType::API, "production", None, None, None, None)
+            .await
+            .unwrap();
+        
+        assert_eq!(store.sweep_expired().await, 0);
+        
+        clock.advance(Duration::minutes(90));
+        assert_eq!(store.sweep_expired().await, 1);
+        
+        assert!(matches!(store.validate_token(&short).await, Err(BeaError::TokenNotFound)));
+        assert!(store.get_token_metadata(&short_id).await.is_none());
+        assert!(store.validate_token(&long).await.is_ok());
+        assert!(store.validate_token(&forever).await.is_ok());
+    }
+    
+    #[tokio::test]
+    async fn rotated_tokens_replace_the_old_one() {
+        let (store, clock) = store_with_mock_clock();
+        let (old_id, old) = store
+            .generate_token_with_id(TokenType::API, "production", Some(Duration::hours(1)), Some("support"), None, None)
+            .await
+            .unwrap();
+        clock.advance(Duration::minutes(30));
+        
+        let rotated = store.rotate_token(&old_id).await.unwrap();
+        
+        assert!(matches!(store.validate_token(&old).await, Err(BeaError::TokenNotFound)));
+        let metadata = store.validate_token(&rotated).await.unwrap();
+        assert_ne!(metadata.id, old_id);
+        assert_eq!(metadata.agent_id.as_deref(), Some("support"));
+        assert_eq!(metadata.created_at, clock.now());
+        assert_eq!(metadata.expires_at, Some(clock.now() + Duration::hours(1)));
+        assert_eq!(metadata.metadata.get("rotated_from"), Some(&old_id));
+        assert!(!metadata.metadata.contains_key("rotated_after_expiry"));
+    }
+    
+    #[tokio::test]
+    async fn expired_tokens_can_be_rotated() {
+        let (store, clock) = store_with_mock_clock();
+        let (old_id, old) = store
+            .generate_token_with_id(TokenType::Session, "production", Some(Duration::hours(1)), None, Some("ada"), None)
+            .await
+            .unwrap();
+        let expired_at = store.get_token_metadata(&old_id).await.unwrap().expires_at.unwrap();
+        clock.advance(Duration::hours(3));
+        assert!(matches!(store.validate_token(&old).await, Err(BeaError::TokenExpired)));
+        
+        let rotated = store.rotate_token(&old_id).await.unwrap();
+        
+        let metadata = store.validate_token(&rotated).await.unwrap();
+        assert_eq!(metadata.expires_at, Some(clock.now() + Duration::hours(1)));
+        assert_eq!(metadata.metadata.get("rotated_after_expiry"), Some(&expired_at.to_rfc3339()));
+        
+        // Rotating the new token again drops the expiry note
+        let again = store.rotate_token(&metadata.id).await.unwrap();
+        let metadata = store.validate_token(&again).await.unwrap();
+        assert!(!metadata.metadata.contains_key("rotated_after_expiry"));
+    }
+    
+    #[tokio::test]
+    async fn rotating_an_unknown_token_fails() {
+        let store = TokenStore::new(SECRET);
+        assert!(matches!(store.rotate_token("tok_missing").await, Err(BeaError::TokenNotFound)));
+    }
+}
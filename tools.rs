@@ -1,9 +1,17 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
-use tokio::sync::Mutex;
+use futures::future::join_all;
+use tokio::sync::{Mutex, Semaphore};
+
+use crate::{http, metrics, AgentStateManager, BeaError, ProviderError};
+use crate::models::{Message, ModelRegistry, ModelRequest, TRUNCATION_MARKER};
+use crate::vector_store::{Embedder, VectorStore};
 
 /// Tool capability for agents
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,9 +66,16 @@ pub trait Tool: Send + Sync {
     async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>>;
 }
 
+/// Tool held by a `ToolRegistry`, with the timeout it was registered with
+struct RegisteredTool {
+    tool: Arc<dyn Tool>,
+    timeout: Option<Duration>,
+}
+
 /// Tool registry for managing tools
 pub struct ToolRegistry {
-    tools: Arc<Mutex<HashMap<String, Box<dyn Tool>>>>,
+    tools: Arc<Mutex<HashMap<String, RegisteredTool>>>,
+    max_result_chars: Option<usize>,
 }
 
 impl ToolRegistry {
@@ -68,20 +83,62 @@ impl ToolRegistry {
     pub fn new() -> Self {
         Self {
             tools: Arc::new(Mutex::new(HashMap::new())),
+            max_result_chars: None,
+        }
+    }
+    
+    /// Clip tool results longer than `max_chars` before they are fed back to the model
+    pub fn with_max_result_chars(mut self, max_chars: usize) -> Self {
+        self.max_result_chars = Some(max_chars);
+        self
+    }
+    
+    /// Build the tool message that reports a result back to the model
+    ///
+    /// The result is serialized as JSON and clipped to the registry's
+    /// `max_result_chars`, so one huge tool output can't blow the context window.
+    pub fn result_message(&self, result: &ToolResult) -> Message {
+        let content = match &result.error {
+            Some(error) => serde_json::json!({ "error": error }).to_string(),
+            None => result.data.to_string(),
+        };
+        
+        let mut message = Message::tool(&content, &result.name);
+        if let Some(max_chars) = self.max_result_chars {
+            message.truncate_content(max_chars, TRUNCATION_MARKER);
         }
+        message
     }
     
     /// Register a new tool
     pub async fn register_tool<T: Tool + 'static>(&self, tool: T) -> Result<(), Box<dyn Error>> {
-        let mut tools = self.tools.lock().await;
-        tools.insert(tool.name().to_string(), Box::new(tool));
+        self.insert_tool(tool, None).await;
+        Ok(())
+    }
+    
+    /// Register a new tool whose executions are stopped after `timeout`
+    ///
+    /// The timeout applies to every `execute_tool` and `execute_tool_as`
+    /// call; `execute_tool_with_timeout` overrides it.
+    pub async fn register_tool_with_timeout<T: Tool + 'static>(
+        &self,
+        tool: T,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        self.insert_tool(tool, Some(timeout)).await;
         Ok(())
     }
     
+    /// Add a tool to the registry, replacing any tool with the same name
+    async fn insert_tool<T: Tool + 'static>(&self, tool: T, timeout: Option<Duration>) {
+        let mut tools = self.tools.lock().await;
+        tools.insert(tool.name().to_string(), RegisteredTool { tool: Arc::new(tool), timeout });
+    }
+    
     /// Get a tool by name
     pub async fn get_tool(&self, name: &str) -> Option<ToolCapability> {
         let tools = self.tools.lock().await;
-        tools.get(name).map(|tool| ToolCapability {
+        tools.get(name).map(|RegisteredTool { tool, .. }| ToolCapability {
             name: tool.name().to_string(),
             description: tool.description().to_string(),
             parameters: tool.parameters_schema(),
@@ -89,12 +146,142 @@ impl ToolRegistry {
         })
     }
     
-    /// Execute a tool
+    /// Execute a tool, granting it every permission it requires
+    ///
+    /// Parameters are checked against the tool's schema first; violations
+    /// are reported as an error result without running the tool.
     pub async fn execute_tool(&self, name: &str, parameters: serde_json::Value) -> Result<ToolResult, Box<dyn Error>> {
-        let tools = self.tools.lock().await;
-        let tool = tools.get(name).ok_or_else(|| format!("Tool not found: {}", name))?;
+        self.execute(name, parameters, None, None).await
+    }
+    
+    /// Execute a tool, stopping it if it runs longer than `timeout`
+    ///
+    /// The tool's future is dropped when the timeout expires, cancelling
+    /// whatever it was waiting on, and an error result is returned.
+    pub async fn execute_tool_with_timeout(
+        &self,
+        name: &str,
+        parameters: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<ToolResult, Box<dyn Error>> {
+        self.execute(name, parameters, None, Some(timeout)).await
+    }
+    
+    /// Execute several tool calls concurrently
+    ///
+    /// At most `max_concurrency` calls run at once; a limit of 0 is treated
+    /// as 1. Results are returned in the same order as `calls`, and a call
+    /// that fails, including one naming an unknown tool, gets an error result
+    /// without affecting the others.
+    pub async fn execute_many(
+        &self,
+        calls: Vec<(String, serde_json::Value)>,
+        max_concurrency: usize,
+    ) -> Vec<ToolResult> {
+        let semaphore = Semaphore::new(max_concurrency.max(1));
+        
+        let executions = calls.into_iter().map(|(name, parameters)| {
+            let semaphore = &semaphore;
+            async move {
+                let _permit = semaphore.acquire().await;
+                let result = self.execute_tool(&name, parameters).await;
+                result.unwrap_or_else(|e| ToolResult {
+                    name,
+                    status: ToolStatus::Error,
+                    data: serde_json::json!(null),
+                    error: Some(e.to_string()),
+                })
+            }
+        });
+        
+        join_all(executions).await
+    }
+    
+    /// Execute a tool on behalf of a caller holding the `granted` permissions
+    ///
+    /// Fails with `BeaError::PermissionDenied` listing the missing permissions
+    /// if the tool requires any that weren't granted.
+    pub async fn execute_tool_as(
+        &self,
+        name: &str,
+        parameters: serde_json::Value,
+        granted: &[String],
+    ) -> Result<ToolResult, Box<dyn Error>> {
+        self.execute(name, parameters, Some(granted), None).await
+    }
+    
+    /// Run a tool and record the execution in the metrics
+    async fn execute(
+        &self,
+        name: &str,
+        parameters: serde_json::Value,
+        granted: Option<&[String]>,
+        timeout: Option<Duration>,
+    ) -> Result<ToolResult, Box<dyn Error>> {
+        let registered = self.tools.lock().await.contains_key(name);
+        
+        let result = self.run_tool(name, parameters, granted, timeout).await;
+        let success = matches!(&result, Ok(ToolResult { status: ToolStatus::Success, .. }));
+        metrics::record_tool_execution(registered.then_some(name), success);
+        result
+    }
+    
+    /// Check permissions and parameters, then run the tool
+    ///
+    /// Without an explicit `timeout` the tool's registered timeout, if any,
+    /// applies. The registry isn't locked while the tool runs.
+    async fn run_tool(
+        &self,
+        name: &str,
+        parameters: serde_json::Value,
+        granted: Option<&[String]>,
+        timeout: Option<Duration>,
+    ) -> Result<ToolResult, Box<dyn Error>> {
+        let (tool, timeout) = {
+            let tools = self.tools.lock().await;
+            let registered = tools.get(name).ok_or_else(|| format!("Tool not found: {}", name))?;
+            (registered.tool.clone(), timeout.or(registered.timeout))
+        };
+        
+        if let Some(granted) = granted {
+            let missing: Vec<String> = tool
+                .required_permissions()
+                .into_iter()
+                .filter(|p| !granted.contains(p))
+                .collect();
+            if !missing.is_empty() {
+                return Err(BeaError::PermissionDenied { tool: name.to_string(), missing }.into());
+            }
+        }
         
-        match tool.execute(parameters.clone()).await {
+        let mut violations = Vec::new();
+        validate_value(&tool.parameters_schema(), &parameters, "parameters", &mut violations);
+        if !violations.is_empty() {
+            return Ok(ToolResult {
+                name: name.to_string(),
+                status: ToolStatus::Error,
+                data: serde_json::json!(null),
+                error: Some(format!("Invalid parameters: {}", violations.join("; "))),
+            });
+        }
+        
+        let execution = tool.execute(parameters);
+        let outcome = match timeout {
+            Some(limit) => match tokio::time::timeout(limit, execution).await {
+                Ok(outcome) => outcome,
+                Err(_) => {
+                    return Ok(ToolResult {
+                        name: name.to_string(),
+                        status: ToolStatus::Error,
+                        data: serde_json::json!(null),
+                        error: Some(format!("tool timed out after {}s", limit.as_secs_f64())),
+                    })
+                }
+            },
+            None => execution.await,
+        };
+        
+        match outcome {
             Ok(data) => Ok(ToolResult {
                 name: name.to_string(),
                 status: ToolStatus::Success,
@@ -113,13 +300,231 @@ impl ToolRegistry {
     /// List all available tools
     pub async fn list_tools(&self) -> Vec<ToolCapability> {
         let tools = self.tools.lock().await;
-        tools.values().map(|tool| ToolCapability {
+        tools.values().map(|RegisteredTool { tool, .. }| ToolCapability {
             name: tool.name().to_string(),
             description: tool.description().to_string(),
             parameters: tool.parameters_schema(),
             required_permissions: tool.required_permissions(),
         }).collect()
     }
+    
+    /// Export a machine-readable catalog of all tools, keyed by tool name
+    pub async fn catalog_json(&self) -> serde_json::Value {
+        let catalog: serde_json::Map<String, serde_json::Value> = self
+            .list_tools()
+            .await
+            .into_iter()
+            .map(|tool| {
+                let entry = serde_json::json!({
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                    "required_permissions": tool.required_permissions,
+                });
+                (tool.name, entry)
+            })
+            .collect();
+        
+        serde_json::Value::Object(catalog)
+    }
+    
+    /// Export the tool catalog as an OpenAPI document
+    ///
+    /// Each tool becomes a `POST /tools/{name}` operation whose request body
+    /// is the tool's parameters schema.
+    pub async fn catalog_openapi(&self) -> serde_json::Value {
+        let paths: serde_json::Map<String, serde_json::Value> = self
+            .list_tools()
+            .await
+            .into_iter()
+            .map(|tool| {
+                let operation = serde_json::json!({
+                    "post": {
+                        "operationId": tool.name,
+                        "summary": tool.description,
+                        "x-required-permissions": tool.required_permissions,
+                        "requestBody": {
+                            "required": true,
+                            "content": {
+                                "application/json": {
+                                    "schema": tool.parameters
+                                }
+                            }
+                        },
+                        "responses": {
+                            "200": {
+                                "description": "Tool execution result",
+                                "content": {
+                                    "application/json": {
+                                        "schema": { "type": "object" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                });
+                (format!("/tools/{}", tool.name), operation)
+            })
+            .collect();
+        
+        serde_json::json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Bea Bot Tools",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "paths": paths,
+        })
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check a value against a JSON Schema, collecting violations
+///
+/// Covers the subset of JSON Schema that tool schemas use: `type`,
+/// `required`, `properties`, `items` and `enum`. Violations are described
+/// with the path of the offending value.
+fn validate_value(schema: &serde_json::Value, value: &serde_json::Value, path: &str, violations: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type").and_then(|t| t.as_str()) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => true,
+        };
+        if !matches {
+            violations.push(format!("{} must be of type {}", path, expected));
+            return;
+        }
+    }
+    
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            violations.push(format!("{} must be one of {}", path, allowed.join(", ")));
+        }
+    }
+    
+    if let Some(object) = value.as_object() {
+        for required in schema.get("required").and_then(|r| r.as_array()).into_iter().flatten() {
+            if let Some(field) = required.as_str() {
+                if !object.contains_key(field) {
+                    violations.push(format!("{}.{} is required", path, field));
+                }
+            }
+        }
+        
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (field, field_value) in object {
+                if let Some(field_schema) = properties.get(field) {
+                    validate_value(field_schema, field_value, &format!("{}.{}", path, field), violations);
+                }
+            }
+        }
+    }
+    
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            validate_value(items, item, &format!("{}[{}]", path, i), violations);
+        }
+    }
+}
+
+/// Knowledge base search result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KbResult {
+    /// ID of the matching document, if the backend has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Document title
+    pub title: String,
+    /// Matching content
+    pub content: String,
+    /// Relevance score, higher is better
+    pub relevance: f64,
+}
+
+/// Backend searched by `KnowledgeBaseSearchTool`
+#[async_trait]
+pub trait KnowledgeBackend: Send + Sync {
+    /// Search for up to `limit` results matching `query`, best first
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<KbResult>, BeaError>;
+}
+
+/// Knowledge backend returning the same canned documents for every query
+#[derive(Debug, Clone, Default)]
+pub struct MockKnowledgeBackend;
+
+#[async_trait]
+impl KnowledgeBackend for MockKnowledgeBackend {
+    async fn search(&self, _query: &str, limit: usize) -> Result<Vec<KbResult>, BeaError> {
+        let results = [
+            ("About Bea Bot", "Bea Bot is a platform for deploying AI agents, tokens, and sites.", 0.95),
+            ("Deployment Guide", "Learn how to deploy your agent to production.", 0.87),
+            ("Token Management", "Secure token management for your agents.", 0.82),
+        ];
+        
+        Ok(results
+            .into_iter()
+            .take(limit)
+            .map(|(title, content, relevance)| KbResult {
+                id: None,
+                title: title.to_string(),
+                content: content.to_string(),
+                relevance,
+            })
+            .collect())
+    }
+}
+
+/// Knowledge backend doing nearest-neighbor lookups in a vector store
+///
+/// Entries' `title` and `content` metadata fields are returned with their
+/// similarity as the relevance.
+pub struct VectorStoreBackend {
+    store: Arc<VectorStore>,
+    embedder: Arc<dyn Embedder>,
+}
+
+impl VectorStoreBackend {
+    /// Create a new backend embedding queries with `embedder`
+    pub fn new(store: Arc<VectorStore>, embedder: Arc<dyn Embedder>) -> Self {
+        Self { store, embedder }
+    }
+}
+
+#[async_trait]
+impl KnowledgeBackend for VectorStoreBackend {
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<KbResult>, BeaError> {
+        let embedding = self.embedder
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| BeaError::InvalidEmbedding("no embedding returned for query".to_string()))?;
+        let field = |metadata: &serde_json::Value, key: &str| {
+            metadata.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string()
+        };
+        
+        Ok(self.store
+            .search(embedding, limit)
+            .await
+            .into_iter()
+            .map(|(id, score, metadata)| KbResult {
+                id: Some(id),
+                title: field(&metadata, "title"),
+                content: field(&metadata, "content"),
+                relevance: score as f64,
+            })
+            .collect())
+    }
 }
 
 /// Knowledge base search tool
@@ -128,10 +533,13 @@ pub struct KnowledgeBaseSearchTool {
     description: String,
     parameters_schema: serde_json::Value,
     permissions: Vec<String>,
+    backend: Arc<dyn KnowledgeBackend>,
 }
 
 impl KnowledgeBaseSearchTool {
     /// Create a new knowledge base search tool
+    ///
+    /// Searches `MockKnowledgeBackend` until a backend is set.
     pub fn new() -> Self {
         Self {
             name: "search_knowledge_base".to_string(),
@@ -152,8 +560,26 @@ impl KnowledgeBaseSearchTool {
                 }
             }),
             permissions: vec!["knowledge_base:read".to_string()],
+            backend: Arc::new(MockKnowledgeBackend),
         }
     }
+    
+    /// Search the given backend
+    pub fn with_backend(mut self, backend: Arc<dyn KnowledgeBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+    
+    /// Search a vector store, embedding queries with `embedder`
+    pub fn with_vector_store(self, store: Arc<VectorStore>, embedder: Arc<dyn Embedder>) -> Self {
+        self.with_backend(Arc::new(VectorStoreBackend::new(store, embedder)))
+    }
+}
+
+impl Default for KnowledgeBaseSearchTool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[async_trait]
@@ -175,8 +601,6 @@ impl Tool for KnowledgeBaseSearchTool {
     }
     
     async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
-        // In a real implementation, this would search the knowledge base
-        
         let query = parameters.get("query")
             .and_then(|q| q.as_str())
             .ok_or("Missing query parameter")?;
@@ -185,43 +609,213 @@ impl Tool for KnowledgeBaseSearchTool {
             .and_then(|l| l.as_u64())
             .unwrap_or(5);
             
-        // Mock search results
-        let results = vec![
-            serde_json::json!({
-                "title": "About Bea Bot",
-                "content": "Bea Bot is a platform for deploying AI agents, tokens, and sites.",
-                "relevance": 0.95
-            }),
-            serde_json::json!({
-                "title": "Deployment Guide",
-                "content": "Learn how to deploy your agent to production.",
-                "relevance": 0.87
-            }),
-            serde_json::json!({
-                "title": "Token Management",
-                "content": "Secure token management for your agents.",
-                "relevance": 0.82
-            }),
-        ];
+        let results = self.backend.search(query, limit as usize).await?;
         
         Ok(serde_json::json!({
             "query": query,
-            "results": results.into_iter().take(limit as usize).collect::<Vec<_>>()
+            "results": results
         }))
     }
 }
 
+/// Web search result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    /// Page title
+    pub title: String,
+    /// Page URL
+    pub url: String,
+    /// Snippet of the page matching the query
+    pub snippet: String,
+    /// Relevance score between 0 and 1, higher is better
+    pub relevance: f64,
+}
+
+/// Search API used by `WebSearchTool`
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Search the web for up to `limit` hits, best first
+    async fn query(&self, q: &str, limit: usize) -> Result<Vec<SearchHit>, BeaError>;
+}
+
+/// Default Brave Search API URL
+const BRAVE_SEARCH_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+
+/// Search provider backed by the Brave Search API
+pub struct BraveSearchProvider {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl BraveSearchProvider {
+    /// Create a new Brave search provider with the given subscription token
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            base_url: BRAVE_SEARCH_URL.to_string(),
+            client: http::client(),
+        }
+    }
+    
+    /// Use a different API URL
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl SearchProvider for BraveSearchProvider {
+    async fn query(&self, q: &str, limit: usize) -> Result<Vec<SearchHit>, BeaError> {
+        let response = self.client
+            .get(&self.base_url)
+            .header("X-Subscription-Token", &self.api_key)
+            .header("Accept", "application/json")
+            .query(&[("q", q), ("count", &limit.to_string())])
+            .send()
+            .await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body).into());
+        }
+        
+        let body: serde_json::Value = response.json().await?;
+        let results = body["web"]["results"].as_array().cloned().unwrap_or_default();
+        
+        // Brave doesn't score results, so relevance falls off with rank
+        let count = results.len().max(1) as f64;
+        Ok(results
+            .iter()
+            .take(limit)
+            .enumerate()
+            .map(|(rank, result)| SearchHit {
+                title: result["title"].as_str().unwrap_or_default().to_string(),
+                url: result["url"].as_str().unwrap_or_default().to_string(),
+                snippet: result["description"].as_str().unwrap_or_default().to_string(),
+                relevance: 1.0 - rank as f64 / count,
+            })
+            .collect())
+    }
+}
+
+/// Default Tavily API URL
+const TAVILY_SEARCH_URL: &str = "https://api.tavily.com/search";
+
+/// Search provider backed by the Tavily API
+pub struct TavilySearchProvider {
+    api_key: String,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl TavilySearchProvider {
+    /// Create a new Tavily search provider with the given API key
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            base_url: TAVILY_SEARCH_URL.to_string(),
+            client: http::client(),
+        }
+    }
+    
+    /// Use a different API URL
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl SearchProvider for TavilySearchProvider {
+    async fn query(&self, q: &str, limit: usize) -> Result<Vec<SearchHit>, BeaError> {
+        let response = self.client
+            .post(&self.base_url)
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({
+                "query": q,
+                "max_results": limit,
+            }))
+            .send()
+            .await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ProviderError::from_body(status.as_u16(), &body).into());
+        }
+        
+        let body: serde_json::Value = response.json().await?;
+        Ok(body["results"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .take(limit)
+            .map(|result| SearchHit {
+                title: result["title"].as_str().unwrap_or_default().to_string(),
+                url: result["url"].as_str().unwrap_or_default().to_string(),
+                snippet: result["content"].as_str().unwrap_or_default().to_string(),
+                relevance: result["score"].as_f64().unwrap_or_default(),
+            })
+            .collect())
+    }
+}
+
+/// Search provider returning the same canned hits for every query
+#[derive(Debug, Clone, Default)]
+pub struct StubSearchProvider;
+
+#[async_trait]
+impl SearchProvider for StubSearchProvider {
+    async fn query(&self, _q: &str, limit: usize) -> Result<Vec<SearchHit>, BeaError> {
+        let hits = [
+            (
+                "Rust Programming Language",
+                "https://www.rust-lang.org/",
+                "A language empowering everyone to build reliable and efficient software.",
+                0.95,
+            ),
+            (
+                "Anthropic",
+                "https://www.anthropic.com/",
+                "AI research and deployment company, focused on building AI systems that are safe, honest, and helpful.",
+                0.92,
+            ),
+            (
+                "GitHub: Build software better, together",
+                "https://github.com/",
+                "GitHub is where over 100 million developers shape the future of software, together.",
+                0.88,
+            ),
+        ];
+        
+        Ok(hits
+            .into_iter()
+            .take(limit)
+            .map(|(title, url, snippet, relevance)| SearchHit {
+                title: title.to_string(),
+                url: url.to_string(),
+                snippet: snippet.to_string(),
+                relevance,
+            })
+            .collect())
+    }
+}
+
 /// Web search tool
 pub struct WebSearchTool {
     name: String,
     description: String,
     parameters_schema: serde_json::Value,
     permissions: Vec<String>,
+    provider: Box<dyn SearchProvider>,
 }
 
 impl WebSearchTool {
-    /// Create a new web search tool
-    pub fn new() -> Self {
+    /// Create a new web search tool searching with the given provider
+    pub fn new(provider: Box<dyn SearchProvider>) -> Self {
         Self {
             name: "web_search".to_string(),
             description: "Search the web for information".to_string(),
@@ -241,6 +835,7 @@ impl WebSearchTool {
                 }
             }),
             permissions: vec!["web:search".to_string()],
+            provider,
         }
     }
 }
@@ -264,8 +859,6 @@ impl Tool for WebSearchTool {
     }
     
     async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
-        // In a real implementation, this would search the web
-        
         let query = parameters.get("query")
             .and_then(|q| q.as_str())
             .ok_or("Missing query parameter")?;
@@ -274,31 +867,11 @@ impl Tool for WebSearchTool {
             .and_then(|l| l.as_u64())
             .unwrap_or(5);
             
-        // Mock search results
-        let results = vec![
-            serde_json::json!({
-                "title": "Rust Programming Language",
-                "url": "https://www.rust-lang.org/",
-                "snippet": "A language empowering everyone to build reliable and efficient software.",
-                "relevance": 0.95
-            }),
-            serde_json::json!({
-                "title": "Anthropic",
-                "url": "https://www.anthropic.com/",
-                "snippet": "AI research and deployment company, focused on building AI systems that are safe, honest, and helpful.",
-                "relevance": 0.92
-            }),
-            serde_json::json!({
-                "title": "GitHub: Build software better, together",
-                "url": "https://github.com/",
-                "snippet": "GitHub is where over 100 million developers shape the future of software, together.",
-                "relevance": 0.88
-            }),
-        ];
+        let results = self.provider.query(query, limit as usize).await?;
         
         Ok(serde_json::json!({
             "query": query,
-            "results": results.into_iter().take(limit as usize).collect::<Vec<_>>()
+            "results": results
         }))
     }
 }
@@ -346,6 +919,12 @@ impl CreateTicketTool {
     }
 }
 
+impl Default for CreateTicketTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Tool for CreateTicketTool {
     fn name(&self) -> &str {
@@ -382,8 +961,8 @@ impl Tool for CreateTicketTool {
         let assignee = parameters.get("assignee")
             .and_then(|a| a.as_str());
             
-        // Generate ticket ID
-        let ticket_id = format!("TICKET-{}", uuid::Uuid::new_v4().to_string().split('-').next().unwrap());
+        // Ticket IDs are "TICKET-" followed by a full hyphenated UUID v4
+        let ticket_id = format!("TICKET-{}", uuid::Uuid::new_v4());
         
         Ok(serde_json::json!({
             "ticket_id": ticket_id,
@@ -396,3 +975,1525 @@ impl Tool for CreateTicketTool {
         }))
     }
 }
+
+/// Calculator tool
+pub struct CalculatorTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+}
+
+impl CalculatorTool {
+    /// Create a new calculator tool
+    pub fn new() -> Self {
+        Self {
+            name: "calculator".to_string(),
+            description: "Evaluate an arithmetic expression using + - * / and parentheses".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "required": ["expression"],
+                "properties": {
+                    "expression": {
+                        "type": "string",
+                        "description": "The expression to evaluate, e.g. \"(2 + 3) * 4\""
+                    }
+                }
+            }),
+            permissions: Vec::new(),
+        }
+    }
+}
+
+impl Default for CalculatorTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Tool for CalculatorTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        let expression = parameters.get("expression")
+            .and_then(|e| e.as_str())
+            .ok_or("Missing expression parameter")?;
+        
+        let result = ExpressionParser::new(expression).evaluate()?;
+        
+        Ok(serde_json::json!({
+            "expression": expression,
+            "result": result
+        }))
+    }
+}
+
+/// Recursive-descent evaluator for arithmetic expressions
+///
+/// Grammar, lowest precedence first:
+///
+/// ```text
+/// expr   = term (('+' | '-') term)*
+/// term   = factor (('*' | '/') factor)*
+/// factor = ('+' | '-') factor | number | '(' expr ')'
+/// ```
+///
+/// Parentheses and unary signs may nest at most `MAX_EXPRESSION_DEPTH`
+/// levels, so a model can't overflow the stack with a deeply nested input.
+struct ExpressionParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+/// Deepest nesting of parentheses and unary signs the calculator accepts
+const MAX_EXPRESSION_DEPTH: usize = 64;
+
+impl<'a> ExpressionParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0, depth: 0 }
+    }
+    
+    /// Evaluate the whole input, rejecting trailing characters
+    fn evaluate(mut self) -> Result<f64, String> {
+        let value = self.expr()?;
+        self.skip_whitespace();
+        if let Some(c) = self.peek() {
+            return Err(format!("Unexpected '{}' at position {}", c, self.pos));
+        }
+        if !value.is_finite() {
+            return Err("Result is not a finite number".to_string());
+        }
+        Ok(value)
+    }
+    
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            match self.next_operator(&['+', '-']) {
+                Some('+') => value += self.term()?,
+                Some('-') => value -= self.term()?,
+                _ => return Ok(value),
+            }
+        }
+    }
+    
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        loop {
+            match self.next_operator(&['*', '/']) {
+                Some('*') => value *= self.factor()?,
+                Some('/') => {
+                    let divisor = self.factor()?;
+                    if divisor == 0.0 {
+                        return Err("Division by zero".to_string());
+                    }
+                    value /= divisor;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+    
+    fn factor(&mut self) -> Result<f64, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('+') => {
+                self.pos += 1;
+                self.nested(Self::factor)
+            }
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.nested(Self::factor)?)
+            }
+            Some('(') => {
+                self.pos += 1;
+                let value = self.nested(Self::expr)?;
+                self.skip_whitespace();
+                if self.peek() != Some(')') {
+                    return Err(format!("Expected ')' at position {}", self.pos));
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let number = &self.input[start..self.pos];
+                number.parse().map_err(|_| format!("Invalid number '{}'", number))
+            }
+            Some(c) => Err(format!("Unexpected '{}' at position {}", c, self.pos)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+    
+    /// Run `parse` one nesting level deeper, refusing to go past `MAX_EXPRESSION_DEPTH`
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<f64, String>) -> Result<f64, String> {
+        if self.depth == MAX_EXPRESSION_DEPTH {
+            return Err(format!("Expression is nested more than {} levels deep", MAX_EXPRESSION_DEPTH));
+        }
+        
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+    
+    /// Consume the next character if it is one of `operators`
+    fn next_operator(&mut self, operators: &[char]) -> Option<char> {
+        self.skip_whitespace();
+        let c = self.peek().filter(|c| operators.contains(c))?;
+        self.pos += 1;
+        Some(c)
+    }
+    
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+    
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+}
+
+/// Date and time tool
+pub struct DateTimeTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+}
+
+impl DateTimeTool {
+    /// Create a new date and time tool
+    pub fn new() -> Self {
+        Self {
+            name: "datetime".to_string(),
+            description: "Get the current date and time, optionally shifted, in a given timezone".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "timezone": {
+                        "type": "string",
+                        "description": "\"UTC\" or a fixed offset such as \"+05:30\" or \"UTC-8\"",
+                        "default": "UTC"
+                    },
+                    "expression": {
+                        "type": "string",
+                        "description": "Date arithmetic relative to now, e.g. \"now + 3 days\" or \"now - 2 hours\"",
+                        "default": "now"
+                    }
+                }
+            }),
+            permissions: Vec::new(),
+        }
+    }
+    
+    /// Parse "UTC", "Z", "+05:30", "-8" or "UTC+2" into a fixed offset
+    fn parse_timezone(timezone: &str) -> Result<chrono::FixedOffset, String> {
+        let invalid = || format!("Unsupported timezone '{}', expected UTC or an offset like +05:30", timezone);
+        
+        let trimmed = timezone.trim();
+        let offset = trimmed
+            .strip_prefix("UTC")
+            .or_else(|| trimmed.strip_prefix("GMT"))
+            .unwrap_or(trimmed);
+        if offset.is_empty() || offset == "Z" {
+            return Ok(chrono::FixedOffset::east_opt(0).unwrap());
+        }
+        
+        let (sign, rest) = match offset.chars().next() {
+            Some('+') => (1, &offset[1..]),
+            Some('-') => (-1, &offset[1..]),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 14 || minutes >= 60 {
+            return Err(invalid());
+        }
+        
+        chrono::FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+    }
+    
+    /// Parse "now", "now + 3 days" or "now - 90 minutes" into an offset from now
+    fn parse_shift(expression: &str) -> Result<chrono::Duration, String> {
+        let invalid = || format!("Unsupported expression '{}', expected e.g. \"now + 3 days\"", expression);
+        
+        let tokens: Vec<&str> = expression.split_whitespace().collect();
+        let (sign, amount, unit) = match tokens.as_slice() {
+            ["now"] => return Ok(chrono::Duration::zero()),
+            ["now", "+", amount, unit] => (1, *amount, *unit),
+            ["now", "-", amount, unit] => (-1, *amount, *unit),
+            _ => return Err(invalid()),
+        };
+        
+        let amount: i64 = amount.parse().map_err(|_| invalid())?;
+        let amount = sign * amount;
+        match unit.trim_end_matches('s') {
+            "second" => chrono::Duration::try_seconds(amount),
+            "minute" => chrono::Duration::try_minutes(amount),
+            "hour" => chrono::Duration::try_hours(amount),
+            "day" => chrono::Duration::try_days(amount),
+            "week" => chrono::Duration::try_weeks(amount),
+            _ => None,
+        }
+        .ok_or_else(invalid)
+    }
+}
+
+impl Default for DateTimeTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Tool for DateTimeTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        let timezone = match parameters.get("timezone") {
+            None => "UTC",
+            Some(tz) => tz.as_str().ok_or("timezone must be a string")?,
+        };
+        
+        let expression = match parameters.get("expression") {
+            None => "now",
+            Some(expr) => expr.as_str().ok_or("expression must be a string")?,
+        };
+        
+        let offset = Self::parse_timezone(timezone)?;
+        let shift = Self::parse_shift(expression)?;
+        
+        let datetime = chrono::Utc::now()
+            .checked_add_signed(shift)
+            .ok_or("Date is out of range")?
+            .with_timezone(&offset);
+        
+        Ok(serde_json::json!({
+            "timezone": timezone,
+            "expression": expression,
+            "datetime": datetime.to_rfc3339(),
+            "weekday": datetime.format("%A").to_string(),
+            "unix": datetime.timestamp()
+        }))
+    }
+}
+
+/// Largest file `FileReadTool` reads by default
+const DEFAULT_MAX_READ_BYTES: u64 = 1_000_000;
+
+/// Directory that file tools are confined to
+///
+/// Paths are resolved against the root, and the resolved path, with every
+/// symlink followed, must still be inside it.
+#[derive(Debug, Clone)]
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// Canonical form of the root
+    async fn canonical_root(&self) -> Result<PathBuf, String> {
+        tokio::fs::canonicalize(&self.root)
+            .await
+            .map_err(|e| format!("Sandbox root {} is unavailable: {}", self.root.display(), e))
+    }
+    
+    /// Check that a canonical path is inside the root, returning it relative to the root
+    fn relative(root: &Path, canonical: &Path, requested: &str) -> Result<String, String> {
+        canonical
+            .strip_prefix(root)
+            .map(|relative| relative.display().to_string())
+            .map_err(|_| format!("Path {} is outside the sandbox", requested))
+    }
+    
+    /// Resolve a path to an existing file or directory
+    async fn resolve_existing(&self, requested: &str) -> Result<(PathBuf, String), String> {
+        let root = self.canonical_root().await?;
+        let canonical = tokio::fs::canonicalize(root.join(requested))
+            .await
+            .map_err(|e| format!("Cannot open {}: {}", requested, e))?;
+        
+        let relative = Self::relative(&root, &canonical, requested)?;
+        Ok((canonical, relative))
+    }
+    
+    /// Resolve a path that may not exist yet, creating missing parent directories
+    ///
+    /// The deepest existing ancestor is canonicalized and checked; the
+    /// components below it can't be symlinks, but must not contain `..`.
+    async fn resolve_for_write(&self, requested: &str) -> Result<(PathBuf, String), String> {
+        let root = self.canonical_root().await?;
+        
+        let mut existing = root.join(requested);
+        let mut missing = Vec::new();
+        loop {
+            match tokio::fs::symlink_metadata(&existing).await {
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Cannot open {}: {}", requested, e)),
+            }
+            
+            match existing.components().next_back() {
+                Some(Component::Normal(name)) => missing.push(name.to_os_string()),
+                Some(Component::CurDir) => {}
+                _ => return Err(format!("Path {} is outside the sandbox", requested)),
+            }
+            existing.pop();
+        }
+        
+        let mut target = tokio::fs::canonicalize(&existing)
+            .await
+            .map_err(|e| format!("Cannot open {}: {}", requested, e))?;
+        Self::relative(&root, &target, requested)?;
+        
+        target.extend(missing.iter().rev());
+        if missing.len() > 1 {
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("Cannot create {}: {}", requested, e))?;
+            }
+        }
+        
+        let relative = Self::relative(&root, &target, requested)?;
+        Ok((target, relative))
+    }
+}
+
+/// Tool that reads text files inside a sandbox directory
+///
+/// Paths that resolve outside the root, through `..` or symlinks, are
+/// rejected without being read.
+pub struct FileReadTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+    sandbox: Sandbox,
+    max_bytes: u64,
+}
+
+impl FileReadTool {
+    /// Create a new file read tool confined to `root`
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            name: "read_file".to_string(),
+            description: "Read a text file".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path of the file, relative to the sandbox root"
+                    }
+                }
+            }),
+            permissions: vec!["fs:read".to_string()],
+            sandbox: Sandbox { root },
+            max_bytes: DEFAULT_MAX_READ_BYTES,
+        }
+    }
+    
+    /// Refuse to read files larger than `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for FileReadTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        let requested = parameters.get("path")
+            .and_then(|p| p.as_str())
+            .ok_or("Missing path parameter")?;
+        
+        let (path, relative) = self.sandbox.resolve_existing(requested).await?;
+        
+        let metadata = tokio::fs::metadata(&path).await?;
+        if !metadata.is_file() {
+            return Err(format!("{} is not a file", requested).into());
+        }
+        if metadata.len() > self.max_bytes {
+            return Err(format!(
+                "{} is {} bytes, more than the limit of {}",
+                requested,
+                metadata.len(),
+                self.max_bytes
+            )
+            .into());
+        }
+        
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", requested, e))?;
+        
+        Ok(serde_json::json!({
+            "path": relative,
+            "bytes": metadata.len(),
+            "content": content
+        }))
+    }
+}
+
+/// Tool that writes text files inside a sandbox directory
+///
+/// Missing parent directories are created. Paths that resolve outside the
+/// root, through `..` or symlinks, are rejected without being written.
+pub struct FileWriteTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+    sandbox: Sandbox,
+}
+
+impl FileWriteTool {
+    /// Create a new file write tool confined to `root`
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            name: "write_file".to_string(),
+            description: "Write or append to a text file".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "required": ["path", "content"],
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path of the file, relative to the sandbox root"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Text to write"
+                    },
+                    "append": {
+                        "type": "boolean",
+                        "description": "Append to the file instead of replacing it",
+                        "default": false
+                    }
+                }
+            }),
+            permissions: vec!["fs:write".to_string()],
+            sandbox: Sandbox { root },
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FileWriteTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        use tokio::io::AsyncWriteExt;
+        
+        let requested = parameters.get("path")
+            .and_then(|p| p.as_str())
+            .ok_or("Missing path parameter")?;
+        
+        let content = parameters.get("content")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing content parameter")?;
+        
+        let append = parameters.get("append")
+            .and_then(|a| a.as_bool())
+            .unwrap_or(false);
+        
+        let (path, relative) = self.sandbox.resolve_for_write(requested).await?;
+        
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .await
+            .map_err(|e| format!("Cannot write {}: {}", requested, e))?;
+        file.write_all(content.as_bytes()).await?;
+        file.flush().await?;
+        
+        Ok(serde_json::json!({
+            "path": relative,
+            "bytes_written": content.len(),
+            "append": append
+        }))
+    }
+}
+
+/// Largest response body `HttpRequestTool` returns by default
+const DEFAULT_MAX_BODY_BYTES: usize = 100_000;
+
+/// Time `HttpRequestTool` allows for each request by default
+const DEFAULT_HTTP_TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of redirects `HttpRequestTool` follows
+const MAX_REDIRECTS: usize = 5;
+
+/// Headers dropped when a redirect leads to a different origin
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization"];
+
+/// Tool that makes HTTP requests to allow-listed hosts
+///
+/// Host patterns are exact names like `api.example.com`, `*.example.com`
+/// for any subdomain, or `*` for any host. Redirects are followed by hand
+/// so every hop is checked against the allow-list too. Unless private
+/// addresses are allowed, every address a host resolves to must be public,
+/// and the connection is pinned to the address that was checked so a second
+/// DNS lookup can't swap in a private one. Requests sent through a proxy
+/// are resolved again by the proxy, so there the pin doesn't apply.
+pub struct HttpRequestTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+    allowed_hosts: Vec<String>,
+    allow_private: bool,
+    max_body_bytes: usize,
+    timeout: Duration,
+    http: http::HttpClientBuilder,
+}
+
+impl HttpRequestTool {
+    /// Create a new HTTP request tool allowed to reach the given host patterns
+    pub fn new(allowed_hosts: Vec<String>) -> Self {
+        Self {
+            name: "http_request".to_string(),
+            description: "Make an HTTP request and return the response".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "required": ["url"],
+                "properties": {
+                    "method": {
+                        "type": "string",
+                        "description": "HTTP method",
+                        "enum": ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD"],
+                        "default": "GET"
+                    },
+                    "url": {
+                        "type": "string",
+                        "description": "Absolute http or https URL"
+                    },
+                    "headers": {
+                        "type": "object",
+                        "description": "Request headers, as name-value pairs"
+                    },
+                    "body": {
+                        "type": "string",
+                        "description": "Request body"
+                    }
+                }
+            }),
+            permissions: vec!["http:request".to_string()],
+            allowed_hosts: allowed_hosts.into_iter().map(|h| h.to_lowercase()).collect(),
+            allow_private: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            timeout: DEFAULT_HTTP_TOOL_TIMEOUT,
+            http: http::HttpClientBuilder::new(),
+        }
+    }
+    
+    /// Build each request's client from the given settings, e.g. to set a proxy
+    ///
+    /// The tool's own timeout replaces the builder's.
+    pub fn with_http_client_builder(mut self, builder: http::HttpClientBuilder) -> Self {
+        self.http = builder;
+        self
+    }
+    
+    /// Allow requests to loopback, private and link-local addresses
+    pub fn with_allow_private(mut self, allow_private: bool) -> Self {
+        self.allow_private = allow_private;
+        self
+    }
+    
+    /// Truncate response bodies longer than `max_bytes`
+    pub fn with_max_body_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_body_bytes = max_bytes;
+        self
+    }
+    
+    /// Set the time allowed for each request, including each redirect
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    
+    /// Whether a host matches one of the allowed patterns
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_lowercase();
+        self.allowed_hosts.iter().any(|pattern| match pattern.strip_prefix("*.") {
+            Some(domain) => host.ends_with(&format!(".{}", domain)),
+            None => pattern == "*" || *pattern == host,
+        })
+    }
+    
+    /// Check a URL against the allow-list and resolve the address to connect to
+    async fn check_url(&self, url: &reqwest::Url) -> Result<SocketAddr, String> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Unsupported URL scheme: {}", url.scheme()));
+        }
+        
+        let host = url.host_str().ok_or_else(|| format!("URL has no host: {}", url))?;
+        if !self.host_allowed(host) {
+            return Err(format!("Host {} is not allowed", host));
+        }
+        
+        let port = url.port_or_known_default().unwrap_or(80);
+        let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = match bare_host.parse::<IpAddr>() {
+            Ok(ip) => vec![SocketAddr::new(ip, port)],
+            Err(_) => tokio::net::lookup_host((bare_host, port))
+                .await
+                .map_err(|e| format!("Cannot resolve {}: {}", host, e))?
+                .collect(),
+        };
+        
+        if !self.allow_private {
+            if let Some(addr) = addrs.iter().find(|addr| is_private_ip(addr.ip())) {
+                return Err(format!("Host {} resolves to private address {}", host, addr.ip()));
+            }
+        }
+        
+        addrs.into_iter().next().ok_or_else(|| format!("Cannot resolve {}", host))
+    }
+}
+
+/// Whether an address is loopback, private, link-local or otherwise not public
+///
+/// IPv6 forms that carry an IPv4 address (mapped, NAT64 and 6to4) are
+/// judged by the IPv4 address they carry.
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || a == 0
+                // Reserved 240.0.0.0/4
+                || a >= 240
+                // Shared address space used by carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // Benchmarking 198.18.0.0/15
+                || (a == 198 && (b == 18 || b == 19))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_private_ip(IpAddr::V4(mapped));
+            }
+            
+            let segments = ip.segments();
+            let embedded = |high: u16, low: u16| {
+                let [a, b] = high.to_be_bytes();
+                let [c, d] = low.to_be_bytes();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+            };
+            // NAT64 64:ff9b::/96 carries the IPv4 address in its last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_private_ip(embedded(segments[6], segments[7]));
+            }
+            // 6to4 2002::/16 carries it in the 32 bits after the prefix
+            if segments[0] == 0x2002 && is_private_ip(embedded(segments[1], segments[2])) {
+                return true;
+            }
+            
+            let first = segments[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for HttpRequestTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        let mut url: reqwest::Url = parameters.get("url")
+            .and_then(|u| u.as_str())
+            .ok_or("Missing url parameter")?
+            .parse()
+            .map_err(|e| format!("Invalid url: {}", e))?;
+        
+        let mut method: reqwest::Method = parameters.get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or("GET")
+            .to_uppercase()
+            .parse()
+            .map_err(|_| "Invalid method")?;
+        
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(pairs) = parameters.get("headers").and_then(|h| h.as_object()) {
+            for (name, value) in pairs {
+                let value = value.as_str().ok_or_else(|| format!("Header {} must be a string", name))?;
+                headers.insert(
+                    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|_| format!("Invalid header name: {}", name))?,
+                    reqwest::header::HeaderValue::from_str(value)
+                        .map_err(|_| format!("Invalid value for header {}", name))?,
+                );
+            }
+        }
+        
+        let mut body = parameters.get("body")
+            .and_then(|b| b.as_str())
+            .map(|b| b.to_string());
+        
+        let mut redirects = 0;
+        let response = loop {
+            let addr = self.check_url(&url).await?;
+            let host = url.host_str().unwrap_or_default().to_string();
+            
+            let client = self.http
+                .client_builder()?
+                .redirect(reqwest::redirect::Policy::none())
+                .resolve(&host, addr)
+                .timeout(self.timeout)
+                .build()?;
+            
+            let mut request = client.request(method.clone(), url.clone()).headers(headers.clone());
+            if let Some(body) = &body {
+                request = request.body(body.clone());
+            }
+            let response = request.send().await?;
+            
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .map(|l| l.to_string());
+            let location = match location {
+                Some(location) if response.status().is_redirection() => location,
+                _ => break response,
+            };
+            
+            redirects += 1;
+            if redirects > MAX_REDIRECTS {
+                return Err(format!("Stopped after {} redirects", MAX_REDIRECTS).into());
+            }
+            
+            let next = url.join(&location).map_err(|e| format!("Invalid redirect location: {}", e))?;
+            // Scheme, host and port make up the origin
+            if next.origin() != url.origin() {
+                for name in SENSITIVE_HEADERS {
+                    headers.remove(*name);
+                }
+            }
+            
+            // Like browsers, 303 and POST after 301/302 continue as a GET without a body
+            let status = response.status().as_u16();
+            if status == 303 || (matches!(status, 301 | 302) && method == reqwest::Method::POST) {
+                method = reqwest::Method::GET;
+                body = None;
+            }
+            url = next;
+        };
+        
+        let status = response.status().as_u16();
+        let mut response_headers = serde_json::Map::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+            match response_headers.get_mut(name.as_str()) {
+                Some(serde_json::Value::String(existing)) => {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                }
+                _ => {
+                    response_headers.insert(name.to_string(), serde_json::Value::String(value));
+                }
+            }
+        }
+        
+        let mut response = response;
+        let mut bytes = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await? {
+            let room = self.max_body_bytes - bytes.len();
+            if chunk.len() > room {
+                bytes.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        
+        Ok(serde_json::json!({
+            "url": url.to_string(),
+            "status": status,
+            "headers": response_headers,
+            "body": String::from_utf8_lossy(&bytes),
+            "truncated": truncated
+        }))
+    }
+}
+
+/// Delegations allowed in a chain before `DelegateAgentTool` refuses
+pub const DEFAULT_MAX_DELEGATION_DEPTH: u64 = 3;
+
+tokio::task_local! {
+    /// Delegations already made in the chain the running task belongs to
+    static DELEGATION_DEPTH: u64;
+}
+
+/// Agent delegation tool
+///
+/// Sends a prompt to another registered agent and returns its reply. The
+/// depth of the chain is kept with the task rather than in the model's
+/// arguments, and the target runs one level deeper, so chains of agents
+/// calling each other stop at the maximum depth instead of looping forever.
+pub struct DelegateAgentTool {
+    name: String,
+    description: String,
+    parameters_schema: serde_json::Value,
+    permissions: Vec<String>,
+    agents: Arc<AgentStateManager>,
+    registry: Arc<ModelRegistry>,
+    max_depth: u64,
+}
+
+impl DelegateAgentTool {
+    /// Create a new delegation tool over the registered agents
+    pub fn new(agents: Arc<AgentStateManager>, registry: Arc<ModelRegistry>) -> Self {
+        Self {
+            name: "delegate_agent".to_string(),
+            description: "Ask another agent to handle a prompt and return its answer".to_string(),
+            parameters_schema: serde_json::json!({
+                "type": "object",
+                "required": ["target_agent", "prompt"],
+                "properties": {
+                    "target_agent": {
+                        "type": "string",
+                        "description": "Name of the agent to delegate to"
+                    },
+                    "prompt": {
+                        "type": "string",
+                        "description": "Prompt to send to the agent"
+                    }
+                }
+            }),
+            permissions: vec!["agents:delegate".to_string()],
+            agents,
+            registry,
+            max_depth: DEFAULT_MAX_DELEGATION_DEPTH,
+        }
+    }
+    
+    /// Set how many delegations a chain may make
+    pub fn with_max_depth(mut self, max_depth: u64) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for DelegateAgentTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    
+    fn description(&self) -> &str {
+        &self.description
+    }
+    
+    fn parameters_schema(&self) -> serde_json::Value {
+        self.parameters_schema.clone()
+    }
+    
+    fn required_permissions(&self) -> Vec<String> {
+        self.permissions.clone()
+    }
+    
+    async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+        let target = parameters.get("target_agent")
+            .and_then(|t| t.as_str())
+            .ok_or("Missing target_agent parameter")?;
+        
+        let prompt = parameters.get("prompt")
+            .and_then(|p| p.as_str())
+            .ok_or("Missing prompt parameter")?;
+        
+        let depth = DELEGATION_DEPTH.try_with(|depth| *depth).unwrap_or(0);
+        if depth >= self.max_depth {
+            return Err(format!(
+                "Delegation depth {} reached the limit of {}",
+                depth, self.max_depth
+            ).into());
+        }
+        
+        let agent = self.agents.get_state_snapshot(target).await
+            .ok_or_else(|| BeaError::AgentNotFound(target.to_string()))?
+            .agent;
+        
+        let _permit = self.agents.acquire_slot(target).await?;
+        let request = ModelRequest::from_agent(&agent, vec![Message::user(prompt)]);
+        let response = DELEGATION_DEPTH.scope(depth + 1, self.registry.generate(request)).await?;
+        self.agents.record_request(target, response.usage()).await?;
+        
+        Ok(serde_json::json!({
+            "agent": target,
+            "response": response.message().content()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    async fn ticket_registry() -> ToolRegistry {
+        let registry = ToolRegistry::new();
+        registry.register_tool(CreateTicketTool::new()).await.unwrap();
+        registry
+    }
+    
+    #[tokio::test]
+    async fn missing_required_parameter_is_reported() {
+        let registry = ticket_registry().await;
+        let result = registry
+            .execute_tool("create_ticket", serde_json::json!({ "description": "Printer is on fire" }))
+            .await
+            .unwrap();
+        
+        assert!(matches!(result.status, ToolStatus::Error));
+        assert_eq!(result.error.as_deref(), Some("Invalid parameters: parameters.title is required"));
+    }
+    
+    #[tokio::test]
+    async fn invalid_enum_value_is_reported() {
+        let registry = ticket_registry().await;
+        let parameters = serde_json::json!({
+            "title": "Printer",
+            "description": "Printer is on fire",
+            "priority": "urgent",
+        });
+        let result = registry.execute_tool("create_ticket", parameters).await.unwrap();
+        
+        assert!(matches!(result.status, ToolStatus::Error));
+        let error = result.error.unwrap();
+        assert!(error.contains(r#"parameters.priority must be one of "low", "medium", "high", "critical""#), "{}", error);
+    }
+    
+    fn ticket_parameters() -> serde_json::Value {
+        serde_json::json!({ "title": "Printer", "description": "Printer is on fire" })
+    }
+    
+    #[tokio::test]
+    async fn granted_permissions_allow_execution() {
+        let registry = ticket_registry().await;
+        let granted = vec!["tickets:write".to_string()];
+        let result = registry.execute_tool_as("create_ticket", ticket_parameters(), &granted).await.unwrap();
+        
+        assert!(matches!(result.status, ToolStatus::Success));
+        assert_eq!(result.data["title"], "Printer");
+    }
+    
+    #[tokio::test]
+    async fn missing_permissions_are_denied() {
+        let registry = ticket_registry().await;
+        let granted = vec!["tickets:read".to_string()];
+        let err = registry.execute_tool_as("create_ticket", ticket_parameters(), &granted).await.unwrap_err();
+        
+        match err.downcast_ref::<BeaError>() {
+            Some(BeaError::PermissionDenied { tool, missing }) => {
+                assert_eq!(tool, "create_ticket");
+                assert_eq!(missing, &vec!["tickets:write".to_string()]);
+            }
+            other => panic!("expected PermissionDenied, got {:?}", other),
+        }
+    }
+    
+    /// Fresh sandbox root holding `inside.txt`, next to a file outside it
+    async fn sandbox_dir() -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("bea-sandbox-{}", uuid::Uuid::new_v4()));
+        let root = base.join("root");
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        tokio::fs::write(root.join("inside.txt"), "inside").await.unwrap();
+        tokio::fs::write(base.join("secret.txt"), "secret").await.unwrap();
+        (base, root)
+    }
+    
+    #[tokio::test]
+    async fn file_tools_read_and_write_inside_the_root() {
+        let (base, root) = sandbox_dir().await;
+        let read = FileReadTool::new(root.clone());
+        let write = FileWriteTool::new(root.clone());
+        
+        let result = read.execute(serde_json::json!({ "path": "inside.txt" })).await.unwrap();
+        assert_eq!(result["content"], "inside");
+        assert_eq!(result["path"], "inside.txt");
+        
+        let result = write
+            .execute(serde_json::json!({ "path": "notes/today.txt", "content": "hello" }))
+            .await
+            .unwrap();
+        assert_eq!(result["path"], "notes/today.txt");
+        assert_eq!(tokio::fs::read_to_string(root.join("notes/today.txt")).await.unwrap(), "hello");
+        
+        tokio::fs::remove_dir_all(base).await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn file_read_rejects_parent_directory_escape() {
+        let (base, root) = sandbox_dir().await;
+        let read = FileReadTool::new(root);
+        
+        let error = read.execute(serde_json::json!({ "path": "../secret.txt" })).await.unwrap_err();
+        assert_eq!(error.to_string(), "Path ../secret.txt is outside the sandbox");
+        
+        tokio::fs::remove_dir_all(base).await.unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn file_tools_reject_symlinks_out_of_the_root() {
+        let (base, root) = sandbox_dir().await;
+        std::os::unix::fs::symlink(base.join("secret.txt"), root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&base, root.join("outside")).unwrap();
+        
+        let read = FileReadTool::new(root.clone());
+        let error = read.execute(serde_json::json!({ "path": "link.txt" })).await.unwrap_err();
+        assert_eq!(error.to_string(), "Path link.txt is outside the sandbox");
+        
+        let write = FileWriteTool::new(root);
+        let error = write
+            .execute(serde_json::json!({ "path": "outside/planted.txt", "content": "x" }))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Path outside/planted.txt is outside the sandbox");
+        assert!(!base.join("planted.txt").exists());
+        assert_eq!(tokio::fs::read_to_string(base.join("secret.txt")).await.unwrap(), "secret");
+        
+        tokio::fs::remove_dir_all(base).await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn file_write_rejects_parent_directory_escape() {
+        let (base, root) = sandbox_dir().await;
+        let write = FileWriteTool::new(root);
+        
+        for path in ["../secret.txt", "new/../../escaped.txt"] {
+            let error = write
+                .execute(serde_json::json!({ "path": path, "content": "overwritten" }))
+                .await
+                .unwrap_err();
+            assert_eq!(error.to_string(), format!("Path {} is outside the sandbox", path));
+        }
+        assert_eq!(tokio::fs::read_to_string(base.join("secret.txt")).await.unwrap(), "secret");
+        assert!(!base.join("escaped.txt").exists());
+        
+        tokio::fs::remove_dir_all(base).await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn tool_executions_are_counted_under_bounded_labels() {
+        let registry = ticket_registry().await;
+        registry
+            .execute_tool("create_ticket", serde_json::json!({ "title": "Printer", "description": "On fire" }))
+            .await
+            .unwrap();
+        let _ = registry.execute_tool("metrics_probe_missing_tool", serde_json::json!({})).await;
+        
+        let rendered = metrics::render();
+        assert!(
+            rendered.contains(r#"bea_tool_executions_total{status="success",tool="create_ticket"}"#),
+            "{}",
+            rendered
+        );
+        assert!(rendered.contains(r#"tool="unknown""#), "{}", rendered);
+        assert!(!rendered.contains("metrics_probe_missing_tool"), "{}", rendered);
+    }
+    
+    async fn calculate(expression: &str) -> Result<f64, String> {
+        CalculatorTool::new()
+            .execute(serde_json::json!({ "expression": expression }))
+            .await
+            .map(|result| result["result"].as_f64().unwrap())
+            .map_err(|e| e.to_string())
+    }
+    
+    #[tokio::test]
+    async fn calculator_evaluates_with_operator_precedence() {
+        assert_eq!(calculate("2 + 3 * 4").await, Ok(14.0));
+        assert_eq!(calculate("(2 + 3) * 4").await, Ok(20.0));
+        assert_eq!(calculate("10 - 4 - 3").await, Ok(3.0));
+        assert_eq!(calculate("-2 * -(1.5 + 0.5) / 4").await, Ok(1.0));
+    }
+    
+    #[tokio::test]
+    async fn calculator_rejects_division_by_zero_and_bad_input() {
+        assert_eq!(calculate("1 / (2 - 2)").await, Err("Division by zero".to_string()));
+        assert_eq!(calculate("2 +").await, Err("Unexpected end of expression".to_string()));
+        assert_eq!(calculate("(1 + 2").await, Err("Expected ')' at position 6".to_string()));
+        assert_eq!(calculate("1 + 2)").await, Err("Unexpected ')' at position 5".to_string()));
+    }
+    
+    #[tokio::test]
+    async fn calculator_refuses_deeply_nested_expressions() {
+        let nested = format!("{}1{}", "(".repeat(MAX_EXPRESSION_DEPTH), ")".repeat(MAX_EXPRESSION_DEPTH));
+        assert_eq!(calculate(&nested).await, Ok(1.0));
+        
+        let too_deep = "Expression is nested more than 64 levels deep".to_string();
+        let parentheses = format!("{}1{}", "(".repeat(50_000), ")".repeat(50_000));
+        assert_eq!(calculate(&parentheses).await, Err(too_deep.clone()));
+        assert_eq!(calculate(&format!("{}1", "-".repeat(50_000))).await, Err(too_deep));
+    }
+    
+    #[tokio::test]
+    async fn datetime_shifts_now_into_the_timezone() {
+        let before = chrono::Utc::now();
+        let result = DateTimeTool::new()
+            .execute(serde_json::json!({ "timezone": "UTC+05:30", "expression": "now + 2 days" }))
+            .await
+            .unwrap();
+        
+        let datetime = chrono::DateTime::parse_from_rfc3339(result["datetime"].as_str().unwrap()).unwrap();
+        assert_eq!(datetime.offset().local_minus_utc(), 5 * 3600 + 30 * 60);
+        let shift = datetime.with_timezone(&chrono::Utc) - before;
+        assert!(shift >= chrono::Duration::days(2) && shift < chrono::Duration::days(2) + chrono::Duration::minutes(1));
+        assert_eq!(result["unix"], datetime.timestamp());
+    }
+    
+    #[tokio::test]
+    async fn datetime_rejects_unsupported_input() {
+        let tool = DateTimeTool::new();
+        
+        let error = tool.execute(serde_json::json!({ "timezone": "Mars/Olympus" })).await.unwrap_err();
+        assert!(error.to_string().starts_with("Unsupported timezone 'Mars/Olympus'"), "{}", error);
+        
+        let nested = format!("now {}", "+ (".repeat(50_000));
+        let error = tool.execute(serde_json::json!({ "expression": nested })).await.unwrap_err();
+        assert!(error.to_string().starts_with("Unsupported expression"), "{}", error);
+        
+        let error = tool.execute(serde_json::json!({ "expression": "now + 1 fortnight" })).await.unwrap_err();
+        assert!(error.to_string().starts_with("Unsupported expression"), "{}", error);
+    }
+    
+    #[test]
+    fn private_addresses_include_those_embedded_in_ipv6() {
+        let private = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "::1",
+            "fe80::1",
+            "fd00::1",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "64:ff9b::a00:1",
+            "2002:c0a8:1::1",
+        ];
+        for ip in private {
+            assert!(is_private_ip(ip.parse().unwrap()), "{} should be private", ip);
+        }
+        
+        let public = ["93.184.216.34", "8.8.8.8", "2606:4700::1111", "::ffff:8.8.8.8", "64:ff9b::808:808", "2002:808:808::1"];
+        for ip in public {
+            assert!(!is_private_ip(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+    
+    #[test]
+    fn hosts_are_matched_against_the_allow_list() {
+        let tool = HttpRequestTool::new(vec!["api.example.com".to_string(), "*.Internal.example".to_string()]);
+        assert!(tool.host_allowed("api.example.com"));
+        assert!(tool.host_allowed("API.example.com."));
+        assert!(tool.host_allowed("a.b.internal.example"));
+        assert!(!tool.host_allowed("internal.example"));
+        assert!(!tool.host_allowed("evilinternal.example"));
+        assert!(!tool.host_allowed("api.example.com.evil"));
+        assert!(!tool.host_allowed("example.com"));
+        
+        assert!(HttpRequestTool::new(vec!["*".to_string()]).host_allowed("anything.test"));
+        assert!(!HttpRequestTool::new(Vec::new()).host_allowed("api.example.com"));
+    }
+    
+    /// Serve `app` on a local port, returning its base URL
+    async fn serve(app: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+    
+    /// Server that redirects every request to `location`
+    async fn redirecting_to(location: &str) -> String {
+        let location = location.to_string();
+        serve(axum::Router::new().fallback(move || async move { axum::response::Redirect::temporary(&location) })).await
+    }
+    
+    async fn fetch(tool: &HttpRequestTool, url: &str) -> Result<serde_json::Value, String> {
+        tool.execute(serde_json::json!({
+            "url": url,
+            "headers": { "authorization": "Bearer secret", "x-trace": "1" }
+        }))
+        .await
+        .map_err(|e| e.to_string())
+    }
+    
+    #[tokio::test]
+    async fn redirects_to_disallowed_hosts_are_refused() {
+        let url = redirecting_to("http://evil.example/").await;
+        let tool = HttpRequestTool::new(vec!["127.0.0.1".to_string()]).with_allow_private(true);
+        
+        assert_eq!(fetch(&tool, &url).await.unwrap_err(), "Host evil.example is not allowed");
+    }
+    
+    #[tokio::test]
+    async fn redirects_to_private_addresses_are_refused_through_a_proxy() {
+        // The proxy answers for the public address with a redirect to a private one
+        let proxied = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = proxied.clone();
+        let proxy = serve(axum::Router::new().fallback(move |uri: axum::http::Uri| {
+            seen.lock().unwrap().push(uri.to_string());
+            async { axum::response::Redirect::temporary("http://10.0.0.1/admin") }
+        }))
+        .await;
+        let tool = HttpRequestTool::new(vec!["*".to_string()])
+            .with_http_client_builder(http::HttpClientBuilder::new().with_proxy(&proxy));
+        
+        let err = fetch(&tool, "http://93.184.216.34/start").await.unwrap_err();
+        assert_eq!(err, "Host 10.0.0.1 resolves to private address 10.0.0.1");
+        assert_eq!(*proxied.lock().unwrap(), vec!["http://93.184.216.34/start".to_string()]);
+    }
+    
+    #[tokio::test]
+    async fn credentials_are_dropped_on_cross_origin_redirects() {
+        let echo = serve(axum::Router::new().fallback(|headers: axum::http::HeaderMap| async move {
+            format!("{:?} {:?}", headers.get("authorization"), headers.get("x-trace"))
+        }))
+        .await;
+        let tool = HttpRequestTool::new(vec!["127.0.0.1".to_string(), "localhost".to_string()]).with_allow_private(true);
+        
+        // Same origin keeps the credentials
+        let same = serve(axum::Router::new()
+            .route("/start", axum::routing::get(|| async { axum::response::Redirect::temporary("/echo") }))
+            .route("/echo", axum::routing::get(|headers: axum::http::HeaderMap| async move {
+                format!("{:?}", headers.get("authorization"))
+            })))
+            .await;
+        let response = fetch(&tool, &format!("{}/start", same)).await.unwrap();
+        assert_eq!(response["body"], r#"Some("Bearer secret")"#);
+        
+        // Only the port differs
+        let response = fetch(&tool, &redirecting_to(&echo).await).await.unwrap();
+        assert_eq!(response["body"], r#"None Some("1")"#);
+        
+        // Only the host differs
+        let response = fetch(&tool, &redirecting_to(&echo.replace("127.0.0.1", "localhost")).await).await.unwrap();
+        assert_eq!(response["body"], r#"None Some("1")"#);
+    }
+    
+    /// Delegation tool over a registered "billing" agent whose replies report the depth they ran at
+    async fn delegation_tool() -> DelegateAgentTool {
+        let agents = Arc::new(AgentStateManager::new());
+        agents.register_agent(&crate::Agent::new("billing").with_model("anthropic/billing")).await.unwrap();
+        
+        let provider = crate::models::MockProvider::from_fn(|_| {
+            let depth = DELEGATION_DEPTH.try_with(|depth| *depth).unwrap_or(0);
+            crate::models::ModelResponse::new(Message::assistant(&format!("depth {}", depth)), "")
+        })
+        .with_name("anthropic");
+        let registry = Arc::new(ModelRegistry::new());
+        registry.register_provider(provider).await.unwrap();
+        
+        DelegateAgentTool::new(agents, registry).with_max_depth(2)
+    }
+    
+    #[tokio::test]
+    async fn delegates_run_one_level_deeper() {
+        let tool = delegation_tool().await;
+        let parameters = serde_json::json!({ "target_agent": "billing", "prompt": "Refund order 7" });
+        
+        let result = tool.execute(parameters.clone()).await.unwrap();
+        assert_eq!(result["response"], "depth 1");
+        
+        let result = DELEGATION_DEPTH.scope(1, tool.execute(parameters)).await.unwrap();
+        assert_eq!(result["response"], "depth 2");
+    }
+    
+    #[tokio::test]
+    async fn delegation_is_refused_at_the_maximum_depth() {
+        let tool = delegation_tool().await;
+        
+        // A depth in the arguments is ignored
+        let parameters = serde_json::json!({ "target_agent": "billing", "prompt": "Refund order 7", "depth": 0 });
+        let err = DELEGATION_DEPTH.scope(2, tool.execute(parameters)).await.unwrap_err();
+        assert_eq!(err.to_string(), "Delegation depth 2 reached the limit of 2");
+    }
+    
+    #[tokio::test]
+    async fn delegating_to_an_unknown_agent_fails() {
+        let tool = delegation_tool().await;
+        
+        let err = tool
+            .execute(serde_json::json!({ "target_agent": "nobody", "prompt": "Hello" }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), BeaError::AgentNotFound("nobody".to_string()).to_string());
+    }
+    
+    /// Tool that sleeps for `delay_ms` and tracks how many calls overlap
+    #[derive(Default)]
+    struct SleepTool {
+        running: std::sync::atomic::AtomicUsize,
+        peak: std::sync::atomic::AtomicUsize,
+    }
+    
+    #[async_trait]
+    impl Tool for Arc<SleepTool> {
+        fn name(&self) -> &str {
+            "sleep"
+        }
+        
+        fn description(&self) -> &str {
+            "Sleep for a while"
+        }
+        
+        fn parameters_schema(&self) -> serde_json::Value {
+            serde_json::json!({
+                "type": "object",
+                "properties": { "delay_ms": { "type": "integer" } },
+                "required": ["delay_ms"],
+            })
+        }
+        
+        fn required_permissions(&self) -> Vec<String> {
+            Vec::new()
+        }
+        
+        async fn execute(&self, parameters: serde_json::Value) -> Result<serde_json::Value, Box<dyn Error>> {
+            use std::sync::atomic::Ordering;
+            
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(parameters["delay_ms"].as_u64().unwrap_or(0))).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(parameters)
+        }
+    }
+    
+    /// Run five sleeps, longest first, with the given limit and return the
+    /// delays in result order along with the peak number of overlapping calls
+    async fn run_sleeps(max_concurrency: usize) -> (Vec<u64>, usize) {
+        let tool = Arc::new(SleepTool::default());
+        let registry = ToolRegistry::new();
+        registry.register_tool(tool.clone()).await.unwrap();
+        
+        let calls = (1..=5)
+            .rev()
+            .map(|i| ("sleep".to_string(), serde_json::json!({ "delay_ms": i * 20 })))
+            .collect();
+        let results = tokio::time::timeout(Duration::from_secs(5), registry.execute_many(calls, max_concurrency))
+            .await
+            .expect("execute_many finishes");
+        
+        let delays = results.iter().map(|r| r.data["delay_ms"].as_u64().unwrap()).collect();
+        (delays, tool.peak.load(std::sync::atomic::Ordering::SeqCst))
+    }
+    
+    #[tokio::test]
+    async fn execute_many_bounds_concurrency_and_keeps_call_order() {
+        let (delays, peak) = run_sleeps(2).await;
+        
+        assert_eq!(delays, vec![100, 80, 60, 40, 20]);
+        assert_eq!(peak, 2);
+    }
+    
+    #[tokio::test]
+    async fn execute_many_runs_one_at_a_time_with_a_limit_of_zero() {
+        let (delays, peak) = run_sleeps(0).await;
+        
+        assert_eq!(delays, vec![100, 80, 60, 40, 20]);
+        assert_eq!(peak, 1);
+    }
+}